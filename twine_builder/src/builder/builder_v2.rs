@@ -1,30 +1,37 @@
 //! Twine builder for version 2 data
+use super::prepared::{PreparedStrand, PreparedTixel};
 use super::*;
+use crate::DerivationPath;
 use twine_lib::{
+  cosign::{CosignPolicy, CosignRequest, Cosignature, COSIGNERS_DETAILS_KEY},
   crypto::PublicKey,
   errors::{SpecificationError, VerificationError},
   ipld_core::{codec::Codec, serde::to_ipld},
   multihash_codetable::Code,
+  schemas::v2,
   semver::Version,
   skiplist::get_layer_pos,
   specification::Subspec,
-  twine::{CrossStitches, Stitch, Strand, Tixel, Twine},
+  twine::{CrossStitches, Stitch, Strand, Tixel, Twine, SUCCESSOR_FIELD, TIMESTAMP_FIELD},
   verify::Verified,
-  Ipld,
+  Cid, Ipld,
 };
 
 /// A builder for constructing a Tixel
 ///
 /// Don't create this directly, use [`TwineBuilder`] instead.
-pub struct TixelBuilder<'a, 'b, S: Signer<Key = PublicKey>> {
+pub struct TixelBuilder<'a, 'b, S> {
   signer: &'a S,
   strand: Strand,
   prev: Option<&'b Twine>,
   stitches: CrossStitches,
   payload: Ipld,
+  timestamp: Option<chrono::DateTime<chrono::Utc>>,
+  successor: Option<Cid>,
+  cosignatures: Vec<Cosignature>,
 }
 
-impl<'a, 'b, S: Signer<Key = PublicKey>> TixelBuilder<'a, 'b, S> {
+impl<'a, 'b, S> TixelBuilder<'a, 'b, S> {
   pub(crate) fn new_first(signer: &'a S, strand: Strand) -> Self {
     Self {
       signer,
@@ -32,6 +39,9 @@ impl<'a, 'b, S: Signer<Key = PublicKey>> TixelBuilder<'a, 'b, S> {
       prev: None,
       stitches: CrossStitches::default(),
       payload: Ipld::Null,
+      timestamp: None,
+      successor: None,
+      cosignatures: Vec::new(),
     }
   }
 
@@ -42,6 +52,9 @@ impl<'a, 'b, S: Signer<Key = PublicKey>> TixelBuilder<'a, 'b, S> {
       prev: Some(prev),
       stitches: prev.cross_stitches(),
       payload: Ipld::Null,
+      timestamp: None,
+      successor: None,
+      cosignatures: Vec::new(),
     }
   }
 
@@ -62,6 +75,119 @@ impl<'a, 'b, S: Signer<Key = PublicKey>> TixelBuilder<'a, 'b, S> {
     self
   }
 
+  /// Record a timestamp in the payload of this tixel
+  ///
+  /// The timestamp is written to the [`twine_lib::twine::TIMESTAMP_FIELD`]
+  /// of the payload when `done()` is called, replacing any existing value.
+  /// The payload must be a map (or unset).
+  pub fn timestamp(mut self, timestamp: chrono::DateTime<chrono::Utc>) -> Self {
+    self.timestamp = Some(timestamp);
+    self
+  }
+
+  /// Record the current time in the payload of this tixel
+  ///
+  /// See [`TixelBuilder::timestamp`]
+  pub fn timestamp_now(self) -> Self {
+    self.timestamp(chrono::Utc::now())
+  }
+
+  /// Name the successor of this tixel's strand
+  ///
+  /// The successor strand CID is written as a link to the
+  /// [`twine_lib::twine::SUCCESSOR_FIELD`] of the payload, marking this
+  /// tixel as a key handoff. It should be the last tixel of the strand.
+  /// The payload must be a map (or unset).
+  /// See [`TwineBuilder::build_handoff`].
+  pub fn successor(mut self, successor: &Strand) -> Self {
+    self.successor = Some(successor.cid());
+    self
+  }
+
+  /// Add a co-signature to this tixel
+  ///
+  /// Co-signatures are collected from the co-signers of a strand with a
+  /// [`CosignPolicy`], who each sign the request from
+  /// [`TixelBuilder::cosign_request`] (see [`TwineBuilder::cosign`]).
+  /// They are written to the [`twine_lib::cosign::COSIGNATURES_FIELD`]
+  /// of the payload, which must be a map (or unset).
+  /// On such a strand, building fails unless the co-signatures meet the
+  /// policy's threshold, even if none were added.
+  pub fn cosignature(mut self, cosignature: Cosignature) -> Self {
+    self.cosignatures.push(cosignature);
+    self
+  }
+
+  /// Add several co-signatures to this tixel
+  ///
+  /// See [`TixelBuilder::cosignature`]
+  pub fn cosignatures<I: IntoIterator<Item = Cosignature>>(mut self, cosignatures: I) -> Self {
+    self.cosignatures.extend(cosignatures);
+    self
+  }
+
+  /// Get the request that co-signers must sign for this tixel
+  ///
+  /// The payload, timestamp, successor and cross-stitches must be set
+  /// before calling this, since the co-signatures cover the whole content
+  /// of the tixel.
+  pub fn cosign_request(&self) -> Result<CosignRequest, BuildError> {
+    use twine_lib::serde_ipld_dagcbor::codec::DagCborCodec;
+    let content = self.unsigned_content(self.stamped_payload()?)?;
+    // round trip through the encoding so the request matches the signed bytes
+    let bytes = DagCborCodec::encode_to_vec(&content).unwrap();
+    let content = DagCborCodec::decode_from_slice(&bytes)
+      .map_err(|e| BuildError::PayloadConstruction(e.to_string()))?;
+    Ok(CosignRequest::new(content)?)
+  }
+
+  fn index(&self) -> Result<u64, BuildError> {
+    self
+      .prev
+      .as_ref()
+      .map(|p| (p.index()).checked_add(1).ok_or(BuildError::IndexMaximum))
+      .unwrap_or(Ok(0))
+  }
+
+  fn signed_payload(&self) -> Result<Ipld, BuildError> {
+    let policy = match CosignPolicy::from_strand(&self.strand) {
+      Ok(Some(policy)) => policy,
+      // strands that use the details key for something else are built as usual
+      Ok(None) | Err(_) if self.cosignatures.is_empty() => return self.stamped_payload(),
+      res => res?.ok_or_else(|| {
+        VerificationError::General("Strand does not require co-signatures".into())
+      })?,
+    };
+    let request = self.cosign_request()?;
+    policy.verify_request(&self.strand, &request, &self.cosignatures)?;
+    Ok(request.payload_with(&self.cosignatures)?)
+  }
+
+  fn stamped_payload(&self) -> Result<Ipld, BuildError> {
+    if self.timestamp.is_none() && self.successor.is_none() {
+      return Ok(self.payload.clone());
+    }
+    let mut map = match &self.payload {
+      Ipld::Null => Default::default(),
+      Ipld::Map(map) => map.clone(),
+      _ => {
+        return Err(BuildError::PayloadConstruction(
+          "Payload must be a map to record a timestamp or successor".into(),
+        ))
+      }
+    };
+    if let Some(timestamp) = self.timestamp {
+      map.insert(
+        TIMESTAMP_FIELD.to_string(),
+        Ipld::String(timestamp.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true)),
+      );
+    }
+    if let Some(successor) = self.successor {
+      map.insert(SUCCESSOR_FIELD.to_string(), Ipld::Link(successor));
+    }
+    Ok(Ipld::Map(map))
+  }
+
   fn next_back_stitches(&self) -> Result<Vec<Stitch>, BuildError> {
     if let Some(prev) = &self.prev {
       let mut stitches = prev.back_stitches().into_inner();
@@ -132,21 +258,21 @@ impl<'a, 'b, S: Signer<Key = PublicKey>> TixelBuilder<'a, 'b, S> {
   where
     F: FnOnce(&Strand, Option<&Twine>) -> Result<P, BuildError>,
     P: serde::ser::Serialize,
+    S: Signer<Key = PublicKey>,
   {
     let payload = build_fn(&self.strand, self.prev)?;
     self.payload = to_ipld(payload).unwrap();
     self.done()
   }
 
-  /// Finalize the tixel and return the constructed twine
-  pub fn done(self) -> Result<Twine, BuildError> {
-    use twine_lib::schemas::*;
+  fn content(self) -> Result<(Strand, v2::TixelContentV2), BuildError> {
+    let content = self.unsigned_content(self.signed_payload()?)?;
+    Ok((self.strand, content))
+  }
 
-    let index = self
-      .prev
-      .as_ref()
-      .map(|p| (p.index()).checked_add(1).ok_or(BuildError::IndexMaximum))
-      .unwrap_or(Ok(0))?;
+  /// The content of the tixel with the given payload
+  fn unsigned_content(&self, payload: Ipld) -> Result<v2::TixelContentV2, BuildError> {
+    let index = self.index()?;
 
     // The drop index becomes the current tixel index if
     // the specified cross-stitches are not a superset of the previous ones
@@ -163,8 +289,8 @@ impl<'a, 'b, S: Signer<Key = PublicKey>> TixelBuilder<'a, 'b, S> {
       None => 0,
     };
 
-    let content: v2::TixelContentV2 = match self.strand.version().major {
-      2 => v2::TixelContentV2 {
+    match self.strand.version().major {
+      2 => Ok(v2::TixelContentV2 {
         code: self.strand.hasher().into(),
         specification: self.strand.spec_str().parse()?,
         fields: Verified::try_new(v2::TixelFields {
@@ -174,33 +300,88 @@ impl<'a, 'b, S: Signer<Key = PublicKey>> TixelBuilder<'a, 'b, S> {
             .into_iter()
             .map(|s| Some(s.tixel))
             .collect(),
-          payload: self.payload,
-          cross_stitches: self.stitches.into(),
+          payload,
+          cross_stitches: self.stitches.clone().into(),
           strand: self.strand.cid(),
           drop,
         })?,
-      },
-      _ => {
-        return Err(BuildError::BadSpecification(SpecificationError::new(
-          format!("Unsupported version: {}", self.strand.version()),
-        )))
-      }
-    };
+      }),
+      _ => Err(BuildError::BadSpecification(SpecificationError::new(
+        format!("Unsupported version: {}", self.strand.version()),
+      ))),
+    }
+  }
 
+  /// Finalize the tixel and return the constructed twine
+  pub fn done(self) -> Result<Twine, BuildError>
+  where
+    S: Signer<Key = PublicKey>,
+  {
+    let signer = self.signer;
+    let (strand, content) = self.content()?;
     let bytes =
       twine_lib::serde_ipld_dagcbor::codec::DagCborCodec::encode_to_vec(&content).unwrap();
-    let signature = self.signer.sign(&bytes)?;
+    let signature = signer.sign(&bytes)?;
 
     let container = v2::ContainerV2::new_from_parts(Verified::try_new(content)?, signature);
     let tixel = Tixel::try_new(container)?;
-    Ok(Twine::try_new(self.strand, tixel)?)
+    Ok(Twine::try_new(strand, tixel)?)
+  }
+
+  /// Prepare the tixel for signing elsewhere
+  ///
+  /// Instead of signing with the builder's [`Signer`], this returns
+  /// the unsigned content as a [`PreparedTixel`] which can be saved to a file,
+  /// signed on another machine, and then finalized with the detached signature.
+  /// Only the signer's public key is needed, so this can be used with a
+  /// [`crate::PublicKeySigner`].
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use twine_builder::{TwineBuilder, RingSigner, PublicKeySigner, Signer};
+  /// use twine_builder::builder::prepared::PreparedStrand;
+  /// // this key is kept on an air-gapped machine
+  /// let offline = RingSigner::generate_ed25519().unwrap();
+  /// // only the public key is needed to build
+  /// let builder = TwineBuilder::new(PublicKeySigner::new(offline.public_key()));
+  /// let file = builder.build_strand().prepare().unwrap().to_dag_json();
+  /// // ...on the air-gapped machine
+  /// let prepared = PreparedStrand::from_dag_json(&file).unwrap();
+  /// let signature = offline.sign(prepared.message()).unwrap();
+  /// // ...back online
+  /// let strand = prepared.finalize(signature).unwrap();
+  /// let prepared = builder.build_first(strand).payload("hello").prepare().unwrap();
+  /// let signature = offline.sign(prepared.message()).unwrap();
+  /// let twine = prepared.finalize(signature).unwrap();
+  /// ```
+  pub fn prepare(self) -> Result<PreparedTixel, BuildError>
+  where
+    S: Signer<Key = PublicKey>,
+  {
+    let (strand, content) = self.content()?;
+    Ok(PreparedTixel::new(&strand, &content))
+  }
+
+  /// Finalize the tixel using an [`AsyncSigner`] and return the constructed twine
+  ///
+  /// See [`crate::AsyncTwineBuilder`] for an example.
+  pub async fn done_async(self) -> Result<Twine, BuildError>
+  where
+    S: AsyncSigner<Key = PublicKey>,
+  {
+    let signer = self.signer;
+    let (strand, content) = self.content()?;
+    let prepared = PreparedTixel::new(&strand, &content);
+    let signature = signer.sign_async(prepared.message()).await?;
+    prepared.finalize(signature)
   }
 }
 
 /// A builder for constructing a Strand
 ///
 /// Don't create this directly, use [`TwineBuilder`] instead.
-pub struct StrandBuilder<'a, S: Signer<Key = PublicKey>> {
+pub struct StrandBuilder<'a, S> {
   signer: &'a S,
   hasher: Code,
   version: Version,
@@ -208,9 +389,11 @@ pub struct StrandBuilder<'a, S: Signer<Key = PublicKey>> {
   genesis: Option<chrono::DateTime<chrono::Utc>>,
   subspec: Option<Subspec>,
   radix: u8,
+  derivation_path: Option<DerivationPath>,
+  cosigners: Option<CosignPolicy>,
 }
 
-impl<'a, S: Signer<Key = PublicKey>> StrandBuilder<'a, S> {
+impl<'a, S> StrandBuilder<'a, S> {
   pub(crate) fn new(signer: &'a S) -> Self {
     Self {
       signer,
@@ -220,6 +403,8 @@ impl<'a, S: Signer<Key = PublicKey>> StrandBuilder<'a, S> {
       genesis: None,
       subspec: None,
       radix: 32,
+      derivation_path: None,
+      cosigners: None,
     }
   }
 
@@ -266,11 +451,51 @@ impl<'a, S: Signer<Key = PublicKey>> StrandBuilder<'a, S> {
     self
   }
 
-  /// Finalize the strand and return the constructed strand
-  pub fn done(self) -> Result<Strand, BuildError> {
-    use twine_lib::schemas::*;
-    let key = self.signer.public_key();
+  /// Record the derivation path of the strand's key in its details
+  ///
+  /// This is useful for keys from [`crate::RingSigner::derive`], so that the
+  /// key can be recreated from the master seed. The path is stored under
+  /// [`DerivationPath::DETAILS_KEY`], so the details must be a map.
+  pub fn derivation_path(mut self, path: &DerivationPath) -> Self {
+    self.derivation_path = Some(path.clone());
+    self
+  }
+
+  /// Require every tixel of this strand to be co-signed
+  ///
+  /// The policy is recorded in the details under
+  /// [`twine_lib::cosign::COSIGNERS_DETAILS_KEY`], so the details must be a map.
+  /// The builder then refuses to finish a tixel without enough co-signatures.
+  /// Plain verification does not check the policy, so tixels built some other
+  /// way are only rejected when resolved through a
+  /// [`twine_lib::resolver::CosignedResolver`].
+  /// See [`TixelBuilder::cosignature`].
+  pub fn cosigners(mut self, policy: &CosignPolicy) -> Self {
+    self.cosigners = Some(policy.clone());
+    self
+  }
+
+  fn insert_detail(&mut self, key: &str, value: Ipld) -> Result<(), BuildError> {
+    match &mut self.details {
+      Ipld::Map(map) => {
+        map.insert(key.to_string(), value);
+        Ok(())
+      }
+      _ => Err(BuildError::BadData(VerificationError::General(format!(
+        "Details must be a map to record {}",
+        key
+      )))),
+    }
+  }
 
+  fn content(mut self, key: PublicKey) -> Result<v2::StrandContentV2, BuildError> {
+    if let Some(path) = self.derivation_path.take() {
+      self.insert_detail(DerivationPath::DETAILS_KEY, Ipld::String(path.to_string()))?;
+    }
+    if let Some(policy) = self.cosigners.take() {
+      let policy = to_ipld(policy).map_err(|e| BuildError::PayloadConstruction(e.to_string()))?;
+      self.insert_detail(COSIGNERS_DETAILS_KEY, policy)?;
+    }
     let content = match self.version.major {
       2 => v2::StrandContentV2 {
         code: self.hasher.into(),
@@ -282,7 +507,7 @@ impl<'a, S: Signer<Key = PublicKey>> StrandBuilder<'a, S> {
           radix: self.radix,
           details: self.details,
           key,
-          genesis: self.genesis.unwrap_or_else(|| chrono::Utc::now()),
+          genesis: self.genesis.unwrap_or_else(chrono::Utc::now),
           expiry: None,
         })?,
       },
@@ -293,12 +518,46 @@ impl<'a, S: Signer<Key = PublicKey>> StrandBuilder<'a, S> {
       }
     };
 
+    Ok(content)
+  }
+
+  /// Finalize the strand and return the constructed strand
+  pub fn done(self) -> Result<Strand, BuildError>
+  where
+    S: Signer<Key = PublicKey>,
+  {
+    let signer = self.signer;
+    let content = self.content(signer.public_key())?;
     let bytes =
       twine_lib::serde_ipld_dagcbor::codec::DagCborCodec::encode_to_vec(&content).unwrap();
-    let signature = self.signer.sign(&bytes)?;
+    let signature = signer.sign(&bytes)?;
     let container = v2::ContainerV2::new_from_parts(Verified::try_new(content)?, signature);
     Ok(Strand::try_new(container)?)
   }
+
+  /// Prepare the strand for signing elsewhere
+  ///
+  /// See [`TixelBuilder::prepare`] for details.
+  pub fn prepare(self) -> Result<PreparedStrand, BuildError>
+  where
+    S: Signer<Key = PublicKey>,
+  {
+    let key = self.signer.public_key();
+    Ok(PreparedStrand::new(&self.content(key)?))
+  }
+
+  /// Finalize the strand using an [`AsyncSigner`] and return the constructed strand
+  ///
+  /// See [`crate::AsyncTwineBuilder`] for an example.
+  pub async fn done_async(self) -> Result<Strand, BuildError>
+  where
+    S: AsyncSigner<Key = PublicKey>,
+  {
+    let signer = self.signer;
+    let prepared = PreparedStrand::new(&self.content(signer.public_key())?);
+    let signature = signer.sign_async(prepared.message()).await?;
+    prepared.finalize(signature)
+  }
 }
 
 #[cfg(feature = "rsa")]
@@ -1,9 +1,10 @@
 //! Provides the interface to build Twine data.
-use crate::{signer::SigningError, Signer};
+use crate::{signer::SigningError, AsyncSigner, Signer};
 use twine_lib::{
+  cosign::{CosignPolicy, CosignRequest, Cosignature},
   crypto::PublicKey,
   errors::{SpecificationError, VerificationError},
-  twine::{Strand, Twine},
+  twine::{Stitch, Strand, Twine},
 };
 
 #[cfg(feature = "v1")]
@@ -11,6 +12,7 @@ use biscuit::jwk::JWK;
 #[cfg(feature = "v1")]
 pub mod builder_v1;
 pub mod builder_v2;
+pub mod prepared;
 
 /// Errors that can occur when building Twine data.
 #[derive(Debug, thiserror::Error)]
@@ -253,6 +255,158 @@ impl<S: Signer<Key = PublicKey>> TwineBuilder<2, S> {
   pub fn build_next<'a, 'b>(&'a self, prev: &'b Twine) -> builder_v2::TixelBuilder<'a, 'b, S> {
     builder_v2::TixelBuilder::new_next(&self.signer, prev)
   }
+
+  /// Begin building the handoff tixel of a strand that is retiring its key
+  ///
+  /// The tixel names `successor` as the strand that continues this one.
+  /// It should be the last tixel added to the strand. The successor's
+  /// first tixel must then be built with [`TwineBuilder::build_first_successor`]
+  /// using the successor's key, so that both keys sign the handoff.
+  ///
+  /// # Example
+  ///
+  /// ```no_run
+  /// use twine_builder::{TwineBuilder, RingSigner};
+  /// let old = TwineBuilder::new(RingSigner::generate_ed25519().unwrap());
+  /// let new = TwineBuilder::new(RingSigner::generate_ed25519().unwrap());
+  /// let strand = old.build_strand().done().unwrap();
+  /// let prev = old.build_first(strand).done().unwrap();
+  /// let successor = new.build_strand().done().unwrap();
+  /// let handoff = old.build_handoff(&prev, &successor).done().unwrap();
+  /// let first = new.build_first_successor(successor, &handoff).done().unwrap();
+  /// ```
+  pub fn build_handoff<'a, 'b>(
+    &'a self,
+    prev: &'b Twine,
+    successor: &Strand,
+  ) -> builder_v2::TixelBuilder<'a, 'b, S> {
+    self.build_next(prev).successor(successor)
+  }
+
+  /// Begin building the first tixel of a successor strand
+  ///
+  /// The tixel cross-stitches back to the `handoff` tixel of the
+  /// predecessor strand. See [`TwineBuilder::build_handoff`].
+  /// Setting other cross-stitches should keep the stitch to the handoff.
+  pub fn build_first_successor<'a>(
+    &'a self,
+    strand: Strand,
+    handoff: &Twine,
+  ) -> builder_v2::TixelBuilder<'a, 'a, S> {
+    self
+      .build_first(strand)
+      .cross_stitches(vec![Stitch::from(handoff.clone())])
+  }
+
+  /// Co-sign a tixel of a strand that requires co-signatures
+  ///
+  /// The builder's signer must be one of the co-signers in the strand's
+  /// [`CosignPolicy`]. The request comes from the tixel's builder with
+  /// [`builder_v2::TixelBuilder::cosign_request`], and the returned
+  /// co-signature is handed back to be added with
+  /// [`builder_v2::TixelBuilder::cosignature`].
+  ///
+  /// # Example
+  ///
+  /// ```no_run
+  /// use twine_builder::{TwineBuilder, RingSigner, Signer};
+  /// use twine_lib::cosign::CosignPolicy;
+  /// let operator = TwineBuilder::new(RingSigner::generate_ed25519().unwrap());
+  /// let signers: Vec<_> = (0..3).map(|_| RingSigner::generate_ed25519().unwrap()).collect();
+  /// let keys = signers.iter().map(|s| s.public_key()).collect();
+  /// let cosigners: Vec<TwineBuilder<2, _>> = signers.into_iter().map(TwineBuilder::new).collect();
+  /// let policy = CosignPolicy::new(2, keys).unwrap();
+  /// let strand = operator.build_strand().cosigners(&policy).done().unwrap();
+  /// let request = operator.build_first(strand.clone()).payload(()).cosign_request().unwrap();
+  /// let first = operator.build_first(strand.clone())
+  ///   .cosignature(cosigners[0].cosign(&strand, &request).unwrap())
+  ///   .cosignature(cosigners[2].cosign(&strand, &request).unwrap())
+  ///   .done()
+  ///   .unwrap();
+  /// ```
+  pub fn cosign(
+    &self,
+    strand: &Strand,
+    request: &CosignRequest,
+  ) -> Result<Cosignature, BuildError> {
+    let policy = CosignPolicy::from_strand(strand)?
+      .ok_or_else(|| VerificationError::General("Strand does not require co-signatures".into()))?;
+    let key = policy.position(&self.signer.public_key()).ok_or_else(|| {
+      VerificationError::General("Signer is not a co-signer of the strand".into())
+    })?;
+    let digest = request.digest(strand)?;
+    let signature = self.signer.sign(digest.to_bytes())?;
+    Ok(Cosignature { key, signature })
+  }
+}
+
+/// Provides the interface to build Strands and Tixels with an [`AsyncSigner`].
+///
+/// This mirrors the v2 methods of [`TwineBuilder`], but the resulting
+/// builders are finalized with their `done_async()` methods.
+///
+/// # Example
+///
+/// ```no_run
+/// use twine_builder::{AsyncSigner, AsyncTwineBuilder, SigningError};
+/// use twine_lib::crypto::{PublicKey, Signature};
+///
+/// struct KmsSigner {
+///   // ...
+/// #  key: PublicKey,
+/// }
+///
+/// #[async_trait::async_trait]
+/// impl AsyncSigner for KmsSigner {
+///   type Key = PublicKey;
+///
+///   async fn sign_async(&self, data: &[u8]) -> Result<Signature, SigningError> {
+///     // ask the remote service for a signature
+/// #    unimplemented!()
+///   }
+///
+///   fn public_key(&self) -> PublicKey {
+///     self.key.clone()
+///   }
+/// }
+///
+/// # async fn example(signer: KmsSigner) {
+/// let builder = AsyncTwineBuilder::new(signer);
+/// let strand = builder.build_strand().done_async().await.unwrap();
+/// let first = builder.build_first(strand).payload("hello").done_async().await.unwrap();
+/// let next = builder.build_next(&first).done_async().await.unwrap();
+/// # }
+/// ```
+pub struct AsyncTwineBuilder<S: AsyncSigner> {
+  signer: S,
+}
+
+impl<S: AsyncSigner<Key = PublicKey>> AsyncTwineBuilder<S> {
+  /// Create a new AsyncTwineBuilder with the provided [`AsyncSigner`].
+  pub fn new(signer: S) -> Self {
+    Self { signer }
+  }
+
+  /// Begin building a new [`Strand`].
+  ///
+  /// See [`TwineBuilder::build_strand`].
+  pub fn build_strand<'a>(&'a self) -> builder_v2::StrandBuilder<'a, S> {
+    builder_v2::StrandBuilder::new(&self.signer)
+  }
+
+  /// Begin building the first tixel (as a [`Twine`])
+  ///
+  /// See [`TwineBuilder::build_first`].
+  pub fn build_first<'a>(&'a self, strand: Strand) -> builder_v2::TixelBuilder<'a, 'a, S> {
+    builder_v2::TixelBuilder::new_first(&self.signer, strand)
+  }
+
+  /// Begin building subsequent tixel (as a [`Twine`])
+  ///
+  /// See [`TwineBuilder::build_next`].
+  pub fn build_next<'a, 'b>(&'a self, prev: &'b Twine) -> builder_v2::TixelBuilder<'a, 'b, S> {
+    builder_v2::TixelBuilder::new_next(&self.signer, prev)
+  }
 }
 
 #[cfg(feature = "v1")]
@@ -451,13 +605,150 @@ mod testv1 {
 #[cfg(test)]
 mod testv2 {
   use super::*;
+  use crate::RingSigner;
   use ring::signature::Ed25519KeyPair;
   use twine_lib::{
     ipld_core::ipld,
     store::MemoryStore,
-    twine::{CrossStitches, Twine, TwineBlock},
+    twine::{CrossStitches, Strand, Twine, TwineBlock},
   };
 
+  #[test]
+  fn test_build_rustcrypto_algorithms() {
+    let signers = [
+      RingSigner::generate_secp256k1().unwrap(),
+      RingSigner::generate_p521().unwrap(),
+      RingSigner::generate_ed448().unwrap(),
+    ];
+    for signer in signers {
+      let builder: TwineBuilder<2, _> = TwineBuilder::new(signer);
+      let strand = builder.build_strand().done().unwrap();
+      let first = builder.build_first(strand.clone()).done().unwrap();
+      let next = builder.build_next(&first).done().unwrap();
+      assert_eq!(next.index(), 1);
+      // survives a round trip through the block bytes
+      let decoded = Strand::from_block(strand.cid(), strand.bytes()).unwrap();
+      assert_eq!(decoded, strand);
+    }
+  }
+
+  #[test]
+  fn test_derivation_path_details() {
+    use crate::DerivationPath;
+    use twine_lib::crypto::SignatureAlgorithm;
+    let seed = [7u8; 32];
+    let path: DerivationPath = "m/0'/12'".parse().unwrap();
+    let signer = RingSigner::derive(SignatureAlgorithm::Ed25519, &seed, &path).unwrap();
+    let builder: TwineBuilder<2, _> = TwineBuilder::new(signer);
+    let strand = builder
+      .build_strand()
+      .details(ipld!({ "sensor": 12 }))
+      .derivation_path(&path)
+      .done()
+      .unwrap();
+    let recorded = DerivationPath::from_details(strand.details()).unwrap();
+    assert_eq!(recorded, path);
+    let rederived = RingSigner::derive(SignatureAlgorithm::Ed25519, &seed, &recorded).unwrap();
+    assert_eq!(rederived.public_key().key, strand.key().key);
+
+    let not_a_map = builder
+      .build_strand()
+      .details("sensor")
+      .derivation_path(&path)
+      .done();
+    assert!(not_a_map.is_err());
+  }
+
+  #[cfg(feature = "rsa")]
+  #[tokio::test]
+  async fn test_rsa_key_sizes() {
+    use twine_lib::{resolver::Resolver, store::Store};
+    let generators = [
+      RingSigner::generate_rs256,
+      RingSigner::generate_rs384,
+      RingSigner::generate_rs512,
+      RingSigner::generate_ps256,
+      RingSigner::generate_ps384,
+      RingSigner::generate_ps512,
+    ];
+    for bitsize in [2048, 3072, 4096] {
+      for generate in generators {
+        let signer = generate(bitsize).unwrap();
+        let builder: TwineBuilder<2, _> = TwineBuilder::new(signer);
+        let strand = builder.build_strand().done().unwrap();
+        let first = builder.build_first(strand.clone()).done().unwrap();
+        let next = builder.build_next(&first).done().unwrap();
+
+        let store = MemoryStore::new();
+        store.save(strand.clone()).await.unwrap();
+        store.save_many(vec![first, next.clone()]).await.unwrap();
+        let resolved = store.resolve_strand(strand.cid()).await.unwrap().unpack();
+        assert_eq!(resolved.key().alg.to_string(), strand.key().alg.to_string());
+        let latest = store.resolve_latest(&strand).await.unwrap().unpack();
+        assert_eq!(latest, next);
+      }
+    }
+  }
+
+  #[cfg(feature = "pq")]
+  #[tokio::test]
+  async fn test_post_quantum_roundtrip() {
+    use crate::PqSigner;
+    use twine_lib::{
+      car::{from_car_bytes, to_car_bytes},
+      crypto::SignatureAlgorithm,
+      resolver::Resolver,
+      store::Store,
+      twine::AnyTwine,
+    };
+
+    // SLH-DSA is slow to sign, so it only signs the strand
+    let slh = TwineBuilder::new(PqSigner::generate(SignatureAlgorithm::SlhDsaSha2_128s).unwrap());
+    let slh_strand = slh.build_strand().done().unwrap();
+    let builder = TwineBuilder::new(PqSigner::generate(SignatureAlgorithm::MlDsa87).unwrap());
+    let strand = builder.build_strand().done().unwrap();
+    let first = builder
+      .build_first(strand.clone())
+      .payload(ipld!({ "index": 0 }))
+      .done()
+      .unwrap();
+    let second = builder.build_next(&first).done().unwrap();
+
+    let blocks: Vec<AnyTwine> = vec![
+      slh_strand.clone().into(),
+      strand.clone().into(),
+      first.tixel().clone().into(),
+      second.tixel().clone().into(),
+    ];
+    for block in &blocks {
+      // DAG-CBOR and DAG-JSON
+      let decoded = AnyTwine::from_block(block.cid(), block.bytes()).unwrap();
+      assert_eq!(decoded.bytes(), block.bytes());
+      let decoded = AnyTwine::from_tagged_dag_json(block.tagged_dag_json()).unwrap();
+      assert_eq!(decoded.bytes(), block.bytes());
+    }
+
+    // CAR
+    let car = to_car_bytes(blocks.clone(), vec![strand.cid()]);
+    let decoded = from_car_bytes(&mut car.as_slice()).unwrap();
+    assert_eq!(decoded.len(), blocks.len());
+    for (a, b) in decoded.iter().zip(blocks.iter()) {
+      assert_eq!(a.bytes(), b.bytes());
+    }
+
+    // stores
+    let store = MemoryStore::new();
+    store.save_many(blocks).await.unwrap();
+    let latest = store.resolve_latest(&strand).await.unwrap().unpack();
+    assert_eq!(latest, second);
+    let resolved = store
+      .resolve_strand(slh_strand.cid())
+      .await
+      .unwrap()
+      .unpack();
+    assert_eq!(resolved, slh_strand);
+  }
+
   #[test]
   fn test_v2() {
     let rng = ring::rand::SystemRandom::new();
@@ -593,6 +884,633 @@ mod testv2 {
     );
   }
 
+  #[tokio::test]
+  async fn test_resolve_at_time() {
+    use twine_lib::{errors::ResolutionError, resolver::Resolver};
+    let rng = ring::rand::SystemRandom::new();
+    let pkcs8 = ring::signature::Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+    let key = ring::signature::Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+    let builder = TwineBuilder::new(key);
+    let store = MemoryStore::new();
+    let start: chrono::DateTime<chrono::Utc> = "2025-01-01T00:00:00Z".parse().unwrap();
+    let minutes = |m: i64| start + chrono::Duration::minutes(m);
+
+    let strand = builder.build_strand().radix(2).done().unwrap();
+    store.save_sync(strand.clone().into()).unwrap();
+    let mut prev = builder
+      .build_first(strand.clone())
+      .timestamp(start)
+      .done()
+      .unwrap();
+    store.save_sync(prev.clone().into()).unwrap();
+    for i in 1..20 {
+      prev = builder
+        .build_next(&prev)
+        .payload(ipld!({ "index": i }))
+        .timestamp(minutes(i * 10))
+        .done()
+        .unwrap();
+      store.save_sync(prev.clone().into()).unwrap();
+    }
+
+    assert_eq!(prev.timestamp().unwrap(), minutes(190));
+    let at = store.resolve_at_time(&strand, minutes(50)).await.unwrap();
+    assert_eq!(at.index(), 5);
+    let at = store.resolve_at_time(&strand, minutes(59)).await.unwrap();
+    assert_eq!(at.index(), 5);
+    let at = store.resolve_at_time(&strand, minutes(0)).await.unwrap();
+    assert_eq!(at.index(), 0);
+    let at = store.resolve_at_time(&strand, minutes(1000)).await.unwrap();
+    assert_eq!(at.index(), 19);
+    let res = store.resolve_at_time(&strand, minutes(-1)).await;
+    assert!(matches!(res, Err(ResolutionError::NotFound)));
+  }
+
+  #[tokio::test]
+  async fn test_resolve_at_time_not_monotonic() {
+    use twine_lib::{errors::ResolutionError, resolver::Resolver};
+    let rng = ring::rand::SystemRandom::new();
+    let pkcs8 = ring::signature::Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+    let key = ring::signature::Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+    let builder = TwineBuilder::new(key);
+    let store = MemoryStore::new();
+    let start: chrono::DateTime<chrono::Utc> = "2025-01-01T00:00:00Z".parse().unwrap();
+
+    let strand = builder.build_strand().done().unwrap();
+    store.save_sync(strand.clone().into()).unwrap();
+    let mut prev = builder
+      .build_first(strand.clone())
+      .timestamp(start)
+      .done()
+      .unwrap();
+    store.save_sync(prev.clone().into()).unwrap();
+    for i in 1..9 {
+      // the middle tixel claims to be older than the first
+      let offset = if i == 4 { -60 } else { i * 10 };
+      prev = builder
+        .build_next(&prev)
+        .timestamp(start + chrono::Duration::minutes(offset))
+        .done()
+        .unwrap();
+      store.save_sync(prev.clone().into()).unwrap();
+    }
+
+    let res = store
+      .resolve_at_time(&strand, start + chrono::Duration::minutes(45))
+      .await;
+    assert!(matches!(res, Err(ResolutionError::BadData(_))));
+  }
+
+  #[tokio::test]
+  async fn test_key_succession() {
+    use twine_lib::{errors::ResolutionError, resolver::Resolver};
+    let builder: TwineBuilder<2, _> = TwineBuilder::new(RingSigner::generate_ed25519().unwrap());
+    let next_builder: TwineBuilder<2, _> =
+      TwineBuilder::new(RingSigner::generate_ed25519().unwrap());
+
+    let strand = builder.build_strand().done().unwrap();
+    let successor = next_builder.build_strand().done().unwrap();
+    let first = builder.build_first(strand.clone()).done().unwrap();
+    let handoff = builder
+      .build_handoff(&first, &successor)
+      .payload(ipld!({ "reason": "rotation" }))
+      .done()
+      .unwrap();
+    assert_eq!(handoff.successor().unwrap(), Some(successor.cid()));
+    assert_eq!(first.successor().unwrap(), None);
+
+    let new_store = || {
+      let store = MemoryStore::new();
+      store.save_sync(strand.clone().into()).unwrap();
+      store.save_sync(successor.clone().into()).unwrap();
+      store.save_sync(first.clone().into()).unwrap();
+      store
+    };
+
+    let store = new_store();
+    assert!(store.resolve_successor(&strand).await.unwrap().is_none());
+    store.save_sync(handoff.clone().into()).unwrap();
+
+    // a successor that does not stitch back is rejected
+    let unstitched = next_builder.build_first(successor.clone()).done().unwrap();
+    store.save_sync(unstitched.into()).unwrap();
+    let res = store.resolve_successor(&strand).await;
+    assert!(matches!(res, Err(ResolutionError::Invalid(_))));
+
+    let store = new_store();
+    let next_first = next_builder
+      .build_first_successor(successor.clone(), &handoff)
+      .done()
+      .unwrap();
+    let latest = next_builder.build_next(&next_first).done().unwrap();
+    for twine in [&handoff, &next_first, &latest] {
+      store.save_sync(twine.clone().into()).unwrap();
+    }
+    let found = store.resolve_successor(&strand).await.unwrap().unwrap();
+    assert_eq!(found.cid(), next_first.cid());
+    // a tixel appended after the handoff does not hide it
+    let appended = builder.build_next(&handoff).done().unwrap();
+    store.save_sync(appended.into()).unwrap();
+    let found = store.resolve_successor(&strand).await.unwrap().unwrap();
+    assert_eq!(found.cid(), next_first.cid());
+    let chain = store.resolve_succession(&strand).await.unwrap();
+    assert_eq!(chain, vec![strand.clone(), successor.clone()]);
+    let current = store.resolve_latest_in_succession(&strand).await.unwrap();
+    assert_eq!(current.cid(), latest.cid());
+    // the successor itself has no successor
+    let current = store
+      .resolve_latest_in_succession(&successor)
+      .await
+      .unwrap();
+    assert_eq!(current.cid(), latest.cid());
+  }
+
+  #[tokio::test]
+  async fn test_key_succession_bounded_reads() {
+    use futures::StreamExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use twine_lib::{
+      errors::ResolutionError,
+      resolver::{unchecked_base::*, AbsoluteRange, Resolver, SUCCESSION_LOOKBACK},
+      twine::Tixel,
+      Cid,
+    };
+
+    // counts every tixel read from the wrapped store
+    struct CountingStore {
+      store: MemoryStore,
+      reads: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl BaseResolver for CountingStore {
+      async fn has_index(&self, strand: &Cid, index: u64) -> Result<bool, ResolutionError> {
+        self.store.has_index(strand, index).await
+      }
+      async fn has_twine(&self, strand: &Cid, cid: &Cid) -> Result<bool, ResolutionError> {
+        self.store.has_twine(strand, cid).await
+      }
+      async fn has_strand(&self, cid: &Cid) -> Result<bool, ResolutionError> {
+        self.store.has_strand(cid).await
+      }
+      async fn fetch_latest(&self, strand: &Cid) -> Result<Tixel, ResolutionError> {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+        self.store.fetch_latest(strand).await
+      }
+      async fn fetch_index(&self, strand: &Cid, index: u64) -> Result<Tixel, ResolutionError> {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+        self.store.fetch_index(strand, index).await
+      }
+      async fn fetch_tixel(&self, strand: &Cid, tixel: &Cid) -> Result<Tixel, ResolutionError> {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+        self.store.fetch_tixel(strand, tixel).await
+      }
+      async fn fetch_strand(&self, strand: &Cid) -> Result<Strand, ResolutionError> {
+        self.store.fetch_strand(strand).await
+      }
+      async fn range_stream<'a>(
+        &'a self,
+        range: AbsoluteRange,
+      ) -> Result<TwineStream<'a, Tixel>, ResolutionError> {
+        let stream = self.store.range_stream(range).await?;
+        Ok(Box::pin(stream.inspect(|_| {
+          self.reads.fetch_add(1, Ordering::SeqCst);
+        })))
+      }
+      async fn fetch_strands<'a>(&'a self) -> Result<TwineStream<'a, Strand>, ResolutionError> {
+        self.store.fetch_strands().await
+      }
+    }
+    impl Resolver for CountingStore {}
+
+    let builder: TwineBuilder<2, _> = TwineBuilder::new(RingSigner::generate_ed25519().unwrap());
+    let strand = builder.build_strand().done().unwrap();
+    let store = CountingStore {
+      store: MemoryStore::new(),
+      reads: AtomicUsize::new(0),
+    };
+    store.store.save_sync(strand.clone().into()).unwrap();
+    let mut prev = builder.build_first(strand.clone()).done().unwrap();
+    store.store.save_sync(prev.clone().into()).unwrap();
+    let len = 10 * SUCCESSION_LOOKBACK as usize;
+    for _ in 1..len {
+      prev = builder.build_next(&prev).done().unwrap();
+      store.store.save_sync(prev.clone().into()).unwrap();
+    }
+
+    assert!(store.resolve_successor(&strand).await.unwrap().is_none());
+    let reads = store.reads.load(Ordering::SeqCst);
+    assert!(reads <= SUCCESSION_LOOKBACK as usize + 2, "{} reads", reads);
+    store.reads.store(0, Ordering::SeqCst);
+    let current = store.resolve_latest_in_succession(&strand).await.unwrap();
+    assert_eq!(current.cid(), prev.cid());
+    assert!(store.reads.load(Ordering::SeqCst) < len);
+  }
+
+  #[tokio::test]
+  async fn test_cosigned_tixels() {
+    use twine_lib::{
+      cosign::CosignPolicy,
+      errors::ResolutionError,
+      ipld_core::codec::Codec,
+      resolver::{CosignedResolver, Resolver, SingleQuery},
+      schemas::v2,
+      serde_ipld_dagcbor::codec::DagCborCodec,
+      twine::Tixel,
+      verify::Verified,
+    };
+    let operator: TwineBuilder<2, _> = TwineBuilder::new(RingSigner::generate_ed25519().unwrap());
+    let signers: Vec<_> = (0..3)
+      .map(|_| RingSigner::generate_ed25519().unwrap())
+      .collect();
+    let keys = signers.iter().map(Signer::public_key).collect();
+    let cosigners: Vec<TwineBuilder<2, _>> = signers.into_iter().map(TwineBuilder::new).collect();
+    let outsider: TwineBuilder<2, _> = TwineBuilder::new(RingSigner::generate_ed25519().unwrap());
+    let policy = CosignPolicy::new(2, keys).unwrap();
+    let strand = operator.build_strand().cosigners(&policy).done().unwrap();
+    assert_eq!(
+      CosignPolicy::from_strand(&strand)
+        .unwrap()
+        .unwrap()
+        .threshold,
+      2
+    );
+
+    let request = operator
+      .build_first(strand.clone())
+      .payload(ipld!({ "value": 1 }))
+      .cosign_request()
+      .unwrap();
+    let sigs: Vec<_> = cosigners
+      .iter()
+      .map(|c| c.cosign(&strand, &request).unwrap())
+      .collect();
+    assert!(outsider.cosign(&strand, &request).is_err());
+
+    // not enough co-signatures
+    let res = operator
+      .build_first(strand.clone())
+      .payload(ipld!({ "value": 1 }))
+      .cosignature(sigs[0].clone())
+      .done();
+    assert!(res.is_err());
+    // duplicates do not count twice
+    let res = operator
+      .build_first(strand.clone())
+      .payload(ipld!({ "value": 1 }))
+      .cosignatures([sigs[1].clone(), sigs[1].clone()])
+      .done();
+    assert!(res.is_err());
+    // a null payload can be co-signed
+    let request = operator
+      .build_first(strand.clone())
+      .cosign_request()
+      .unwrap();
+    operator
+      .build_first(strand.clone())
+      .cosignatures(
+        cosigners
+          .iter()
+          .map(|c| c.cosign(&strand, &request).unwrap()),
+      )
+      .done()
+      .unwrap();
+    // co-signatures only cover the requested payload
+    let res = operator
+      .build_first(strand.clone())
+      .payload(ipld!({ "value": 2 }))
+      .cosignatures([sigs[0].clone(), sigs[2].clone()])
+      .done();
+    assert!(res.is_err());
+    // ...and the rest of the requested content, like the cross-stitches
+    let other = operator.build_strand().done().unwrap();
+    let stitched = operator.build_first(other).done().unwrap();
+    let res = operator
+      .build_first(strand.clone())
+      .payload(ipld!({ "value": 1 }))
+      .cross_stitches(vec![Stitch::from(stitched)])
+      .cosignatures([sigs[0].clone(), sigs[2].clone()])
+      .done();
+    assert!(res.is_err());
+
+    let first = operator
+      .build_first(strand.clone())
+      .payload(ipld!({ "value": 1 }))
+      .cosignatures([sigs[0].clone(), sigs[2].clone()])
+      .done()
+      .unwrap();
+    let builder = operator.build_next(&first).timestamp_now();
+    let request = builder.cosign_request().unwrap();
+    let next = builder
+      .cosignatures(
+        cosigners
+          .iter()
+          .map(|c| c.cosign(&strand, &request).unwrap()),
+      )
+      .done()
+      .unwrap();
+
+    let store = MemoryStore::new();
+    store.save_sync(strand.clone().into()).unwrap();
+    store.save_sync(first.clone().into()).unwrap();
+    store.save_sync(next.clone().into()).unwrap();
+    let latest = store.resolve_latest(&strand).await.unwrap();
+    assert_eq!(latest.cid(), next.cid());
+    assert!(latest.timestamp().is_ok());
+
+    // the policy is only enforced by a cosigned resolver
+    let resolver = CosignedResolver::new(store.clone());
+    let latest = resolver.resolve_latest(&strand).await.unwrap();
+    assert_eq!(latest.cid(), next.cid());
+    // the builder refuses to skip the co-signers...
+    assert!(operator.build_next(&next).done().is_err());
+    // ...but the operator's key can still sign such a tixel
+    let builder = operator.build_next(&next);
+    let request = builder.cosign_request().unwrap();
+    let prepared = builder
+      .cosignatures(
+        cosigners
+          .iter()
+          .map(|c| c.cosign(&strand, &request).unwrap()),
+      )
+      .prepare()
+      .unwrap();
+    let mut content: v2::TixelContentV2 =
+      DagCborCodec::decode_from_slice(prepared.message()).unwrap();
+    let mut fields = content.fields.into_inner();
+    fields.payload = request.payload().clone();
+    content.fields = Verified::try_new(fields).unwrap();
+    let bytes = DagCborCodec::encode_to_vec(&content).unwrap();
+    let signature = operator.signer.sign(&bytes).unwrap();
+    let container = v2::ContainerV2::new_from_parts(Verified::try_new(content).unwrap(), signature);
+    let unsigned = Twine::try_new(strand.clone(), Tixel::try_new(container).unwrap()).unwrap();
+    store.save_sync(unsigned.clone().into()).unwrap();
+    let latest = store.resolve_latest(&strand).await.unwrap();
+    assert_eq!(latest.cid(), unsigned.cid());
+    let res = resolver.resolve_latest(&strand).await;
+    assert!(matches!(res, Err(ResolutionError::Invalid(_))));
+    let query = SingleQuery::Index(strand.cid(), unsigned.index() as i64);
+    assert!(store.has(query).await.unwrap());
+    assert!(!resolver.has(query).await.unwrap());
+    assert!(resolver
+      .has(SingleQuery::Index(strand.cid(), 1))
+      .await
+      .unwrap());
+
+    // strands that happen to use the details key are not affected otherwise
+    let other = operator
+      .build_strand()
+      .details(ipld!({ "cosigners": "alice and bob" }))
+      .done()
+      .unwrap();
+    operator.build_first(other).done().unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_witness_attestations() {
+    use twine_lib::witness::{find_attestations, WitnessPayload, WITNESS_SUBSPEC};
+    let builder: TwineBuilder<2, _> = TwineBuilder::new(RingSigner::generate_ed25519().unwrap());
+    let store = MemoryStore::new();
+    let strand = builder.build_strand().done().unwrap();
+    let first = builder.build_first(strand.clone()).done().unwrap();
+    let second = builder.build_next(&first).done().unwrap();
+    for twine in [
+      strand.clone().into(),
+      first.clone().into(),
+      second.clone().into(),
+    ] {
+      store.save_sync(twine).unwrap();
+    }
+
+    for n in 0..3 {
+      let witness: TwineBuilder<2, _> = TwineBuilder::new(RingSigner::generate_ed25519().unwrap());
+      let strand = witness
+        .build_strand()
+        .subspec(WITNESS_SUBSPEC.into())
+        .done()
+        .unwrap();
+      store.save_sync(strand.clone().into()).unwrap();
+      let mut prev = witness
+        .build_first(strand)
+        .payload(WitnessPayload::new([first.clone().into()]))
+        .timestamp_now()
+        .done()
+        .unwrap();
+      store.save_sync(prev.clone().into()).unwrap();
+      // only some witnesses saw the second tixel, some twice
+      for _ in 0..n {
+        prev = witness
+          .build_next(&prev)
+          .payload(WitnessPayload::new([second.clone().into()]))
+          .timestamp_now()
+          .done()
+          .unwrap();
+        store.save_sync(prev.clone().into()).unwrap();
+      }
+    }
+
+    let attestations = find_attestations(&store, first.clone()).await.unwrap();
+    assert_eq!(attestations.len(), 3);
+    assert!(attestations.iter().all(|a| a.time().is_some()));
+    let attestations = find_attestations(&store, second.clone()).await.unwrap();
+    assert_eq!(attestations.len(), 2);
+    assert!(attestations.iter().all(|a| a.witness.index() == 1));
+    let other = builder.build_next(&second).done().unwrap();
+    assert!(find_attestations(&store, other).await.unwrap().is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_trusted_resolver() {
+    use futures::TryStreamExt;
+    use twine_lib::{
+      errors::ResolutionError,
+      resolver::{Resolver, TrustedResolver},
+      trust::{Fingerprint, Revocation, TrustStore},
+    };
+    let signer = RingSigner::generate_ed25519().unwrap();
+    let fingerprint = Fingerprint::of(&signer.public_key()).unwrap();
+    let builder: TwineBuilder<2, _> = TwineBuilder::new(signer);
+    let other: TwineBuilder<2, _> = TwineBuilder::new(RingSigner::generate_ed25519().unwrap());
+    let start: chrono::DateTime<chrono::Utc> = "2025-01-01T00:00:00Z".parse().unwrap();
+    let store = MemoryStore::new();
+
+    let strand = builder.build_strand().done().unwrap();
+    store.save_sync(strand.clone().into()).unwrap();
+    let mut prev = builder
+      .build_first(strand.clone())
+      .timestamp(start)
+      .done()
+      .unwrap();
+    store.save_sync(prev.clone().into()).unwrap();
+    for i in 1..5 {
+      prev = builder
+        .build_next(&prev)
+        .timestamp(start + chrono::Duration::days(i))
+        .done()
+        .unwrap();
+      store.save_sync(prev.clone().into()).unwrap();
+    }
+    let other_strand = other.build_strand().done().unwrap();
+    store.save_sync(other_strand.clone().into()).unwrap();
+    let other_first = other.build_first(other_strand.clone()).done().unwrap();
+    store.save_sync(other_first.into()).unwrap();
+    // a second strand signed by the same key
+    let sibling = builder
+      .build_strand()
+      .details(ipld!({ "name": "sibling" }))
+      .done()
+      .unwrap();
+    store.save_sync(sibling.clone().into()).unwrap();
+    let mut sibling_prev = builder.build_first(sibling.clone()).done().unwrap();
+    store.save_sync(sibling_prev.clone().into()).unwrap();
+    for _ in 1..5 {
+      sibling_prev = builder.build_next(&sibling_prev).done().unwrap();
+      store.save_sync(sibling_prev.clone().into()).unwrap();
+    }
+
+    let untrusted = |res| matches!(res, Err(ResolutionError::Untrusted(_)));
+
+    // revoked after an index
+    let trust =
+      TrustStore::default().revoke(Revocation::new(fingerprint).after_index(strand.cid(), 2));
+    let resolver = TrustedResolver::new(store.clone(), trust);
+    assert!(resolver.resolve_index(&strand, 2).await.is_ok());
+    assert!(untrusted(
+      resolver.resolve_index(&strand, 3).await.map(|_| ())
+    ));
+    assert!(untrusted(
+      resolver.resolve_latest(&strand).await.map(|_| ())
+    ));
+    assert!(resolver.resolve_latest(&other_strand).await.is_ok());
+    // ranges need the latest tixel, which is itself untrusted
+    assert!(untrusted(
+      resolver
+        .resolve_range((strand.cid(), 0, 4))
+        .await
+        .map(|_| ())
+    ));
+    let range: Vec<_> = resolver
+      .resolve_range((other_strand.cid(), 0, 0))
+      .await
+      .unwrap()
+      .try_collect()
+      .await
+      .unwrap();
+    assert_eq!(range.len(), 1);
+
+    assert!(resolver.has((strand.cid(), 2)).await.unwrap());
+    assert!(!resolver.has((strand.cid(), 3)).await.unwrap());
+    // the cut-off does not carry over to other strands of the key
+    assert!(untrusted(
+      resolver.resolve_index(&sibling, 0).await.map(|_| ())
+    ));
+    let trust = TrustStore::default().revoke(
+      Revocation::new(fingerprint)
+        .after_index(strand.cid(), 2)
+        .after_index(sibling.cid(), 3),
+    );
+    let resolver = TrustedResolver::new(store.clone(), trust);
+    assert!(resolver.resolve_index(&sibling, 3).await.is_ok());
+    assert!(untrusted(
+      resolver.resolve_index(&sibling, 4).await.map(|_| ())
+    ));
+    assert!(untrusted(
+      resolver.resolve_index(&strand, 3).await.map(|_| ())
+    ));
+
+    // revoked after a time, recorded as an index
+    let after = start + chrono::Duration::hours(36);
+    let revocation = Revocation::new(fingerprint)
+      .after_time(&store, strand.cid(), after)
+      .await
+      .unwrap();
+    assert_eq!(revocation.cutoff(&strand.cid()), Some(1));
+    assert_eq!(revocation.cutoff(&sibling.cid()), None);
+    // a tixel backdated by whoever holds the revoked key is still untrusted
+    let backdated = builder.build_next(&prev).timestamp(start).done().unwrap();
+    store.save_sync(backdated.clone().into()).unwrap();
+    let trust = TrustStore::default().revoke(revocation);
+    let resolver = TrustedResolver::new(store.clone(), trust);
+    assert!(resolver.resolve_index(&strand, 1).await.is_ok());
+    assert!(untrusted(
+      resolver.resolve_index(&strand, 2).await.map(|_| ())
+    ));
+    assert!(untrusted(
+      resolver
+        .resolve_stitch(strand.cid(), backdated.cid())
+        .await
+        .map(|_| ())
+    ));
+    assert!(!resolver.has((strand.cid(), backdated.cid())).await.unwrap());
+
+    // revoked entirely
+    let trust = TrustStore::default().revoke(Revocation::new(fingerprint));
+    let resolver = TrustedResolver::new(store.clone(), trust);
+    assert!(untrusted(
+      resolver.resolve_strand(&strand).await.map(|_| ())
+    ));
+    assert!(untrusted(
+      resolver.resolve_index(&strand, 0).await.map(|_| ())
+    ));
+    let strands: Vec<_> = resolver
+      .strands()
+      .await
+      .unwrap()
+      .try_collect()
+      .await
+      .unwrap();
+    assert_eq!(strands, vec![other_strand.clone()]);
+    assert!(!resolver.has((strand.cid(), 0)).await.unwrap());
+
+    // pinned
+    let trust = TrustStore::default().pin(other_strand.cid());
+    let resolver = TrustedResolver::new(store.clone(), trust);
+    assert!(resolver.resolve_latest(&other_strand).await.is_ok());
+    assert!(untrusted(
+      resolver.resolve_latest(&strand).await.map(|_| ())
+    ));
+  }
+
+  #[tokio::test]
+  async fn test_notary_receipts() {
+    use twine_lib::multihash_codetable::{Code, MultihashDigest};
+    use twine_lib::notary::{verify_receipt, Notary};
+    let rng = ring::rand::SystemRandom::new();
+    let pkcs8 = ring::signature::Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+    let key = ring::signature::Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+    let builder = TwineBuilder::new(key);
+    let store = MemoryStore::new();
+    let strand = builder.build_strand().done().unwrap();
+    store.save_sync(strand.clone().into()).unwrap();
+
+    let mut notary = Notary::new(Code::Sha3_256);
+    for i in 0..100u32 {
+      notary.submit(Code::Sha3_256.digest(&i.to_be_bytes()));
+    }
+    let batch = notary.seal().unwrap();
+    let tixel = builder
+      .build_first(strand.clone())
+      .payload(batch.payload())
+      .done()
+      .unwrap();
+    store.save_sync(tixel.clone().into()).unwrap();
+
+    let receipts = batch.receipts(&tixel).unwrap();
+    assert_eq!(receipts.len(), 100);
+    for receipt in &receipts {
+      let twine = verify_receipt(receipt, &store).await.unwrap();
+      assert_eq!(twine, tixel);
+    }
+
+    let mut forged = receipts[7].clone();
+    forged.document = Code::Sha3_256.digest(b"forged").to_bytes().into();
+    assert!(verify_receipt(&forged, &store).await.is_err());
+    // the path only proves the position it was made for
+    let mut moved = receipts[7].clone();
+    moved.position = 6;
+    assert!(verify_receipt(&moved, &store).await.is_err());
+  }
+
   #[test]
   fn test_deny_stitches_to_self() {
     let rng = ring::rand::SystemRandom::new();
@@ -0,0 +1,194 @@
+//! Unsigned Twine data prepared for signing elsewhere
+//!
+//! These are produced by the `prepare()` methods of the v2 builders,
+//! and are intended for workflows where the private key is not available
+//! to the process doing the building (for example, an air-gapped machine).
+//! They can be written to a file as DAG-JSON, the [`message`](PreparedTixel::message)
+//! signed by the key holder, and then finalized using the detached signature.
+use super::BuildError;
+use twine_lib::{
+  crypto::{PublicKey, Signature},
+  errors::VerificationError,
+  ipld_core::codec::Codec,
+  schemas::v2,
+  serde_ipld_dagcbor::codec::DagCborCodec,
+  serde_ipld_dagjson::codec::DagJsonCodec,
+  twine::{Strand, Tixel, Twine, TwineBlock},
+  verify::Verified,
+  Bytes, Cid,
+};
+
+fn decode_content<C>(bytes: &[u8]) -> Result<C, BuildError>
+where
+  C: serde::Serialize + for<'de> serde::Deserialize<'de>,
+{
+  Ok(DagCborCodec::decode_from_slice(bytes).map_err(VerificationError::from)?)
+}
+
+/// An unsigned Strand
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PreparedStrand {
+  /// The DAG-CBOR encoded strand content
+  content: Bytes,
+}
+
+impl PreparedStrand {
+  pub(crate) fn new(content: &v2::StrandContentV2) -> Self {
+    Self {
+      content: DagCborCodec::encode_to_vec(content).unwrap().into(),
+    }
+  }
+
+  /// The bytes that must be signed
+  pub fn message(&self) -> &[u8] {
+    &self.content
+  }
+
+  /// The public key that must produce the signature
+  pub fn key(&self) -> Result<PublicKey, BuildError> {
+    let content: v2::StrandContentV2 = decode_content(&self.content)?;
+    Ok(content.key.clone())
+  }
+
+  /// Serialize as a DAG-JSON string, suitable for saving to a file
+  pub fn to_dag_json(&self) -> String {
+    String::from_utf8(DagJsonCodec::encode_to_vec(self).unwrap()).unwrap()
+  }
+
+  /// Deserialize from a DAG-JSON string
+  pub fn from_dag_json<S: AsRef<str>>(json: S) -> Result<Self, BuildError> {
+    Ok(DagJsonCodec::decode_from_slice(json.as_ref().as_bytes()).map_err(VerificationError::from)?)
+  }
+
+  /// Assemble the strand using a detached signature
+  ///
+  /// The signature is verified against the strand's key.
+  pub fn finalize(self, signature: Signature) -> Result<Strand, BuildError> {
+    let content: v2::StrandContentV2 = decode_content(&self.content)?;
+    let container = v2::ContainerV2::new_from_parts(Verified::try_new(content)?, signature);
+    Ok(Strand::try_new(container)?)
+  }
+}
+
+/// An unsigned Tixel, along with the Strand it belongs to
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PreparedTixel {
+  /// The CID of the strand
+  strand: Cid,
+  /// The DAG-CBOR encoded strand block
+  strand_block: Bytes,
+  /// The DAG-CBOR encoded tixel content
+  content: Bytes,
+}
+
+impl PreparedTixel {
+  pub(crate) fn new(strand: &Strand, content: &v2::TixelContentV2) -> Self {
+    Self {
+      strand: strand.cid(),
+      strand_block: strand.bytes().to_vec().into(),
+      content: DagCborCodec::encode_to_vec(content).unwrap().into(),
+    }
+  }
+
+  /// The bytes that must be signed
+  pub fn message(&self) -> &[u8] {
+    &self.content
+  }
+
+  /// The strand this tixel will belong to
+  pub fn strand(&self) -> Result<Strand, BuildError> {
+    Ok(Strand::from_block(self.strand, &self.strand_block)?)
+  }
+
+  /// The public key that must produce the signature
+  pub fn key(&self) -> Result<PublicKey, BuildError> {
+    Ok(self.strand()?.key())
+  }
+
+  /// Serialize as a DAG-JSON string, suitable for saving to a file
+  pub fn to_dag_json(&self) -> String {
+    String::from_utf8(DagJsonCodec::encode_to_vec(self).unwrap()).unwrap()
+  }
+
+  /// Deserialize from a DAG-JSON string
+  pub fn from_dag_json<S: AsRef<str>>(json: S) -> Result<Self, BuildError> {
+    Ok(DagJsonCodec::decode_from_slice(json.as_ref().as_bytes()).map_err(VerificationError::from)?)
+  }
+
+  /// Assemble the tixel using a detached signature
+  ///
+  /// The signature is verified against the strand's key.
+  pub fn finalize(self, signature: Signature) -> Result<Twine, BuildError> {
+    let strand = self.strand()?;
+    let content: v2::TixelContentV2 = decode_content(&self.content)?;
+    let container = v2::ContainerV2::new_from_parts(Verified::try_new(content)?, signature);
+    let tixel = Tixel::try_new(container)?;
+    Ok(Twine::try_new(strand, tixel)?)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{PublicKeySigner, RingSigner, Signer, TwineBuilder};
+
+  use super::*;
+
+  #[test]
+  fn test_prepare_and_finalize() {
+    let offline = RingSigner::generate_p256().unwrap();
+    let builder = TwineBuilder::new(PublicKeySigner::new(offline.public_key()));
+    assert!(builder.build_strand().done().is_err());
+
+    let file = builder.build_strand().prepare().unwrap().to_dag_json();
+    let prepared = PreparedStrand::from_dag_json(&file).unwrap();
+    assert!(PreparedTixel::from_dag_json(&file).is_err());
+    let signature = offline.sign(prepared.message()).unwrap();
+    let strand = prepared.finalize(signature).unwrap();
+    assert_eq!(strand.key().key, offline.public_key().key);
+
+    let file = builder
+      .build_first(strand.clone())
+      .payload("offline")
+      .prepare()
+      .unwrap()
+      .to_dag_json();
+    let prepared = PreparedTixel::from_dag_json(&file).unwrap();
+    assert!(PreparedStrand::from_dag_json(&file).is_err());
+    assert_eq!(prepared.strand().unwrap(), strand);
+    let signature = offline.sign(prepared.message()).unwrap();
+    let first = prepared.finalize(signature).unwrap();
+    assert_eq!(first.extract_payload::<String>().unwrap(), "offline");
+
+    let prepared = builder.build_next(&first).prepare().unwrap();
+    let next = prepared
+      .clone()
+      .finalize(offline.sign(prepared.message()).unwrap())
+      .unwrap();
+    assert_eq!(next.index(), 1);
+  }
+
+  #[test]
+  fn test_finalize_wrong_key() {
+    let offline = RingSigner::generate_ed25519().unwrap();
+    let other = RingSigner::generate_ed25519().unwrap();
+    let builder = TwineBuilder::new(PublicKeySigner::new(offline.public_key()));
+
+    let prepared = builder.build_strand().prepare().unwrap();
+    let signature = other.sign(prepared.message()).unwrap();
+    assert!(prepared.clone().finalize(signature).is_err());
+    let strand = prepared
+      .clone()
+      .finalize(offline.sign(prepared.message()).unwrap())
+      .unwrap();
+
+    let prepared = builder.build_first(strand).prepare().unwrap();
+    let signature = other.sign(prepared.message()).unwrap();
+    assert!(prepared.finalize(signature).is_err());
+  }
+}
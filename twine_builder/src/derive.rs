@@ -0,0 +1,396 @@
+//! Hierarchical deterministic key derivation
+//!
+//! Implements [SLIP-0010](https://github.com/satoshilabs/slips/blob/master/slip-0010.md)
+//! for Ed25519 and P-256, so that many strand keys can be derived from a
+//! single master seed. Only the seed needs to be backed up; any strand key
+//! can be recreated from it and the derivation path.
+use crate::{RingSigner, RingSignerError};
+use p256::elliptic_curve::{sec1::ToEncodedPoint, Field, PrimeField};
+use pkcs8::{der::asn1::OctetStringRef, der::Encode, EncodePrivateKey, SecretDocument};
+use std::{fmt::Display, str::FromStr};
+use thiserror::Error;
+use twine_lib::{crypto::SignatureAlgorithm, Ipld};
+
+/// Indices at or above this value are hardened
+pub const HARDENED: u32 = 0x8000_0000;
+
+const ED25519_CURVE: &[u8] = b"ed25519 seed";
+const P256_CURVE: &[u8] = b"Nist256p1 seed";
+const SEED_LABEL: &str = "ENCRYPTED TWINE SEED";
+
+/// Error returned when a derivation path can not be parsed
+#[derive(Debug, Error)]
+#[error("Invalid derivation path: {0}")]
+pub struct InvalidDerivationPath(String);
+
+/// A SLIP-0010 derivation path, such as `m/44'/0'/7'`
+///
+/// Hardened indices are written with a trailing `'` or `h`.
+///
+/// # Example
+///
+/// ```rust
+/// use twine_builder::{DerivationPath, RingSigner, Signer};
+/// use twine_lib::crypto::SignatureAlgorithm;
+/// let path: DerivationPath = "m/0'/7'".parse().unwrap();
+/// let seed = [42u8; 32];
+/// let signer = RingSigner::derive(SignatureAlgorithm::Ed25519, &seed, &path).unwrap();
+/// let again = RingSigner::derive(SignatureAlgorithm::Ed25519, &seed, &path).unwrap();
+/// assert_eq!(signer.public_key().key, again.public_key().key);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+  /// The key used to record the path in a strand's details
+  pub const DETAILS_KEY: &'static str = "derivation_path";
+
+  /// Create a path from raw indices
+  pub fn new(indices: Vec<u32>) -> Self {
+    Self(indices)
+  }
+
+  /// The raw indices of the path. Hardened indices include [`HARDENED`].
+  pub fn indices(&self) -> &[u32] {
+    &self.0
+  }
+
+  /// A new path with a hardened child index appended
+  pub fn hardened_child(&self, index: u32) -> Self {
+    let mut indices = self.0.clone();
+    indices.push(index | HARDENED);
+    Self(indices)
+  }
+
+  /// Read the derivation path recorded in a strand's details, if any
+  ///
+  /// See [`crate::builder::StrandBuilder::derivation_path`]
+  pub fn from_details(details: &Ipld) -> Option<Self> {
+    match details {
+      Ipld::Map(map) => match map.get(Self::DETAILS_KEY) {
+        Some(Ipld::String(path)) => path.parse().ok(),
+        _ => None,
+      },
+      _ => None,
+    }
+  }
+}
+
+impl Display for DerivationPath {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "m")?;
+    for index in &self.0 {
+      if index & HARDENED != 0 {
+        write!(f, "/{}'", index & !HARDENED)?;
+      } else {
+        write!(f, "/{}", index)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+impl FromStr for DerivationPath {
+  type Err = InvalidDerivationPath;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut parts = s.trim().split('/');
+    if parts.next() != Some("m") {
+      return Err(InvalidDerivationPath(format!("{} must start with m", s)));
+    }
+    let indices = parts
+      .map(|part| {
+        let (digits, hardened) = match part.strip_suffix(['\'', 'h', 'H']) {
+          Some(digits) => (digits, true),
+          None => (part, false),
+        };
+        let index: u32 = digits
+          .parse()
+          .map_err(|_| InvalidDerivationPath(format!("bad index {}", part)))?;
+        if index >= HARDENED {
+          return Err(InvalidDerivationPath(format!("index {} too large", part)));
+        }
+        Ok(if hardened { index | HARDENED } else { index })
+      })
+      .collect::<Result<_, _>>()?;
+    Ok(Self(indices))
+  }
+}
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> ([u8; 32], [u8; 32]) {
+  let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA512, key);
+  let tag = ring::hmac::sign(&key, data);
+  let (left, right) = tag.as_ref().split_at(32);
+  (left.try_into().unwrap(), right.try_into().unwrap())
+}
+
+fn derivation_error<S: Into<String>>(msg: S) -> RingSignerError {
+  RingSignerError::Derivation(msg.into())
+}
+
+/// Derive an Ed25519 private key (seed). Ed25519 only allows hardened children.
+fn derive_ed25519(seed: &[u8], path: &DerivationPath) -> Result<[u8; 32], RingSignerError> {
+  let (mut key, mut chain) = hmac_sha512(ED25519_CURVE, seed);
+  for index in path.indices() {
+    if index & HARDENED == 0 {
+      return Err(derivation_error(
+        "Ed25519 only supports hardened derivation",
+      ));
+    }
+    let mut data = vec![0];
+    data.extend(key);
+    data.extend(index.to_be_bytes());
+    (key, chain) = hmac_sha512(&chain, &data);
+  }
+  Ok(key)
+}
+
+fn p256_scalar(bytes: [u8; 32]) -> Option<p256::Scalar> {
+  Option::from(p256::Scalar::from_repr(bytes.into()))
+}
+
+/// Derive a P-256 private key, retrying as SLIP-0010 specifies for invalid keys
+fn derive_p256(seed: &[u8], path: &DerivationPath) -> Result<p256::SecretKey, RingSignerError> {
+  let mut data = seed.to_vec();
+  let (mut key, mut chain) = loop {
+    let (left, right) = hmac_sha512(P256_CURVE, &data);
+    match p256_scalar(left) {
+      Some(k) if !bool::from(k.is_zero()) => break (k, right),
+      // retry with the whole of I as the new seed
+      _ => data = [left, right].concat(),
+    }
+  };
+  for index in path.indices() {
+    let mut data = if index & HARDENED != 0 {
+      let mut data = vec![0];
+      data.extend(key.to_bytes());
+      data
+    } else {
+      let public = p256::SecretKey::new(key.into()).public_key();
+      public.to_encoded_point(true).as_bytes().to_vec()
+    };
+    data.extend(index.to_be_bytes());
+    loop {
+      let (left, right) = hmac_sha512(&chain, &data);
+      if let Some(child) = p256_scalar(left).map(|k| k + key) {
+        if !bool::from(child.is_zero()) {
+          key = child;
+          chain = right;
+          break;
+        }
+      }
+      // retry with 0x01 || I_R || ser32(index), where I_R is the right half of this I
+      data = vec![1];
+      data.extend(right);
+      data.extend(index.to_be_bytes());
+    }
+  }
+  Ok(p256::SecretKey::new(key.into()))
+}
+
+/// Encrypt a master seed with a passphrase and return it as a PEM string
+///
+/// The seed is encrypted the same way as
+/// [`RingSigner::encrypted_private_key_pem`], using PBES2 with scrypt and
+/// AES-256-CBC. See [`decrypt_seed`] to read it again.
+///
+/// # Example
+///
+/// ```rust
+/// use twine_builder::{decrypt_seed, encrypt_seed};
+/// let seed = [42u8; 32];
+/// let pem = encrypt_seed(&seed, "correct horse").unwrap();
+/// assert_eq!(decrypt_seed(&pem, "correct horse").unwrap(), seed);
+/// assert!(decrypt_seed(&pem, "wrong").is_err());
+/// ```
+pub fn encrypt_seed<P: AsRef<[u8]>>(seed: &[u8], passphrase: P) -> Result<String, RingSignerError> {
+  use ring::rand::SecureRandom;
+  let rng = ring::rand::SystemRandom::new();
+  let mut salt = [0u8; 16];
+  let mut iv = [0u8; 16];
+  rng
+    .fill(&mut salt)
+    .and_then(|_| rng.fill(&mut iv))
+    .map_err(|_| RingSignerError::RandomFailure)?;
+  let params = pkcs8::pkcs5::pbes2::Parameters::scrypt_aes256cbc(Default::default(), &salt, &iv)
+    .map_err(pkcs8::Error::from)?;
+  let scheme = pkcs8::pkcs5::EncryptionScheme::from(params);
+  let encrypted = scheme
+    .encrypt(passphrase, seed)
+    .map_err(pkcs8::Error::from)?;
+  let info = pkcs8::EncryptedPrivateKeyInfo {
+    encryption_algorithm: scheme,
+    encrypted_data: &encrypted,
+  };
+  let der = info.to_der()?;
+  let pem = pkcs8::der::pem::encode_string(SEED_LABEL, pkcs8::LineEnding::LF, &der)
+    .map_err(pkcs8::der::Error::from)?;
+  Ok(pem)
+}
+
+/// Decrypt a master seed produced by [`encrypt_seed`]
+pub fn decrypt_seed<S: AsRef<str>, P: AsRef<[u8]>>(
+  pem: S,
+  passphrase: P,
+) -> Result<Vec<u8>, RingSignerError> {
+  use pkcs8::der::Decode;
+  let (label, doc) = pkcs8::Document::from_pem(pem.as_ref())?;
+  if label != SEED_LABEL {
+    return Err(derivation_error(format!(
+      "expected an {}, found {}",
+      SEED_LABEL, label
+    )));
+  }
+  let info = pkcs8::EncryptedPrivateKeyInfo::from_der(doc.as_bytes())?;
+  Ok(
+    info
+      .encryption_algorithm
+      .decrypt(passphrase, info.encrypted_data)
+      .map_err(pkcs8::Error::from)?,
+  )
+}
+
+impl RingSigner {
+  /// Derive a signer from a master seed and a derivation path
+  ///
+  /// Supports [`SignatureAlgorithm::Ed25519`] (hardened paths only) and
+  /// [`SignatureAlgorithm::EcdsaP256`]. The seed must be 16 to 64 bytes.
+  /// The same seed and path always produce the same key.
+  pub fn derive(
+    alg: SignatureAlgorithm,
+    seed: &[u8],
+    path: &DerivationPath,
+  ) -> Result<Self, RingSignerError> {
+    if !(16..=64).contains(&seed.len()) {
+      return Err(derivation_error("seed must be between 16 and 64 bytes"));
+    }
+    let pkcs8 = match alg {
+      SignatureAlgorithm::Ed25519 => {
+        let secret = derive_ed25519(seed, path)?;
+        // ring only accepts PKCS#8 v2, which includes the public key
+        let keypair = ring::signature::Ed25519KeyPair::from_seed_unchecked(&secret)?;
+        let public = ring::signature::KeyPair::public_key(&keypair);
+        let private = OctetStringRef::new(&secret)?.to_der()?;
+        let info = pkcs8::PrivateKeyInfo {
+          algorithm: pkcs8::AlgorithmIdentifierRef {
+            oid: const_oid::db::rfc8410::ID_ED_25519,
+            parameters: None,
+          },
+          private_key: &private,
+          public_key: Some(public.as_ref()),
+        };
+        SecretDocument::try_from(info)?
+      }
+      SignatureAlgorithm::EcdsaP256 => derive_p256(seed, path)?.to_pkcs8_der()?,
+      _ => return Err(RingSignerError::UnsupportedAlgorithm),
+    };
+    Self::new(alg, pkcs8)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::Signer;
+
+  fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+  }
+
+  // SLIP-0010 test vector 1
+  const SEED: [u8; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+
+  #[test]
+  fn test_path_parsing() {
+    let path: DerivationPath = "m/0'/1h/2".parse().unwrap();
+    assert_eq!(path.indices(), &[HARDENED, 1 | HARDENED, 2]);
+    assert_eq!(path.to_string(), "m/0'/1'/2");
+    assert_eq!(
+      "m".parse::<DerivationPath>().unwrap(),
+      DerivationPath::default()
+    );
+    assert!("0/1".parse::<DerivationPath>().is_err());
+    assert!("m/x".parse::<DerivationPath>().is_err());
+    assert!("m/2147483648".parse::<DerivationPath>().is_err());
+  }
+
+  #[test]
+  fn test_ed25519_vectors() {
+    let vectors = [
+      (
+        "m",
+        "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7",
+      ),
+      (
+        "m/0'",
+        "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3",
+      ),
+      (
+        "m/0'/1'/2'",
+        "92a5b23c0b8a99e37d07df3fb9966917f5d06e02ddbd909c7e184371463e9fc9",
+      ),
+    ];
+    for (path, expected) in vectors {
+      let key = derive_ed25519(&SEED, &path.parse().unwrap()).unwrap();
+      assert_eq!(hex(&key), expected, "{}", path);
+    }
+    assert!(derive_ed25519(&SEED, &"m/0".parse().unwrap()).is_err());
+  }
+
+  #[test]
+  fn test_p256_vectors() {
+    let vectors = [
+      (
+        "m",
+        "612091aaa12e22dd2abef664f8a01a82cae99ad7441b7ef8110424915c268bc2",
+      ),
+      (
+        "m/0'",
+        "6939694369114c67917a182c59ddb8cafc3004e63ca5d3b84403ba8613debc0c",
+      ),
+    ];
+    for (path, expected) in vectors {
+      let key = derive_p256(&SEED, &path.parse().unwrap()).unwrap();
+      assert_eq!(hex(&key.to_bytes()), expected, "{}", path);
+    }
+  }
+
+  #[test]
+  fn test_p256_retry_vectors() {
+    // SLIP-0010 "derivation retry" vector: the first child key is invalid
+    let key = derive_p256(&SEED, &"m/28578'/33941".parse().unwrap()).unwrap();
+    assert_eq!(
+      hex(&key.to_bytes()),
+      "092154eed4af83e078ff9b84322015aefe5769e31270f62c3f66c33888335f3a"
+    );
+    // SLIP-0010 "seed retry" vector: the first master key is invalid
+    let seed = [
+      0xa7, 0x30, 0x5b, 0xc8, 0xdf, 0x8d, 0x09, 0x51, 0xf0, 0xcb, 0x22, 0x4c, 0x0e, 0x95, 0xd7,
+      0x70, 0x7c, 0xbd, 0xf2, 0xc6, 0xce, 0x7e, 0x8d, 0x48, 0x1f, 0xec, 0x69, 0xc7, 0xff, 0x5e,
+      0x94, 0x46,
+    ];
+    let key = derive_p256(&seed, &DerivationPath::default()).unwrap();
+    assert_eq!(
+      hex(&key.to_bytes()),
+      "3b8c18469a4634517d6d0b65448f8e6c62091b45540a1743c5846be55d47d88f"
+    );
+  }
+
+  #[test]
+  fn test_derive_signer() {
+    let path: DerivationPath = "m/7'".parse().unwrap();
+    for alg in [SignatureAlgorithm::Ed25519, SignatureAlgorithm::EcdsaP256] {
+      let signer = RingSigner::derive(alg.clone(), &SEED, &path).unwrap();
+      let again = RingSigner::derive(alg.clone(), &SEED, &path).unwrap();
+      let other = RingSigner::derive(alg, &SEED, &path.hardened_child(0)).unwrap();
+      assert_eq!(signer.public_key().key, again.public_key().key);
+      assert_ne!(signer.public_key().key, other.public_key().key);
+      let signature = signer.sign(b"hello").unwrap();
+      again.public_key().verify(signature, b"hello").unwrap();
+      // survives a pem round trip
+      let pem = signer.private_key_pem().unwrap();
+      let loaded = RingSigner::from_pem(pem).unwrap();
+      assert_eq!(loaded.public_key().key, signer.public_key().key);
+    }
+  }
+}
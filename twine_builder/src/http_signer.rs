@@ -0,0 +1,207 @@
+//! A reference [`AsyncSigner`] that signs using a remote HTTP service.
+//!
+//! Requires the `remote` feature to be enabled.
+//!
+//! The service is expected to implement two endpoints, relative to the base URL:
+//!
+//! - `GET public-key` responds with the [`PublicKey`] encoded as DAG-JSON.
+//! - `POST sign` accepts the message as the raw request body
+//!   (`application/octet-stream`) and responds with the raw signature bytes.
+//!
+//! Any other status than a success is reported as a [`SigningError`]
+//! containing the status and the response body.
+use crate::{AsyncSigner, SigningError};
+use async_trait::async_trait;
+use reqwest::{header::CONTENT_TYPE, Url};
+use twine_lib::{
+  crypto::{PublicKey, Signature},
+  ipld_core::codec::Codec,
+  serde_ipld_dagjson::codec::DagJsonCodec,
+};
+
+/// An [`AsyncSigner`] that delegates signing to a remote HTTP service
+///
+/// See the [module documentation](self) for the expected API.
+///
+/// # Example
+///
+/// ```no_run
+/// # async fn example() {
+/// use twine_builder::{AsyncTwineBuilder, HttpSigner};
+/// let client = reqwest::Client::builder()
+///   .timeout(std::time::Duration::from_secs(10))
+///   .build()
+///   .unwrap();
+/// let signer = HttpSigner::connect(client, "http://localhost:8200/twine")
+///   .await
+///   .unwrap();
+/// let builder = AsyncTwineBuilder::new(signer);
+/// let strand = builder.build_strand().done_async().await.unwrap();
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct HttpSigner {
+  client: reqwest::Client,
+  url: Url,
+  key: PublicKey,
+}
+
+fn parse_url(url: &str) -> Result<Url, SigningError> {
+  format!("{}/", url.trim_end_matches('/'))
+    .parse()
+    .map_err(|e| SigningError(format!("Invalid URL: {}", e)))
+}
+
+async fn check_response(response: reqwest::Response) -> Result<reqwest::Response, SigningError> {
+  if response.status().is_success() {
+    return Ok(response);
+  }
+  let status = response.status();
+  let body = response.text().await.unwrap_or_default();
+  Err(SigningError(format!(
+    "Remote signer responded with {}: {}",
+    status, body
+  )))
+}
+
+impl HttpSigner {
+  /// Connect to the signing service and fetch its public key
+  pub async fn connect(client: reqwest::Client, url: &str) -> Result<Self, SigningError> {
+    let url = parse_url(url)?;
+    let response = client
+      .get(url.join("public-key").unwrap())
+      .send()
+      .await
+      .map_err(|e| SigningError(e.to_string()))?;
+    let bytes = check_response(response)
+      .await?
+      .bytes()
+      .await
+      .map_err(|e| SigningError(e.to_string()))?;
+    let key = DagJsonCodec::decode_from_slice(&bytes)
+      .map_err(|e| SigningError(format!("Invalid public key: {}", e)))?;
+    Ok(Self { client, url, key })
+  }
+
+  /// Create a signer for a service whose public key is already known
+  ///
+  /// No request is made until the first signature.
+  pub fn with_key(
+    client: reqwest::Client,
+    url: &str,
+    key: PublicKey,
+  ) -> Result<Self, SigningError> {
+    Ok(Self {
+      client,
+      url: parse_url(url)?,
+      key,
+    })
+  }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl AsyncSigner for HttpSigner {
+  type Key = PublicKey;
+
+  async fn sign_async(&self, data: &[u8]) -> Result<Signature, SigningError> {
+    let response = self
+      .client
+      .post(self.url.join("sign").unwrap())
+      .header(CONTENT_TYPE, "application/octet-stream")
+      .body(data.to_vec())
+      .send()
+      .await
+      .map_err(|e| SigningError(e.to_string()))?;
+    let bytes = check_response(response)
+      .await?
+      .bytes()
+      .await
+      .map_err(|e| SigningError(e.to_string()))?;
+    Ok(bytes.to_vec().into())
+  }
+
+  fn public_key(&self) -> Self::Key {
+    self.key.clone()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{AsyncTwineBuilder, RingSigner, Signer};
+  use axum::{body::Bytes, extract::State, http::StatusCode, routing, Router};
+  use std::sync::Arc;
+
+  /// A mock signing service backed by an in-memory key
+  async fn mock_server(signer: RingSigner) -> String {
+    let signer = Arc::new(signer);
+    let app = Router::new()
+      .route(
+        "/signer/public-key",
+        routing::get(|State(signer): State<Arc<RingSigner>>| async move {
+          DagJsonCodec::encode_to_vec(&signer.public_key()).unwrap()
+        }),
+      )
+      .route(
+        "/signer/sign",
+        routing::post(
+          |State(signer): State<Arc<RingSigner>>, body: Bytes| async move {
+            if body.is_empty() {
+              return Err((StatusCode::BAD_REQUEST, "empty message"));
+            }
+            Ok(signer.sign(&body).unwrap().to_vec())
+          },
+        ),
+      )
+      .with_state(signer);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{}/signer", addr)
+  }
+
+  #[tokio::test]
+  async fn test_build_with_remote_signer() {
+    let key = RingSigner::generate_p256().unwrap();
+    let public_key = key.public_key();
+    let url = mock_server(key).await;
+    let signer = HttpSigner::connect(reqwest::Client::new(), &url)
+      .await
+      .unwrap();
+    assert_eq!(signer.public_key().key, public_key.key);
+
+    let builder = AsyncTwineBuilder::new(signer);
+    let strand = builder.build_strand().done_async().await.unwrap();
+    let first = builder
+      .build_first(strand)
+      .payload("remote")
+      .done_async()
+      .await
+      .unwrap();
+    let next = builder.build_next(&first).done_async().await.unwrap();
+    assert_eq!(next.index(), 1);
+    assert_eq!(first.extract_payload::<String>().unwrap(), "remote");
+  }
+
+  #[tokio::test]
+  async fn test_remote_signer_errors() {
+    let url = mock_server(RingSigner::generate_ed25519().unwrap()).await;
+    let signer = HttpSigner::connect(reqwest::Client::new(), &url)
+      .await
+      .unwrap();
+    let err = signer.sign_async(&[]).await.unwrap_err();
+    assert!(err.0.contains("400"), "{}", err);
+
+    // a signer whose key does not match the service is caught when verifying
+    let other = RingSigner::generate_ed25519().unwrap().public_key();
+    let signer = HttpSigner::with_key(reqwest::Client::new(), &url, other).unwrap();
+    let builder = AsyncTwineBuilder::new(signer);
+    assert!(builder.build_strand().done_async().await.is_err());
+
+    let missing = format!("{}/nope", url);
+    assert!(HttpSigner::connect(reqwest::Client::new(), &missing)
+      .await
+      .is_err());
+  }
+}
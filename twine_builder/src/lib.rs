@@ -1,10 +1,10 @@
 #![doc = include_str!("../README.md")]
 
 pub mod signer;
-pub use signer::{Signer, SigningError};
+pub use signer::{AsyncSigner, PublicKeySigner, Signer, SigningError};
 
 pub mod builder;
-pub use builder::TwineBuilder;
+pub use builder::{AsyncTwineBuilder, TwineBuilder};
 
 #[cfg(feature = "v1")]
 pub use biscuit;
@@ -13,8 +13,31 @@ mod biscuit_signer;
 #[cfg(feature = "v1")]
 pub use biscuit_signer::BiscuitSigner;
 
+#[cfg(feature = "pkcs11")]
+pub use cryptoki;
+#[cfg(feature = "pkcs11")]
+mod pkcs11_signer;
+#[cfg(feature = "pkcs11")]
+pub use pkcs11_signer::{Pkcs11Key, Pkcs11Signer, Pkcs11SignerError};
+
+#[cfg(feature = "remote")]
+mod http_signer;
+#[cfg(feature = "remote")]
+pub use http_signer::HttpSigner;
+
+#[cfg(feature = "pq")]
+mod pq_signer;
+#[cfg(feature = "pq")]
+pub use pq_signer::PqSigner;
+
 mod ring_signer;
-pub use ring_signer::RingSigner;
+pub use ring_signer::{RingSigner, RingSignerError};
+
+mod derive;
+pub use derive::{decrypt_seed, encrypt_seed, DerivationPath, InvalidDerivationPath, HARDENED};
+
+#[cfg(feature = "test-util")]
+pub mod test_util;
 
 pub use pkcs8;
 pub use ring;
@@ -0,0 +1,491 @@
+//! A signer backed by a PKCS#11 token, such as an HSM.
+//!
+//! Requires the `pkcs11` feature to be enabled. The `cryptoki` crate
+//! it depends on is fetched from crates.io, so offline builds with this
+//! feature (or `--all-features`) need it in the local registry or vendored.
+use crate::{Signer, SigningError};
+use cryptoki::{
+  context::{CInitializeArgs, Pkcs11},
+  error::{Error as CryptokiError, RvError},
+  mechanism::Mechanism,
+  object::{Attribute, AttributeType, KeyType, ObjectClass, ObjectHandle},
+  session::{Session, UserType},
+  types::AuthPin,
+};
+use pkcs8::der::{
+  asn1::{ObjectIdentifier, OctetStringRef, SequenceOf, UintRef},
+  Decode, Encode,
+};
+use std::{
+  collections::HashMap,
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex, OnceLock, Weak},
+};
+use thiserror::Error;
+use twine_lib::crypto::{PublicKey, Signature, SignatureAlgorithm};
+
+/// Errors that can occur when setting up a [`Pkcs11Signer`]
+#[derive(Debug, Error)]
+pub enum Pkcs11SignerError {
+  /// The PKCS#11 module reported an error
+  #[error("PKCS#11 error: {0}")]
+  Pkcs11(#[from] cryptoki::error::Error),
+  /// No token has the requested label
+  #[error("No token with label {0:?}")]
+  TokenNotFound(String),
+  /// No key matched the selection
+  #[error("No private key found for {0}")]
+  KeyNotFound(Pkcs11Key),
+  /// Several keys matched the selection
+  #[error("More than one private key found for {0}")]
+  AmbiguousKey(Pkcs11Key),
+  /// The public key object lacks a required attribute
+  #[error("Missing attribute {0}")]
+  MissingAttribute(AttributeType),
+  /// The key type or curve is not supported
+  #[error("Unsupported algorithm")]
+  UnsupportedAlgorithm,
+  /// An attribute could not be decoded
+  #[error("der decode error: {0}")]
+  DerDecodeError(#[from] pkcs8::der::Error),
+}
+
+/// How to find a key on the token
+///
+/// Both the private key and its matching public key object
+/// are expected to share the same label or ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pkcs11Key {
+  /// Select the key by its `CKA_LABEL`
+  Label(String),
+  /// Select the key by its `CKA_ID`
+  Id(Vec<u8>),
+}
+
+impl std::fmt::Display for Pkcs11Key {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Pkcs11Key::Label(label) => write!(f, "label {:?}", label),
+      Pkcs11Key::Id(id) => {
+        write!(f, "id ")?;
+        id.iter().try_for_each(|b| write!(f, "{:02x}", b))
+      }
+    }
+  }
+}
+
+impl Pkcs11Key {
+  fn attribute(&self) -> Attribute {
+    match self {
+      Pkcs11Key::Label(label) => Attribute::Label(label.as_bytes().to_vec()),
+      Pkcs11Key::Id(id) => Attribute::Id(id.clone()),
+    }
+  }
+}
+
+/// A signer that uses a key stored on a PKCS#11 token
+///
+/// The private key never leaves the token. The signature algorithm is
+/// determined from the key type, and the mechanisms map as follows:
+///
+/// | Key type | Algorithm | Mechanism |
+/// |----------|-----------|-----------|
+/// | Ed25519 | `Ed25519` | `CKM_EDDSA` |
+/// | EC P-256 | `EcdsaP256` | `CKM_ECDSA` (digest computed locally) |
+/// | EC P-384 | `EcdsaP384` | `CKM_ECDSA` (digest computed locally) |
+/// | RSA | `Sha256Rsa`, `Sha384Rsa`, `Sha512Rsa` | `CKM_SHA*_RSA_PKCS` |
+///
+/// RSA keys default to the same digest as [`RingSigner::from_pem`](crate::RingSigner::from_pem)
+/// picks for bare RSA keys. Use [`Pkcs11Signer::with_algorithm`] to choose another.
+///
+/// A PKCS#11 module can only be initialized once per process, so signers
+/// opened with [`Pkcs11Signer::open`] share one context per module, which
+/// is finalized when the last of them is dropped. To manage the context
+/// yourself, use [`Pkcs11Signer::open_with`].
+///
+/// Requires the `pkcs11` feature to be enabled.
+///
+/// # Example
+///
+/// ```no_run
+/// use twine_builder::{Pkcs11Key, Pkcs11Signer, TwineBuilder};
+/// let signer = Pkcs11Signer::open(
+///   "/usr/lib/softhsm/libsofthsm2.so",
+///   "twine",
+///   "1234",
+///   Pkcs11Key::Label("root".into()),
+/// ).unwrap();
+/// let builder = TwineBuilder::new(signer);
+/// let strand = builder.build_strand().done().unwrap();
+/// ```
+pub struct Pkcs11Signer {
+  session: Mutex<Session>,
+  private_key: ObjectHandle,
+  public_key: PublicKey,
+  // dropped after the session
+  _context: Option<Arc<Pkcs11>>,
+}
+
+/// The initialized contexts of each module loaded by [`Pkcs11Signer::open`]
+fn shared_context(module: &Path) -> Result<Arc<Pkcs11>, Pkcs11SignerError> {
+  static CONTEXTS: OnceLock<Mutex<HashMap<PathBuf, Weak<Pkcs11>>>> = OnceLock::new();
+  let mut contexts = CONTEXTS
+    .get_or_init(Default::default)
+    .lock()
+    .unwrap_or_else(|e| e.into_inner());
+  if let Some(context) = contexts.get(module).and_then(Weak::upgrade) {
+    return Ok(context);
+  }
+  let context = Arc::new(Pkcs11::new(module)?);
+  match context.initialize(CInitializeArgs::OsThreads) {
+    Ok(()) => {}
+    Err(CryptokiError::Pkcs11(RvError::CryptokiAlreadyInitialized, ..)) => {
+      // someone else in this process owns the module, so never finalize it
+      std::mem::forget(Arc::clone(&context));
+    }
+    Err(e) => return Err(e.into()),
+  }
+  contexts.insert(module.to_path_buf(), Arc::downgrade(&context));
+  Ok(context)
+}
+
+impl Pkcs11Signer {
+  /// Load a PKCS#11 module, log in to the token with the given label, and find the key
+  ///
+  /// The module is initialized the first time it is opened, and its
+  /// context is shared with any other signer opened from it.
+  pub fn open<P: AsRef<Path>>(
+    module: P,
+    token_label: &str,
+    pin: &str,
+    key: Pkcs11Key,
+  ) -> Result<Self, Pkcs11SignerError> {
+    let context = shared_context(module.as_ref())?;
+    Self::open_with(context, token_label, pin, key)
+  }
+
+  /// Log in to the token with the given label using an initialized
+  /// context, and find the key
+  ///
+  /// The context must already be initialized. The signer holds a
+  /// reference to it, so the context is finalized once it and every
+  /// other holder are dropped.
+  pub fn open_with(
+    context: Arc<Pkcs11>,
+    token_label: &str,
+    pin: &str,
+    key: Pkcs11Key,
+  ) -> Result<Self, Pkcs11SignerError> {
+    let slot = context
+      .get_slots_with_token()?
+      .into_iter()
+      .find(|slot| {
+        context
+          .get_token_info(*slot)
+          .map(|info| info.label() == token_label)
+          .unwrap_or(false)
+      })
+      .ok_or_else(|| Pkcs11SignerError::TokenNotFound(token_label.to_string()))?;
+    let session = context.open_ro_session(slot)?;
+    // the login is shared by all sessions of the application
+    match session.login(UserType::User, Some(&AuthPin::new(pin.to_string()))) {
+      Ok(()) | Err(CryptokiError::Pkcs11(RvError::UserAlreadyLoggedIn, ..)) => {}
+      Err(e) => return Err(e.into()),
+    }
+    let mut signer = Self::new(session, key)?;
+    signer._context = Some(context);
+    Ok(signer)
+  }
+
+  /// Create a signer from an already logged-in session
+  pub fn new(session: Session, key: Pkcs11Key) -> Result<Self, Pkcs11SignerError> {
+    let private_key = find_one(&session, ObjectClass::PRIVATE_KEY, &key)?;
+    let public_key = find_one(&session, ObjectClass::PUBLIC_KEY, &key)?;
+    let public_key = read_public_key(&session, public_key)?;
+    Ok(Self {
+      session: Mutex::new(session),
+      private_key,
+      public_key,
+      _context: None,
+    })
+  }
+
+  /// Choose the signature algorithm
+  ///
+  /// This is only useful for RSA keys, to select the digest. The algorithm
+  /// must be compatible with the key on the token.
+  pub fn with_algorithm(mut self, alg: SignatureAlgorithm) -> Result<Self, Pkcs11SignerError> {
+    let bits = match self.public_key.alg {
+      SignatureAlgorithm::Sha256Rsa(bits)
+      | SignatureAlgorithm::Sha384Rsa(bits)
+      | SignatureAlgorithm::Sha512Rsa(bits) => Some(bits),
+      _ => None,
+    };
+    let compatible = match alg {
+      SignatureAlgorithm::Sha256Rsa(b)
+      | SignatureAlgorithm::Sha384Rsa(b)
+      | SignatureAlgorithm::Sha512Rsa(b) => bits == Some(b),
+      _ => std::mem::discriminant(&alg) == std::mem::discriminant(&self.public_key.alg),
+    };
+    if !compatible {
+      return Err(Pkcs11SignerError::UnsupportedAlgorithm);
+    }
+    self.public_key.alg = alg;
+    Ok(self)
+  }
+
+  /// Access the algorithm for this signer
+  pub fn alg(&self) -> &SignatureAlgorithm {
+    &self.public_key.alg
+  }
+}
+
+fn find_one(
+  session: &Session,
+  class: ObjectClass,
+  key: &Pkcs11Key,
+) -> Result<ObjectHandle, Pkcs11SignerError> {
+  let found = session.find_objects(&[Attribute::Class(class), key.attribute()])?;
+  match found.as_slice() {
+    [handle] => Ok(*handle),
+    [] => Err(Pkcs11SignerError::KeyNotFound(key.clone())),
+    _ => Err(Pkcs11SignerError::AmbiguousKey(key.clone())),
+  }
+}
+
+fn read_public_key(
+  session: &Session,
+  handle: ObjectHandle,
+) -> Result<PublicKey, Pkcs11SignerError> {
+  let attributes = session.get_attributes(
+    handle,
+    &[
+      AttributeType::KeyType,
+      AttributeType::EcParams,
+      AttributeType::EcPoint,
+      AttributeType::Modulus,
+      AttributeType::PublicExponent,
+    ],
+  )?;
+  let mut key_type = None;
+  let mut params = None;
+  let mut point = None;
+  let mut modulus = None;
+  let mut exponent = None;
+  for attribute in attributes {
+    match attribute {
+      Attribute::KeyType(t) => key_type = Some(t),
+      Attribute::EcParams(p) => params = Some(p),
+      Attribute::EcPoint(p) => point = Some(p),
+      Attribute::Modulus(m) => modulus = Some(m),
+      Attribute::PublicExponent(e) => exponent = Some(e),
+      _ => {}
+    }
+  }
+  let key_type = key_type.ok_or(Pkcs11SignerError::MissingAttribute(AttributeType::KeyType))?;
+  let params = || params.ok_or(Pkcs11SignerError::MissingAttribute(AttributeType::EcParams));
+  let point = || point.ok_or(Pkcs11SignerError::MissingAttribute(AttributeType::EcPoint));
+
+  if key_type == KeyType::EC_EDWARDS {
+    // the curve may be given as an OID or as the printable string "edwards25519"
+    let params = params()?;
+    let is_ed25519 = match ObjectIdentifier::from_der(&params) {
+      Ok(oid) => oid == const_oid::db::rfc8410::ID_ED_25519,
+      Err(_) => params.ends_with(b"edwards25519"),
+    };
+    if !is_ed25519 {
+      return Err(Pkcs11SignerError::UnsupportedAlgorithm);
+    }
+    Ok(PublicKey {
+      alg: SignatureAlgorithm::Ed25519,
+      key: unwrap_ec_point(point()?, 32)?.into(),
+    })
+  } else if key_type == KeyType::EC {
+    let (alg, len) = match ObjectIdentifier::from_der(&params()?)? {
+      const_oid::db::rfc5912::SECP_256_R_1 => (SignatureAlgorithm::EcdsaP256, 65),
+      const_oid::db::rfc5912::SECP_384_R_1 => (SignatureAlgorithm::EcdsaP384, 97),
+      _ => return Err(Pkcs11SignerError::UnsupportedAlgorithm),
+    };
+    Ok(PublicKey {
+      alg,
+      key: unwrap_ec_point(point()?, len)?.into(),
+    })
+  } else if key_type == KeyType::RSA {
+    let modulus = modulus.ok_or(Pkcs11SignerError::MissingAttribute(AttributeType::Modulus))?;
+    let exponent = exponent.ok_or(Pkcs11SignerError::MissingAttribute(
+      AttributeType::PublicExponent,
+    ))?;
+    let n = UintRef::new(&modulus)?;
+    let e = UintRef::new(&exponent)?;
+    let alg = match n.as_bytes().len() * 8 {
+      2048 => SignatureAlgorithm::Sha256Rsa(2048),
+      3072 => SignatureAlgorithm::Sha384Rsa(3072),
+      4096 => SignatureAlgorithm::Sha512Rsa(4096),
+      _ => return Err(Pkcs11SignerError::UnsupportedAlgorithm),
+    };
+    // PKCS#1 RSAPublicKey, the same encoding ring uses
+    Ok(PublicKey {
+      alg,
+      key: der_sequence(n, e)?.into(),
+    })
+  } else {
+    Err(Pkcs11SignerError::UnsupportedAlgorithm)
+  }
+}
+
+/// `CKA_EC_POINT` should be a DER octet string, but some tokens return the raw point
+fn unwrap_ec_point(point: Vec<u8>, len: usize) -> Result<Vec<u8>, Pkcs11SignerError> {
+  if point.len() == len {
+    return Ok(point);
+  }
+  let inner = OctetStringRef::from_der(&point)?.as_bytes().to_vec();
+  if inner.len() != len {
+    return Err(Pkcs11SignerError::UnsupportedAlgorithm);
+  }
+  Ok(inner)
+}
+
+fn der_sequence(a: UintRef, b: UintRef) -> pkcs8::der::Result<Vec<u8>> {
+  let mut seq = SequenceOf::<UintRef, 2>::new();
+  seq.add(a)?;
+  seq.add(b)?;
+  seq.to_der()
+}
+
+impl Signer for Pkcs11Signer {
+  type Key = PublicKey;
+
+  fn sign<T: AsRef<[u8]>>(&self, data: T) -> Result<Signature, SigningError> {
+    let data = data.as_ref();
+    let session = self
+      .session
+      .lock()
+      .map_err(|e| SigningError(e.to_string()))?;
+    let sign = |mechanism: Mechanism, data: &[u8]| {
+      session
+        .sign(&mechanism, self.private_key, data)
+        .map_err(|e| SigningError(e.to_string()))
+    };
+    let signature = match self.public_key.alg {
+      SignatureAlgorithm::Ed25519 => sign(Mechanism::Eddsa, data)?,
+      SignatureAlgorithm::EcdsaP256 | SignatureAlgorithm::EcdsaP384 => {
+        let digest = match self.public_key.alg {
+          SignatureAlgorithm::EcdsaP256 => ring::digest::digest(&ring::digest::SHA256, data),
+          _ => ring::digest::digest(&ring::digest::SHA384, data),
+        };
+        // PKCS#11 returns r || s, but twine uses the ASN.1 encoding
+        let raw = sign(Mechanism::Ecdsa, digest.as_ref())?;
+        let (r, s) = raw.split_at(raw.len() / 2);
+        let to_uint = |b| UintRef::new(b).map_err(|e| SigningError(e.to_string()));
+        der_sequence(to_uint(r)?, to_uint(s)?).map_err(|e| SigningError(e.to_string()))?
+      }
+      SignatureAlgorithm::Sha256Rsa(_) => sign(Mechanism::Sha256RsaPkcs, data)?,
+      SignatureAlgorithm::Sha384Rsa(_) => sign(Mechanism::Sha384RsaPkcs, data)?,
+      SignatureAlgorithm::Sha512Rsa(_) => sign(Mechanism::Sha512RsaPkcs, data)?,
+      _ => return Err(SigningError("Unsupported algorithm".into())),
+    };
+    Ok(signature.into())
+  }
+
+  fn public_key(&self) -> Self::Key {
+    self.public_key.clone()
+  }
+}
+
+/// These tests need SoftHSM2 and a token prepared with keys, e.g.
+///
+/// ```sh
+/// softhsm2-util --init-token --free --label twine --pin 1234 --so-pin 1234
+/// pkcs11-tool --module $MODULE --login --pin 1234 --keypairgen --key-type EC:prime256v1 --label p256
+/// pkcs11-tool --module $MODULE --login --pin 1234 --keypairgen --key-type EC:secp384r1 --label p384
+/// pkcs11-tool --module $MODULE --login --pin 1234 --keypairgen --key-type EC:edwards25519 --label ed25519
+/// pkcs11-tool --module $MODULE --login --pin 1234 --keypairgen --key-type rsa:2048 --label rsa --id 01
+/// ```
+///
+/// Run them with `TWINE_PKCS11_MODULE=$MODULE cargo test --features pkcs11 -- --ignored`
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::TwineBuilder;
+
+  fn open(key: Pkcs11Key) -> Pkcs11Signer {
+    let module =
+      std::env::var("TWINE_PKCS11_MODULE").unwrap_or("/usr/lib/softhsm/libsofthsm2.so".to_string());
+    Pkcs11Signer::open(module, "twine", "1234", key).unwrap()
+  }
+
+  fn build_with(signer: Pkcs11Signer) {
+    let builder = TwineBuilder::new(signer);
+    let strand = builder.build_strand().done().unwrap();
+    let first = builder.build_first(strand).payload("hsm").done().unwrap();
+    builder.build_next(&first).done().unwrap();
+  }
+
+  #[test]
+  #[ignore = "requires SoftHSM2"]
+  fn test_softhsm_ecdsa() {
+    let signer = open(Pkcs11Key::Label("p256".into()));
+    assert!(matches!(signer.alg(), SignatureAlgorithm::EcdsaP256));
+    build_with(signer);
+    let signer = open(Pkcs11Key::Label("p384".into()));
+    assert!(matches!(signer.alg(), SignatureAlgorithm::EcdsaP384));
+    build_with(signer);
+  }
+
+  #[test]
+  #[ignore = "requires SoftHSM2"]
+  fn test_softhsm_ed25519() {
+    let signer = open(Pkcs11Key::Label("ed25519".into()));
+    assert!(matches!(signer.alg(), SignatureAlgorithm::Ed25519));
+    build_with(signer);
+  }
+
+  #[test]
+  #[ignore = "requires SoftHSM2"]
+  fn test_softhsm_rsa_by_id() {
+    let signer = open(Pkcs11Key::Id(vec![1]));
+    assert!(matches!(signer.alg(), SignatureAlgorithm::Sha256Rsa(2048)));
+    build_with(signer);
+    let signer = open(Pkcs11Key::Id(vec![1]))
+      .with_algorithm(SignatureAlgorithm::Sha384Rsa(2048))
+      .unwrap();
+    build_with(signer);
+    assert!(open(Pkcs11Key::Id(vec![1]))
+      .with_algorithm(SignatureAlgorithm::EcdsaP256)
+      .is_err());
+  }
+
+  #[test]
+  #[ignore = "requires SoftHSM2"]
+  fn test_softhsm_shared_context() {
+    let first = open(Pkcs11Key::Label("ed25519".into()));
+    let second = open(Pkcs11Key::Label("p256".into()));
+    // dropping one signer leaves the other usable
+    drop(first);
+    build_with(second);
+    build_with(open(Pkcs11Key::Label("ed25519".into())));
+  }
+
+  #[test]
+  #[ignore = "requires SoftHSM2"]
+  fn test_softhsm_missing_key() {
+    let module =
+      std::env::var("TWINE_PKCS11_MODULE").unwrap_or("/usr/lib/softhsm/libsofthsm2.so".to_string());
+    let res = Pkcs11Signer::open(module, "twine", "1234", Pkcs11Key::Label("nope".into()));
+    assert!(matches!(res, Err(Pkcs11SignerError::KeyNotFound(_))));
+  }
+
+  #[test]
+  fn test_der_sequence() {
+    // r and s with a leading zero and a high bit
+    let seq = der_sequence(
+      UintRef::new(&[0, 1]).unwrap(),
+      UintRef::new(&[0x80]).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+      seq,
+      vec![0x30, 0x07, 0x02, 0x01, 0x01, 0x02, 0x02, 0x00, 0x80]
+    );
+  }
+}
@@ -0,0 +1,312 @@
+//! Post-quantum signing using ML-DSA (FIPS 204) and SLH-DSA (FIPS 205)
+//!
+//! Requires the `pq` feature to be enabled.
+//!
+//! Keys are stored as PKCS8 documents using the OIDs from
+//! RFC 9881 (ML-DSA) and RFC 9909 (SLH-DSA), so they can be exchanged
+//! with other tools such as OpenSSL 3.5+. ML-DSA keys are always written
+//! in the compact "seed" form.
+use crate::{RingSignerError, Signer, SigningError};
+use ml_dsa::{KeyGen, MlDsa44, MlDsa65, MlDsa87, MlDsaParams, B32};
+use pkcs8::{
+  der::{
+    asn1::{OctetStringRef, SequenceOf},
+    Decode,
+  },
+  rand_core::{OsRng, RngCore},
+  AlgorithmIdentifierRef, EncodePrivateKey, ObjectIdentifier, PrivateKeyInfo, SecretDocument,
+};
+use slh_dsa::{ParameterSet, Sha2_128s, Sha2_256s};
+use twine_lib::crypto::{PublicKey, Signature, SignatureAlgorithm};
+
+const ID_ML_DSA_44: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.17");
+const ID_ML_DSA_65: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.18");
+const ID_ML_DSA_87: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.19");
+
+enum Keys {
+  MlDsa44(Box<ml_dsa::KeyPair<MlDsa44>>),
+  MlDsa65(Box<ml_dsa::KeyPair<MlDsa65>>),
+  MlDsa87(Box<ml_dsa::KeyPair<MlDsa87>>),
+  SlhDsaSha2_128s(Box<slh_dsa::SigningKey<Sha2_128s>>),
+  SlhDsaSha2_256s(Box<slh_dsa::SigningKey<Sha2_256s>>),
+}
+
+/// A signer for post-quantum signature algorithms
+///
+/// Messages are signed in "pure" mode with an empty context string,
+/// using hedged (randomized) signing.
+///
+/// Post-quantum signatures are large (several kilobytes), but
+/// are otherwise handled like any other signature. They are a good fit
+/// for long-lived strands that need to remain verifiable for decades.
+///
+/// # Example
+///
+/// ```rust
+/// use twine_builder::{PqSigner, TwineBuilder};
+/// use twine_lib::crypto::SignatureAlgorithm;
+/// let signer = PqSigner::generate(SignatureAlgorithm::MlDsa65).unwrap();
+/// let pem = signer.private_key_pem().unwrap();
+/// let signer = PqSigner::from_pem(&pem).unwrap();
+/// let builder = TwineBuilder::new(signer);
+/// let strand = builder.build_strand().done().unwrap();
+/// ```
+pub struct PqSigner {
+  alg: SignatureAlgorithm,
+  keypair: Keys,
+  pkcs8: SecretDocument,
+}
+
+impl PqSigner {
+  /// Create a new `PqSigner` with the given algorithm and private key
+  ///
+  /// It is likely more convenient to use the `from_pem` method to create a signer
+  pub fn new(alg: SignatureAlgorithm, pkcs8: SecretDocument) -> Result<Self, RingSignerError> {
+    let info = PrivateKeyInfo::from_der(pkcs8.as_bytes())?;
+    if algorithm_for_oid(info.algorithm.oid)?.to_string() != alg.to_string() {
+      return Err(RingSignerError::KeyRejected(format!(
+        "Key does not match algorithm {}",
+        alg
+      )));
+    }
+    let keypair = match alg {
+      SignatureAlgorithm::MlDsa44 => Keys::MlDsa44(Box::new(MlDsa44::key_gen_internal(
+        &ml_dsa_seed(info.private_key)?,
+      ))),
+      SignatureAlgorithm::MlDsa65 => Keys::MlDsa65(Box::new(MlDsa65::key_gen_internal(
+        &ml_dsa_seed(info.private_key)?,
+      ))),
+      SignatureAlgorithm::MlDsa87 => Keys::MlDsa87(Box::new(MlDsa87::key_gen_internal(
+        &ml_dsa_seed(info.private_key)?,
+      ))),
+      SignatureAlgorithm::SlhDsaSha2_128s => Keys::SlhDsaSha2_128s(Box::new(info.try_into()?)),
+      SignatureAlgorithm::SlhDsaSha2_256s => Keys::SlhDsaSha2_256s(Box::new(info.try_into()?)),
+      _ => return Err(RingSignerError::UnsupportedAlgorithm),
+    };
+    Ok(Self {
+      alg,
+      keypair,
+      pkcs8,
+    })
+  }
+
+  /// Create a new `PqSigner` from a PEM formatted private key
+  ///
+  /// The PEM string should contain a `PRIVATE KEY` in PKCS8 format.
+  pub fn from_pem<S: AsRef<str>>(pem: S) -> Result<Self, RingSignerError> {
+    let (label, pkcs8) = SecretDocument::from_pem(pem.as_ref())?;
+    if label == "ENCRYPTED PRIVATE KEY" {
+      return Err(RingSignerError::Encrypted);
+    }
+    let info = PrivateKeyInfo::from_der(pkcs8.as_bytes())?;
+    let alg = algorithm_for_oid(info.algorithm.oid)?;
+    Self::new(alg, pkcs8)
+  }
+
+  /// Generate a new signer with a random keypair for the given algorithm
+  pub fn generate(alg: SignatureAlgorithm) -> Result<Self, RingSignerError> {
+    let pkcs8 = match alg {
+      SignatureAlgorithm::MlDsa44 => ml_dsa_pkcs8(ID_ML_DSA_44)?,
+      SignatureAlgorithm::MlDsa65 => ml_dsa_pkcs8(ID_ML_DSA_65)?,
+      SignatureAlgorithm::MlDsa87 => ml_dsa_pkcs8(ID_ML_DSA_87)?,
+      SignatureAlgorithm::SlhDsaSha2_128s => {
+        slh_dsa::SigningKey::<Sha2_128s>::new(&mut OsRng).to_pkcs8_der()?
+      }
+      SignatureAlgorithm::SlhDsaSha2_256s => {
+        slh_dsa::SigningKey::<Sha2_256s>::new(&mut OsRng).to_pkcs8_der()?
+      }
+      _ => return Err(RingSignerError::UnsupportedAlgorithm),
+    };
+    Self::new(alg, pkcs8)
+  }
+
+  /// Access the algorithm for this signer
+  pub fn alg(&self) -> &SignatureAlgorithm {
+    &self.alg
+  }
+
+  /// Access the PKCS8 document for this signer
+  pub fn pkcs8(&self) -> &SecretDocument {
+    &self.pkcs8
+  }
+
+  /// Convert the PKCS8 document to a PEM formatted string
+  pub fn private_key_pem(&self) -> pkcs8::der::Result<String> {
+    self
+      .pkcs8
+      .to_pem("PRIVATE KEY", pkcs8::LineEnding::LF)
+      .map(|s| s.to_string())
+  }
+}
+
+fn algorithm_for_oid(oid: ObjectIdentifier) -> Result<SignatureAlgorithm, RingSignerError> {
+  match oid {
+    ID_ML_DSA_44 => Ok(SignatureAlgorithm::MlDsa44),
+    ID_ML_DSA_65 => Ok(SignatureAlgorithm::MlDsa65),
+    ID_ML_DSA_87 => Ok(SignatureAlgorithm::MlDsa87),
+    Sha2_128s::ALGORITHM_OID => Ok(SignatureAlgorithm::SlhDsaSha2_128s),
+    Sha2_256s::ALGORITHM_OID => Ok(SignatureAlgorithm::SlhDsaSha2_256s),
+    _ => Err(RingSignerError::UnsupportedAlgorithm),
+  }
+}
+
+fn ml_dsa_pkcs8(oid: ObjectIdentifier) -> Result<SecretDocument, RingSignerError> {
+  let mut private_key = [0u8; 34];
+  // seed [0] IMPLICIT OCTET STRING (SIZE (32))
+  private_key[0] = 0x80;
+  private_key[1] = 32;
+  OsRng
+    .try_fill_bytes(&mut private_key[2..])
+    .map_err(|_| RingSignerError::RandomFailure)?;
+  let algorithm = AlgorithmIdentifierRef {
+    oid,
+    parameters: None,
+  };
+  Ok(SecretDocument::encode_msg(&PrivateKeyInfo::new(
+    algorithm,
+    &private_key,
+  ))?)
+}
+
+/// Extract the seed from any of the ML-DSA private key encodings
+fn ml_dsa_seed(private_key: &[u8]) -> Result<B32, RingSignerError> {
+  let seed = match private_key {
+    // bare seed, as written by early implementations
+    seed if seed.len() == 32 => seed,
+    // seed [0] IMPLICIT OCTET STRING
+    [0x80, 32, seed @ ..] if seed.len() == 32 => seed,
+    // both SEQUENCE { seed, expandedKey }, as written by OpenSSL
+    [0x30, ..] => {
+      let both = SequenceOf::<OctetStringRef, 2>::from_der(private_key)?;
+      return seed_from_slice(both.get(0).map(|s| s.as_bytes()).unwrap_or_default());
+    }
+    _ => {
+      return Err(RingSignerError::KeyRejected(
+        "ML-DSA private keys must include the seed".into(),
+      ))
+    }
+  };
+  seed_from_slice(seed)
+}
+
+fn seed_from_slice(seed: &[u8]) -> Result<B32, RingSignerError> {
+  B32::try_from(seed).map_err(|_| RingSignerError::KeyRejected("Invalid ML-DSA seed".into()))
+}
+
+fn sign_ml_dsa<P: MlDsaParams>(
+  keypair: &ml_dsa::KeyPair<P>,
+  message: &[u8],
+) -> Result<Vec<u8>, SigningError> {
+  keypair
+    .signing_key()
+    .sign_randomized(message, &[], &mut OsRng)
+    .map(|signature| signature.encode().to_vec())
+    .map_err(|e| SigningError(e.to_string()))
+}
+
+fn sign_slh_dsa<P: ParameterSet>(
+  key: &slh_dsa::SigningKey<P>,
+  message: &[u8],
+) -> Result<Vec<u8>, SigningError> {
+  use slh_dsa::signature::RandomizedSigner;
+  key
+    .try_sign_with_rng(&mut OsRng, message)
+    .map(|signature| signature.to_vec())
+    .map_err(|e| SigningError(e.to_string()))
+}
+
+impl Signer for PqSigner {
+  type Key = PublicKey;
+
+  fn sign<T: AsRef<[u8]>>(&self, message: T) -> Result<Signature, SigningError> {
+    let message = message.as_ref();
+    let signature = match &self.keypair {
+      Keys::MlDsa44(keypair) => sign_ml_dsa(keypair, message)?,
+      Keys::MlDsa65(keypair) => sign_ml_dsa(keypair, message)?,
+      Keys::MlDsa87(keypair) => sign_ml_dsa(keypair, message)?,
+      Keys::SlhDsaSha2_128s(key) => sign_slh_dsa(key, message)?,
+      Keys::SlhDsaSha2_256s(key) => sign_slh_dsa(key, message)?,
+    };
+    Ok(signature.into())
+  }
+
+  fn public_key(&self) -> Self::Key {
+    use slh_dsa::signature::Keypair;
+    let key = match &self.keypair {
+      Keys::MlDsa44(keypair) => keypair.verifying_key().encode().to_vec(),
+      Keys::MlDsa65(keypair) => keypair.verifying_key().encode().to_vec(),
+      Keys::MlDsa87(keypair) => keypair.verifying_key().encode().to_vec(),
+      Keys::SlhDsaSha2_128s(key) => key.verifying_key().to_vec(),
+      Keys::SlhDsaSha2_256s(key) => key.verifying_key().to_vec(),
+    };
+    PublicKey::new(self.alg.clone(), key.into())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn roundtrip(alg: SignatureAlgorithm) {
+    let signer = PqSigner::generate(alg).unwrap();
+    let pem = signer.private_key_pem().unwrap();
+    let signer2 = PqSigner::from_pem(&pem).unwrap();
+    assert_eq!(signer.pkcs8().as_bytes(), signer2.pkcs8().as_bytes());
+
+    let key = signer.public_key();
+    assert_eq!(key.key, signer2.public_key().key);
+    let signature = signer.sign(b"hello").unwrap();
+    key.verify(signature.clone(), b"hello").unwrap();
+    assert!(key.verify(signature, b"goodbye").is_err());
+  }
+
+  #[test]
+  fn test_ml_dsa() {
+    roundtrip(SignatureAlgorithm::MlDsa44);
+    roundtrip(SignatureAlgorithm::MlDsa65);
+    roundtrip(SignatureAlgorithm::MlDsa87);
+  }
+
+  // SLH-DSA signing is slow without optimizations,
+  // so these are split up to run in parallel
+
+  #[test]
+  fn test_slh_dsa_sha2_128s() {
+    roundtrip(SignatureAlgorithm::SlhDsaSha2_128s);
+  }
+
+  #[test]
+  fn test_slh_dsa_sha2_256s() {
+    roundtrip(SignatureAlgorithm::SlhDsaSha2_256s);
+  }
+
+  #[test]
+  fn test_wrong_algorithm() {
+    let signer = PqSigner::generate(SignatureAlgorithm::MlDsa44).unwrap();
+    let pkcs8 = SecretDocument::try_from(signer.pkcs8().as_bytes()).unwrap();
+    assert!(PqSigner::new(SignatureAlgorithm::MlDsa65, pkcs8).is_err());
+    assert!(PqSigner::generate(SignatureAlgorithm::Ed25519).is_err());
+  }
+
+  // generated with `openssl genpkey -algorithm ML-DSA-44` (OpenSSL 3.5),
+  // which writes both the seed and the expanded key
+  #[test]
+  fn test_openssl_ml_dsa() {
+    let pem = include_str!("test_data/openssl-ml-dsa-44.pem");
+    let signer = PqSigner::from_pem(pem).unwrap();
+    assert!(matches!(signer.alg(), SignatureAlgorithm::MlDsa44));
+    // sha256 of the public key reported by `openssl pkey -pubout`
+    let key = signer.public_key();
+    let digest = ring::digest::digest(&ring::digest::SHA256, &key.key);
+    assert_eq!(
+      hex(digest.as_ref()),
+      "fc33cf6f824ba7280920ad99ff63b9d9c662df506072a761a8b3d3ef1422b841"
+    );
+    let signature = signer.sign(b"hello").unwrap();
+    key.verify(signature, b"hello").unwrap();
+  }
+
+  fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+  }
+}
@@ -1,4 +1,5 @@
 //! Defines the `Signer` trait for creating digital signatures
+use async_trait::async_trait;
 use ring::signature::Ed25519KeyPair;
 use std::fmt::Display;
 use twine_lib::crypto::{PublicKey, Signature, SignatureAlgorithm};
@@ -47,6 +48,31 @@ pub trait Signer {
   fn public_key(&self) -> Self::Key;
 }
 
+/// A [`Signer`] whose signatures are produced asynchronously
+///
+/// This is intended for keys that live behind a network service, such as a
+/// Vault transit engine or a cloud KMS, where blocking on [`Signer::sign`]
+/// would stall the executor. Use it with an [`crate::AsyncTwineBuilder`],
+/// whose builders provide `done_async()` methods.
+///
+/// See [`crate::HttpSigner`] (requires the `remote` feature) for a reference
+/// remote implementation.
+///
+/// The public key is expected to be known up front (for example, fetched
+/// once when the signer is created), so it is not async.
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait AsyncSigner {
+  /// The type of public key that this signer produces.
+  type Key;
+  /// Sign the given data and return the signature.
+  ///
+  /// The data is the message to sign.
+  async fn sign_async(&self, data: &[u8]) -> Result<Signature, SigningError>;
+  /// Get the public key for this signer.
+  fn public_key(&self) -> Self::Key;
+}
+
 impl Signer for Ed25519KeyPair {
   type Key = PublicKey;
 
@@ -61,3 +87,33 @@ impl Signer for Ed25519KeyPair {
     }
   }
 }
+
+/// A [`Signer`] that only knows a public key
+///
+/// This is useful for building data whose private key lives elsewhere,
+/// such as on an air-gapped machine. Calling [`Signer::sign`] always fails,
+/// so it is meant to be used with the `prepare()` methods of the v2 builders
+/// (see [`crate::builder::prepared`]).
+#[derive(Debug, Clone)]
+pub struct PublicKeySigner(PublicKey);
+
+impl PublicKeySigner {
+  /// Create a new signer for the given public key
+  pub fn new(key: PublicKey) -> Self {
+    Self(key)
+  }
+}
+
+impl Signer for PublicKeySigner {
+  type Key = PublicKey;
+
+  fn sign<T: AsRef<[u8]>>(&self, _data: T) -> Result<Signature, SigningError> {
+    Err(SigningError(
+      "This signer has no private key. Use prepare() to sign elsewhere.".into(),
+    ))
+  }
+
+  fn public_key(&self) -> Self::Key {
+    self.0.clone()
+  }
+}
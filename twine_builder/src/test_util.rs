@@ -0,0 +1,27 @@
+//! Fixtures for testing stores and resolvers
+//!
+//! Requires the `test-util` feature, which is meant to be enabled
+//! from `[dev-dependencies]` only.
+use crate::{RingSigner, TwineBuilder};
+use twine_lib::twine::{Strand, Twine};
+
+/// Build a strand and a chain of `n` tixels on it
+///
+/// The chain is signed with a freshly generated Ed25519 key.
+pub fn chain(n: usize) -> (Strand, Vec<Twine>) {
+  chain_with_radix(n, 32)
+}
+
+/// Build a strand with the given skiplist radix and a chain of `n` tixels on it
+///
+/// See [`chain`].
+pub fn chain_with_radix(n: usize, radix: u8) -> (Strand, Vec<Twine>) {
+  let builder = TwineBuilder::new(RingSigner::generate_ed25519().unwrap());
+  let strand = builder.build_strand().radix(radix).done().unwrap();
+  let mut tixels = vec![builder.build_first(strand.clone()).done().unwrap()];
+  for _ in 1..n {
+    let next = builder.build_next(tixels.last().unwrap()).done().unwrap();
+    tixels.push(next);
+  }
+  (strand, tixels)
+}
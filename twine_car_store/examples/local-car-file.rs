@@ -52,8 +52,8 @@ async fn main() {
 
     println!("saving {} tixels", n);
 
-    // saved on drop
-    // store.flush().await.unwrap();
+    // blocks are appended as they are saved, flushing updates the roots
+    store.flush().await.unwrap();
   }
 
   let store2 = CarStore::new(filename).unwrap();
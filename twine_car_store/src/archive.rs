@@ -0,0 +1,1039 @@
+//! A store that archives each strand as rolling CAR segments
+//!
+//! Every strand gets its own directory:
+//!
+//! ```text
+//! <root>/<strand cid>/manifest.json
+//! <root>/<strand cid>/strand.car
+//! <root>/<strand cid>/00000000000000000000.car
+//! <root>/<strand cid>/00000000000000065536.car
+//! ...
+//! ```
+//!
+//! Tixels are appended to the last (open) segment. Once a segment holds
+//! `segment_size` tixels it is closed: its CAR root is set to its last
+//! tixel and its sha2-256 checksum is recorded in the manifest. Closed
+//! segments are never written again. Each one is checksum-verified the
+//! first time it is read after the store is opened, which also finds
+//! the offsets of its blocks, and after that reads only load the blocks
+//! they need.
+//!
+//! The manifest is DAG-JSON and lists the index range, root cid and
+//! checksum of each segment, so it stays small however long the strand
+//! gets. The entry for the open segment is refreshed from the segment
+//! itself when the store is opened.
+use super::{block_frame, invalid_data, parse_blocks, parse_car, ParsedCar};
+use async_std::sync::Mutex as AsyncMutex;
+use async_trait::async_trait;
+use futures::stream::Stream;
+use futures::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use twine_lib::car::CarHeader;
+use twine_lib::multihash_codetable::{Code, MultihashDigest};
+use twine_lib::resolver::{unchecked_base::BaseResolver, AbsoluteRange, Resolver};
+use twine_lib::{as_cid::AsCid, errors::*, store::Store, twine::*, Cid};
+
+const MANIFEST_FILE: &str = "manifest.json";
+const STRAND_FILE: &str = "strand.car";
+
+fn fetch_error(e: io::Error) -> ResolutionError {
+  match e.kind() {
+    io::ErrorKind::InvalidData => ResolutionError::BadData(e.to_string()),
+    _ => ResolutionError::Fetch(e.to_string()),
+  }
+}
+
+fn saving_error(e: io::Error) -> StoreError {
+  StoreError::Saving(e.to_string())
+}
+
+fn segment_file(start: u64) -> String {
+  format!("{:020}.car", start)
+}
+
+fn checksum(bytes: &[u8]) -> String {
+  Code::Sha2_256
+    .digest(bytes)
+    .digest()
+    .iter()
+    .map(|b| format!("{:02x}", b))
+    .collect()
+}
+
+/// The largest power of the radix that fits in the requested size
+///
+/// Aligning segments this way keeps the skiplist links of the first
+/// tixel in each segment pointing at segment boundaries.
+fn aligned_segment_size(size: u64, radix: u8) -> u64 {
+  let radix = radix as u64;
+  if radix < 2 || size < radix {
+    return size;
+  }
+  let mut aligned = radix;
+  while let Some(next) = aligned.checked_mul(radix).filter(|n| *n <= size) {
+    aligned = next;
+  }
+  aligned
+}
+
+fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+  let mut tmp = path.as_os_str().to_owned();
+  tmp.push(".tmp");
+  let tmp = PathBuf::from(tmp);
+  let mut file = File::create(&tmp)?;
+  file.write_all(bytes)?;
+  file.sync_all()?;
+  std::fs::rename(&tmp, path)
+}
+
+/// Options for the ArchiveStore
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveStoreOptions {
+  segment_size: u64,
+  buffer_size: usize,
+}
+
+impl Default for ArchiveStoreOptions {
+  fn default() -> Self {
+    Self {
+      segment_size: 100_000,
+      buffer_size: 1000,
+    }
+  }
+}
+
+impl ArchiveStoreOptions {
+  /// Set the maximum number of tixels per segment
+  ///
+  /// New strands round this down to the largest power of their radix,
+  /// so a radix 10 strand gets 100,000 tixel segments by default, and a
+  /// radix 2 strand gets 65,536. Existing strands keep the segment size
+  /// recorded in their manifest.
+  pub fn segment_size(mut self, segment_size: u64) -> Self {
+    self.segment_size = segment_size.max(1);
+    self
+  }
+
+  /// Set the number of tixels read at once when streaming ranges
+  pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+    self.buffer_size = buffer_size.max(1);
+    self
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Manifest {
+  strand: Cid,
+  segment_size: u64,
+  segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Segment {
+  /// First tixel index
+  start: u64,
+  /// Last tixel index
+  end: u64,
+  /// The CAR root, which is the last tixel once closed
+  root: Cid,
+  /// Hex sha2-256 of the file, set when the segment is closed
+  checksum: Option<String>,
+  // offset of each block in the file, followed by the file length,
+  // known once the segment is verified
+  #[serde(skip)]
+  offsets: Vec<u64>,
+  // the file matches the checksum, or is being written by this store
+  #[serde(skip)]
+  verified: bool,
+}
+
+impl Segment {
+  /// Byte range of the blocks for `lower..=upper`
+  fn byte_range(&self, lower: u64, upper: u64) -> (u64, u64) {
+    (
+      self.offsets[(lower - self.start) as usize],
+      self.offsets[(upper - self.start + 1) as usize],
+    )
+  }
+}
+
+#[derive(Debug)]
+struct OpenSegment {
+  file: File,
+  cids: Vec<Cid>,
+}
+
+#[derive(Debug)]
+struct StrandArchive {
+  dir: PathBuf,
+  strand: Strand,
+  manifest: Manifest,
+  // the last segment, unless it is closed
+  open: Option<OpenSegment>,
+}
+
+impl StrandArchive {
+  fn create(dir: PathBuf, strand: Strand, segment_size: u64) -> io::Result<Self> {
+    std::fs::create_dir_all(&dir)?;
+    let car = twine_lib::car::to_car_bytes(vec![strand.clone()], vec![strand.cid()]);
+    write_atomic(&dir.join(STRAND_FILE), &car)?;
+    let archive = Self {
+      dir,
+      manifest: Manifest {
+        strand: strand.cid(),
+        segment_size: aligned_segment_size(segment_size, strand.radix()),
+        segments: vec![],
+      },
+      strand,
+      open: None,
+    };
+    archive.write_manifest()?;
+    Ok(archive)
+  }
+
+  fn load(dir: PathBuf) -> io::Result<Self> {
+    let manifest: Manifest =
+      twine_lib::serde_ipld_dagjson::from_slice(&std::fs::read(dir.join(MANIFEST_FILE))?)
+        .map_err(invalid_data)?;
+    let car = parse_car(&std::fs::read(dir.join(STRAND_FILE))?)?;
+    let strand = car
+      .frames
+      .into_iter()
+      .find(|frame| frame.cid == manifest.strand)
+      .ok_or_else(|| invalid_data(format!("Missing strand block in {}", dir.display())))?;
+    let strand = Strand::from_block(strand.cid, strand.data).map_err(invalid_data)?;
+
+    let mut archive = Self {
+      dir,
+      strand,
+      manifest,
+      open: None,
+    };
+    if let Some(segment) = archive
+      .manifest
+      .segments
+      .last()
+      .filter(|s| s.checksum.is_none())
+    {
+      let path = archive.dir.join(segment_file(segment.start));
+      let mut file = OpenOptions::new().read(true).write(true).open(&path)?;
+      let mut bytes = Vec::new();
+      file.read_to_end(&mut bytes)?;
+      let ParsedCar { frames, len, .. } = parse_car(&bytes)?;
+      if len < bytes.len() as u64 {
+        log::warn!("Ignoring incomplete block at the end of {}", path.display());
+        file.set_len(len)?;
+      }
+      let last = frames
+        .last()
+        .ok_or_else(|| invalid_data(format!("Empty segment {}", path.display())))?;
+      let segment = archive.manifest.segments.last_mut().unwrap();
+      segment.end = segment.start + frames.len() as u64 - 1;
+      segment.root = last.cid;
+      segment.offsets = frames
+        .iter()
+        .map(|frame| frame.offset)
+        .chain(std::iter::once(len))
+        .collect();
+      segment.verified = true;
+      let cids: Vec<Cid> = frames.iter().map(|frame| frame.cid).collect();
+      let full = (segment.end + 1) % archive.manifest.segment_size == 0;
+      archive.open = Some(OpenSegment { file, cids });
+      if full {
+        // interrupted while closing
+        archive.close()?;
+      }
+    }
+    Ok(archive)
+  }
+
+  fn write_manifest(&self) -> io::Result<()> {
+    let bytes = twine_lib::serde_ipld_dagjson::to_vec(&self.manifest).map_err(invalid_data)?;
+    write_atomic(&self.dir.join(MANIFEST_FILE), &bytes)
+  }
+
+  fn latest_index(&self) -> Option<u64> {
+    self.manifest.segments.last().map(|s| s.end)
+  }
+
+  /// The index of a tixel in the open segment
+  fn find_open(&self, cid: &Cid) -> Option<u64> {
+    let open = self.open.as_ref()?;
+    let pos = open.cids.iter().position(|c| c == cid)?;
+    Some(self.manifest.segments.last()?.start + pos as u64)
+  }
+
+  fn find_segment(&self, index: u64) -> Option<&Segment> {
+    let pos = self
+      .manifest
+      .segments
+      .partition_point(|s| s.start <= index)
+      .checked_sub(1)?;
+    Some(&self.manifest.segments[pos]).filter(|s| index <= s.end)
+  }
+
+  fn append(&mut self, tixel: &Tixel) -> io::Result<()> {
+    let index = tixel.index();
+    if self.open.is_none() {
+      let path = self.dir.join(segment_file(index));
+      let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+      let header = CarHeader::new(1, vec![tixel.cid()]).encode_to_bytes();
+      file.write_all(&header)?;
+      self.open = Some(OpenSegment { file, cids: vec![] });
+      self.manifest.segments.push(Segment {
+        start: index,
+        end: index,
+        root: tixel.cid(),
+        checksum: None,
+        offsets: vec![header.len() as u64],
+        verified: true,
+      });
+    }
+
+    let open = self.open.as_mut().unwrap();
+    let segment = self.manifest.segments.last_mut().unwrap();
+    let offset = *segment.offsets.last().unwrap();
+    let cid = tixel.cid();
+    let frame = block_frame(&cid, &tixel.bytes());
+    open.file.seek(SeekFrom::Start(offset))?;
+    if let Err(e) = open.file.write_all(&frame) {
+      // drop any partially written block
+      let _ = open.file.set_len(offset);
+      return Err(e);
+    }
+    open.cids.push(cid);
+    segment.offsets.push(offset + frame.len() as u64);
+    segment.end = index;
+    segment.root = cid;
+
+    if open.cids.len() == 1 {
+      self.write_manifest()?;
+    }
+    if (index + 1) % self.manifest.segment_size == 0 {
+      self.close()?;
+    }
+    Ok(())
+  }
+
+  fn close(&mut self) -> io::Result<()> {
+    let Some(mut open) = self.open.take() else {
+      return Ok(());
+    };
+    let segment = self.manifest.segments.last_mut().unwrap();
+    let header = CarHeader::new(1, vec![segment.root]).encode_to_bytes();
+    // tixels of a strand share a hasher, so their cids have the same length
+    if header.len() as u64 != segment.offsets[0] {
+      return Err(invalid_data("Segment root has an unexpected length"));
+    }
+    open.file.seek(SeekFrom::Start(0))?;
+    open.file.write_all(&header)?;
+    open.file.sync_all()?;
+    drop(open);
+
+    // this store wrote the file, so it doesn't need verifying again
+    let bytes = std::fs::read(self.dir.join(segment_file(segment.start)))?;
+    segment.checksum = Some(checksum(&bytes));
+    self.write_manifest()
+  }
+
+  fn remove_latest(&mut self, cid: &Cid) -> io::Result<bool> {
+    let Some(open) = self.open.as_mut() else {
+      return Ok(false);
+    };
+    if open.cids.last() != Some(cid) {
+      return Ok(false);
+    }
+    let segment = self.manifest.segments.last_mut().unwrap();
+    open.cids.pop();
+    segment.offsets.pop();
+    open.file.set_len(*segment.offsets.last().unwrap())?;
+    if let Some(last) = open.cids.last() {
+      segment.end -= 1;
+      segment.root = *last;
+      self.write_manifest()?;
+    } else {
+      // the manifest goes first, so it never lists a missing segment
+      let segment = self.manifest.segments.pop().unwrap();
+      self.open = None;
+      self.write_manifest()?;
+      std::fs::remove_file(self.dir.join(segment_file(segment.start)))?;
+    }
+    Ok(true)
+  }
+}
+
+/// Read a closed segment, returning the cids of its tixels and the
+/// offsets of its blocks followed by the file length
+///
+/// The file is checked against the checksum in its manifest entry,
+/// unless it was already verified.
+fn read_segment(path: &Path, segment: &Segment) -> io::Result<(Vec<Cid>, Vec<u64>)> {
+  let bytes = std::fs::read(path)?;
+  if !segment.verified && segment.checksum.as_deref() != Some(checksum(&bytes).as_str()) {
+    return Err(invalid_data(format!(
+      "Checksum mismatch for segment {}",
+      path.display()
+    )));
+  }
+  let ParsedCar {
+    header,
+    frames,
+    len,
+    ..
+  } = parse_car(&bytes)?;
+  if len != bytes.len() as u64
+    || header.map(|h| h.roots) != Some(vec![segment.root])
+    || frames.len() as u64 != segment.end - segment.start + 1
+  {
+    return Err(invalid_data(format!("Corrupt segment {}", path.display())));
+  }
+  let offsets = frames
+    .iter()
+    .map(|frame| frame.offset)
+    .chain(std::iter::once(len))
+    .collect();
+  Ok((frames.into_iter().map(|frame| frame.cid).collect(), offsets))
+}
+
+/// Read the blocks between two offsets with one read
+fn read_blocks(path: &Path, from: u64, to: u64) -> io::Result<Vec<(Cid, Vec<u8>)>> {
+  let mut buf = vec![0; (to - from) as usize];
+  let mut file = File::open(path)?;
+  file.seek(SeekFrom::Start(from))?;
+  file.read_exact(&mut buf)?;
+  let (frames, len) = parse_blocks(&buf, 0)?;
+  if len != buf.len() as u64 {
+    return Err(invalid_data(format!("Corrupt segment {}", path.display())));
+  }
+  Ok(
+    frames
+      .into_iter()
+      .map(|frame| (frame.cid, frame.data))
+      .collect(),
+  )
+}
+
+type SharedArchive = Arc<AsyncMutex<StrandArchive>>;
+
+/// A store that archives each strand as a series of CAR files
+///
+/// Meant for long strands like beacons. Each strand is split into
+/// segments of up to `segment_size` tixels, and range reads only open
+/// the segments they need. See the [module docs](self) for the layout.
+///
+/// Tixels must be saved in order: after the first tixel of a strand,
+/// each saved tixel must be the next index. Closed segments are
+/// immutable, so only strands and the latest tixel of the open segment
+/// can be deleted, and deleting any other tixel is an error. Since
+/// that means searching the closed segments for it, deleting a tixel
+/// that isn't stored reads every segment. Fetching a tixel by cid searches the open segment, and
+/// otherwise reads the closed segments (newest first) until the tixel
+/// is found, so prefer fetching by index.
+#[derive(Debug, Clone)]
+pub struct ArchiveStore {
+  root: PathBuf,
+  options: ArchiveStoreOptions,
+  strands: Arc<Mutex<HashMap<Cid, SharedArchive>>>,
+}
+
+impl ArchiveStore {
+  /// Open (or create) an archive in the given directory
+  pub fn open<P: AsRef<Path>>(root: P, options: ArchiveStoreOptions) -> io::Result<Self> {
+    let root = root.as_ref().to_path_buf();
+    std::fs::create_dir_all(&root)?;
+    let mut strands = HashMap::new();
+    for dir in std::fs::read_dir(&root)? {
+      let dir = dir?.path();
+      if !dir.join(MANIFEST_FILE).is_file() {
+        continue;
+      }
+      let archive = StrandArchive::load(dir)?;
+      strands.insert(archive.strand.cid(), Arc::new(AsyncMutex::new(archive)));
+    }
+    Ok(Self {
+      root,
+      options,
+      strands: Arc::new(Mutex::new(strands)),
+    })
+  }
+
+  /// Get the options for the store
+  pub fn options(&self) -> &ArchiveStoreOptions {
+    &self.options
+  }
+
+  fn archive(&self, strand: &Cid) -> Option<SharedArchive> {
+    self.strands.lock().unwrap().get(strand).cloned()
+  }
+
+  fn archives(&self) -> Vec<SharedArchive> {
+    self.strands.lock().unwrap().values().cloned().collect()
+  }
+
+  /// Read the closed segment starting at `start`, verifying it if it
+  /// isn't already, and return the cids of its tixels
+  async fn scan(archive: &AsyncMutex<StrandArchive>, start: u64) -> io::Result<Vec<Cid>> {
+    let (path, segment) = {
+      let archive = archive.lock().await;
+      let Some(segment) = archive.find_segment(start).filter(|s| s.start == start) else {
+        return Ok(vec![]);
+      };
+      (archive.dir.join(segment_file(start)), segment.clone())
+    };
+    // outside of the lock, since it reads the whole segment
+    let (cids, offsets) = read_segment(&path, &segment)?;
+    if !segment.verified {
+      let mut archive = archive.lock().await;
+      if let Some(segment) = archive
+        .manifest
+        .segments
+        .iter_mut()
+        .find(|s| s.start == start)
+      {
+        segment.offsets = offsets;
+        segment.verified = true;
+      }
+    }
+    Ok(cids)
+  }
+
+  /// Find the segment file and byte range for `lower..=upper`, which
+  /// must be in one segment
+  async fn locate(
+    archive: &AsyncMutex<StrandArchive>,
+    lower: u64,
+    upper: u64,
+  ) -> io::Result<Option<(PathBuf, u64, u64)>> {
+    loop {
+      let start = {
+        let archive = archive.lock().await;
+        let Some(segment) = archive.find_segment(lower).filter(|s| upper <= s.end) else {
+          return Ok(None);
+        };
+        if segment.verified {
+          let (from, to) = segment.byte_range(lower, upper);
+          return Ok(Some((
+            archive.dir.join(segment_file(segment.start)),
+            from,
+            to,
+          )));
+        }
+        segment.start
+      };
+      Self::scan(archive, start).await?;
+    }
+  }
+
+  /// Find the index of a tixel, reading closed segments (newest first)
+  /// until it turns up
+  async fn find_index(archive: &AsyncMutex<StrandArchive>, cid: &Cid) -> io::Result<Option<u64>> {
+    let closed: Vec<u64> = {
+      let archive = archive.lock().await;
+      if let Some(index) = archive.find_open(cid) {
+        return Ok(Some(index));
+      }
+      let open = archive.open.is_some() as usize;
+      let segments = &archive.manifest.segments;
+      segments[..segments.len() - open]
+        .iter()
+        .rev()
+        .map(|s| s.start)
+        .collect()
+    };
+    for start in closed {
+      let cids = Self::scan(archive, start).await?;
+      if let Some(pos) = cids.iter().position(|c| c == cid) {
+        return Ok(Some(start + pos as u64));
+      }
+    }
+    Ok(None)
+  }
+
+  async fn read_range(
+    &self,
+    strand: &Cid,
+    lower: u64,
+    upper: u64,
+  ) -> io::Result<Option<Vec<Tixel>>> {
+    let Some(archive) = self.archive(strand) else {
+      return Ok(None);
+    };
+    let Some((path, from, to)) = Self::locate(&archive, lower, upper).await? else {
+      return Ok(None);
+    };
+    let blocks = read_blocks(&path, from, to)?;
+    if blocks.len() as u64 != upper - lower + 1 {
+      return Err(invalid_data(format!("Corrupt segment {}", path.display())));
+    }
+    blocks
+      .into_iter()
+      .map(|(cid, bytes)| Tixel::from_block(cid, bytes).map_err(invalid_data))
+      .collect::<io::Result<Vec<_>>>()
+      .map(Some)
+  }
+
+  /// Split a range into pieces that each fall in a single segment
+  async fn pieces(&self, range: AbsoluteRange) -> Vec<(u64, u64)> {
+    let size = match self.archive(&range.strand) {
+      Some(archive) => archive.lock().await.manifest.segment_size,
+      None => return vec![(range.lower(), range.upper())],
+    };
+    let mut pieces = Vec::new();
+    for batch in range.batches(self.options.buffer_size as u64) {
+      let mut batch_pieces = Vec::new();
+      let mut lower = batch.lower();
+      while lower <= batch.upper() {
+        let upper = (lower - lower % size + size - 1).min(batch.upper());
+        batch_pieces.push((lower, upper));
+        lower = upper + 1;
+      }
+      if range.is_decreasing() {
+        batch_pieces.reverse();
+      }
+      pieces.extend(batch_pieces);
+    }
+    pieces
+  }
+
+  fn save_strand(&self, strand: Strand) -> io::Result<()> {
+    if self.archive(&strand.cid()).is_some() {
+      return Ok(());
+    }
+    let dir = self.root.join(strand.cid().to_string());
+    let archive = StrandArchive::create(dir, strand, self.options.segment_size)?;
+    self
+      .strands
+      .lock()
+      .unwrap()
+      .entry(archive.strand.cid())
+      .or_insert_with(|| Arc::new(AsyncMutex::new(archive)));
+    Ok(())
+  }
+
+  async fn save_tixel(&self, tixel: &Tixel) -> io::Result<()> {
+    let strand = tixel.strand_cid();
+    let index = tixel.index();
+    let archive = self
+      .archive(&strand)
+      .ok_or_else(|| io::Error::other(format!("Strand {} not saved yet", strand)))?;
+    {
+      let mut archive = archive.lock().await;
+      match archive.latest_index() {
+        Some(latest) if index <= latest => {}
+        Some(latest) if index > latest + 1 => {
+          return Err(io::Error::other(format!(
+            "Tixel {} of strand {} is out of order, expected index {}",
+            index,
+            strand,
+            latest + 1
+          )));
+        }
+        _ => return archive.append(tixel),
+      }
+    }
+    // already archived, as long as it's the same tixel
+    match self.read_range(&strand, index, index).await? {
+      Some(existing) if existing[0].cid() == tixel.cid() => Ok(()),
+      Some(_) => Err(io::Error::other(format!(
+        "A different tixel is already archived at index {} of strand {}",
+        index, strand
+      ))),
+      None => Err(io::Error::other(format!(
+        "Tixel {} of strand {} comes before the start of the archive",
+        index, strand
+      ))),
+    }
+  }
+}
+
+#[async_trait]
+impl BaseResolver for ArchiveStore {
+  async fn fetch_strands(
+    &self,
+  ) -> Result<
+    Pin<Box<dyn Stream<Item = Result<Strand, ResolutionError>> + Send + '_>>,
+    ResolutionError,
+  > {
+    let strands: Vec<Strand> = futures::stream::iter(self.archives())
+      .then(|archive| async move { archive.lock().await.strand.clone() })
+      .collect()
+      .await;
+    Ok(futures::stream::iter(strands).map(Ok).boxed())
+  }
+
+  async fn has_strand(&self, cid: &Cid) -> Result<bool, ResolutionError> {
+    Ok(self.archive(cid).is_some())
+  }
+
+  async fn has_index(&self, strand: &Cid, index: u64) -> Result<bool, ResolutionError> {
+    let Some(archive) = self.archive(strand) else {
+      return Ok(false);
+    };
+    let has = archive.lock().await.find_segment(index).is_some();
+    Ok(has)
+  }
+
+  async fn has_twine(&self, strand: &Cid, cid: &Cid) -> Result<bool, ResolutionError> {
+    if strand == cid {
+      return self.has_strand(strand).await;
+    }
+    match self.fetch_tixel(strand, cid).await {
+      Ok(_) => Ok(true),
+      Err(ResolutionError::NotFound) => Ok(false),
+      Err(e) => Err(e),
+    }
+  }
+
+  async fn fetch_strand(&self, strand: &Cid) -> Result<Strand, ResolutionError> {
+    let archive = self.archive(strand).ok_or(ResolutionError::NotFound)?;
+    let strand = archive.lock().await.strand.clone();
+    Ok(strand)
+  }
+
+  async fn fetch_tixel(&self, strand: &Cid, tixel: &Cid) -> Result<Tixel, ResolutionError> {
+    let archive = self.archive(strand).ok_or(ResolutionError::NotFound)?;
+    let index = Self::find_index(&archive, tixel)
+      .await
+      .map_err(fetch_error)?
+      .ok_or(ResolutionError::NotFound)?;
+    let found = self.fetch_index(strand, index).await?;
+    if found.cid() != *tixel {
+      return Err(ResolutionError::NotFound);
+    }
+    Ok(found)
+  }
+
+  async fn fetch_index(&self, strand: &Cid, index: u64) -> Result<Tixel, ResolutionError> {
+    self
+      .read_range(strand, index, index)
+      .await
+      .map_err(fetch_error)?
+      .and_then(|mut tixels| tixels.pop())
+      .ok_or(ResolutionError::NotFound)
+  }
+
+  async fn fetch_latest(&self, strand: &Cid) -> Result<Tixel, ResolutionError> {
+    let archive = self.archive(strand).ok_or(ResolutionError::NotFound)?;
+    let latest = archive.lock().await.latest_index();
+    self
+      .fetch_index(strand, latest.ok_or(ResolutionError::NotFound)?)
+      .await
+  }
+
+  async fn range_stream(
+    &self,
+    range: AbsoluteRange,
+  ) -> Result<
+    Pin<Box<dyn Stream<Item = Result<Tixel, ResolutionError>> + Send + '_>>,
+    ResolutionError,
+  > {
+    let strand = range.strand;
+    let decreasing = range.is_decreasing();
+    let stream = futures::stream::iter(self.pieces(range).await)
+      .then(move |(lower, upper)| async move {
+        let mut tixels = self
+          .read_range(&strand, lower, upper)
+          .await
+          .map_err(fetch_error)?
+          .ok_or(ResolutionError::NotFound)?;
+        if decreasing {
+          tixels.reverse();
+        }
+        Ok::<_, ResolutionError>(futures::stream::iter(tixels.into_iter().map(Ok)))
+      })
+      .try_flatten();
+    Ok(stream.boxed())
+  }
+}
+
+impl Resolver for ArchiveStore {}
+
+#[async_trait]
+impl Store for ArchiveStore {
+  async fn save<T: Into<AnyTwine> + Send>(&self, twine: T) -> Result<(), StoreError> {
+    match twine.into() {
+      AnyTwine::Strand(strand) => self.save_strand(strand).map_err(saving_error),
+      AnyTwine::Tixel(tixel) => self.save_tixel(&tixel).await.map_err(saving_error),
+    }
+  }
+
+  async fn save_many<
+    I: Into<AnyTwine> + Send,
+    S: Iterator<Item = I> + Send,
+    T: IntoIterator<Item = I, IntoIter = S> + Send,
+  >(
+    &self,
+    twines: T,
+  ) -> Result<(), StoreError> {
+    for twine in twines {
+      self.save(twine).await?;
+    }
+    Ok(())
+  }
+
+  async fn save_stream<I: Into<AnyTwine> + Send, T: Stream<Item = I> + Send + Unpin>(
+    &self,
+    twines: T,
+  ) -> Result<(), StoreError> {
+    twines
+      .chunks(self.options.buffer_size)
+      .then(|chunk| self.save_many(chunk))
+      .try_for_each(|_| async { Ok(()) })
+      .await?;
+    Ok(())
+  }
+
+  async fn delete<C: AsCid + Send>(&self, cid: C) -> Result<(), StoreError> {
+    let cid = *cid.as_cid();
+    let removed = self.strands.lock().unwrap().remove(&cid);
+    if let Some(archive) = removed {
+      // wait for any writes to finish
+      let archive = archive.lock().await;
+      return std::fs::remove_dir_all(&archive.dir).map_err(saving_error);
+    }
+    let cannot_delete = || {
+      StoreError::Saving(format!(
+        "Cannot delete {}: only strands and the latest tixel of an open segment can be deleted",
+        cid
+      ))
+    };
+    // only the open segments can change, so they are searched first
+    let archives = self.archives();
+    for archive in archives.iter() {
+      let mut archive = archive.lock().await;
+      if archive.remove_latest(&cid).map_err(saving_error)? {
+        return Ok(());
+      }
+      if archive.find_open(&cid).is_some() {
+        return Err(cannot_delete());
+      }
+    }
+    for archive in archives.iter() {
+      if Self::find_index(archive, &cid)
+        .await
+        .map_err(saving_error)?
+        .is_some()
+      {
+        return Err(cannot_delete());
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::test::chain;
+
+  fn small_segments() -> ArchiveStoreOptions {
+    // rounded down to 8 for radix 2
+    ArchiveStoreOptions::default()
+      .segment_size(10)
+      .buffer_size(5)
+  }
+
+  async fn manifest(store: &ArchiveStore, strand: &Strand) -> Manifest {
+    let archive = store.archive(&strand.cid()).unwrap();
+    let manifest = archive.lock().await.manifest.clone();
+    manifest
+  }
+
+  #[test]
+  fn test_aligned_segment_size() {
+    assert_eq!(aligned_segment_size(100_000, 10), 100_000);
+    assert_eq!(aligned_segment_size(100_000, 2), 65_536);
+    assert_eq!(aligned_segment_size(100_000, 32), 32_768);
+    assert_eq!(aligned_segment_size(5, 10), 5);
+    assert_eq!(aligned_segment_size(100, 0), 100);
+  }
+
+  #[tokio::test]
+  async fn test_segments() {
+    let (strand, tixels) = chain(30);
+    let dir = tempfile::tempdir().unwrap();
+    {
+      let store = ArchiveStore::open(dir.path(), small_segments()).unwrap();
+      store.save(strand.clone()).await.unwrap();
+      store
+        .save_stream(futures::stream::iter(tixels.clone()))
+        .await
+        .unwrap();
+
+      let manifest = manifest(&store, &strand).await;
+      assert_eq!(manifest.segment_size, 8);
+      let ranges: Vec<_> = manifest.segments.iter().map(|s| (s.start, s.end)).collect();
+      assert_eq!(ranges, vec![(0, 7), (8, 15), (16, 23), (24, 29)]);
+      for segment in &manifest.segments[..3] {
+        assert!(segment.checksum.is_some());
+        assert_eq!(segment.root, tixels[segment.end as usize].cid());
+        let path = dir
+          .path()
+          .join(strand.cid().to_string())
+          .join(segment_file(segment.start));
+        let bytes = std::fs::read(path).unwrap();
+        let ParsedCar {
+          header,
+          frames,
+          len,
+          ..
+        } = parse_car(&bytes).unwrap();
+        assert_eq!(header.unwrap().roots, vec![segment.root]);
+        let offsets: Vec<u64> = frames.iter().map(|f| f.offset).chain([len]).collect();
+        assert_eq!(segment.offsets, offsets);
+      }
+      assert!(manifest.segments[3].checksum.is_none());
+      // only the segment bounds are written to the manifest
+      let json = std::fs::read_to_string(
+        dir
+          .path()
+          .join(strand.cid().to_string())
+          .join(MANIFEST_FILE),
+      )
+      .unwrap();
+      assert!(!json.contains("offsets"));
+    }
+
+    let store = ArchiveStore::open(dir.path(), small_segments()).unwrap();
+    assert_eq!(manifest(&store, &strand).await.segments[3].end, 29);
+    assert_eq!(
+      store.resolve_latest(&strand).await.unwrap().unpack(),
+      tixels[29]
+    );
+    let all: Vec<Tixel> = store
+      .range_stream(AbsoluteRange::new(strand.cid(), 0, 29))
+      .await
+      .unwrap()
+      .try_collect()
+      .await
+      .unwrap();
+    assert_eq!(all.len(), 30);
+    assert!(all.iter().zip(tixels.iter()).all(|(a, b)| a == b));
+
+    let down: Vec<u64> = store
+      .range_stream(AbsoluteRange::new(strand.cid(), 20, 2))
+      .await
+      .unwrap()
+      .map_ok(|t| t.index())
+      .try_collect()
+      .await
+      .unwrap();
+    assert_eq!(down, (2..=20).rev().collect::<Vec<_>>());
+
+    // looking up cids verifies segments newest first
+    for i in [3, 27, 12] {
+      let old = store
+        .fetch_tixel(&strand.cid(), &tixels[i].cid())
+        .await
+        .unwrap();
+      assert_eq!(old, tixels[i]);
+    }
+    let (other, _) = chain(1);
+    assert!(!store.has_twine(&strand.cid(), &other.cid()).await.unwrap());
+    assert!(store.has_index(&strand.cid(), 29).await.unwrap());
+    assert!(!store.has_index(&strand.cid(), 30).await.unwrap());
+  }
+
+  #[tokio::test]
+  async fn test_sequential_saves() {
+    let (strand, tixels) = chain(5);
+    let dir = tempfile::tempdir().unwrap();
+    let store = ArchiveStore::open(dir.path(), small_segments()).unwrap();
+    assert!(store.save(tixels[0].clone()).await.is_err());
+    store.save(strand.clone()).await.unwrap();
+    store.save_many(tixels[..2].to_vec()).await.unwrap();
+    assert!(store.save(tixels[3].clone()).await.is_err());
+    // saving again is fine
+    store.save(tixels[0].clone()).await.unwrap();
+    store.save_many(tixels[2..].to_vec()).await.unwrap();
+    assert_eq!(
+      store.resolve_latest(&strand).await.unwrap().unpack(),
+      tixels[4]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_checksum_verified() {
+    let (strand, tixels) = chain(10);
+    let dir = tempfile::tempdir().unwrap();
+    {
+      let store = ArchiveStore::open(dir.path(), small_segments()).unwrap();
+      store.save(strand.clone()).await.unwrap();
+      store.save_many(tixels.clone()).await.unwrap();
+    }
+    let path = dir
+      .path()
+      .join(strand.cid().to_string())
+      .join(segment_file(0));
+    let mut bytes = std::fs::read(&path).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    std::fs::write(&path, bytes).unwrap();
+
+    let store = ArchiveStore::open(dir.path(), small_segments()).unwrap();
+    assert!(matches!(
+      store.fetch_index(&strand.cid(), 3).await,
+      Err(ResolutionError::BadData(_))
+    ));
+    // the open segment is still readable
+    assert_eq!(
+      store.fetch_index(&strand.cid(), 9).await.unwrap(),
+      tixels[9]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_delete() {
+    let (strand, tixels) = chain(10);
+    let dir = tempfile::tempdir().unwrap();
+    let store = ArchiveStore::open(dir.path(), small_segments()).unwrap();
+    store.save(strand.clone()).await.unwrap();
+    store.save_many(tixels.clone()).await.unwrap();
+
+    // closed segments are immutable
+    assert!(store.delete(tixels[3].cid()).await.is_err());
+    assert_eq!(
+      store.fetch_index(&strand.cid(), 3).await.unwrap(),
+      tixels[3]
+    );
+    // only the latest tixel of the open segment can be deleted
+    assert!(store.delete(tixels[8].cid()).await.is_err());
+    // deleting something that isn't stored is fine
+    let (other, _) = chain(1);
+    store.delete(other.cid()).await.unwrap();
+    store.delete(tixels[9].cid()).await.unwrap();
+    assert_eq!(
+      store.resolve_latest(&strand).await.unwrap().unpack(),
+      tixels[8]
+    );
+    // emptying the open segment removes it
+    store.delete(tixels[8].cid()).await.unwrap();
+    assert_eq!(manifest(&store, &strand).await.segments.len(), 1);
+    assert_eq!(
+      store.resolve_latest(&strand).await.unwrap().unpack(),
+      tixels[7]
+    );
+    store.save(tixels[8].clone()).await.unwrap();
+    assert_eq!(
+      store.resolve_latest(&strand).await.unwrap().unpack(),
+      tixels[8]
+    );
+
+    store.delete(strand.cid()).await.unwrap();
+    assert!(store.resolve_strand(&strand).await.is_err());
+    assert!(!dir.path().join(strand.cid().to_string()).exists());
+    let store = ArchiveStore::open(dir.path(), small_segments()).unwrap();
+    assert!(!store.has_strand(&strand.cid()).await.unwrap());
+  }
+}
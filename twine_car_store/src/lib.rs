@@ -1,129 +1,574 @@
 #![doc = include_str!("../README.md")]
 
+use async_std::sync::Mutex;
 use async_trait::async_trait;
 use futures::stream::Stream;
 use futures::StreamExt;
 use futures::TryStreamExt;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::Path;
 use std::path::PathBuf;
 use std::pin::Pin;
+use std::sync::{mpsc, Arc, Weak};
+use std::time::Duration;
+use twine_lib::car::CarHeader;
 use twine_lib::resolver::RangeQuery;
 use twine_lib::resolver::{unchecked_base::BaseResolver, AbsoluteRange, Resolver};
 use twine_lib::store::MemoryStore;
-use twine_lib::{as_cid::AsCid, errors::*, store::Store, twine::*, Cid};
+use twine_lib::{as_cid::AsCid, errors::*, store::Store, twine::*, Cid, Ipld};
 
-/// A store that saves twines to a single file in CARv1 format
+pub mod archive;
+pub use archive::{ArchiveStore, ArchiveStoreOptions};
+
+// Max size of u64 varint
+const U64_LEN: usize = 10;
+
+// The header is padded to a multiple of this, so it can be rewritten in
+// place as the roots change
+const HEADER_SIZE: u64 = 4096;
+
+fn saving_error(e: io::Error) -> StoreError {
+  StoreError::Saving(e.to_string())
+}
+
+fn encode_varint(mut n: u64, out: &mut Vec<u8>) {
+  loop {
+    let b = n as u8 & 0b0111_1111;
+    n >>= 7;
+    if n == 0 {
+      out.push(b);
+      return;
+    }
+    out.push(b | 0b1000_0000);
+  }
+}
+
+/// Returns the value and the number of bytes read, or None if the
+/// varint is incomplete
+fn decode_varint(bytes: &[u8]) -> Option<(u64, usize)> {
+  let mut n = 0u64;
+  for (i, b) in bytes.iter().take(U64_LEN).enumerate() {
+    n |= ((b & 0b0111_1111) as u64) << (7 * i);
+    if b & 0b1000_0000 == 0 {
+      return Some((n, i + 1));
+    }
+  }
+  None
+}
+
+fn block_frame(cid: &Cid, data: &[u8]) -> Vec<u8> {
+  let cid_bytes = cid.to_bytes();
+  let mut frame = Vec::with_capacity(U64_LEN + cid_bytes.len() + data.len());
+  encode_varint((cid_bytes.len() + data.len()) as u64, &mut frame);
+  frame.extend_from_slice(&cid_bytes);
+  frame.extend_from_slice(data);
+  frame
+}
+
+/// Encode a CAR header padded to exactly `size` bytes
 ///
-/// The store is completely loaded into memory and then
-/// flushed to disk whenever a [`Store`] operation is called.
-#[derive(Debug, Clone)]
-pub struct CarStore {
+/// The padding is an extra `pad` field of zero bytes, which CAR readers
+/// ignore. Returns None if the roots don't fit.
+fn padded_header(roots: &[Cid], size: u64) -> Option<Vec<u8>> {
+  let encode = |pad: usize| {
+    let header = Ipld::Map(
+      [
+        ("version".to_string(), Ipld::Integer(1)),
+        (
+          "roots".to_string(),
+          Ipld::List(roots.iter().copied().map(Ipld::Link).collect()),
+        ),
+        ("pad".to_string(), Ipld::Bytes(vec![0; pad])),
+      ]
+      .into(),
+    );
+    let cbor = twine_lib::serde_ipld_dagcbor::to_vec(&header).expect("header should encode");
+    let mut bytes = Vec::with_capacity(U64_LEN + cbor.len());
+    encode_varint(cbor.len() as u64, &mut bytes);
+    bytes.extend_from_slice(&cbor);
+    bytes
+  };
+  // the padding's length prefix grows at some sizes, so a few sizes
+  // can't be hit exactly
+  let mut pad = 0;
+  for _ in 0..3 {
+    let bytes = encode(pad);
+    if bytes.len() as u64 == size {
+      return Some(bytes);
+    }
+    pad = (pad as u64 + size).checked_sub(bytes.len() as u64)? as usize;
+  }
+  None
+}
+
+/// The smallest padded header that holds the roots
+fn sized_header(roots: &[Cid]) -> (Vec<u8>, u64) {
+  let mut size = HEADER_SIZE;
+  loop {
+    if let Some(header) = padded_header(roots, size) {
+      return (header, size);
+    }
+    size *= 2;
+  }
+}
+
+fn invalid_data<E: ToString>(e: E) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+/// A block read back from the CAR file
+struct Frame {
+  offset: u64,
+  cid: Cid,
+  data: Vec<u8>,
+}
+
+/// A CARv1 file read back from disk
+struct ParsedCar {
+  // None if the header could not be decoded
+  header: Option<CarHeader>,
+  // length of the header, including its length prefix
+  header_len: u64,
+  frames: Vec<Frame>,
+  // length of the valid prefix of the file
+  len: u64,
+}
+
+/// Parse a CARv1 file
+///
+/// A block cut short at the end of the file (eg: by a crash during an
+/// append) is ignored, so the caller can truncate it away. The length
+/// prefix of the header never changes when it is rewritten in place,
+/// so if the header itself is unreadable (eg: by a crash while it was
+/// rewritten) the blocks are still found.
+fn parse_car(bytes: &[u8]) -> io::Result<ParsedCar> {
+  let (header_len, n) = decode_varint(bytes).ok_or_else(|| invalid_data("Bad CAR header"))?;
+  let start = n + header_len as usize;
+  let header_bytes = bytes
+    .get(n..start)
+    .ok_or_else(|| invalid_data("Bad CAR header"))?;
+  let header = twine_lib::serde_ipld_dagcbor::from_slice::<CarHeader>(header_bytes).ok();
+  if let Some(header) = &header {
+    if header.version != 1 {
+      return Err(invalid_data(format!(
+        "Unsupported CAR version {}",
+        header.version
+      )));
+    }
+  }
+  let (frames, len) = parse_blocks(bytes, start)?;
+  Ok(ParsedCar {
+    header,
+    header_len: start as u64,
+    frames,
+    len,
+  })
+}
+
+/// Parse the blocks from `pos` onwards, returning them and the end of
+/// the last complete block
+fn parse_blocks(bytes: &[u8], mut pos: usize) -> io::Result<(Vec<Frame>, u64)> {
+  let mut frames = Vec::new();
+  while pos < bytes.len() {
+    let Some((len, n)) = decode_varint(&bytes[pos..]) else {
+      break;
+    };
+    let end = (len as usize).checked_add(pos + n);
+    let Some(block) = end.and_then(|end| bytes.get(pos + n..end)) else {
+      break;
+    };
+    let mut cursor = io::Cursor::new(block);
+    let cid = Cid::read_bytes(&mut cursor).map_err(invalid_data)?;
+    let data = block[cursor.position() as usize..].to_vec();
+    frames.push(Frame {
+      offset: pos as u64,
+      cid,
+      data,
+    });
+    pos += n + len as usize;
+  }
+  Ok((frames, pos as u64))
+}
+
+/// Options for the CarStore
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CarStoreOptions {
+  flush_interval: Option<Duration>,
+}
+
+impl CarStoreOptions {
+  /// Flush the store from a background thread at the given interval
+  ///
+  /// By default the store is only flushed when [`CarStore::flush`] is
+  /// called.
+  pub fn flush_interval(mut self, interval: Duration) -> Self {
+    self.flush_interval = Some(interval);
+    self
+  }
+}
+
+#[derive(Debug)]
+struct CarFile {
+  file: File,
+  len: u64,
+  // length of the padded CAR header on disk, including its length prefix
+  header_len: u64,
+  // the header roots on disk are out of date
+  dirty: bool,
+  // blocks were deleted, so the next flush rewrites the file
+  deleted: bool,
+}
+
+impl CarFile {
+  fn append(&mut self, frame: &[u8]) -> io::Result<()> {
+    let offset = self.len;
+    let res = self
+      .file
+      .seek(SeekFrom::Start(offset))
+      .and_then(|_| self.file.write_all(frame));
+    if let Err(e) = res {
+      // drop any partially written block
+      let _ = self.file.set_len(offset);
+      return Err(e);
+    }
+    self.len += frame.len() as u64;
+    Ok(())
+  }
+
+  /// Drop everything after `len`
+  fn truncate(&mut self, len: u64) -> io::Result<()> {
+    self.file.set_len(len)?;
+    self.len = len;
+    Ok(())
+  }
+
+  /// Rewrite the header in place, returning false if the roots don't
+  /// fit in the space reserved for it
+  ///
+  /// The header keeps the same length, so the blocks never move. If the
+  /// write is torn by a crash, the roots are recomputed on the next open.
+  fn write_header(&mut self, roots: &[Cid]) -> io::Result<bool> {
+    let Some(header) = padded_header(roots, self.header_len) else {
+      return Ok(false);
+    };
+    // the blocks must be on disk before the header points to them
+    self.file.sync_data()?;
+    self.file.seek(SeekFrom::Start(0))?;
+    self.file.write_all(&header)?;
+    self.file.sync_data()?;
+    Ok(true)
+  }
+}
+
+#[derive(Debug)]
+struct Inner {
   memstore: MemoryStore,
   filename: PathBuf,
+  file: Mutex<CarFile>,
+  // dropping this stops the background flush thread
+  _stop: Option<mpsc::Sender<()>>,
 }
 
-impl Drop for CarStore {
+impl Drop for Inner {
   fn drop(&mut self) {
-    if let Err(e) = async_std::task::block_on(self.flush()) {
-      eprintln!("Error flushing store: {:?}", e);
+    // flushing may compact the whole file, which is too much to do here
+    let file = self.file.get_mut();
+    if file.deleted {
+      log::warn!(
+        "{} dropped without a flush; deleted twines will be loaded again",
+        self.filename.display()
+      );
+    } else if file.dirty {
+      log::warn!(
+        "{} dropped without a flush; its CAR roots are out of date",
+        self.filename.display()
+      );
     }
   }
 }
 
+/// Point the header at the current roots, rewriting the whole file
+/// if blocks were deleted or the roots outgrew the header
+async fn flush_file(
+  memstore: &MemoryStore,
+  filename: &Path,
+  file: &mut CarFile,
+) -> Result<(), StoreError> {
+  if !file.dirty {
+    return Ok(());
+  }
+  let roots = roots(memstore).await?;
+  if !file.deleted && file.write_header(&roots).map_err(saving_error)? {
+    file.dirty = false;
+    return Ok(());
+  }
+  compact_file(memstore, filename, file, roots).await
+}
+
+/// Write a new file with only the current data and rename it into place
+async fn compact_file(
+  memstore: &MemoryStore,
+  filename: &Path,
+  file: &mut CarFile,
+  roots: Vec<Cid>,
+) -> Result<(), StoreError> {
+  let (header, header_len) = sized_header(&roots);
+
+  let mut tmp_name = OsString::from(filename.as_os_str());
+  tmp_name.push(".compact");
+  let tmp_name = PathBuf::from(tmp_name);
+  let mut out = BufWriter::new(File::create(&tmp_name).map_err(saving_error)?);
+  out.write_all(&header).map_err(saving_error)?;
+  let mut twines = all_twines(memstore).await?;
+  while let Some(twine) = twines.try_next().await? {
+    out
+      .write_all(&block_frame(&twine.cid(), &twine.bytes()))
+      .map_err(saving_error)?;
+  }
+  let out = out.into_inner().map_err(|e| saving_error(e.into_error()))?;
+  out.sync_all().map_err(saving_error)?;
+  drop(out);
+  std::fs::rename(&tmp_name, filename).map_err(saving_error)?;
+
+  file.file = OpenOptions::new()
+    .read(true)
+    .write(true)
+    .open(filename)
+    .map_err(saving_error)?;
+  file.len = file.file.metadata().map_err(saving_error)?.len();
+  file.header_len = header_len;
+  file.dirty = false;
+  file.deleted = false;
+  Ok(())
+}
+
+/// Strands and their latest tixels
+async fn roots(memstore: &MemoryStore) -> Result<Vec<Cid>, StoreError> {
+  let strands: Vec<Strand> = memstore.fetch_strands().await?.try_collect().await?;
+  let latests: Vec<Tixel> = futures::stream::iter(strands.iter())
+    .then(|s| async move {
+      let cid = s.cid();
+      memstore.fetch_latest(&cid).await
+    })
+    // filter out notfounds
+    .filter_map(|r| match r {
+      Ok(_) => futures::future::ready(Some(r)),
+      Err(e) => match e {
+        ResolutionError::NotFound => futures::future::ready(None),
+        _ => futures::future::ready(Some(Err(e))),
+      },
+    })
+    .try_collect()
+    .await?;
+  Ok(
+    strands
+      .iter()
+      .map(|s| s.cid())
+      .chain(latests.iter().map(|t| t.cid()))
+      .collect(),
+  )
+}
+
+async fn all_twines(
+  memstore: &MemoryStore,
+) -> Result<Pin<Box<dyn Stream<Item = Result<AnyTwine, ResolutionError>> + Send + '_>>, StoreError>
+{
+  let strands: Vec<Strand> = memstore.fetch_strands().await?.try_collect().await?;
+  let all_tixels = futures::stream::iter(strands.clone())
+    .filter_map(move |strand| async move {
+      let q = match RangeQuery::from((strand.cid(), ..))
+        .try_to_absolute(memstore)
+        .await
+      {
+        Ok(q) => q?,
+        // strands without tixels
+        Err(ResolutionError::NotFound) => return None,
+        Err(e) => return Some(Err(e)),
+      };
+      Some(memstore.range_stream(q).await)
+    })
+    .try_flatten()
+    .map_ok(AnyTwine::Tixel);
+  let strands = futures::stream::iter(strands).map(AnyTwine::Strand).map(Ok);
+  Ok(strands.chain(all_tixels).boxed())
+}
+
+/// A store that saves twines to a single file in CARv1 format
+///
+/// The store is completely loaded into memory. New blocks are appended
+/// to the file as they are saved, and [`CarStore::flush`] points the
+/// CAR header roots at the strands and their latest tixels. The header
+/// is padded to a fixed size so it can be rewritten in place. When
+/// blocks were deleted or the roots outgrow the header, flushing
+/// rewrites the whole file instead.
+///
+/// The store is not flushed when it is dropped, so call
+/// [`CarStore::flush`] (or set [`CarStoreOptions::flush_interval`])
+/// before dropping it. Saved twines are on disk either way, but
+/// unflushed deletions are lost.
+///
+/// Clones share the same file and data.
+#[derive(Debug, Clone)]
+pub struct CarStore {
+  inner: Arc<Inner>,
+}
+
 impl CarStore {
   /// Create a new store that saves to the given file
-  pub fn new<S: AsRef<Path>>(filename: S) -> Result<Self, StoreError> {
-    let s = Self {
-      memstore: MemoryStore::new(),
-      filename: filename.as_ref().to_path_buf(),
-    };
+  ///
+  /// See [`CarStore::open`]
+  pub fn new<S: AsRef<Path>>(filename: S) -> io::Result<Self> {
+    Self::open(filename, CarStoreOptions::default())
+  }
 
-    s.load()?;
+  /// Open (or create) the given file with options
+  ///
+  /// Files that are not valid CARs fail with [`io::ErrorKind::InvalidData`].
+  pub fn open<S: AsRef<Path>>(filename: S, options: CarStoreOptions) -> io::Result<Self> {
+    let filename = filename.as_ref().to_path_buf();
+    let memstore = MemoryStore::new();
+    let file = Self::load(&filename, &memstore)?;
 
-    Ok(s)
+    let (stop, stopped) = match options.flush_interval {
+      Some(_) => {
+        let (tx, rx) = mpsc::channel();
+        (Some(tx), Some(rx))
+      }
+      None => (None, None),
+    };
+    let inner = Arc::new(Inner {
+      memstore,
+      filename,
+      file: Mutex::new(file),
+      _stop: stop,
+    });
+    if let (Some(interval), Some(stopped)) = (options.flush_interval, stopped) {
+      spawn_flusher(Arc::downgrade(&inner), stopped, interval);
+    }
+    Ok(Self { inner })
   }
 
-  fn load(&self) -> Result<(), StoreError> {
-    // check the file isn't empty first
-    if let Ok(metadata) = std::fs::metadata(&self.filename) {
-      if metadata.len() == 0 {
-        return Ok(());
-      }
-    } else {
-      return Ok(());
+  fn load(filename: &Path, memstore: &MemoryStore) -> io::Result<CarFile> {
+    let mut file = OpenOptions::new()
+      .read(true)
+      .write(true)
+      .create(true)
+      .truncate(false)
+      .open(filename)?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+
+    if bytes.is_empty() {
+      let (header, header_len) = sized_header(&[]);
+      let mut car = CarFile {
+        file,
+        len: 0,
+        header_len,
+        dirty: false,
+        deleted: false,
+      };
+      car.append(&header)?;
+      return Ok(car);
     }
 
-    let file = std::fs::File::open(&self.filename)
-      .map_err(|e| StoreError::Fetching(ResolutionError::Fetch(e.to_string())))?;
-    let mut reader = std::io::BufReader::new(file);
-    let twines = twine_lib::car::from_car_bytes(&mut reader)
-      .map_err(|e| StoreError::Fetching(ResolutionError::BadData(e.to_string())))?;
+    let car = parse_car(&bytes)?;
+    if car.len < bytes.len() as u64 {
+      log::warn!(
+        "Ignoring incomplete block at the end of {}",
+        filename.display()
+      );
+      file.set_len(car.len)?;
+    }
+    let dirty = car.header.is_none();
+    if dirty {
+      log::warn!(
+        "Unreadable CAR header in {}, its roots will be rewritten on flush",
+        filename.display()
+      );
+    }
 
-    for twine in twines {
-      self.memstore.save_sync(twine.into())?;
+    for frame in car.frames {
+      let twine = AnyTwine::from_block(frame.cid, &frame.data).map_err(invalid_data)?;
+      memstore.save_sync(twine).map_err(invalid_data)?;
     }
 
-    Ok(())
+    Ok(CarFile {
+      file,
+      len: car.len,
+      header_len: car.header_len,
+      dirty,
+      deleted: false,
+    })
   }
 
-  /// Flush the store to disk
+  /// Update the CAR header roots on disk
+  ///
+  /// Saved blocks are appended to the file immediately, but the roots
+  /// are only updated by a flush. If blocks were deleted or the roots
+  /// no longer fit in the header, the file is compacted instead.
   pub async fn flush(&self) -> Result<(), StoreError> {
-    let strands: Vec<Strand> = self.memstore.fetch_strands().await?.try_collect().await?;
-    let latests: Vec<Tixel> = futures::stream::iter(strands.iter())
-      .then(|s| async move {
-        let cid = s.cid();
-        self.memstore.fetch_latest(&cid).await
-      })
-      // filter out notfounds
-      .filter_map(|r| match r {
-        Ok(_) => futures::future::ready(Some(r)),
-        Err(e) => match e {
-          ResolutionError::NotFound => futures::future::ready(None),
-          _ => futures::future::ready(Some(Err(e))),
-        }
-      })
-      .try_collect().await?;
-    let roots = strands
-      .iter()
-      .map(|s| s.cid())
-      .chain(latests.iter().map(|t| t.cid()))
-      .collect::<Vec<_>>();
+    let mut file = self.inner.file.lock().await;
+    flush_file(&self.inner.memstore, &self.inner.filename, &mut file).await
+  }
+
+  /// Rewrite the file with only the current data
+  ///
+  /// The new file is written next to the old one and renamed into
+  /// place.
+  pub async fn compact(&self) -> Result<(), StoreError> {
+    let mut file = self.inner.file.lock().await;
+    let roots = roots(&self.inner.memstore).await?;
+    compact_file(&self.inner.memstore, &self.inner.filename, &mut file, roots).await
+  }
 
-    let all_tixels = futures::stream::iter(strands.iter())
-      .filter_map(|strand| async {
-        let q = match RangeQuery::from((strand.cid(), ..))
-          .try_to_absolute(&self.memstore)
+  async fn contains(&self, twine: &AnyTwine) -> Result<bool, ResolutionError> {
+    match twine {
+      AnyTwine::Strand(strand) => self.inner.memstore.has_strand(&strand.cid()).await,
+      AnyTwine::Tixel(tixel) => {
+        self
+          .inner
+          .memstore
+          .has_twine(&tixel.strand_cid(), &tixel.cid())
           .await
-        {
-          Ok(q) => q,
-          Err(e) => return Some(Err(e)),
-        };
-        if q.is_none() {
-          return None;
-        }
-        let twines = self.memstore.range_stream(q.unwrap()).await;
-        Some(twines)
-      })
-      .try_flatten()
-      .filter_map(|r| async { r.ok() });
-
-    let strands = futures::stream::iter(strands.iter()).map(|s| AnyTwine::Strand(s.clone()));
-    let all_tixels = all_tixels.map(|t| AnyTwine::Tixel(t));
-    let all = strands.chain(all_tixels);
-
-    let mut bytes = twine_lib::car::to_car_stream(all, roots).boxed();
-
-    let map_err = |e: std::io::Error| StoreError::Saving(e.to_string());
-    let mut file = std::fs::File::create(&self.filename).map_err(map_err)?; // Create or truncate the file
-    use std::io::Write;
-    while let Some(chunk) = bytes.next().await {
-      file.write_all(&chunk).map_err(map_err)?; // Write each chunk
-    }
-    file.flush().map_err(map_err)?; // Ensure all data is written
+      }
+    }
+  }
+
+  async fn save_locked(&self, file: &mut CarFile, twine: AnyTwine) -> Result<(), StoreError> {
+    if self.contains(&twine).await? {
+      return Ok(());
+    }
+    // the block goes to disk first, so a failed append can't leave a
+    // twine in memory that later saves would skip
+    let frame = block_frame(&twine.cid(), &twine.bytes());
+    let offset = file.len;
+    file.append(&frame).map_err(saving_error)?;
+    if let Err(e) = self.inner.memstore.save(twine).await {
+      file.truncate(offset).map_err(saving_error)?;
+      return Err(e);
+    }
+    file.dirty = true;
     Ok(())
   }
 }
 
+fn spawn_flusher(inner: Weak<Inner>, stopped: mpsc::Receiver<()>, interval: Duration) {
+  std::thread::spawn(move || {
+    while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+      let Some(inner) = inner.upgrade() else {
+        break;
+      };
+      let store = CarStore { inner };
+      // this thread isn't part of any async runtime, so blocking is fine
+      if let Err(e) = async_std::task::block_on(store.flush()) {
+        log::error!("Error flushing store: {}", e);
+      }
+    }
+  });
+}
+
 #[async_trait]
 impl BaseResolver for CarStore {
   async fn fetch_strands(
@@ -132,35 +577,35 @@ impl BaseResolver for CarStore {
     Pin<Box<dyn Stream<Item = Result<Strand, ResolutionError>> + Send + '_>>,
     ResolutionError,
   > {
-    self.memstore.fetch_strands().await
+    self.inner.memstore.fetch_strands().await
   }
 
   async fn has_strand(&self, cid: &Cid) -> Result<bool, ResolutionError> {
-    self.memstore.has_strand(cid).await
+    self.inner.memstore.has_strand(cid).await
   }
 
   async fn has_index(&self, strand: &Cid, index: u64) -> Result<bool, ResolutionError> {
-    self.memstore.has_index(strand, index).await
+    self.inner.memstore.has_index(strand, index).await
   }
 
   async fn has_twine(&self, strand: &Cid, cid: &Cid) -> Result<bool, ResolutionError> {
-    self.memstore.has_twine(strand, cid).await
+    self.inner.memstore.has_twine(strand, cid).await
   }
 
   async fn fetch_strand(&self, strand: &Cid) -> Result<Strand, ResolutionError> {
-    self.memstore.fetch_strand(strand).await
+    self.inner.memstore.fetch_strand(strand).await
   }
 
   async fn fetch_tixel(&self, strand: &Cid, tixel: &Cid) -> Result<Tixel, ResolutionError> {
-    self.memstore.fetch_tixel(strand, tixel).await
+    self.inner.memstore.fetch_tixel(strand, tixel).await
   }
 
   async fn fetch_index(&self, strand: &Cid, index: u64) -> Result<Tixel, ResolutionError> {
-    self.memstore.fetch_index(strand, index).await
+    self.inner.memstore.fetch_index(strand, index).await
   }
 
   async fn fetch_latest(&self, strand: &Cid) -> Result<Tixel, ResolutionError> {
-    self.memstore.fetch_latest(strand).await
+    self.inner.memstore.fetch_latest(strand).await
   }
 
   async fn range_stream(
@@ -170,7 +615,7 @@ impl BaseResolver for CarStore {
     Pin<Box<dyn Stream<Item = Result<Tixel, ResolutionError>> + Send + '_>>,
     ResolutionError,
   > {
-    self.memstore.range_stream(range).await
+    self.inner.memstore.range_stream(range).await
   }
 }
 
@@ -179,9 +624,8 @@ impl Resolver for CarStore {}
 #[async_trait]
 impl Store for CarStore {
   async fn save<T: Into<AnyTwine> + Send>(&self, twine: T) -> Result<(), StoreError> {
-    self.memstore.save(twine).await?;
-    self.flush().await?;
-    Ok(())
+    let mut file = self.inner.file.lock().await;
+    self.save_locked(&mut file, twine.into()).await
   }
 
   async fn save_many<
@@ -192,8 +636,11 @@ impl Store for CarStore {
     &self,
     twines: T,
   ) -> Result<(), StoreError> {
-    self.memstore.save_many(twines).await?;
-    self.flush().await?;
+    let twines: Vec<AnyTwine> = twines.into_iter().map(Into::into).collect();
+    let mut file = self.inner.file.lock().await;
+    for twine in twines {
+      self.save_locked(&mut file, twine).await?;
+    }
     Ok(())
   }
 
@@ -203,18 +650,263 @@ impl Store for CarStore {
   ) -> Result<(), StoreError> {
     twines
       .chunks(100)
-      .then(|chunk| async {
-        self.memstore.save_many(chunk).await?;
-        Ok::<_, StoreError>(())
-      })
+      .then(|chunk| self.save_many(chunk))
       .try_collect::<Vec<_>>()
       .await?;
     Ok(())
   }
 
   async fn delete<C: AsCid + Send>(&self, cid: C) -> Result<(), StoreError> {
-    self.memstore.delete(cid).await?;
-    self.flush().await?;
+    let mut file = self.inner.file.lock().await;
+    self.inner.memstore.delete(cid).await?;
+    file.deleted = true;
+    file.dirty = true;
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use twine_lib::{resolver::Resolver, twine::Twine};
+
+  /// A radix 2 strand and `n` tixels, so archive segments stay small
+  pub(crate) fn chain(n: usize) -> (Strand, Vec<Twine>) {
+    twine_builder::test_util::chain_with_radix(n, 2)
+  }
+
+  fn read_car(path: &Path) -> (Vec<Cid>, Vec<AnyTwine>) {
+    let bytes = std::fs::read(path).unwrap();
+    let header = parse_car(&bytes).unwrap().header.unwrap();
+    let twines = twine_lib::car::from_car_bytes(&mut bytes.as_slice()).unwrap();
+    (header.roots, twines)
+  }
+
+  #[tokio::test]
+  async fn test_append_only() {
+    let (strand, tixels) = chain(10);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("store.car");
+    let store = CarStore::new(&path).unwrap();
+    store.save(strand.clone()).await.unwrap();
+    store.save_many(tixels[..5].to_vec()).await.unwrap();
+    store.flush().await.unwrap();
+    let before = std::fs::read(&path).unwrap();
+
+    store.save_many(tixels[5..].to_vec()).await.unwrap();
+    // saving again doesn't append duplicates
+    store.save(tixels[0].clone()).await.unwrap();
+    store.flush().await.unwrap();
+    let after = std::fs::read(&path).unwrap();
+    let appended: usize = tixels[5..]
+      .iter()
+      .map(|t| block_frame(&t.cid(), &t.bytes()).len())
+      .sum();
+    assert_eq!(after.len(), before.len() + appended);
+    // everything but the header is untouched
+    let header_len = HEADER_SIZE as usize;
+    assert_eq!(after[header_len..before.len()], before[header_len..]);
+
+    // the file is a plain twine CAR with the latest tixel as a root
+    let (roots, twines) = read_car(&path);
+    assert_eq!(roots, vec![strand.cid(), tixels[9].cid()]);
+    assert_eq!(twines.len(), 11);
+
+    // a new strand only changes the header too
+    let (other, other_tixels) = chain(1);
+    store.save(other.clone()).await.unwrap();
+    store.save_many(other_tixels).await.unwrap();
+    store.flush().await.unwrap();
+    let with_other = std::fs::read(&path).unwrap();
+    assert_eq!(with_other[header_len..after.len()], after[header_len..]);
+    assert_eq!(read_car(&path).0.len(), 4);
+
+    drop(store);
+    let store = CarStore::new(&path).unwrap();
+    assert_eq!(
+      store.resolve_latest(&strand).await.unwrap().unpack(),
+      tixels[9]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_delete_and_compact() {
+    let (strand, tixels) = chain(10);
+    let (other, other_tixels) = chain(3);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("store.car");
+    let store = CarStore::new(&path).unwrap();
+    store.save(strand.clone()).await.unwrap();
+    store.save_many(tixels.clone()).await.unwrap();
+    store.save(other.clone()).await.unwrap();
+    store.save_many(other_tixels.clone()).await.unwrap();
+    store.delete(tixels[9].cid()).await.unwrap();
+    store.delete(other.cid()).await.unwrap();
+    store.flush().await.unwrap();
+
+    // deleted blocks are gone from the file
+    let (roots, twines) = read_car(&path);
+    assert_eq!(roots, vec![strand.cid(), tixels[8].cid()]);
+    assert_eq!(twines.len(), 10);
+    assert!(twines.iter().all(|t| t.cid() != tixels[9].cid()));
+    assert!(twines.iter().all(|t| t.strand_cid() != other.cid()));
+    drop(store);
+
+    let store = CarStore::new(&path).unwrap();
+    assert_eq!(
+      store.resolve_latest(&strand).await.unwrap().unpack(),
+      tixels[8]
+    );
+    assert!(store.resolve_strand(&other).await.is_err());
+
+    // re-saving after a delete brings the data back
+    store.save(other.clone()).await.unwrap();
+    store.save(tixels[9].clone()).await.unwrap();
+    store.compact().await.unwrap();
+    drop(store);
+
+    let store = CarStore::new(&path).unwrap();
+    assert_eq!(
+      store.resolve_latest(&strand).await.unwrap().unpack(),
+      tixels[9]
+    );
+    assert_eq!(store.resolve_strand(&other).await.unwrap().unpack(), other);
+    assert!(store.resolve_latest(&other).await.is_err());
+  }
+
+  #[tokio::test]
+  async fn test_drop_without_flush() {
+    let (strand, tixels) = chain(3);
+    let (other, _) = chain(1);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("store.car");
+    let store = CarStore::new(&path).unwrap();
+    store.save(strand.clone()).await.unwrap();
+    store.save_many(tixels.clone()).await.unwrap();
+    store.save(other.clone()).await.unwrap();
+    store.delete(other.cid()).await.unwrap();
+    drop(store);
+
+    // saved blocks are on disk, but the roots and the delete are not
+    let (roots, twines) = read_car(&path);
+    assert!(roots.is_empty());
+    assert_eq!(twines.len(), 5);
+    let store = CarStore::new(&path).unwrap();
+    assert_eq!(
+      store.resolve_latest(&strand).await.unwrap().unpack(),
+      tixels[2]
+    );
+    assert!(store.resolve_strand(&other).await.is_ok());
+  }
+
+  #[tokio::test]
+  async fn test_failed_save_is_not_kept() {
+    let (strand, tixels) = chain(2);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("store.car");
+    let store = CarStore::new(&path).unwrap();
+    let len = std::fs::metadata(&path).unwrap().len();
+    // a tixel without its strand can't be saved
+    assert!(store.save(tixels[0].clone()).await.is_err());
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+
+    // so saving it again later writes it
+    store.save(strand.clone()).await.unwrap();
+    store.save_many(tixels.clone()).await.unwrap();
+    store.flush().await.unwrap();
+    drop(store);
+    let store = CarStore::new(&path).unwrap();
+    assert_eq!(
+      store.resolve_latest(&strand).await.unwrap().unpack(),
+      tixels[1]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_torn_header() {
+    let (strand, tixels) = chain(3);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("store.car");
+    let store = CarStore::new(&path).unwrap();
+    store.save(strand.clone()).await.unwrap();
+    store.save_many(tixels.clone()).await.unwrap();
+    store.flush().await.unwrap();
+    drop(store);
+
+    // garble the header after its length prefix
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes[2..40].fill(0xff);
+    std::fs::write(&path, bytes).unwrap();
+    assert!(parse_car(&std::fs::read(&path).unwrap())
+      .unwrap()
+      .header
+      .is_none());
+
+    let store = CarStore::new(&path).unwrap();
+    assert_eq!(
+      store.resolve_latest(&strand).await.unwrap().unpack(),
+      tixels[2]
+    );
+    store.flush().await.unwrap();
+    let (roots, twines) = read_car(&path);
+    assert_eq!(roots, vec![strand.cid(), tixels[2].cid()]);
+    assert_eq!(twines.len(), 4);
+  }
+
+  #[test]
+  fn test_padded_header() {
+    let (strand, tixels) = chain(2);
+    let roots = vec![strand.cid(), tixels[1].cid()];
+    let header = padded_header(&roots, HEADER_SIZE).unwrap();
+    assert_eq!(header.len() as u64, HEADER_SIZE);
+    let car = parse_car(&header).unwrap();
+    assert_eq!(car.header.unwrap().roots, roots);
+    assert_eq!(car.header_len, HEADER_SIZE);
+    // too many roots for the size
+    let many: Vec<Cid> = std::iter::repeat(strand.cid()).take(200).collect();
+    assert!(padded_header(&many, HEADER_SIZE).is_none());
+    let (header, size) = sized_header(&many);
+    assert_eq!(header.len() as u64, size);
+    assert!(size > HEADER_SIZE);
+  }
+
+  #[tokio::test]
+  async fn test_plain_car_file() {
+    let (strand, tixels) = chain(3);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("store.car");
+    let blocks = std::iter::once(AnyTwine::from(strand.clone()))
+      .chain(tixels.iter().cloned().map(AnyTwine::from));
+    let bytes = twine_lib::car::to_car_bytes(blocks, vec![strand.cid()]);
+    std::fs::write(&path, bytes).unwrap();
+
+    let store = CarStore::new(&path).unwrap();
+    assert_eq!(
+      store.resolve_latest(&strand).await.unwrap().unpack(),
+      tixels[2]
+    );
+    let (other, _) = chain(1);
+    store.save(other.clone()).await.unwrap();
+    store.flush().await.unwrap();
+    drop(store);
+
+    let (roots, twines) = read_car(&path);
+    assert_eq!(roots.len(), 3);
+    assert_eq!(twines.len(), 5);
+  }
+
+  #[tokio::test]
+  async fn test_background_flush() {
+    let (strand, tixels) = chain(2);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("store.car");
+    let options = CarStoreOptions::default().flush_interval(Duration::from_millis(10));
+    let store = CarStore::open(&path, options).unwrap();
+    store.save(strand.clone()).await.unwrap();
+    store.save_many(tixels).await.unwrap();
+    store.delete(strand.cid()).await.unwrap();
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(!store.inner.file.lock().await.dirty);
+    assert!(read_car(&path).1.is_empty());
+  }
+}
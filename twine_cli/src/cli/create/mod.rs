@@ -1,3 +1,4 @@
+use crate::passphrase::PassphraseArgs;
 use crate::prompt::not_empty;
 use crate::prompt::prompt_for_directory;
 use anyhow::Result;
@@ -7,7 +8,7 @@ use std::{
   collections::HashMap,
   path::{Path, PathBuf},
 };
-use twine_builder::RingSigner;
+use twine_builder::{RingSigner, RingSignerError};
 use twine_lib::ipld_core::ipld;
 use twine_lib::{multihash_codetable::Code, twine::TwineBlock};
 
@@ -16,6 +17,8 @@ pub struct CreateCommand {
   /// Key to sign the strand with
   #[arg(short, long)]
   key: PathBuf,
+  #[command(flatten)]
+  passphrase: PassphraseArgs,
 }
 
 impl CreateCommand {
@@ -43,8 +46,13 @@ impl CreateCommand {
     }
 
     let pem = tokio::fs::read_to_string(&self.key).await?;
-    let signer =
-      RingSigner::from_pem(&pem).map_err(|e| anyhow::anyhow!("Failed to load key. {}", e))?;
+    let signer = match RingSigner::from_pem(&pem) {
+      Err(RingSignerError::Encrypted) => {
+        RingSigner::from_encrypted_pem(&pem, self.passphrase.passphrase(false)?)
+      }
+      res => res,
+    }
+    .map_err(|e| anyhow::anyhow!("Failed to load key. {}", e))?;
 
     log::info!("Using key with algorithm: {}", signer.alg());
 
@@ -0,0 +1,89 @@
+use crate::passphrase::PassphraseArgs;
+use crate::stores::resolver_from_args;
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use std::path::Path;
+use twine_builder::{RingSigner, RingSignerError, Signer};
+use twine_lib::{crypto::PublicKey, resolver::Resolver, trust::Fingerprint, Cid};
+
+#[derive(Debug, Parser)]
+pub struct KeyCommand {
+  #[command(subcommand)]
+  subcommand: KeySubCommands,
+}
+
+#[derive(Debug, Subcommand)]
+enum KeySubCommands {
+  /// Print a public key in every supported format
+  Inspect(InspectCommand),
+}
+
+impl KeyCommand {
+  pub async fn run(&self, ctx: crate::Context) -> Result<()> {
+    match &self.subcommand {
+      KeySubCommands::Inspect(inspect) => inspect.run(ctx).await,
+    }
+  }
+}
+
+#[derive(Debug, Parser)]
+pub struct InspectCommand {
+  /// A PEM file (public or private key), an OpenSSH public key file, or a strand CID
+  input: String,
+  /// Use specified resolver to find the strand (otherwise use default resolver)
+  #[arg(short, long)]
+  resolver: Option<String>,
+  #[command(flatten)]
+  passphrase: PassphraseArgs,
+}
+
+impl InspectCommand {
+  pub async fn run(&self, ctx: crate::Context) -> Result<()> {
+    let key = if Path::new(&self.input).exists() {
+      let contents = tokio::fs::read_to_string(&self.input).await?;
+      self.key_from_file(&contents)?
+    } else {
+      let cid: Cid = self
+        .input
+        .parse()
+        .map_err(|_| anyhow::anyhow!("{} is neither a file nor a strand CID", self.input))?;
+      let resolver = resolver_from_args(&self.resolver, &ctx.cfg)?;
+      resolver.resolve_strand(cid).await?.unpack().key()
+    };
+
+    let na = |e: twine_lib::errors::KeyFormatError| format!("n/a ({})", e);
+    let fingerprint = Fingerprint::of(&key)
+      .map(|f| f.to_string())
+      .unwrap_or_else(na);
+    println!("Algorithm: {}", key.alg);
+    println!("Fingerprint: {}", fingerprint);
+    println!("did:key: {}", key.to_did_key().unwrap_or_else(na));
+    println!("OpenSSH: {}", key.to_openssh().unwrap_or_else(na));
+    match key.to_jwk() {
+      Ok(jwk) => println!("JWK: {}", serde_json::to_string_pretty(&jwk)?),
+      Err(e) => println!("JWK: {}", na(e)),
+    }
+    match key.to_spki_pem() {
+      Ok(pem) => print!("{}", pem),
+      Err(e) => println!("PEM: {}", na(e)),
+    }
+    Ok(())
+  }
+
+  fn key_from_file(&self, contents: &str) -> Result<PublicKey> {
+    if contents.contains("-----BEGIN PUBLIC KEY-----") {
+      return Ok(PublicKey::from_spki_pem(contents)?);
+    }
+    if contents.contains("PRIVATE KEY-----") {
+      let signer = match RingSigner::from_pem(contents) {
+        Err(RingSignerError::Encrypted) => {
+          RingSigner::from_encrypted_pem(contents, self.passphrase.passphrase(false)?)
+        }
+        res => res,
+      }
+      .map_err(|e| anyhow::anyhow!("Failed to load key. {}", e))?;
+      return Ok(signer.public_key());
+    }
+    Ok(PublicKey::from_openssh(contents.trim())?)
+  }
+}
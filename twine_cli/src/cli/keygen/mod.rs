@@ -1,14 +1,29 @@
+use crate::passphrase::PassphraseArgs;
 use crate::prompt::prompt_for_filename;
 use anyhow::Result;
 use clap::Parser;
 use inquire::Select;
-use twine_builder::RingSigner;
+use std::path::{Path, PathBuf};
+use twine_builder::{decrypt_seed, encrypt_seed, DerivationPath, RingSigner};
+use twine_lib::crypto::SignatureAlgorithm;
 
 #[derive(Debug, Parser)]
 pub struct KeygenCommand {
   /// Output the private key to a file
   #[arg(short, long)]
   output: Option<String>,
+  /// Save the private key without a passphrase
+  #[arg(long)]
+  unencrypted: bool,
+  #[command(flatten)]
+  passphrase: PassphraseArgs,
+  /// Derive the key from a master seed along this SLIP-0010 path (eg: m/0'/7')
+  #[arg(long, value_name = "PATH", requires = "seed")]
+  derive: Option<DerivationPath>,
+  /// Master seed file used with --derive. It is created if it does not exist,
+  /// encrypted with the same passphrase as the key.
+  #[arg(long, value_name = "FILE", requires = "derive")]
+  seed: Option<PathBuf>,
 }
 
 impl KeygenCommand {
@@ -23,38 +38,121 @@ impl KeygenCommand {
       "Ed25519",
       "EcdsaP256",
       "EcdsaP384",
+      "EcdsaP521",
+      "EcdsaSecp256k1",
+      "Ed448",
       "RSA2048 (sha256)",
       "RSA3072 (sha384)",
       "RSA4096 (sha512)",
     ];
 
+    if let Some(path) = &self.derive {
+      let items = vec!["Ed25519", "EcdsaP256"];
+      let alg = match Select::new("Select key type", items).prompt()? {
+        "Ed25519" => SignatureAlgorithm::Ed25519,
+        _ => SignatureAlgorithm::EcdsaP256,
+      };
+      let passphrase = self.new_passphrase()?;
+      let seed = load_or_create_seed(self.seed.as_ref().unwrap(), &passphrase).await?;
+      let signer = RingSigner::derive(alg, &seed, path)?;
+      log::info!("Derived key at {}", path);
+      return self.save(signer, &filename, &passphrase).await;
+    }
+
     let key_type = Select::new("Select key type", items).prompt()?;
 
     let signer = match key_type {
       "Ed25519" => RingSigner::generate_ed25519().map_err(|e| anyhow::anyhow!(e))?,
       "EcdsaP256" => RingSigner::generate_p256().map_err(|e| anyhow::anyhow!(e))?,
       "EcdsaP384" => RingSigner::generate_p384().map_err(|e| anyhow::anyhow!(e))?,
+      "EcdsaP521" => RingSigner::generate_p521()?,
+      "EcdsaSecp256k1" => RingSigner::generate_secp256k1()?,
+      "Ed448" => RingSigner::generate_ed448()?,
       "RSA2048 (sha256)" => RingSigner::generate_rs256(2048)?,
       "RSA3072 (sha384)" => RingSigner::generate_rs384(3072)?,
       "RSA4096 (sha512)" => RingSigner::generate_rs512(4096)?,
       _ => unreachable!(),
     };
 
-    let pem = signer.private_key_pem()?;
-
-    // write the file and set permissions to 600
-    tokio::fs::write(&filename, pem).await?;
+    let passphrase = self.new_passphrase()?;
+    self.save(signer, &filename, &passphrase).await
+  }
 
-    #[cfg(unix)]
-    {
-      use std::os::unix::fs::PermissionsExt;
-      let mut perms = tokio::fs::metadata(&filename).await?.permissions();
-      perms.set_mode(0o600);
-      tokio::fs::set_permissions(&filename, perms).await?;
+  /// The passphrase to encrypt with. Empty when saving unencrypted.
+  fn new_passphrase(&self) -> Result<String> {
+    if self.unencrypted {
+      Ok(String::new())
+    } else {
+      self.passphrase.passphrase(true)
     }
+  }
 
+  async fn save(&self, signer: RingSigner, filename: &str, passphrase: &str) -> Result<()> {
+    let pem = if passphrase.is_empty() {
+      log::warn!("Saving the private key without a passphrase");
+      signer.private_key_pem()?
+    } else {
+      signer.encrypted_private_key_pem(passphrase)?
+    };
+
+    write_private(filename, pem).await?;
     log::info!("Private key saved to {}", filename);
 
     Ok(())
   }
 }
+
+/// Write a file and set permissions to 600
+async fn write_private<P: AsRef<Path>>(filename: P, contents: String) -> Result<()> {
+  tokio::fs::write(&filename, contents).await?;
+
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    let mut perms = tokio::fs::metadata(&filename).await?.permissions();
+    perms.set_mode(0o600);
+    tokio::fs::set_permissions(&filename, perms).await?;
+  }
+
+  Ok(())
+}
+
+/// Load the master seed, or create and save a new one
+///
+/// New seeds are encrypted unless the passphrase is empty. Seed files
+/// holding plain hex are still read, with a warning.
+async fn load_or_create_seed(filename: &Path, passphrase: &str) -> Result<Vec<u8>> {
+  if filename.exists() {
+    let contents = tokio::fs::read_to_string(filename).await?;
+    if contents.trim_start().starts_with("-----BEGIN") {
+      if passphrase.is_empty() {
+        return Err(anyhow::anyhow!(
+          "The seed file is encrypted. A passphrase is required."
+        ));
+      }
+      return Ok(decrypt_seed(&contents, passphrase)?);
+    }
+    let seed = hex::decode(contents.trim())
+      .map_err(|e| anyhow::anyhow!("Seed file must contain hex: {}", e))?;
+    log::warn!("The master seed at {} is not encrypted", filename.display());
+    return Ok(seed);
+  }
+
+  use twine_builder::ring::rand::SecureRandom;
+  let mut seed = vec![0u8; 32];
+  twine_builder::ring::rand::SystemRandom::new()
+    .fill(&mut seed)
+    .map_err(|_| anyhow::anyhow!("Random number generation failed"))?;
+  let contents = if passphrase.is_empty() {
+    log::warn!("Saving the master seed without a passphrase");
+    hex::encode(&seed) + "\n"
+  } else {
+    encrypt_seed(&seed, passphrase)?
+  };
+  write_private(filename, contents).await?;
+  log::warn!(
+    "Created a new master seed at {}. Back it up, every derived key depends on it",
+    filename.display()
+  );
+  Ok(seed)
+}
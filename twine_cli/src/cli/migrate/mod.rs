@@ -0,0 +1,26 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use twine_sql_store::SqlStore;
+
+const SQL_SCHEMES: [&str; 4] = ["sqlite:", "mysql:", "postgres:", "postgresql:"];
+
+#[derive(Debug, Parser)]
+pub struct MigrateCommand {
+  /// Uri of the sql database (sqlite:, mysql:// or postgres://)
+  uri: String,
+}
+
+impl MigrateCommand {
+  // bring the schema of a sql store up to date
+  pub async fn run(&self, _ctx: crate::Context) -> Result<()> {
+    log::trace!("Migrate: {:?}", self);
+
+    if !SQL_SCHEMES.iter().any(|s| self.uri.starts_with(s)) {
+      return Err(anyhow!("Not a sql store uri: {}", self.uri));
+    }
+    let store = SqlStore::open(&self.uri).await?;
+    store.migrate().await?;
+    log::info!("Migrations applied");
+    Ok(())
+  }
+}
@@ -3,8 +3,10 @@ use clap::{Parser, Subcommand};
 mod check;
 mod create;
 mod init;
+mod key;
 mod keygen;
 mod list;
+mod migrate;
 mod sync;
 
 #[derive(Debug, Parser)]
@@ -30,10 +32,14 @@ pub enum SubCommands {
   Create(create::CreateCommand),
   /// Generate a keypair
   Keygen(keygen::KeygenCommand),
+  /// Inspect public keys
+  Key(key::KeyCommand),
   /// Initialize a new configuration and store
   Init(init::InitCommand),
   /// Check strand connectivity
   Check(check::CheckCommand),
+  /// Apply pending schema migrations to a sql store
+  Migrate(migrate::MigrateCommand),
 }
 
 impl Cli {
@@ -43,8 +49,10 @@ impl Cli {
       SubCommands::Sync(sync) => sync.run(ctx).await,
       SubCommands::Create(create) => create.run(ctx).await,
       SubCommands::Keygen(keygen) => keygen.run(ctx).await,
+      SubCommands::Key(key) => key.run(ctx).await,
       SubCommands::Init(init) => init.run(ctx).await,
       SubCommands::Check(check) => check.run(ctx).await,
+      SubCommands::Migrate(migrate) => migrate.run(ctx).await,
     }
   }
 }
@@ -6,7 +6,7 @@ use std::{
   collections::HashMap,
   path::{Path, PathBuf},
 };
-use twine_lib::resolver::ResolverSetSeries;
+use twine_lib::{resolver::ResolverSetSeries, trust::TrustStore};
 
 #[serde_as]
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,6 +25,8 @@ pub(crate) struct Config {
   pub path: Option<PathBuf>,
   pub resolvers: HashMap<String, StoreUriString>,
   pub store: Option<StoreUriString>,
+  /// Pinned strands and revoked keys enforced when resolving
+  pub trust: TrustStore,
 }
 
 impl Default for Config {
@@ -33,6 +35,7 @@ impl Default for Config {
       resolvers: HashMap::new(),
       store: None,
       path: None,
+      trust: TrustStore::default(),
     }
   }
 }
@@ -10,6 +10,7 @@ use simplelog::{ConfigBuilder, TermLogger};
 pub(crate) mod cid_str;
 mod cli;
 mod config;
+mod passphrase;
 mod prompt;
 mod selector;
 mod stores;
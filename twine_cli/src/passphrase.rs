@@ -0,0 +1,68 @@
+use anyhow::Result;
+use clap::Args;
+use inquire::{Password, PasswordDisplayMode};
+
+/// Environment variable that can hold the passphrase for automation
+pub const PASSPHRASE_ENV: &str = "TWINE_KEY_PASSPHRASE";
+
+#[derive(Debug, Args)]
+pub struct PassphraseArgs {
+  /// Read the key passphrase from this file descriptor (first line).
+  /// Otherwise it is read from the TWINE_KEY_PASSPHRASE environment variable,
+  /// or prompted for.
+  #[arg(long, value_name = "FD")]
+  passphrase_fd: Option<i32>,
+}
+
+impl PassphraseArgs {
+  /// Get the passphrase from the file descriptor, the environment, or a prompt (in that order)
+  ///
+  /// When `new` is true, the prompt asks for confirmation.
+  pub fn passphrase(&self, new: bool) -> Result<String> {
+    if let Some(fd) = self.passphrase_fd {
+      return read_fd(fd);
+    }
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+      return Ok(passphrase);
+    }
+    let prompt = if new {
+      Password::new("Passphrase for the private key:")
+        .with_custom_confirmation_message("Confirm passphrase:")
+        .with_help_message("Leave empty to save the key unencrypted")
+    } else {
+      Password::new("Passphrase for the private key:").without_confirmation()
+    };
+    Ok(
+      prompt
+        .with_display_mode(PasswordDisplayMode::Hidden)
+        .prompt()?,
+    )
+  }
+}
+
+#[cfg(unix)]
+fn read_fd(fd: i32) -> Result<String> {
+  use anyhow::Context;
+  use std::io::BufRead;
+  if fd < 0 {
+    return Err(anyhow::anyhow!(
+      "Invalid passphrase file descriptor: {}",
+      fd
+    ));
+  }
+  // open the descriptor through /dev/fd rather than taking ownership of it,
+  // so a bad number is an error instead of undefined behaviour
+  let file = std::fs::File::open(format!("/dev/fd/{}", fd))
+    .with_context(|| format!("Could not read passphrase from file descriptor {}", fd))?;
+  let mut line = String::new();
+  std::io::BufReader::new(file).read_line(&mut line)?;
+  Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+#[cfg(not(unix))]
+fn read_fd(_fd: i32) -> Result<String> {
+  Err(anyhow::anyhow!(
+    "--passphrase-fd is only supported on unix. Use {} instead.",
+    PASSPHRASE_ENV
+  ))
+}
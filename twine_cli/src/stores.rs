@@ -3,7 +3,7 @@ use futures::executor;
 use std::fmt::Display;
 use std::{ops::Deref, str::FromStr};
 use twine_car_store::CarStore;
-use twine_lib::resolver::ResolverSetSeries;
+use twine_lib::resolver::{ResolverSetSeries, TrustedResolver};
 use twine_lib::{errors::StoreError, resolver::unchecked_base, store::Store};
 use twine_http_store::reqwest;
 use twine_pickledb_store::PickleDbStore;
@@ -88,7 +88,10 @@ impl AnyStore {
   ) -> std::result::Result<(), StoreError> {
     match self {
       Self::Sled(s) => s.save(twine).await,
-      Self::Car(s) => s.save(twine).await,
+      Self::Car(s) => {
+        s.save(twine).await?;
+        s.flush().await
+      }
       Self::Pickle(s) => s.save(twine).await,
       Self::HttpV1(s) => s.save(twine).await,
       Self::HttpV2(s) => s.save(twine).await,
@@ -105,7 +108,10 @@ impl AnyStore {
   ) -> std::result::Result<(), StoreError> {
     match self {
       Self::Sled(s) => s.save_many(twines).await,
-      Self::Car(s) => s.save_many(twines).await,
+      Self::Car(s) => {
+        s.save_many(twines).await?;
+        s.flush().await
+      }
       Self::Pickle(s) => s.save_many(twines).await,
       Self::HttpV1(s) => s.save_many(twines).await,
       Self::HttpV2(s) => s.save_many(twines).await,
@@ -121,7 +127,10 @@ impl AnyStore {
   ) -> std::result::Result<(), StoreError> {
     match self {
       Self::Sled(s) => s.save_stream(twines).await,
-      Self::Car(s) => s.save_stream(twines).await,
+      Self::Car(s) => {
+        s.save_stream(twines).await?;
+        s.flush().await
+      }
       Self::Pickle(s) => s.save_stream(twines).await,
       Self::HttpV1(s) => s.save_stream(twines).await,
       Self::HttpV2(s) => s.save_stream(twines).await,
@@ -134,7 +143,10 @@ impl AnyStore {
   ) -> std::result::Result<(), StoreError> {
     match self {
       Self::Sled(s) => s.delete(cid).await,
-      Self::Car(s) => s.delete(cid).await,
+      Self::Car(s) => {
+        s.delete(cid).await?;
+        s.flush().await
+      }
       Self::Pickle(s) => s.delete(cid).await,
       Self::HttpV1(s) => s.delete(cid).await,
       Self::HttpV2(s) => s.delete(cid).await,
@@ -194,11 +206,11 @@ pub fn parse_store(uri: &str) -> Result<AnyStore> {
 pub fn resolver_from_args(
   arg: &Option<String>,
   config: &Option<Config>,
-) -> Result<ResolverSetSeries<AnyStore>> {
+) -> Result<TrustedResolver<ResolverSetSeries<AnyStore>>> {
   // Can be either the arg as a store uri (precedence)
   // or the name of the store in the config
   // or default to all stores in config
-  if let Some(arg) = arg {
+  let resolvers = if let Some(arg) = arg {
     let store = match parse_store(&arg) {
       Ok(s) => s,
       Err(_) => config
@@ -207,12 +219,15 @@ pub fn resolver_from_args(
         .get_named_resolver(&arg)
         .ok_or_else(|| anyhow!("No resolver named: {}", arg))??,
     };
-    Ok(ResolverSetSeries::new(vec![store]))
+    ResolverSetSeries::new(vec![store])
   } else if let Some(config) = config {
-    config.all_resolvers()
+    config.all_resolvers()?
   } else {
-    Err(anyhow!(
+    return Err(anyhow!(
       "Must specify a resolver in arguments or in config file"
-    ))
-  }
+    ));
+  };
+  // the trust policy in the config applies to every resolver
+  let trust = config.as_ref().map(|c| c.trust.clone()).unwrap_or_default();
+  Ok(TrustedResolver::new(resolvers, trust))
 }
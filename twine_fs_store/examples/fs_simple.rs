@@ -0,0 +1,39 @@
+//! Example of creating twine data and saving it to a filesystem store
+use futures::TryStreamExt;
+use twine_builder::RingSigner;
+use twine_builder::TwineBuilder;
+use twine_fs_store::*;
+use twine_lib::resolver::*;
+use twine_lib::store::Store;
+use twine_lib::twine::Twine;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+  let tmp_dir = tempfile::tempdir()?;
+  let store = FsStore::new(tmp_dir.path(), FsStoreOptions::default())?;
+
+  let signer = RingSigner::generate_ed25519().unwrap();
+  let builder = TwineBuilder::new(signer);
+  let strand = builder.build_strand().radix(2).done()?;
+  let first = builder.build_first(strand.clone()).done()?;
+  let next = builder.build_next(&first).done()?;
+
+  store.save(strand.clone()).await?;
+  store.save_many(vec![first, next]).await?;
+
+  let latest = store.resolve_latest(&strand).await?.unpack();
+  println!("latest: {}", latest.index());
+
+  let twines: Vec<Twine> = store
+    .resolve_range((strand.clone(), ..))
+    .await?
+    .try_collect()
+    .await?;
+  println!(
+    "resolved {} tixels from {}",
+    twines.len(),
+    store.root().display()
+  );
+
+  Ok(())
+}
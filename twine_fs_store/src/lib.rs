@@ -0,0 +1,643 @@
+#![doc = include_str!("../README.md")]
+use async_trait::async_trait;
+use futures::stream::{Stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use twine_lib::resolver::{unchecked_base::BaseResolver, AbsoluteRange, Resolver};
+use twine_lib::{as_cid::AsCid, errors::*, store::Store, twine::TwineBlock, twine::*, Cid};
+
+const BLOCKS_DIR: &str = "blocks";
+const STRANDS_DIR: &str = "strands";
+const INDEX_DIR: &str = "index";
+const LATEST_FILE: &str = "latest.json";
+
+/// Keeps temporary file names unique within the process
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// The contents of a strand's latest pointer
+#[derive(Debug, Serialize, Deserialize)]
+struct LatestRecord {
+  index: u64,
+  cid: String,
+}
+
+/// Options for the FsStore
+#[derive(Debug, Clone, PartialEq)]
+pub struct FsStoreOptions {
+  buffer_size: usize,
+  sync: bool,
+}
+
+impl Default for FsStoreOptions {
+  fn default() -> Self {
+    Self {
+      buffer_size: 100,
+      sync: true,
+    }
+  }
+}
+
+impl FsStoreOptions {
+  /// Set the buffer size for the store
+  ///
+  /// The buffer size is the number of files read concurrently when
+  /// streaming ranges, and the number of items per batch when saving
+  /// streams
+  pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+    self.buffer_size = buffer_size.max(1);
+    self
+  }
+
+  /// Set whether files and their directories are synced to disk on write
+  ///
+  /// Each file is synced before it is renamed into place, and its directory
+  /// after, so that a write survives a crash once it has returned. Disabling
+  /// this speeds up bulk imports, but a crash may then lose recent writes or
+  /// leave empty or partial files behind. Defaults to `true`.
+  pub fn sync(mut self, sync: bool) -> Self {
+    self.sync = sync;
+    self
+  }
+}
+
+/// A [`Store`] that saves each block as its own file in a directory
+///
+/// See the crate documentation for the directory layout.
+#[derive(Debug, Clone)]
+pub struct FsStore {
+  root: PathBuf,
+  options: FsStoreOptions,
+  latest_lock: Arc<Mutex<()>>,
+  index_lock: Arc<Mutex<()>>,
+}
+
+fn read_file(path: &Path) -> io::Result<Option<Vec<u8>>> {
+  match fs::read(path) {
+    Ok(bytes) => Ok(Some(bytes)),
+    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+    Err(e) => Err(e),
+  }
+}
+
+fn remove_file(path: &Path) -> io::Result<()> {
+  match fs::remove_file(path) {
+    Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+    _ => Ok(()),
+  }
+}
+
+/// Sync a directory, so that entries renamed into it survive a crash
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+  File::open(dir)?.sync_all()
+}
+
+/// Directories can not be opened for syncing on this platform
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+  Ok(())
+}
+
+fn parse_cid(text: &[u8]) -> io::Result<Cid> {
+  std::str::from_utf8(text)
+    .ok()
+    .and_then(|s| Cid::try_from(s.trim()).ok())
+    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid cid"))
+}
+
+fn fetch_error(e: io::Error) -> ResolutionError {
+  match e.kind() {
+    io::ErrorKind::InvalidData => ResolutionError::BadData(e.to_string()),
+    _ => ResolutionError::Fetch(e.to_string()),
+  }
+}
+
+fn saving_error(e: io::Error) -> StoreError {
+  StoreError::Saving(e.to_string())
+}
+
+impl FsStore {
+  /// Create a new FsStore in a directory
+  ///
+  /// The directory is created if it doesn't exist.
+  ///
+  /// # Example
+  ///
+  /// ```no_run
+  /// use twine_fs_store::*;
+  /// let store = FsStore::new("./my_store", FsStoreOptions::default()).unwrap();
+  /// ```
+  pub fn new<P: AsRef<Path>>(root: P, options: FsStoreOptions) -> io::Result<Self> {
+    let root = root.as_ref().to_path_buf();
+    fs::create_dir_all(root.join(BLOCKS_DIR))?;
+    fs::create_dir_all(root.join(STRANDS_DIR))?;
+    Ok(Self {
+      root,
+      options,
+      latest_lock: Arc::new(Mutex::new(())),
+      index_lock: Arc::new(Mutex::new(())),
+    })
+  }
+
+  /// The root directory of the store
+  pub fn root(&self) -> &Path {
+    &self.root
+  }
+
+  fn block_path(&self, cid: &Cid) -> PathBuf {
+    // every twine cid string shares the same prefix, so shard on the
+    // first bytes of the hash digest instead
+    let digest = cid.hash().digest();
+    let shard = |i: usize| format!("{:02x}", digest.get(i).copied().unwrap_or(0));
+    self
+      .root
+      .join(BLOCKS_DIR)
+      .join(shard(0))
+      .join(shard(1))
+      .join(format!("{}.cbor", cid))
+  }
+
+  fn strand_dir(&self, strand: &Cid) -> PathBuf {
+    self.root.join(STRANDS_DIR).join(strand.to_string())
+  }
+
+  fn index_path(&self, strand: &Cid, index: u64) -> PathBuf {
+    self
+      .strand_dir(strand)
+      .join(INDEX_DIR)
+      .join(index.to_string())
+  }
+
+  fn latest_path(&self, strand: &Cid) -> PathBuf {
+    self.strand_dir(strand).join(LATEST_FILE)
+  }
+
+  /// Write a file by renaming a complete temporary file into place
+  fn write_file(&self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let dir = path.parent().expect("store paths have a parent");
+    let created = !dir.exists();
+    fs::create_dir_all(dir)?;
+    let tmp = dir.join(format!(
+      ".{}.{}.tmp",
+      std::process::id(),
+      TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = File::create(&tmp)
+      .and_then(|mut file| {
+        file.write_all(bytes)?;
+        if self.options.sync {
+          file.sync_all()?;
+        }
+        Ok(())
+      })
+      .and_then(|_| fs::rename(&tmp, path));
+    if result.is_err() {
+      let _ = fs::remove_file(&tmp);
+      return result;
+    }
+    if self.options.sync {
+      sync_dir(dir)?;
+      if created {
+        // new directories are only durable once their parents are synced
+        for parent in dir.ancestors().skip(1) {
+          sync_dir(parent)?;
+          if parent == self.root {
+            break;
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Write a block unless it is already stored
+  fn write_block(&self, twine: &AnyTwine) -> io::Result<()> {
+    let path = self.block_path(&twine.cid());
+    if path.exists() {
+      return Ok(());
+    }
+    self.write_file(&path, &twine.bytes())
+  }
+
+  fn read_latest(&self, strand: &Cid) -> io::Result<Option<LatestRecord>> {
+    match read_file(&self.latest_path(strand))? {
+      Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+      None => Ok(None),
+    }
+  }
+
+  fn write_latest(&self, strand: &Cid, index: u64, cid: &Cid) -> io::Result<()> {
+    let record = LatestRecord {
+      index,
+      cid: cid.to_string(),
+    };
+    self.write_file(&self.latest_path(strand), &serde_json::to_vec(&record)?)
+  }
+
+  /// Point the latest record at a tixel if it is newer
+  fn update_latest(&self, tixel: &Tixel) -> io::Result<()> {
+    let _guard = self.latest_lock.lock().unwrap_or_else(|e| e.into_inner());
+    let strand = tixel.strand_cid();
+    let latest = self.read_latest(&strand)?;
+    if latest.map(|r| tixel.index() > r.index).unwrap_or(true) {
+      self.write_latest(&strand, tixel.index(), &tixel.cid())?;
+      log::debug!("Updated latest for strand {}: {}", strand, tixel.index());
+    }
+    Ok(())
+  }
+
+  /// The stored indices of a strand, in no particular order
+  fn indices(&self, strand: &Cid) -> io::Result<Vec<u64>> {
+    let entries = match fs::read_dir(self.strand_dir(strand).join(INDEX_DIR)) {
+      Ok(entries) => entries,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+      Err(e) => return Err(e),
+    };
+    let mut indices = vec![];
+    for entry in entries {
+      // skips temporary files
+      if let Some(index) = entry?.file_name().to_str().and_then(|s| s.parse().ok()) {
+        indices.push(index);
+      }
+    }
+    Ok(indices)
+  }
+
+  async fn get(&self, cid: &Cid) -> Result<AnyTwine, ResolutionError> {
+    let bytes = read_file(&self.block_path(cid))
+      .map_err(fetch_error)?
+      .ok_or(ResolutionError::NotFound)?;
+    Ok(AnyTwine::from_block(*cid, bytes)?)
+  }
+
+  async fn get_tixel(&self, strand: &Cid, cid: &Cid) -> Result<Tixel, ResolutionError> {
+    let bytes = read_file(&self.block_path(cid))
+      .map_err(fetch_error)?
+      .ok_or(ResolutionError::NotFound)?;
+    let tixel = Tixel::from_block(*cid, bytes)?;
+    if tixel.strand_cid() != *strand {
+      return Err(ResolutionError::BadData(
+        "Tixel does not belong to strand".to_string(),
+      ));
+    }
+    Ok(tixel)
+  }
+
+  async fn save_strand(&self, strand: &AnyTwine) -> Result<(), StoreError> {
+    self.write_block(strand).map_err(saving_error)?;
+    fs::create_dir_all(self.strand_dir(&strand.cid()).join(INDEX_DIR)).map_err(saving_error)?;
+    Ok(())
+  }
+
+  /// Save a tixel without updating the latest record
+  async fn save_tixel(&self, tixel: &Tixel) -> Result<(), StoreError> {
+    let strand = tixel.strand_cid();
+    if !self.has_strand(&strand).await? {
+      return Err(StoreError::Saving(format!(
+        "Strand {} not saved yet",
+        strand
+      )));
+    }
+    let index_path = self.index_path(&strand, tixel.index());
+    let _guard = self.index_lock.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(existing) = read_file(&index_path).map_err(saving_error)? {
+      if parse_cid(&existing).map_err(saving_error)? != tixel.cid() {
+        return Err(StoreError::Saving(format!(
+          "A different tixel is already stored at index {} of strand {}",
+          tixel.index(),
+          strand
+        )));
+      }
+    }
+    // the block is written before the index that points to it
+    self
+      .write_block(&tixel.clone().into())
+      .map_err(saving_error)?;
+    self
+      .write_file(&index_path, tixel.cid().to_string().as_bytes())
+      .map_err(saving_error)?;
+    Ok(())
+  }
+}
+
+#[async_trait]
+impl BaseResolver for FsStore {
+  async fn fetch_strands(
+    &self,
+  ) -> Result<
+    Pin<Box<dyn Stream<Item = Result<Strand, ResolutionError>> + Send + '_>>,
+    ResolutionError,
+  > {
+    let mut cids = vec![];
+    for entry in fs::read_dir(self.root.join(STRANDS_DIR)).map_err(fetch_error)? {
+      let entry = entry.map_err(fetch_error)?;
+      if let Some(cid) = entry
+        .file_name()
+        .to_str()
+        .and_then(|s| Cid::try_from(s).ok())
+      {
+        cids.push(cid);
+      }
+    }
+    let stream =
+      futures::stream::iter(cids).then(move |cid| async move { self.fetch_strand(&cid).await });
+    Ok(stream.boxed())
+  }
+
+  async fn has_strand(&self, cid: &Cid) -> Result<bool, ResolutionError> {
+    Ok(self.strand_dir(cid.as_cid()).is_dir())
+  }
+
+  async fn has_index(&self, strand: &Cid, index: u64) -> Result<bool, ResolutionError> {
+    Ok(self.index_path(strand, index).is_file())
+  }
+
+  async fn has_twine(&self, _strand: &Cid, cid: &Cid) -> Result<bool, ResolutionError> {
+    Ok(self.block_path(cid.as_cid()).is_file())
+  }
+
+  async fn fetch_strand(&self, strand: &Cid) -> Result<Strand, ResolutionError> {
+    let bytes = read_file(&self.block_path(strand))
+      .map_err(fetch_error)?
+      .ok_or(ResolutionError::NotFound)?;
+    Ok(Strand::from_block(*strand, bytes)?)
+  }
+
+  async fn fetch_tixel(&self, strand: &Cid, tixel: &Cid) -> Result<Tixel, ResolutionError> {
+    self.get_tixel(strand, tixel).await
+  }
+
+  async fn fetch_index(&self, strand: &Cid, index: u64) -> Result<Tixel, ResolutionError> {
+    let cid = read_file(&self.index_path(strand, index))
+      .map_err(fetch_error)?
+      .ok_or(ResolutionError::NotFound)?;
+    let cid = parse_cid(&cid).map_err(fetch_error)?;
+    let tixel = self.get_tixel(strand, &cid).await?;
+
+    if tixel.index() != index {
+      return Err(ResolutionError::BadData(format!(
+        "Expected index {}, found {}",
+        index,
+        tixel.index()
+      )));
+    }
+
+    Ok(tixel)
+  }
+
+  async fn fetch_latest(&self, strand: &Cid) -> Result<Tixel, ResolutionError> {
+    let record = self
+      .read_latest(strand)
+      .map_err(fetch_error)?
+      .ok_or(ResolutionError::NotFound)?;
+    let cid = parse_cid(record.cid.as_bytes()).map_err(fetch_error)?;
+    self.get_tixel(strand, &cid).await
+  }
+
+  async fn range_stream(
+    &self,
+    range: AbsoluteRange,
+  ) -> Result<
+    Pin<Box<dyn Stream<Item = Result<Tixel, ResolutionError>> + Send + '_>>,
+    ResolutionError,
+  > {
+    let strand = range.strand;
+    let indices: Box<dyn Iterator<Item = u64> + Send> = if range.is_decreasing() {
+      Box::new((range.end..=range.start).rev())
+    } else {
+      Box::new(range.start..=range.end)
+    };
+    let stream = futures::stream::iter(indices)
+      .map(move |index| async move { self.fetch_index(&strand, index).await })
+      .buffered(self.options.buffer_size);
+    Ok(stream.boxed())
+  }
+}
+
+impl Resolver for FsStore {}
+
+#[async_trait]
+impl Store for FsStore {
+  async fn save<T: Into<AnyTwine> + Send>(&self, twine: T) -> Result<(), StoreError> {
+    let twine = twine.into();
+    match &twine {
+      AnyTwine::Strand(_) => self.save_strand(&twine).await,
+      AnyTwine::Tixel(tixel) => {
+        self.save_tixel(tixel).await?;
+        self.update_latest(tixel).map_err(saving_error)
+      }
+    }
+  }
+
+  async fn save_many<
+    I: Into<AnyTwine> + Send,
+    S: Iterator<Item = I> + Send,
+    T: IntoIterator<Item = I, IntoIter = S> + Send,
+  >(
+    &self,
+    twines: T,
+  ) -> Result<(), StoreError> {
+    let (strands, tixels) = twines
+      .into_iter()
+      .map(|i| i.into())
+      .partition::<Vec<AnyTwine>, _>(|twine| matches!(twine, AnyTwine::Strand(_)));
+
+    for strand in &strands {
+      self.save_strand(strand).await?;
+    }
+
+    let mut latests: HashMap<Cid, Tixel> = HashMap::new();
+    for tixel in tixels.into_iter().map(|t| t.unwrap_tixel()) {
+      self.save_tixel(&tixel).await?;
+      let index = tixel.index();
+      latests
+        .entry(tixel.strand_cid())
+        .and_modify(|t| {
+          if index > t.index() {
+            *t = tixel.clone()
+          }
+        })
+        .or_insert(tixel);
+    }
+    for tixel in latests.values() {
+      self.update_latest(tixel).map_err(saving_error)?;
+    }
+    Ok(())
+  }
+
+  async fn save_stream<I: Into<AnyTwine> + Send, T: Stream<Item = I> + Send + Unpin>(
+    &self,
+    twines: T,
+  ) -> Result<(), StoreError> {
+    // save in batches
+    twines
+      .chunks(self.options.buffer_size)
+      .then(|chunk| self.save_many(chunk))
+      .try_for_each(|_| async { Ok(()) })
+      .await?;
+    Ok(())
+  }
+
+  async fn delete<C: AsCid + Send>(&self, cid: C) -> Result<(), StoreError> {
+    let twine = match self.get(cid.as_cid()).await {
+      Ok(twine) => twine,
+      Err(ResolutionError::NotFound) => return Ok(()),
+      Err(e) => return Err(StoreError::Saving(e.to_string())),
+    };
+    match &twine {
+      AnyTwine::Strand(strand) => {
+        // remove the strand along with all of its tixels
+        let strand_cid = strand.cid();
+        let mut tixels = vec![];
+        for index in self.indices(&strand_cid).map_err(saving_error)? {
+          if let Some(cid) =
+            read_file(&self.index_path(&strand_cid, index)).map_err(saving_error)?
+          {
+            tixels.push(parse_cid(&cid).map_err(saving_error)?);
+          }
+        }
+        fs::remove_dir_all(self.strand_dir(&strand_cid)).map_err(saving_error)?;
+        for cid in tixels {
+          remove_file(&self.block_path(&cid)).map_err(saving_error)?;
+        }
+      }
+      AnyTwine::Tixel(tixel) => {
+        let strand = tixel.strand_cid();
+        let index = tixel.index();
+        remove_file(&self.index_path(&strand, index)).map_err(saving_error)?;
+        let _guard = self.latest_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let latest = self.read_latest(&strand).map_err(saving_error)?;
+        if latest.map(|r| r.index == index).unwrap_or(false) {
+          // the latest now is the highest remaining index
+          let previous = self
+            .indices(&strand)
+            .map_err(saving_error)?
+            .into_iter()
+            .filter(|i| *i < index)
+            .max();
+          match previous {
+            Some(previous) => {
+              let cid = read_file(&self.index_path(&strand, previous))
+                .map_err(saving_error)?
+                .ok_or_else(|| StoreError::Saving("Index file removed concurrently".into()))?;
+              let cid = parse_cid(&cid).map_err(saving_error)?;
+              self
+                .write_latest(&strand, previous, &cid)
+                .map_err(saving_error)?;
+            }
+            None => remove_file(&self.latest_path(&strand)).map_err(saving_error)?,
+          }
+        }
+      }
+    }
+    remove_file(&self.block_path(&twine.cid())).map_err(saving_error)?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use twine_builder::test_util::chain;
+  use twine_lib::twine::Twine;
+
+  #[tokio::test]
+  async fn test_layout() {
+    let (strand, tixels) = chain(3);
+    let dir = tempfile::tempdir().unwrap();
+    let store = FsStore::new(dir.path(), FsStoreOptions::default().sync(false)).unwrap();
+    store.save(strand.clone()).await.unwrap();
+    store.save_many(tixels.clone()).await.unwrap();
+
+    let name = tixels[1].cid().to_string();
+    let digest = tixels[1].cid().hash().digest().to_vec();
+    let block = dir.path().join(format!(
+      "blocks/{:02x}/{:02x}/{}.cbor",
+      digest[0], digest[1], name
+    ));
+    assert_eq!(fs::read(block).unwrap(), tixels[1].bytes().to_vec());
+
+    let strand_dir = dir.path().join(format!("strands/{}", strand.cid()));
+    assert_eq!(
+      fs::read_to_string(strand_dir.join("index/1")).unwrap(),
+      name
+    );
+    let latest: serde_json::Value =
+      serde_json::from_slice(&fs::read(strand_dir.join("latest.json")).unwrap()).unwrap();
+    assert_eq!(
+      latest,
+      serde_json::json!({ "index": 2, "cid": tixels[2].cid().to_string() })
+    );
+
+    // no temporary files are left behind
+    let leftovers: Vec<_> = fs::read_dir(strand_dir.join("index"))
+      .unwrap()
+      .map(|e| e.unwrap().file_name())
+      .filter(|name| name.to_string_lossy().starts_with('.'))
+      .collect();
+    assert!(leftovers.is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_range_and_delete() {
+    let (strand, tixels) = chain(10);
+    let dir = tempfile::tempdir().unwrap();
+    let store = FsStore::new(dir.path(), FsStoreOptions::default().buffer_size(3)).unwrap();
+    store.save(strand.clone()).await.unwrap();
+    store.save_many(tixels.clone()).await.unwrap();
+
+    let decreasing: Vec<Twine> = store
+      .resolve_range((strand.cid(), 9, 0))
+      .await
+      .unwrap()
+      .try_collect()
+      .await
+      .unwrap();
+    let expected: Vec<Twine> = tixels.iter().rev().cloned().collect();
+    assert_eq!(decreasing, expected);
+
+    store.delete(tixels[9].cid()).await.unwrap();
+    assert_eq!(store.latest_index(&strand.cid()).await.unwrap(), 8);
+
+    store.delete(strand.cid()).await.unwrap();
+    assert!(!store.has_strand(&strand.cid()).await.unwrap());
+    assert!(!store
+      .has_twine(&strand.cid(), &tixels[0].cid())
+      .await
+      .unwrap());
+    assert!(store.strands().await.unwrap().next().await.is_none());
+  }
+
+  #[tokio::test]
+  async fn test_rejects_forks() {
+    use twine_builder::{RingSigner, TwineBuilder};
+    use twine_lib::ipld_core::ipld;
+    let builder = TwineBuilder::new(RingSigner::generate_ed25519().unwrap());
+    let strand = builder.build_strand().done().unwrap();
+    let first = builder.build_first(strand.clone()).done().unwrap();
+    let next = builder.build_next(&first).done().unwrap();
+    let fork = builder
+      .build_next(&first)
+      .payload(ipld!({ "fork": true }))
+      .done()
+      .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let store = FsStore::new(dir.path(), FsStoreOptions::default().sync(false)).unwrap();
+    store.save(strand.clone()).await.unwrap();
+    store.save_many([first, next.clone()]).await.unwrap();
+    // saving the same tixel again is fine
+    store.save(next.clone()).await.unwrap();
+    assert!(store.save(fork.clone()).await.is_err());
+    assert!(store.save_many([fork]).await.is_err());
+    assert_eq!(
+      store.resolve_index(&strand, 1).await.unwrap().unpack(),
+      next
+    );
+  }
+}
@@ -0,0 +1,299 @@
+//! Threshold (k-of-n) co-signing of tixels
+//!
+//! A strand can require that each of its tixels is approved by at least
+//! `k` of `n` co-signers, so that no single operator can publish alone.
+//! The strand declares the co-signers' public keys and the threshold
+//! as a [`CosignPolicy`] in its details under [`COSIGNERS_DETAILS_KEY`].
+//!
+//! Each co-signer signs the digest of a [`CosignRequest`], which covers
+//! the whole unsigned content of the tixel (its strand, index, stitches,
+//! drop index and payload), without the co-signatures themselves. The
+//! resulting detached [`Cosignature`]s are placed in the tixel payload
+//! under [`COSIGNATURES_FIELD`], and the tixel is then signed by the
+//! strand's own key as usual.
+//!
+//! The policy is not part of core verification. Wrap a resolver in a
+//! [`crate::resolver::CosignedResolver`] to reject tixels without enough
+//! valid co-signatures.
+use crate::crypto::{PublicKey, Signature};
+use crate::errors::VerificationError;
+use crate::twine::{Strand, Tixel};
+use crate::{Cid, Ipld};
+use ipld_core::codec::Codec;
+use ipld_core::serde::{from_ipld, to_ipld};
+use multihash_codetable::{Multihash, MultihashDigest};
+use serde::{Deserialize, Serialize};
+use serde_ipld_dagcbor::codec::DagCborCodec;
+use std::collections::HashSet;
+
+/// The key in a strand's details that holds its [`CosignPolicy`]
+pub const COSIGNERS_DETAILS_KEY: &str = "cosigners";
+
+/// The payload field that holds the [`Cosignature`]s of a tixel
+pub const COSIGNATURES_FIELD: &str = "cosignatures";
+
+// keys of the v2 tixel content
+const CONTENT_STRAND_KEY: &str = "s";
+const CONTENT_INDEX_KEY: &str = "i";
+const CONTENT_PAYLOAD_KEY: &str = "p";
+
+/// The co-signers of a strand and how many of them must sign each tixel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CosignPolicy {
+  /// The minimum number of co-signatures (k)
+  pub threshold: u32,
+  /// The public keys of the co-signers (n)
+  pub keys: Vec<PublicKey>,
+}
+
+impl CosignPolicy {
+  /// Create a new policy requiring `threshold` of the given keys
+  ///
+  /// The threshold must be at least 1 and no more than the number of keys.
+  pub fn new(threshold: u32, keys: Vec<PublicKey>) -> Result<Self, VerificationError> {
+    let policy = Self { threshold, keys };
+    policy.check()?;
+    Ok(policy)
+  }
+
+  fn check(&self) -> Result<(), VerificationError> {
+    if self.threshold == 0 || self.threshold as usize > self.keys.len() {
+      return Err(VerificationError::General(format!(
+        "Co-signing threshold {} is invalid for {} keys",
+        self.threshold,
+        self.keys.len()
+      )));
+    }
+    Ok(())
+  }
+
+  /// Read the policy declared in a strand's details, if any
+  pub fn from_strand(strand: &Strand) -> Result<Option<Self>, VerificationError> {
+    let value = match strand.details() {
+      Ipld::Map(map) => map.get(COSIGNERS_DETAILS_KEY),
+      _ => None,
+    };
+    let policy: Self = match value {
+      Some(value) => from_ipld(value.clone())
+        .map_err(|e| VerificationError::General(format!("Invalid co-signing policy: {}", e)))?,
+      None => return Ok(None),
+    };
+    policy.check()?;
+    Ok(Some(policy))
+  }
+
+  /// Get the position of a key among the co-signers
+  pub fn position(&self, key: &PublicKey) -> Option<u32> {
+    self
+      .keys
+      .iter()
+      .position(|k| k.key == key.key && k.alg.to_string() == key.alg.to_string())
+      .map(|i| i as u32)
+  }
+
+  /// Check that a tixel carries at least `threshold` valid co-signatures
+  ///
+  /// Every co-signature present must be valid and from a distinct co-signer.
+  pub fn verify(&self, strand: &Strand, tixel: &Tixel) -> Result<(), VerificationError> {
+    let request = CosignRequest::from_tixel(tixel)?;
+    let cosignatures = Cosignature::from_payload(tixel.payload())?;
+    self.verify_request(strand, &request, &cosignatures)
+  }
+
+  /// Check co-signatures against a request before they are added to a tixel
+  ///
+  /// See [`CosignPolicy::verify`].
+  pub fn verify_request(
+    &self,
+    strand: &Strand,
+    request: &CosignRequest,
+    cosignatures: &[Cosignature],
+  ) -> Result<(), VerificationError> {
+    let digest = request.digest(strand)?.to_bytes();
+    let mut signers = HashSet::new();
+    for cosignature in cosignatures {
+      let key = self.keys.get(cosignature.key as usize).ok_or_else(|| {
+        VerificationError::BadSignature(format!("Unknown co-signer {}", cosignature.key))
+      })?;
+      if !signers.insert(cosignature.key) {
+        return Err(VerificationError::BadSignature(format!(
+          "Duplicate co-signature from {}",
+          cosignature.key
+        )));
+      }
+      key.verify(cosignature.signature.clone(), &digest)?;
+    }
+    if signers.len() < self.threshold as usize {
+      return Err(VerificationError::BadSignature(format!(
+        "Only {} of {} required co-signatures",
+        signers.len(),
+        self.threshold
+      )));
+    }
+    Ok(())
+  }
+}
+
+/// A detached signature from one co-signer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cosignature {
+  /// The position of the co-signer's key in the [`CosignPolicy`]
+  pub key: u32,
+  /// The signature over the [`CosignRequest`] digest
+  pub signature: Signature,
+}
+
+impl Cosignature {
+  /// Read the co-signatures recorded in a payload
+  ///
+  /// A payload without the [`COSIGNATURES_FIELD`] has none.
+  pub fn from_payload(payload: &Ipld) -> Result<Vec<Self>, VerificationError> {
+    let value = match payload {
+      Ipld::Map(map) => map.get(COSIGNATURES_FIELD),
+      _ => None,
+    };
+    match value {
+      Some(value) => from_ipld(value.clone())
+        .map_err(|e| VerificationError::Payload(format!("Invalid co-signatures: {}", e))),
+      None => Ok(vec![]),
+    }
+  }
+}
+
+/// The part of a tixel that co-signers sign
+///
+/// This is the unsigned content of the tixel with the co-signatures
+/// left out of its payload. It can be serialized and sent to each
+/// co-signer, who signs the bytes of its [`CosignRequest::digest`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CosignRequest {
+  /// The unsigned content of the tixel, without any co-signatures
+  pub content: Ipld,
+}
+
+impl CosignRequest {
+  /// Create a request from the content of a tixel that has not been signed yet
+  ///
+  /// Any co-signatures in the payload are removed. A null payload is
+  /// treated as an empty map, since the co-signatures will be added to it.
+  pub fn new(mut content: Ipld) -> Result<Self, VerificationError> {
+    let payload = match &mut content {
+      Ipld::Map(map) => map.get_mut(CONTENT_PAYLOAD_KEY),
+      _ => None,
+    }
+    .ok_or_else(|| VerificationError::InvalidTwineFormat("Tixel content has no payload".into()))?;
+    match payload {
+      Ipld::Null => *payload = Ipld::Map(Default::default()),
+      Ipld::Map(map) => {
+        map.remove(COSIGNATURES_FIELD);
+      }
+      _ => {}
+    }
+    Ok(Self { content })
+  }
+
+  /// Recreate the request that the co-signers of a tixel signed
+  pub fn from_tixel(tixel: &Tixel) -> Result<Self, VerificationError> {
+    let content = DagCborCodec::decode_from_slice(&tixel.0.content_bytes())
+      .map_err(|e| VerificationError::InvalidTwineFormat(e.to_string()))?;
+    Self::new(content)
+  }
+
+  fn field(&self, key: &str) -> Option<&Ipld> {
+    match &self.content {
+      Ipld::Map(map) => map.get(key),
+      _ => None,
+    }
+  }
+
+  /// The strand of the tixel
+  pub fn strand(&self) -> Option<Cid> {
+    match self.field(CONTENT_STRAND_KEY) {
+      Some(Ipld::Link(cid)) => Some(*cid),
+      _ => None,
+    }
+  }
+
+  /// The index of the tixel
+  pub fn index(&self) -> Option<u64> {
+    match self.field(CONTENT_INDEX_KEY) {
+      Some(Ipld::Integer(index)) => u64::try_from(*index).ok(),
+      _ => None,
+    }
+  }
+
+  /// The payload of the tixel, without any co-signatures
+  pub fn payload(&self) -> &Ipld {
+    self.field(CONTENT_PAYLOAD_KEY).unwrap_or(&Ipld::Null)
+  }
+
+  /// The digest to sign, using the strand's hasher
+  pub fn digest(&self, strand: &Strand) -> Result<Multihash, VerificationError> {
+    if self.strand() != Some(strand.cid()) {
+      return Err(VerificationError::TixelNotOnStrand);
+    }
+    let bytes = DagCborCodec::encode_to_vec(&self.content)?;
+    Ok(strand.hasher().digest(&bytes))
+  }
+
+  /// Add co-signatures to the payload, replacing any existing ones
+  ///
+  /// The payload must be a map.
+  pub fn payload_with(&self, cosignatures: &[Cosignature]) -> Result<Ipld, VerificationError> {
+    let mut map = match self.payload() {
+      Ipld::Map(map) => map.clone(),
+      _ => {
+        return Err(VerificationError::Payload(
+          "Payload must be a map to record co-signatures".into(),
+        ))
+      }
+    };
+    let cosignatures =
+      to_ipld(cosignatures).map_err(|e| VerificationError::General(e.to_string()))?;
+    map.insert(COSIGNATURES_FIELD.to_string(), cosignatures);
+    Ok(Ipld::Map(map))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::crypto::SignatureAlgorithm;
+
+  #[test]
+  fn test_policy_threshold() {
+    let key = PublicKey::new(SignatureAlgorithm::Ed25519, vec![1; 32].into());
+    assert!(CosignPolicy::new(0, vec![key.clone()]).is_err());
+    assert!(CosignPolicy::new(2, vec![key.clone()]).is_err());
+    let policy = CosignPolicy::new(1, vec![key.clone()]).unwrap();
+    assert_eq!(policy.position(&key), Some(0));
+  }
+
+  #[test]
+  fn test_request_strips_cosignatures() {
+    let cosignatures = vec![Cosignature {
+      key: 0,
+      signature: vec![1, 2, 3].into(),
+    }];
+    let content = |payload| {
+      Ipld::Map(
+        [
+          ("s".to_string(), Ipld::Link(Cid::default())),
+          ("i".to_string(), Ipld::Integer(1)),
+          ("p".to_string(), payload),
+        ]
+        .into(),
+      )
+    };
+    let request = CosignRequest::new(content(Ipld::Null)).unwrap();
+    assert_eq!(request.strand(), Some(Cid::default()));
+    assert_eq!(request.index(), Some(1));
+    let payload = request.payload_with(&cosignatures).unwrap();
+    assert_eq!(Cosignature::from_payload(&payload).unwrap(), cosignatures);
+    let again = CosignRequest::new(content(payload)).unwrap();
+    assert_eq!(again, request);
+    assert_eq!(again.payload(), &Ipld::Map(Default::default()));
+    assert!(Cosignature::from_payload(&Ipld::Null).unwrap().is_empty());
+    assert!(CosignRequest::new(Ipld::Null).is_err());
+  }
+}
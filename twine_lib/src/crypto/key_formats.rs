@@ -0,0 +1,634 @@
+//! Conversions between [`PublicKey`] and other public key formats
+//!
+//! Twine stores keys in the form its signature libraries expect: PKCS#1
+//! for RSA, uncompressed SEC1 points for ECDSA and raw bytes for EdDSA
+//! and the post-quantum schemes. The methods here translate to and from
+//! SPKI, JWK and OpenSSH so that a strand's key can be compared with keys
+//! held by other tools.
+use super::{PublicKey, SignatureAlgorithm};
+use crate::{cid::multibase, errors::KeyFormatError};
+use base64::{
+  engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+  Engine,
+};
+use const_oid::{
+  db::{rfc5912, rfc8410},
+  ObjectIdentifier,
+};
+use elliptic_curve::{pkcs8::AssociatedOid, sec1::ToEncodedPoint};
+use multihash_codetable::{Code, Multihash, MultihashDigest};
+use rsa::{
+  pkcs1::{DecodeRsaPublicKey, EncodeRsaPublicKey},
+  traits::PublicKeyParts,
+  BigUint, RsaPublicKey,
+};
+use serde_json::{json, Value};
+use spki::{
+  der::{asn1::BitString, Any, Decode, Encode},
+  AlgorithmIdentifierOwned, SubjectPublicKeyInfoOwned,
+};
+
+// FIPS 204 and FIPS 205 (RFC 9881, RFC 9909)
+const ID_ML_DSA_44: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.17");
+const ID_ML_DSA_65: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.18");
+const ID_ML_DSA_87: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.19");
+const ID_SLH_DSA_SHA2_128S: ObjectIdentifier =
+  ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.20");
+const ID_SLH_DSA_SHA2_256S: ObjectIdentifier =
+  ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.24");
+
+const PEM_LABEL: &str = "PUBLIC KEY";
+
+fn malformed<E: std::fmt::Display>(e: E) -> KeyFormatError {
+  KeyFormatError::Malformed(e.to_string())
+}
+
+fn unsupported(alg: &SignatureAlgorithm) -> KeyFormatError {
+  KeyFormatError::Unsupported(alg.to_string())
+}
+
+/// The algorithm an RSA key is assumed to use when a format does not say
+///
+/// This matches the sizes offered by `twine keygen`.
+fn default_rsa_alg(bits: usize) -> SignatureAlgorithm {
+  match bits {
+    3072 => SignatureAlgorithm::Sha384Rsa(bits),
+    4096 => SignatureAlgorithm::Sha512Rsa(bits),
+    _ => SignatureAlgorithm::Sha256Rsa(bits),
+  }
+}
+
+fn rsa_bits(key: &RsaPublicKey) -> usize {
+  key.n().bits().div_ceil(8) * 8
+}
+
+/// Normalize a SEC1 point into the uncompressed form ring expects
+fn uncompressed_point(alg: &SignatureAlgorithm, point: &[u8]) -> Result<Vec<u8>, KeyFormatError> {
+  let point = match alg {
+    SignatureAlgorithm::EcdsaP256 => p256::PublicKey::from_sec1_bytes(point)
+      .map_err(malformed)?
+      .to_encoded_point(false)
+      .as_bytes()
+      .to_vec(),
+    SignatureAlgorithm::EcdsaP384 => p384::PublicKey::from_sec1_bytes(point)
+      .map_err(malformed)?
+      .to_encoded_point(false)
+      .as_bytes()
+      .to_vec(),
+    SignatureAlgorithm::EcdsaP521 => p521::PublicKey::from_sec1_bytes(point)
+      .map_err(malformed)?
+      .to_encoded_point(false)
+      .as_bytes()
+      .to_vec(),
+    SignatureAlgorithm::EcdsaSecp256k1 => k256::PublicKey::from_sec1_bytes(point)
+      .map_err(malformed)?
+      .to_encoded_point(false)
+      .as_bytes()
+      .to_vec(),
+    _ => unreachable!(),
+  };
+  Ok(point)
+}
+
+fn compressed_point(alg: &SignatureAlgorithm, point: &[u8]) -> Result<Vec<u8>, KeyFormatError> {
+  let point = match alg {
+    SignatureAlgorithm::EcdsaP256 => p256::PublicKey::from_sec1_bytes(point)
+      .map_err(malformed)?
+      .to_encoded_point(true)
+      .as_bytes()
+      .to_vec(),
+    SignatureAlgorithm::EcdsaP384 => p384::PublicKey::from_sec1_bytes(point)
+      .map_err(malformed)?
+      .to_encoded_point(true)
+      .as_bytes()
+      .to_vec(),
+    SignatureAlgorithm::EcdsaP521 => p521::PublicKey::from_sec1_bytes(point)
+      .map_err(malformed)?
+      .to_encoded_point(true)
+      .as_bytes()
+      .to_vec(),
+    SignatureAlgorithm::EcdsaSecp256k1 => k256::PublicKey::from_sec1_bytes(point)
+      .map_err(malformed)?
+      .to_encoded_point(true)
+      .as_bytes()
+      .to_vec(),
+    _ => unreachable!(),
+  };
+  Ok(point)
+}
+
+fn curve_oid(alg: &SignatureAlgorithm) -> Option<ObjectIdentifier> {
+  match alg {
+    SignatureAlgorithm::EcdsaP256 => Some(p256::NistP256::OID),
+    SignatureAlgorithm::EcdsaP384 => Some(p384::NistP384::OID),
+    SignatureAlgorithm::EcdsaP521 => Some(p521::NistP521::OID),
+    SignatureAlgorithm::EcdsaSecp256k1 => Some(k256::Secp256k1::OID),
+    _ => None,
+  }
+}
+
+fn curve_from_oid(oid: ObjectIdentifier) -> Option<SignatureAlgorithm> {
+  [
+    SignatureAlgorithm::EcdsaP256,
+    SignatureAlgorithm::EcdsaP384,
+    SignatureAlgorithm::EcdsaP521,
+    SignatureAlgorithm::EcdsaSecp256k1,
+  ]
+  .into_iter()
+  .find(|alg| curve_oid(alg) == Some(oid))
+}
+
+fn is_rsa(alg: &SignatureAlgorithm) -> bool {
+  matches!(
+    alg,
+    SignatureAlgorithm::Sha256Rsa(_)
+      | SignatureAlgorithm::Sha384Rsa(_)
+      | SignatureAlgorithm::Sha512Rsa(_)
+      | SignatureAlgorithm::Sha256RsaPss(_)
+      | SignatureAlgorithm::Sha384RsaPss(_)
+      | SignatureAlgorithm::Sha512RsaPss(_)
+  )
+}
+
+/// Append an unsigned varint (as used by multicodec) to a buffer
+fn push_varint(buf: &mut Vec<u8>, mut n: u64) {
+  while n >= 0x80 {
+    buf.push((n as u8) | 0x80);
+    n >>= 7;
+  }
+  buf.push(n as u8);
+}
+
+impl PublicKey {
+  fn rsa_key(&self) -> Result<RsaPublicKey, KeyFormatError> {
+    RsaPublicKey::from_pkcs1_der(&self.key).map_err(malformed)
+  }
+
+  /// The SEC1 point of an ECDSA key
+  ///
+  /// Keys converted from twine/1 JWKs are stored as SPKI, so
+  /// unwrap those first.
+  fn ec_point(&self) -> Result<Vec<u8>, KeyFormatError> {
+    let point = match SubjectPublicKeyInfoOwned::from_der(&self.key) {
+      Ok(info) => info.subject_public_key.raw_bytes().to_vec(),
+      Err(_) => self.key.to_vec(),
+    };
+    uncompressed_point(&self.alg, &point)
+  }
+
+  fn spki_algorithm(&self) -> Result<AlgorithmIdentifierOwned, KeyFormatError> {
+    let (oid, parameters) = match &self.alg {
+      alg if is_rsa(alg) => (rfc5912::RSA_ENCRYPTION, Some(Any::null())),
+      alg @ (SignatureAlgorithm::EcdsaP256
+      | SignatureAlgorithm::EcdsaP384
+      | SignatureAlgorithm::EcdsaP521
+      | SignatureAlgorithm::EcdsaSecp256k1) => {
+        (rfc5912::ID_EC_PUBLIC_KEY, curve_oid(alg).map(Any::from))
+      }
+      SignatureAlgorithm::Ed25519 => (rfc8410::ID_ED_25519, None),
+      SignatureAlgorithm::Ed448 => (rfc8410::ID_ED_448, None),
+      SignatureAlgorithm::MlDsa44 => (ID_ML_DSA_44, None),
+      SignatureAlgorithm::MlDsa65 => (ID_ML_DSA_65, None),
+      SignatureAlgorithm::MlDsa87 => (ID_ML_DSA_87, None),
+      SignatureAlgorithm::SlhDsaSha2_128s => (ID_SLH_DSA_SHA2_128S, None),
+      SignatureAlgorithm::SlhDsaSha2_256s => (ID_SLH_DSA_SHA2_256S, None),
+      alg => return Err(unsupported(alg)),
+    };
+    Ok(AlgorithmIdentifierOwned { oid, parameters })
+  }
+
+  /// Encode the key as a DER `SubjectPublicKeyInfo` (RFC 5280)
+  pub fn to_spki_der(&self) -> Result<Vec<u8>, KeyFormatError> {
+    let key = match &self.alg {
+      alg if is_rsa(alg) => self.rsa_key()?.to_pkcs1_der().map_err(malformed)?.to_vec(),
+      alg if curve_oid(alg).is_some() => self.ec_point()?,
+      _ => self.key.to_vec(),
+    };
+    let info = SubjectPublicKeyInfoOwned {
+      algorithm: self.spki_algorithm()?,
+      subject_public_key: BitString::from_bytes(&key).map_err(malformed)?,
+    };
+    info.to_der().map_err(malformed)
+  }
+
+  /// Encode the key as a PEM `PUBLIC KEY`, as written by `openssl pkey -pubout`
+  pub fn to_spki_pem(&self) -> Result<String, KeyFormatError> {
+    let doc = spki::Document::from_der(&self.to_spki_der()?).map_err(malformed)?;
+    doc
+      .to_pem(PEM_LABEL, spki::der::pem::LineEnding::LF)
+      .map_err(malformed)
+  }
+
+  /// Decode a DER `SubjectPublicKeyInfo`
+  ///
+  /// SPKI does not record which hash an RSA key signs with, so RSA keys
+  /// are given SHA256, SHA384 or SHA512 for 2048, 3072 and 4096 bits
+  /// respectively. Set [`PublicKey::alg`] afterwards if that is wrong.
+  pub fn from_spki_der(der: &[u8]) -> Result<Self, KeyFormatError> {
+    let info = SubjectPublicKeyInfoOwned::from_der(der).map_err(malformed)?;
+    let raw = info
+      .subject_public_key
+      .as_bytes()
+      .ok_or_else(|| malformed("unaligned public key bit string"))?;
+    let (alg, key) = match info.algorithm.oid {
+      rfc5912::RSA_ENCRYPTION => {
+        let key = RsaPublicKey::from_pkcs1_der(raw).map_err(malformed)?;
+        (default_rsa_alg(rsa_bits(&key)), raw.to_vec())
+      }
+      rfc5912::ID_EC_PUBLIC_KEY => {
+        let curve: ObjectIdentifier = info
+          .algorithm
+          .parameters
+          .as_ref()
+          .ok_or_else(|| malformed("missing curve"))?
+          .decode_as()
+          .map_err(malformed)?;
+        let alg = curve_from_oid(curve)
+          .ok_or_else(|| KeyFormatError::Unsupported(format!("curve {}", curve)))?;
+        let point = uncompressed_point(&alg, raw)?;
+        (alg, point)
+      }
+      rfc8410::ID_ED_25519 => (SignatureAlgorithm::Ed25519, raw.to_vec()),
+      rfc8410::ID_ED_448 => (SignatureAlgorithm::Ed448, raw.to_vec()),
+      ID_ML_DSA_44 => (SignatureAlgorithm::MlDsa44, raw.to_vec()),
+      ID_ML_DSA_65 => (SignatureAlgorithm::MlDsa65, raw.to_vec()),
+      ID_ML_DSA_87 => (SignatureAlgorithm::MlDsa87, raw.to_vec()),
+      ID_SLH_DSA_SHA2_128S => (SignatureAlgorithm::SlhDsaSha2_128s, raw.to_vec()),
+      ID_SLH_DSA_SHA2_256S => (SignatureAlgorithm::SlhDsaSha2_256s, raw.to_vec()),
+      oid => return Err(KeyFormatError::Unsupported(format!("OID {}", oid))),
+    };
+    Ok(Self::new(alg, key.into()))
+  }
+
+  /// Decode a PEM `PUBLIC KEY`
+  ///
+  /// See [`PublicKey::from_spki_der`] for how RSA keys are handled.
+  pub fn from_spki_pem<S: AsRef<str>>(pem: S) -> Result<Self, KeyFormatError> {
+    let (label, doc) = spki::Document::from_pem(pem.as_ref()).map_err(malformed)?;
+    if label != PEM_LABEL {
+      return Err(malformed(format!(
+        "expected {}, found {}",
+        PEM_LABEL, label
+      )));
+    }
+    Self::from_spki_der(doc.as_bytes())
+  }
+
+  /// A stable fingerprint of the key
+  ///
+  /// This is the sha2-256 multihash of the DER encoded SPKI, so it
+  /// does not depend on how the key happens to be stored.
+  pub fn fingerprint(&self) -> Result<Multihash, KeyFormatError> {
+    Ok(Code::Sha2_256.digest(&self.to_spki_der()?))
+  }
+
+  /// Render the key as a JSON Web Key
+  ///
+  /// RSA and ECDSA keys follow RFC 7518, EdDSA keys RFC 8037 and
+  /// secp256k1 RFC 8812. Post-quantum keys use the `AKP` key type
+  /// from the JOSE ML-DSA draft.
+  pub fn to_jwk(&self) -> Result<Value, KeyFormatError> {
+    let b64 = |b: &[u8]| URL_SAFE_NO_PAD.encode(b);
+    let jwk = match &self.alg {
+      alg if is_rsa(alg) => {
+        let key = self.rsa_key()?;
+        let name = match alg {
+          SignatureAlgorithm::Sha256Rsa(_) => "RS256",
+          SignatureAlgorithm::Sha384Rsa(_) => "RS384",
+          SignatureAlgorithm::Sha512Rsa(_) => "RS512",
+          SignatureAlgorithm::Sha256RsaPss(_) => "PS256",
+          SignatureAlgorithm::Sha384RsaPss(_) => "PS384",
+          _ => "PS512",
+        };
+        json!({
+          "kty": "RSA",
+          "alg": name,
+          "n": b64(&key.n().to_bytes_be()),
+          "e": b64(&key.e().to_bytes_be()),
+        })
+      }
+      alg if curve_oid(alg).is_some() => {
+        let (crv, name) = match alg {
+          SignatureAlgorithm::EcdsaP256 => ("P-256", "ES256"),
+          SignatureAlgorithm::EcdsaP384 => ("P-384", "ES384"),
+          SignatureAlgorithm::EcdsaP521 => ("P-521", "ES512"),
+          _ => ("secp256k1", "ES256K"),
+        };
+        let point = self.ec_point()?;
+        let (x, y) = point[1..].split_at((point.len() - 1) / 2);
+        json!({ "kty": "EC", "alg": name, "crv": crv, "x": b64(x), "y": b64(y) })
+      }
+      SignatureAlgorithm::Ed25519 => {
+        json!({ "kty": "OKP", "alg": "EdDSA", "crv": "Ed25519", "x": b64(&self.key) })
+      }
+      SignatureAlgorithm::Ed448 => {
+        json!({ "kty": "OKP", "alg": "EdDSA", "crv": "Ed448", "x": b64(&self.key) })
+      }
+      alg @ (SignatureAlgorithm::MlDsa44
+      | SignatureAlgorithm::MlDsa65
+      | SignatureAlgorithm::MlDsa87
+      | SignatureAlgorithm::SlhDsaSha2_128s
+      | SignatureAlgorithm::SlhDsaSha2_256s) => {
+        json!({ "kty": "AKP", "alg": alg.to_string(), "pub": b64(&self.key) })
+      }
+      alg => return Err(unsupported(alg)),
+    };
+    Ok(jwk)
+  }
+
+  /// Read a JSON Web Key produced by [`PublicKey::to_jwk`] or another tool
+  ///
+  /// RSA keys without an `alg` are treated as in [`PublicKey::from_spki_der`].
+  pub fn from_jwk(jwk: &Value) -> Result<Self, KeyFormatError> {
+    let field = |name: &str| {
+      jwk
+        .get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| malformed(format!("missing \"{}\"", name)))
+    };
+    let bytes = |name: &str| URL_SAFE_NO_PAD.decode(field(name)?).map_err(malformed);
+    let key = match field("kty")? {
+      "RSA" => {
+        let key = RsaPublicKey::new(
+          BigUint::from_bytes_be(&bytes("n")?),
+          BigUint::from_bytes_be(&bytes("e")?),
+        )
+        .map_err(malformed)?;
+        let bits = rsa_bits(&key);
+        let alg = match jwk.get("alg").and_then(Value::as_str) {
+          None => default_rsa_alg(bits),
+          Some("RS256") => SignatureAlgorithm::Sha256Rsa(bits),
+          Some("RS384") => SignatureAlgorithm::Sha384Rsa(bits),
+          Some("RS512") => SignatureAlgorithm::Sha512Rsa(bits),
+          Some("PS256") => SignatureAlgorithm::Sha256RsaPss(bits),
+          Some("PS384") => SignatureAlgorithm::Sha384RsaPss(bits),
+          Some("PS512") => SignatureAlgorithm::Sha512RsaPss(bits),
+          Some(other) => return Err(KeyFormatError::Unsupported(other.into())),
+        };
+        let der = key.to_pkcs1_der().map_err(malformed)?;
+        Self::new(alg, der.as_bytes().into())
+      }
+      "EC" => {
+        let alg = match field("crv")? {
+          "P-256" => SignatureAlgorithm::EcdsaP256,
+          "P-384" => SignatureAlgorithm::EcdsaP384,
+          "P-521" => SignatureAlgorithm::EcdsaP521,
+          "secp256k1" => SignatureAlgorithm::EcdsaSecp256k1,
+          other => return Err(KeyFormatError::Unsupported(other.into())),
+        };
+        let mut point = vec![0x04];
+        point.extend(bytes("x")?);
+        point.extend(bytes("y")?);
+        let point = uncompressed_point(&alg, &point)?;
+        Self::new(alg, point.into())
+      }
+      "OKP" => {
+        let alg = match field("crv")? {
+          "Ed25519" => SignatureAlgorithm::Ed25519,
+          "Ed448" => SignatureAlgorithm::Ed448,
+          other => return Err(KeyFormatError::Unsupported(other.into())),
+        };
+        Self::new(alg, bytes("x")?.into())
+      }
+      "AKP" => {
+        let alg = field("alg")?
+          .parse()
+          .map_err(|_| malformed("unknown \"alg\""))?;
+        Self::new(alg, bytes("pub")?.into())
+      }
+      other => return Err(KeyFormatError::Unsupported(other.into())),
+    };
+    Ok(key)
+  }
+
+  /// Render the key as an OpenSSH `authorized_keys` line, without a comment
+  ///
+  /// OpenSSH has no key types for secp256k1, Ed448 or the post-quantum schemes.
+  pub fn to_openssh(&self) -> Result<String, KeyFormatError> {
+    fn put(buf: &mut Vec<u8>, data: &[u8]) {
+      buf.extend((data.len() as u32).to_be_bytes());
+      buf.extend(data);
+    }
+    fn put_mpint(buf: &mut Vec<u8>, n: &BigUint) {
+      let mut bytes = n.to_bytes_be();
+      if bytes.first().is_some_and(|b| b & 0x80 != 0) {
+        bytes.insert(0, 0);
+      }
+      put(buf, &bytes);
+    }
+
+    let mut blob = vec![];
+    let name = match &self.alg {
+      alg if is_rsa(alg) => {
+        let key = self.rsa_key()?;
+        put(&mut blob, b"ssh-rsa");
+        put_mpint(&mut blob, key.e());
+        put_mpint(&mut blob, key.n());
+        "ssh-rsa"
+      }
+      alg @ (SignatureAlgorithm::EcdsaP256
+      | SignatureAlgorithm::EcdsaP384
+      | SignatureAlgorithm::EcdsaP521) => {
+        let curve = match alg {
+          SignatureAlgorithm::EcdsaP256 => "nistp256",
+          SignatureAlgorithm::EcdsaP384 => "nistp384",
+          _ => "nistp521",
+        };
+        let name = match alg {
+          SignatureAlgorithm::EcdsaP256 => "ecdsa-sha2-nistp256",
+          SignatureAlgorithm::EcdsaP384 => "ecdsa-sha2-nistp384",
+          _ => "ecdsa-sha2-nistp521",
+        };
+        put(&mut blob, name.as_bytes());
+        put(&mut blob, curve.as_bytes());
+        put(&mut blob, &self.ec_point()?);
+        name
+      }
+      SignatureAlgorithm::Ed25519 => {
+        put(&mut blob, b"ssh-ed25519");
+        put(&mut blob, &self.key);
+        "ssh-ed25519"
+      }
+      alg => return Err(unsupported(alg)),
+    };
+    Ok(format!("{} {}", name, STANDARD.encode(blob)))
+  }
+
+  /// Read an OpenSSH public key line, such as one from `authorized_keys`
+  ///
+  /// Any trailing comment is ignored. `ssh-rsa` keys are treated as in
+  /// [`PublicKey::from_spki_der`].
+  pub fn from_openssh<S: AsRef<str>>(line: S) -> Result<Self, KeyFormatError> {
+    fn take<'a>(blob: &mut &'a [u8]) -> Result<&'a [u8], KeyFormatError> {
+      if blob.len() < 4 {
+        return Err(malformed("truncated OpenSSH key"));
+      }
+      let (len, rest) = blob.split_at(4);
+      let len = u32::from_be_bytes(len.try_into().unwrap()) as usize;
+      if rest.len() < len {
+        return Err(malformed("truncated OpenSSH key"));
+      }
+      let (data, rest) = rest.split_at(len);
+      *blob = rest;
+      Ok(data)
+    }
+
+    let mut parts = line.as_ref().split_whitespace();
+    let name = parts.next().ok_or_else(|| malformed("empty OpenSSH key"))?;
+    let blob = STANDARD
+      .decode(parts.next().ok_or_else(|| malformed("missing key data"))?)
+      .map_err(malformed)?;
+    let mut blob = blob.as_slice();
+    if take(&mut blob)? != name.as_bytes() {
+      return Err(malformed("key type does not match its data"));
+    }
+    let key = match name {
+      "ssh-rsa" => {
+        let e = BigUint::from_bytes_be(take(&mut blob)?);
+        let n = BigUint::from_bytes_be(take(&mut blob)?);
+        let key = RsaPublicKey::new(n, e).map_err(malformed)?;
+        let der = key.to_pkcs1_der().map_err(malformed)?;
+        Self::new(default_rsa_alg(rsa_bits(&key)), der.as_bytes().into())
+      }
+      "ecdsa-sha2-nistp256" | "ecdsa-sha2-nistp384" | "ecdsa-sha2-nistp521" => {
+        let (alg, curve) = match name {
+          "ecdsa-sha2-nistp256" => (SignatureAlgorithm::EcdsaP256, "nistp256"),
+          "ecdsa-sha2-nistp384" => (SignatureAlgorithm::EcdsaP384, "nistp384"),
+          _ => (SignatureAlgorithm::EcdsaP521, "nistp521"),
+        };
+        if take(&mut blob)? != curve.as_bytes() {
+          return Err(malformed("curve does not match the key type"));
+        }
+        let point = uncompressed_point(&alg, take(&mut blob)?)?;
+        Self::new(alg, point.into())
+      }
+      "ssh-ed25519" => Self::new(SignatureAlgorithm::Ed25519, take(&mut blob)?.into()),
+      other => return Err(KeyFormatError::Unsupported(other.into())),
+    };
+    Ok(key)
+  }
+
+  /// Render the key as a `did:key` identifier
+  ///
+  /// ECDSA keys are compressed and RSA keys are PKCS#1, as the did:key
+  /// method requires. The post-quantum schemes have no registered
+  /// multicodec yet, so they are not supported.
+  pub fn to_did_key(&self) -> Result<String, KeyFormatError> {
+    let (codec, key) = match &self.alg {
+      alg if is_rsa(alg) => (
+        0x1205,
+        self.rsa_key()?.to_pkcs1_der().map_err(malformed)?.to_vec(),
+      ),
+      SignatureAlgorithm::Ed25519 => (0xed, self.key.to_vec()),
+      SignatureAlgorithm::Ed448 => (0x1203, self.key.to_vec()),
+      SignatureAlgorithm::EcdsaSecp256k1 => (0xe7, compressed_point(&self.alg, &self.ec_point()?)?),
+      SignatureAlgorithm::EcdsaP256 => (0x1200, compressed_point(&self.alg, &self.ec_point()?)?),
+      SignatureAlgorithm::EcdsaP384 => (0x1201, compressed_point(&self.alg, &self.ec_point()?)?),
+      SignatureAlgorithm::EcdsaP521 => (0x1202, compressed_point(&self.alg, &self.ec_point()?)?),
+      alg => return Err(unsupported(alg)),
+    };
+    let mut bytes = vec![];
+    push_varint(&mut bytes, codec);
+    bytes.extend(key);
+    Ok(format!(
+      "did:key:{}",
+      multibase::encode(multibase::Base::Base58Btc, bytes)
+    ))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use elliptic_curve::pkcs8::EncodePublicKey;
+
+  const ED25519_SPKI_PEM: &str = "-----BEGIN PUBLIC KEY-----
+MCowBQYDK2VwAyEAGb9ECWmEzf6FQbrBZ9w7lshQhqowtrbLDFw4rXAxZuE=
+-----END PUBLIC KEY-----
+";
+
+  #[test]
+  fn test_ed25519_formats() {
+    // RFC 8410 section 10.1 and the did:key test vectors
+    let key = PublicKey::from_spki_pem(ED25519_SPKI_PEM).unwrap();
+    assert!(matches!(key.alg, SignatureAlgorithm::Ed25519));
+    assert_eq!(key.to_spki_pem().unwrap(), ED25519_SPKI_PEM);
+    assert_eq!(
+      key.to_openssh().unwrap(),
+      "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIBm/RAlphM3+hUG6wWfcO5bIUIaqMLa2ywxcOK1wMWbh"
+    );
+    assert_eq!(
+      key.to_jwk().unwrap()["x"],
+      "Gb9ECWmEzf6FQbrBZ9w7lshQhqowtrbLDFw4rXAxZuE"
+    );
+    assert!(key.to_did_key().unwrap().starts_with("did:key:z6Mk"));
+
+    let fingerprint = key.fingerprint().unwrap();
+    assert_eq!(fingerprint.code(), u64::from(Code::Sha2_256));
+    let from_ssh = PublicKey::from_openssh(key.to_openssh().unwrap() + " comment").unwrap();
+    assert_eq!(from_ssh.fingerprint().unwrap(), fingerprint);
+    let from_jwk = PublicKey::from_jwk(&key.to_jwk().unwrap()).unwrap();
+    assert_eq!(from_jwk.fingerprint().unwrap(), fingerprint);
+  }
+
+  #[test]
+  fn test_ecdsa_formats() {
+    use p256::ecdsa::SigningKey;
+    let signing = SigningKey::from_slice(&[3u8; 32]).unwrap();
+    let point = signing.verifying_key().to_encoded_point(false);
+    let key = PublicKey::new(SignatureAlgorithm::EcdsaP256, point.as_bytes().into());
+
+    let der = key.to_spki_der().unwrap();
+    let expected = p256::PublicKey::from(signing.verifying_key())
+      .to_public_key_der()
+      .unwrap();
+    assert_eq!(der, expected.as_bytes());
+
+    let decoded = PublicKey::from_spki_der(&der).unwrap();
+    assert_eq!(decoded.key, key.key);
+    let decoded = PublicKey::from_jwk(&key.to_jwk().unwrap()).unwrap();
+    assert_eq!(decoded.key, key.key);
+    let decoded = PublicKey::from_openssh(key.to_openssh().unwrap()).unwrap();
+    assert_eq!(decoded.key, key.key);
+    assert!(key.to_did_key().unwrap().starts_with("did:key:zDn"));
+
+    // secp256k1 has no OpenSSH key type
+    let signing = k256::ecdsa::SigningKey::from_slice(&[3u8; 32]).unwrap();
+    let point = signing.verifying_key().to_encoded_point(false);
+    let key = PublicKey::new(SignatureAlgorithm::EcdsaSecp256k1, point.as_bytes().into());
+    assert!(matches!(
+      key.to_openssh(),
+      Err(KeyFormatError::Unsupported(_))
+    ));
+    assert!(key.to_did_key().unwrap().starts_with("did:key:zQ3s"));
+  }
+
+  #[test]
+  fn test_openssh_rejects_mismatches() {
+    use p256::ecdsa::SigningKey;
+    let signing = SigningKey::from_slice(&[3u8; 32]).unwrap();
+    let point = signing.verifying_key().to_encoded_point(false);
+    let key = PublicKey::new(SignatureAlgorithm::EcdsaP256, point.as_bytes().into());
+    let line = key.to_openssh().unwrap();
+
+    // swap the curve identifier inside the blob for another one of the same length
+    let blob = STANDARD
+      .decode(line.split_whitespace().nth(1).unwrap())
+      .unwrap();
+    let pos = blob.windows(8).rposition(|w| w == b"nistp256").unwrap();
+    let mut tampered = blob.clone();
+    tampered[pos..pos + 8].copy_from_slice(b"nistp384");
+    let tampered = format!("ecdsa-sha2-nistp256 {}", STANDARD.encode(tampered));
+    assert!(matches!(
+      PublicKey::from_openssh(tampered),
+      Err(KeyFormatError::Malformed(_))
+    ));
+
+    // ed448 has no OpenSSH key type
+    let key = PublicKey::new(SignatureAlgorithm::Ed448, vec![0u8; 57].into());
+    assert!(matches!(
+      key.to_openssh(),
+      Err(KeyFormatError::Unsupported(_))
+    ));
+  }
+}
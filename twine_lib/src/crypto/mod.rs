@@ -11,5 +11,7 @@ pub use serialize::*;
 mod public_key;
 pub use public_key::*;
 
+mod key_formats;
+
 /// A cryptographic signature
 pub type Signature = crate::Bytes;
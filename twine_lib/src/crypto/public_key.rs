@@ -15,12 +15,34 @@ pub enum SignatureAlgorithm {
   Sha384Rsa(usize),
   /// RSA(bitsize) PKCS1.5 sha512
   Sha512Rsa(usize),
+  /// RSA(bitsize) PSS sha256
+  Sha256RsaPss(usize),
+  /// RSA(bitsize) PSS sha384
+  Sha384RsaPss(usize),
+  /// RSA(bitsize) PSS sha512
+  Sha512RsaPss(usize),
   /// ECDSA P-256 sha256
   EcdsaP256,
   /// ECDSA P-384 sha384
   EcdsaP384,
   /// Ed25519 sha512
   Ed25519,
+  /// ECDSA secp256k1 sha256
+  EcdsaSecp256k1,
+  /// ECDSA P-521 sha512
+  EcdsaP521,
+  /// Ed448 shake256
+  Ed448,
+  /// ML-DSA-44 (FIPS 204)
+  MlDsa44,
+  /// ML-DSA-65 (FIPS 204)
+  MlDsa65,
+  /// ML-DSA-87 (FIPS 204)
+  MlDsa87,
+  /// SLH-DSA-SHA2-128s (FIPS 205)
+  SlhDsaSha2_128s,
+  /// SLH-DSA-SHA2-256s (FIPS 205)
+  SlhDsaSha2_256s,
 }
 
 impl Display for SignatureAlgorithm {
@@ -29,9 +51,20 @@ impl Display for SignatureAlgorithm {
       SignatureAlgorithm::Sha256Rsa(bitsize) => write!(f, "RSA {} SHA256", bitsize),
       SignatureAlgorithm::Sha384Rsa(bitsize) => write!(f, "RSA {} SHA384", bitsize),
       SignatureAlgorithm::Sha512Rsa(bitsize) => write!(f, "RSA {} SHA512", bitsize),
+      SignatureAlgorithm::Sha256RsaPss(bitsize) => write!(f, "RSA-PSS {} SHA256", bitsize),
+      SignatureAlgorithm::Sha384RsaPss(bitsize) => write!(f, "RSA-PSS {} SHA384", bitsize),
+      SignatureAlgorithm::Sha512RsaPss(bitsize) => write!(f, "RSA-PSS {} SHA512", bitsize),
       SignatureAlgorithm::EcdsaP256 => write!(f, "ECDSA P-256 SHA256"),
       SignatureAlgorithm::EcdsaP384 => write!(f, "ECDSA P-384 SHA384"),
       SignatureAlgorithm::Ed25519 => write!(f, "Ed25519 SHA512"),
+      SignatureAlgorithm::EcdsaSecp256k1 => write!(f, "ECDSA secp256k1 SHA256"),
+      SignatureAlgorithm::EcdsaP521 => write!(f, "ECDSA P-521 SHA512"),
+      SignatureAlgorithm::Ed448 => write!(f, "Ed448 SHAKE256"),
+      SignatureAlgorithm::MlDsa44 => write!(f, "ML-DSA-44"),
+      SignatureAlgorithm::MlDsa65 => write!(f, "ML-DSA-65"),
+      SignatureAlgorithm::MlDsa87 => write!(f, "ML-DSA-87"),
+      SignatureAlgorithm::SlhDsaSha2_128s => write!(f, "SLH-DSA-SHA2-128s"),
+      SignatureAlgorithm::SlhDsaSha2_256s => write!(f, "SLH-DSA-SHA2-256s"),
     }
   }
 }
@@ -51,16 +84,39 @@ impl FromStr for SignatureAlgorithm {
       "RSA 2048 SHA512" => Ok(SignatureAlgorithm::Sha512Rsa(2048)),
       "RSA 3072 SHA512" => Ok(SignatureAlgorithm::Sha512Rsa(3072)),
       "RSA 4096 SHA512" => Ok(SignatureAlgorithm::Sha512Rsa(4096)),
+      "RSA-PSS 2048 SHA256" => Ok(SignatureAlgorithm::Sha256RsaPss(2048)),
+      "RSA-PSS 3072 SHA256" => Ok(SignatureAlgorithm::Sha256RsaPss(3072)),
+      "RSA-PSS 4096 SHA256" => Ok(SignatureAlgorithm::Sha256RsaPss(4096)),
+      "RSA-PSS 2048 SHA384" => Ok(SignatureAlgorithm::Sha384RsaPss(2048)),
+      "RSA-PSS 3072 SHA384" => Ok(SignatureAlgorithm::Sha384RsaPss(3072)),
+      "RSA-PSS 4096 SHA384" => Ok(SignatureAlgorithm::Sha384RsaPss(4096)),
+      "RSA-PSS 2048 SHA512" => Ok(SignatureAlgorithm::Sha512RsaPss(2048)),
+      "RSA-PSS 3072 SHA512" => Ok(SignatureAlgorithm::Sha512RsaPss(3072)),
+      "RSA-PSS 4096 SHA512" => Ok(SignatureAlgorithm::Sha512RsaPss(4096)),
       "ECDSA P-256 SHA256" => Ok(SignatureAlgorithm::EcdsaP256),
       "ECDSA P-384 SHA384" => Ok(SignatureAlgorithm::EcdsaP384),
-      "Ed25519 SHA512" => Ok(SignatureAlgorithm::Ed25519),
+      "ED25519 SHA512" => Ok(SignatureAlgorithm::Ed25519),
+      "ECDSA SECP256K1 SHA256" => Ok(SignatureAlgorithm::EcdsaSecp256k1),
+      "ECDSA P-521 SHA512" => Ok(SignatureAlgorithm::EcdsaP521),
+      "ED448 SHAKE256" => Ok(SignatureAlgorithm::Ed448),
+      "ML-DSA-44" => Ok(SignatureAlgorithm::MlDsa44),
+      "ML-DSA-65" => Ok(SignatureAlgorithm::MlDsa65),
+      "ML-DSA-87" => Ok(SignatureAlgorithm::MlDsa87),
+      "SLH-DSA-SHA2-128S" => Ok(SignatureAlgorithm::SlhDsaSha2_128s),
+      "SLH-DSA-SHA2-256S" => Ok(SignatureAlgorithm::SlhDsaSha2_256s),
       // shorthand
       "RS256" => Ok(SignatureAlgorithm::Sha256Rsa(2048)),
       "RS384" => Ok(SignatureAlgorithm::Sha384Rsa(2048)),
       "RS512" => Ok(SignatureAlgorithm::Sha512Rsa(2048)),
+      "PS256" => Ok(SignatureAlgorithm::Sha256RsaPss(2048)),
+      "PS384" => Ok(SignatureAlgorithm::Sha384RsaPss(2048)),
+      "PS512" => Ok(SignatureAlgorithm::Sha512RsaPss(2048)),
       "ES256" => Ok(SignatureAlgorithm::EcdsaP256),
       "ES384" => Ok(SignatureAlgorithm::EcdsaP384),
-      "Ed25519" => Ok(SignatureAlgorithm::Ed25519),
+      "ED25519" => Ok(SignatureAlgorithm::Ed25519),
+      "ES256K" => Ok(SignatureAlgorithm::EcdsaSecp256k1),
+      "ES512" => Ok(SignatureAlgorithm::EcdsaP521),
+      "ED448" => Ok(SignatureAlgorithm::Ed448),
       _ => Err(()),
     }
   }
@@ -93,32 +149,57 @@ impl PublicKey {
     match self.alg {
       SignatureAlgorithm::Sha256Rsa(_)
       | SignatureAlgorithm::Sha384Rsa(_)
-      | SignatureAlgorithm::Sha512Rsa(_) => self.verify_rsa(&signature, message.as_ref()),
+      | SignatureAlgorithm::Sha512Rsa(_)
+      | SignatureAlgorithm::Sha256RsaPss(_)
+      | SignatureAlgorithm::Sha384RsaPss(_)
+      | SignatureAlgorithm::Sha512RsaPss(_) => self.verify_rsa(&signature, message.as_ref()),
       SignatureAlgorithm::EcdsaP256 | SignatureAlgorithm::EcdsaP384 => {
         self.verify_ecdsa(&signature, message.as_ref())
       }
       SignatureAlgorithm::Ed25519 => self.verify_ed25519(&signature, message.as_ref()),
+      SignatureAlgorithm::EcdsaSecp256k1 => self.verify_secp256k1(&signature, message.as_ref()),
+      SignatureAlgorithm::EcdsaP521 => self.verify_p521(&signature, message.as_ref()),
+      SignatureAlgorithm::Ed448 => self.verify_ed448(&signature, message.as_ref()),
+      SignatureAlgorithm::MlDsa44
+      | SignatureAlgorithm::MlDsa65
+      | SignatureAlgorithm::MlDsa87
+      | SignatureAlgorithm::SlhDsaSha2_128s
+      | SignatureAlgorithm::SlhDsaSha2_256s => self.verify_pq(&signature, message.as_ref()),
     }
   }
 
   fn verify_rsa(&self, signature: &Signature, message: &[u8]) -> Result<(), VerificationError> {
-    // Verify the RSA signature
-    let alg = match self.alg {
-      SignatureAlgorithm::Sha256Rsa(bitsize) => match bitsize {
-        2048 => &ring::signature::RSA_PKCS1_2048_8192_SHA256,
-        _ => return Err(VerificationError::UnsupportedKeyAlgorithm),
-      },
-      SignatureAlgorithm::Sha384Rsa(bitsize) => match bitsize {
-        2048 => &ring::signature::RSA_PKCS1_2048_8192_SHA384,
-        3072 => &ring::signature::RSA_PKCS1_3072_8192_SHA384,
-        _ => return Err(VerificationError::UnsupportedKeyAlgorithm),
-      },
-      SignatureAlgorithm::Sha512Rsa(bitsize) => match bitsize {
-        2048 => &ring::signature::RSA_PKCS1_2048_8192_SHA512,
-        _ => return Err(VerificationError::UnsupportedKeyAlgorithm),
-      },
+    use ring::signature::{
+      RSA_PKCS1_2048_8192_SHA256, RSA_PKCS1_2048_8192_SHA384, RSA_PKCS1_2048_8192_SHA512,
+      RSA_PSS_2048_8192_SHA256, RSA_PSS_2048_8192_SHA384, RSA_PSS_2048_8192_SHA512,
+    };
+    use rsa::{pkcs1::DecodeRsaPublicKey, traits::PublicKeyParts};
+    let (alg, bitsize): (&ring::signature::RsaParameters, _) = match self.alg {
+      SignatureAlgorithm::Sha256Rsa(bitsize) => (&RSA_PKCS1_2048_8192_SHA256, bitsize),
+      SignatureAlgorithm::Sha384Rsa(bitsize) => (&RSA_PKCS1_2048_8192_SHA384, bitsize),
+      SignatureAlgorithm::Sha512Rsa(bitsize) => (&RSA_PKCS1_2048_8192_SHA512, bitsize),
+      SignatureAlgorithm::Sha256RsaPss(bitsize) => (&RSA_PSS_2048_8192_SHA256, bitsize),
+      SignatureAlgorithm::Sha384RsaPss(bitsize) => (&RSA_PSS_2048_8192_SHA384, bitsize),
+      SignatureAlgorithm::Sha512RsaPss(bitsize) => (&RSA_PSS_2048_8192_SHA512, bitsize),
       _ => unreachable!(),
     };
+    if !matches!(bitsize, 2048 | 3072 | 4096) {
+      return Err(VerificationError::UnsupportedKeyAlgorithm);
+    }
+    // ring accepts any modulus from 2048 to 8192 bits, so make sure
+    // the key is actually the size the algorithm claims
+    let modulus_bits = rsa::RsaPublicKey::from_pkcs1_der(&self.key)
+      .map_err(bad_signature)?
+      .n()
+      .bits()
+      .div_ceil(8)
+      * 8;
+    if modulus_bits != bitsize {
+      return Err(VerificationError::BadSignature(format!(
+        "RSA key is {} bits but the algorithm specifies {}",
+        modulus_bits, bitsize
+      )));
+    }
 
     let public_key = ring::signature::UnparsedPublicKey::new(alg, &self.key);
     public_key
@@ -151,6 +232,120 @@ impl PublicKey {
 
     Ok(())
   }
+
+  // ring does not support the following algorithms, so RustCrypto is used
+
+  fn verify_secp256k1(
+    &self,
+    signature: &Signature,
+    message: &[u8],
+  ) -> Result<(), VerificationError> {
+    use k256::ecdsa::signature::Verifier;
+    let public_key =
+      k256::ecdsa::VerifyingKey::from_sec1_bytes(&self.key).map_err(bad_signature)?;
+    let signature = k256::ecdsa::Signature::from_der(signature).map_err(bad_signature)?;
+    // k256 only accepts low-S signatures, but other tools may not produce them
+    let signature = signature.normalize_s().unwrap_or(signature);
+    public_key
+      .verify(message, &signature)
+      .map_err(bad_signature)
+  }
+
+  fn verify_p521(&self, signature: &Signature, message: &[u8]) -> Result<(), VerificationError> {
+    use p521::ecdsa::signature::Verifier;
+    let public_key =
+      p521::ecdsa::VerifyingKey::from_sec1_bytes(&self.key).map_err(bad_signature)?;
+    let signature = p521::ecdsa::Signature::from_der(signature).map_err(bad_signature)?;
+    public_key
+      .verify(message, &signature)
+      .map_err(bad_signature)
+  }
+
+  fn verify_ed448(&self, signature: &Signature, message: &[u8]) -> Result<(), VerificationError> {
+    use ed448_goldilocks_plus::{Signature as Ed448Signature, VerifyingKey};
+    let key = self.key[..]
+      .try_into()
+      .map_err(|_| VerificationError::BadSignature("Invalid Ed448 public key length".into()))?;
+    let public_key = VerifyingKey::from_bytes(key).map_err(bad_signature)?;
+    let signature = signature[..]
+      .try_into()
+      .map_err(|_| VerificationError::BadSignature("Invalid Ed448 signature length".into()))?;
+    let signature = Ed448Signature::from_bytes(signature).map_err(bad_signature)?;
+    public_key
+      .verify_raw(&signature, message)
+      .map_err(bad_signature)
+  }
+}
+
+#[cfg(feature = "pq")]
+impl PublicKey {
+  // Keys and signatures use the raw FIPS 204/205 encodings,
+  // and messages are signed in "pure" mode with an empty context string
+  fn verify_pq(&self, signature: &Signature, message: &[u8]) -> Result<(), VerificationError> {
+    match self.alg {
+      SignatureAlgorithm::MlDsa44 => {
+        verify_ml_dsa::<ml_dsa::MlDsa44>(&self.key, signature, message)
+      }
+      SignatureAlgorithm::MlDsa65 => {
+        verify_ml_dsa::<ml_dsa::MlDsa65>(&self.key, signature, message)
+      }
+      SignatureAlgorithm::MlDsa87 => {
+        verify_ml_dsa::<ml_dsa::MlDsa87>(&self.key, signature, message)
+      }
+      SignatureAlgorithm::SlhDsaSha2_128s => {
+        verify_slh_dsa::<slh_dsa::Sha2_128s>(&self.key, signature, message)
+      }
+      SignatureAlgorithm::SlhDsaSha2_256s => {
+        verify_slh_dsa::<slh_dsa::Sha2_256s>(&self.key, signature, message)
+      }
+      _ => unreachable!(),
+    }
+  }
+}
+
+#[cfg(not(feature = "pq"))]
+impl PublicKey {
+  fn verify_pq(&self, _signature: &Signature, _message: &[u8]) -> Result<(), VerificationError> {
+    Err(VerificationError::UnsupportedKeyAlgorithm)
+  }
+}
+
+#[cfg(feature = "pq")]
+fn verify_ml_dsa<P: ml_dsa::MlDsaParams>(
+  key: &[u8],
+  signature: &[u8],
+  message: &[u8],
+) -> Result<(), VerificationError> {
+  let key = ml_dsa::EncodedVerifyingKey::<P>::try_from(key)
+    .map_err(|_| VerificationError::BadSignature("Invalid ML-DSA public key length".into()))?;
+  let public_key = ml_dsa::VerifyingKey::<P>::decode(&key);
+  let signature = ml_dsa::Signature::<P>::try_from(signature)
+    .map_err(|_| VerificationError::BadSignature("Invalid ML-DSA signature".into()))?;
+  if public_key.verify_with_context(message, &[], &signature) {
+    Ok(())
+  } else {
+    Err(VerificationError::BadSignature(
+      "ML-DSA signature mismatch".into(),
+    ))
+  }
+}
+
+#[cfg(feature = "pq")]
+fn verify_slh_dsa<P: slh_dsa::ParameterSet>(
+  key: &[u8],
+  signature: &[u8],
+  message: &[u8],
+) -> Result<(), VerificationError> {
+  use slh_dsa::signature::Verifier;
+  let public_key = slh_dsa::VerifyingKey::<P>::try_from(key).map_err(bad_signature)?;
+  let signature = slh_dsa::Signature::<P>::try_from(signature).map_err(bad_signature)?;
+  public_key
+    .verify(message, &signature)
+    .map_err(bad_signature)
+}
+
+fn bad_signature<E: Display>(e: E) -> VerificationError {
+  VerificationError::BadSignature(e.to_string())
 }
 
 impl From<JWK<()>> for PublicKey {
@@ -165,6 +360,9 @@ impl From<JWK<()>> for PublicKey {
           biscuit::jwa::SignatureAlgorithm::RS256 => SignatureAlgorithm::Sha256Rsa(modulus),
           biscuit::jwa::SignatureAlgorithm::RS384 => SignatureAlgorithm::Sha384Rsa(modulus),
           biscuit::jwa::SignatureAlgorithm::RS512 => SignatureAlgorithm::Sha512Rsa(modulus),
+          biscuit::jwa::SignatureAlgorithm::PS256 => SignatureAlgorithm::Sha256RsaPss(modulus),
+          biscuit::jwa::SignatureAlgorithm::PS384 => SignatureAlgorithm::Sha384RsaPss(modulus),
+          biscuit::jwa::SignatureAlgorithm::PS512 => SignatureAlgorithm::Sha512RsaPss(modulus),
           biscuit::jwa::SignatureAlgorithm::ES256 => SignatureAlgorithm::EcdsaP256,
           biscuit::jwa::SignatureAlgorithm::ES384 => SignatureAlgorithm::EcdsaP384,
           _ => unimplemented!(),
@@ -235,4 +433,50 @@ mod test {
     );
     pk.verify(sig_bytes, MESSAGE).unwrap();
   }
+
+  #[test]
+  fn test_algorithm_names() {
+    let algs = [
+      SignatureAlgorithm::Ed25519,
+      SignatureAlgorithm::EcdsaP256,
+      SignatureAlgorithm::EcdsaP384,
+      SignatureAlgorithm::EcdsaP521,
+      SignatureAlgorithm::EcdsaSecp256k1,
+      SignatureAlgorithm::Ed448,
+      SignatureAlgorithm::Sha256Rsa(3072),
+      SignatureAlgorithm::Sha512Rsa(4096),
+      SignatureAlgorithm::Sha256RsaPss(2048),
+      SignatureAlgorithm::Sha384RsaPss(3072),
+      SignatureAlgorithm::Sha512RsaPss(4096),
+    ];
+    for alg in algs {
+      let parsed: SignatureAlgorithm = alg.to_string().parse().unwrap();
+      assert_eq!(parsed.to_string(), alg.to_string());
+    }
+    assert!(matches!(
+      "ES256K".parse(),
+      Ok(SignatureAlgorithm::EcdsaSecp256k1)
+    ));
+    assert!(matches!("ed448".parse(), Ok(SignatureAlgorithm::Ed448)));
+    assert!(matches!(
+      "PS384".parse(),
+      Ok(SignatureAlgorithm::Sha384RsaPss(2048))
+    ));
+  }
+
+  #[test]
+  fn test_signature_secp256k1_roundtrip() {
+    use k256::ecdsa::{signature::Signer, Signature, SigningKey};
+    let key = SigningKey::from_slice(&[7u8; 32]).unwrap();
+    let sig: Signature = key.sign(b"hello, world");
+    let pk = PublicKey::new(
+      SignatureAlgorithm::EcdsaSecp256k1,
+      Bytes::from(key.verifying_key().to_encoded_point(false).as_bytes()),
+    );
+    pk.verify(sig.to_der().as_bytes().into(), b"hello, world")
+      .unwrap();
+    assert!(pk
+      .verify(sig.to_der().as_bytes().into(), b"goodbye")
+      .is_err());
+  }
 }
@@ -62,6 +62,17 @@ impl From<Infallible> for VerificationError {
   }
 }
 
+/// Errors that can occur when converting a [`crate::crypto::PublicKey`] to or from another format
+#[derive(Debug, Error)]
+pub enum KeyFormatError {
+  /// Indicates that the key type cannot be represented in the format
+  #[error("Unsupported key type: {0}")]
+  Unsupported(String),
+  /// Indicates that the key data could not be parsed
+  #[error("Malformed key: {0}")]
+  Malformed(String),
+}
+
 // TODO: add impl for .is_not_found() to ResolutionError
 
 /// Errors that can occur in Resolver operations
@@ -85,8 +96,18 @@ pub enum ResolutionError {
   /// For example, a network error or a problem with the underlying storage
   #[error("Problem fetching data: {0}")]
   Fetch(String),
+  /// Indicates that the data violates a trust policy
+  ///
+  /// See [`crate::trust::TrustStore`]
+  #[error("Twine is not trusted: {0}")]
+  Untrusted(#[from] TrustViolation),
 }
 
+/// Indicates that data violates a [`crate::trust::TrustStore`] policy
+#[derive(Error, Debug)]
+#[error("{0}")]
+pub struct TrustViolation(pub String);
+
 /// Errors that can occur in Store operations
 #[derive(Error, Debug)]
 pub enum StoreError {
@@ -46,16 +46,20 @@ impl AsRef<[u8]> for Bytes {
 
 pub mod as_cid;
 pub mod car;
+pub mod cosign;
 pub mod crypto;
 pub mod errors;
+pub mod notary;
 pub mod resolver;
 pub mod schemas;
 pub mod serde;
 pub mod skiplist;
 pub mod specification;
 pub mod store;
+pub mod trust;
 pub mod twine;
 pub mod verify;
+pub mod witness;
 
 use std::ops::Deref;
 
@@ -0,0 +1,407 @@
+//! Batched notarization of document hashes using Merkle trees
+//!
+//! A notary collects many document hashes and commits to all of them
+//! at once by placing only the root of a Merkle tree in a tixel payload.
+//! Each submitter then receives a [`NotaryReceipt`] containing the
+//! Merkle path from their document to the root, along with a stitch
+//! to the tixel that recorded it.
+//!
+//! Leaves and nodes are hashed with domain separation (a `0x00` prefix
+//! for leaves and `0x01` for inner nodes). When a level has an odd
+//! number of nodes, the last node is promoted to the next level unchanged.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use twine_lib::{resolver::Resolver, errors::ResolutionError, twine::Twine};
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//! # let resolver = twine_lib::store::MemoryStore::default();
+//! # let make_tixel = |_: twine_lib::notary::NotaryPayload| -> Twine { unimplemented!() };
+//! use twine_lib::multihash_codetable::{Code, MultihashDigest};
+//! use twine_lib::notary::{Notary, verify_receipt};
+//!
+//! let mut notary = Notary::new(Code::Sha3_256);
+//! notary.submit(Code::Sha3_256.digest(b"document one"));
+//! notary.submit(Code::Sha3_256.digest(b"document two"));
+//! let batch = notary.seal().unwrap();
+//! // build and publish a tixel, eg: with a TwineBuilder
+//! // builder.build_next(&prev).payload(batch.payload()).done()
+//! let tixel = make_tixel(batch.payload());
+//! let receipts = batch.receipts(&tixel).unwrap();
+//! // ...later, anyone with the receipt can check it
+//! let twine = verify_receipt(&receipts[0], &resolver).await?;
+//! # Ok::<_, ResolutionError>(())
+//! # });
+//! ```
+use crate::errors::{ResolutionError, VerificationError};
+use crate::resolver::Resolver;
+use crate::twine::{Stitch, Tixel, Twine};
+use crate::{Bytes, Cid};
+use multihash_codetable::{Code, Multihash, MultihashDigest};
+use serde::{Deserialize, Serialize};
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+fn hash_leaf(hasher: Code, document: &Multihash) -> Multihash {
+  let mut bytes = vec![LEAF_PREFIX];
+  bytes.extend(document.to_bytes());
+  hasher.digest(&bytes)
+}
+
+fn hash_node(hasher: Code, left: &Multihash, right: &Multihash) -> Multihash {
+  let mut bytes = vec![NODE_PREFIX];
+  bytes.extend(left.to_bytes());
+  bytes.extend(right.to_bytes());
+  hasher.digest(&bytes)
+}
+
+// Whether each sibling along the path to a position is on the left,
+// following the same promotion rule used to build the tree
+fn path_directions(position: u64, count: u64) -> Vec<bool> {
+  let mut directions = Vec::new();
+  let mut index = position;
+  let mut width = count;
+  while width > 1 {
+    let sibling = index ^ 1;
+    if sibling < width {
+      directions.push(sibling < index);
+    }
+    index /= 2;
+    width = width.div_ceil(2);
+  }
+  directions
+}
+
+fn decode_multihash(bytes: &[u8]) -> Result<Multihash, VerificationError> {
+  Multihash::from_bytes(bytes)
+    .map_err(|e| VerificationError::Payload(format!("Invalid multihash: {}", e)))
+}
+
+/// The payload committing a tixel to a batch of documents
+///
+/// This is what a [`NotaryBatch`] provides to be placed in the tixel.
+/// It can be used as the payload directly, or flattened into a larger
+/// payload struct with `#[serde(flatten)]`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotaryPayload {
+  /// The multihash of the Merkle root
+  pub root: Bytes,
+  /// The number of documents in the batch
+  pub count: u64,
+}
+
+impl NotaryPayload {
+  /// Get the Merkle root as a [`Multihash`]
+  pub fn root(&self) -> Result<Multihash, VerificationError> {
+    decode_multihash(&self.root)
+  }
+}
+
+/// One step along a Merkle path
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleStep {
+  /// The multihash of the sibling node
+  pub sibling: Bytes,
+  /// Whether the sibling is on the left of the node being proven
+  pub left: bool,
+}
+
+/// Proof that a document was included in a notarized tixel
+///
+/// Receipts can be serialized (eg: as DAG-JSON) and handed to the
+/// document submitter. They are checked with [`verify_receipt`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotaryReceipt {
+  /// The CID of the strand of the notarizing tixel
+  pub strand: Cid,
+  /// The CID of the notarizing tixel
+  pub tixel: Cid,
+  /// The multihash of the document
+  pub document: Bytes,
+  /// The position of the document in the batch
+  ///
+  /// This determines the direction of every step in the path.
+  pub position: u64,
+  /// The Merkle path from the document to the root
+  pub path: Vec<MerkleStep>,
+}
+
+impl NotaryReceipt {
+  /// Get the stitch to the notarizing tixel
+  pub fn stitch(&self) -> Stitch {
+    Stitch {
+      strand: self.strand,
+      tixel: self.tixel,
+    }
+  }
+
+  /// Compute the Merkle root implied by this receipt
+  ///
+  /// The hash function used for the tree must be supplied, and is
+  /// normally the one used by the root recorded in the payload.
+  pub fn compute_root(&self, hasher: Code) -> Result<Multihash, VerificationError> {
+    let document = decode_multihash(&self.document)?;
+    self
+      .path
+      .iter()
+      .try_fold(hash_leaf(hasher, &document), |node, step| {
+        let sibling = decode_multihash(&step.sibling)?;
+        Ok(if step.left {
+          hash_node(hasher, &sibling, &node)
+        } else {
+          hash_node(hasher, &node, &sibling)
+        })
+      })
+  }
+
+  /// Check this receipt against the payload of a tixel
+  ///
+  /// This does not check the tixel's authenticity. Use
+  /// [`verify_receipt`] to also resolve and verify the tixel.
+  pub fn verify_with(&self, tixel: &Tixel) -> Result<(), VerificationError> {
+    if tixel.cid() != self.tixel || tixel.strand_cid() != self.strand {
+      return Err(VerificationError::CidMismatch {
+        expected: self.tixel.to_string(),
+        actual: tixel.cid().to_string(),
+      });
+    }
+    let payload: NotaryPayload = tixel.extract_payload()?;
+    if self.position >= payload.count {
+      return Err(VerificationError::Payload(
+        "Receipt position is outside of the notarized batch".into(),
+      ));
+    }
+    let directions = path_directions(self.position, payload.count);
+    if self.path.len() != directions.len()
+      || self
+        .path
+        .iter()
+        .zip(directions)
+        .any(|(step, left)| step.left != left)
+    {
+      return Err(VerificationError::Payload(
+        "Receipt path does not match its position in the batch".into(),
+      ));
+    }
+    let root = payload.root()?;
+    let hasher =
+      Code::try_from(root.code()).map_err(|_| VerificationError::UnsupportedHashAlgorithm)?;
+    if self.compute_root(hasher)? != root {
+      return Err(VerificationError::Payload(
+        "Receipt does not match the notarized Merkle root".into(),
+      ));
+    }
+    Ok(())
+  }
+}
+
+/// Collects document hashes to be notarized together
+///
+/// Once all documents are submitted, call [`Notary::seal`] to build
+/// the Merkle tree.
+#[derive(Debug, Clone)]
+pub struct Notary {
+  hasher: Code,
+  documents: Vec<Multihash>,
+}
+
+impl Notary {
+  /// Create a new notary that builds its tree with the given hash function
+  pub fn new(hasher: Code) -> Self {
+    Self {
+      hasher,
+      documents: Vec::new(),
+    }
+  }
+
+  /// Submit a document hash, returning its position in the batch
+  pub fn submit(&mut self, document: Multihash) -> u64 {
+    self.documents.push(document);
+    (self.documents.len() - 1) as u64
+  }
+
+  /// The number of documents submitted so far
+  pub fn len(&self) -> usize {
+    self.documents.len()
+  }
+
+  /// Check if no documents have been submitted
+  pub fn is_empty(&self) -> bool {
+    self.documents.is_empty()
+  }
+
+  /// Build the Merkle tree for all submitted documents
+  ///
+  /// Returns an error if no documents were submitted.
+  pub fn seal(self) -> Result<NotaryBatch, VerificationError> {
+    if self.documents.is_empty() {
+      return Err(VerificationError::General(
+        "Cannot seal an empty notary batch".into(),
+      ));
+    }
+    let mut levels = vec![self
+      .documents
+      .iter()
+      .map(|d| hash_leaf(self.hasher, d))
+      .collect::<Vec<_>>()];
+    while levels.last().unwrap().len() > 1 {
+      let next = levels
+        .last()
+        .unwrap()
+        .chunks(2)
+        .map(|pair| match pair {
+          [left, right] => hash_node(self.hasher, left, right),
+          [single] => *single,
+          _ => unreachable!(),
+        })
+        .collect();
+      levels.push(next);
+    }
+    Ok(NotaryBatch {
+      documents: self.documents,
+      levels,
+    })
+  }
+}
+
+/// A sealed batch of documents with its Merkle tree
+#[derive(Debug, Clone)]
+pub struct NotaryBatch {
+  documents: Vec<Multihash>,
+  levels: Vec<Vec<Multihash>>,
+}
+
+impl NotaryBatch {
+  /// The Merkle root of the batch
+  pub fn root(&self) -> Multihash {
+    self.levels.last().unwrap()[0]
+  }
+
+  /// The number of documents in the batch
+  pub fn len(&self) -> usize {
+    self.documents.len()
+  }
+
+  /// A sealed batch is never empty
+  pub fn is_empty(&self) -> bool {
+    false
+  }
+
+  /// The payload to place in the notarizing tixel
+  pub fn payload(&self) -> NotaryPayload {
+    NotaryPayload {
+      root: self.root().to_bytes().into(),
+      count: self.documents.len() as u64,
+    }
+  }
+
+  fn path(&self, position: usize) -> Vec<MerkleStep> {
+    let mut path = Vec::new();
+    let mut index = position;
+    for level in &self.levels[..self.levels.len() - 1] {
+      let sibling = index ^ 1;
+      // promoted nodes have no sibling on this level
+      if let Some(node) = level.get(sibling) {
+        path.push(MerkleStep {
+          sibling: node.to_bytes().into(),
+          left: sibling < index,
+        });
+      }
+      index /= 2;
+    }
+    path
+  }
+
+  /// Create the receipt for a single document
+  ///
+  /// The tixel must be the one whose payload was created by [`NotaryBatch::payload`].
+  pub fn receipt(&self, position: u64, tixel: &Tixel) -> Result<NotaryReceipt, VerificationError> {
+    let document = self
+      .documents
+      .get(position as usize)
+      .ok_or_else(|| VerificationError::General(format!("No document at position {}", position)))?;
+    let receipt = NotaryReceipt {
+      strand: tixel.strand_cid(),
+      tixel: tixel.cid(),
+      document: document.to_bytes().into(),
+      position,
+      path: self.path(position as usize),
+    };
+    receipt.verify_with(tixel)?;
+    Ok(receipt)
+  }
+
+  /// Create receipts for every document, in submission order
+  ///
+  /// The tixel must be the one whose payload was created by [`NotaryBatch::payload`].
+  pub fn receipts(&self, tixel: &Tixel) -> Result<Vec<NotaryReceipt>, VerificationError> {
+    (0..self.documents.len() as u64)
+      .map(|position| self.receipt(position, tixel))
+      .collect()
+  }
+}
+
+/// Verify a receipt against its signed tixel
+///
+/// The notarizing tixel is resolved (which checks its signature against
+/// the strand) and the receipt's Merkle path is checked against the root
+/// in its payload. The verified [`Twine`] is returned.
+pub async fn verify_receipt<R: Resolver>(
+  receipt: &NotaryReceipt,
+  resolver: &R,
+) -> Result<Twine, ResolutionError> {
+  let twine = resolver.resolve(receipt.stitch()).await?.unpack();
+  receipt.verify_with(&twine)?;
+  Ok(twine)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_merkle_paths() {
+    for count in 1..=9u8 {
+      let mut notary = Notary::new(Code::Sha3_256);
+      for i in 0..count {
+        notary.submit(Code::Sha3_256.digest(&[i]));
+      }
+      let batch = notary.seal().unwrap();
+      for position in 0..count as usize {
+        let receipt = NotaryReceipt {
+          strand: Cid::default(),
+          tixel: Cid::default(),
+          document: Code::Sha3_256.digest(&[position as u8]).to_bytes().into(),
+          position: position as u64,
+          path: batch.path(position),
+        };
+        assert_eq!(receipt.compute_root(Code::Sha3_256).unwrap(), batch.root());
+        let directions: Vec<_> = receipt.path.iter().map(|step| step.left).collect();
+        assert_eq!(directions, path_directions(position as u64, count as u64));
+      }
+    }
+  }
+
+  #[test]
+  fn test_wrong_document() {
+    let mut notary = Notary::new(Code::Sha3_256);
+    notary.submit(Code::Sha3_256.digest(b"a"));
+    notary.submit(Code::Sha3_256.digest(b"b"));
+    notary.submit(Code::Sha3_256.digest(b"c"));
+    let batch = notary.seal().unwrap();
+    let receipt = NotaryReceipt {
+      strand: Cid::default(),
+      tixel: Cid::default(),
+      document: Code::Sha3_256.digest(b"x").to_bytes().into(),
+      position: 1,
+      path: batch.path(1),
+    };
+    assert_ne!(receipt.compute_root(Code::Sha3_256).unwrap(), batch.root());
+  }
+
+  #[test]
+  fn test_empty_batch() {
+    assert!(Notary::new(Code::Sha3_256).seal().is_err());
+  }
+}
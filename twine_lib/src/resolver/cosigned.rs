@@ -0,0 +1,196 @@
+use super::*;
+use crate::cosign::CosignPolicy;
+use quick_cache::sync::Cache;
+use std::sync::Arc;
+
+/// How many strands' policies a [`CosignedResolver`] keeps parsed
+const POLICY_CACHE_SIZE: usize = 1000;
+
+/// A strand together with its parsed co-signing policy, if it has one
+type StrandPolicy = Option<(Strand, CosignPolicy)>;
+
+/// A resolver that enforces the co-signing policy of strands
+///
+/// Strands can declare a [`CosignPolicy`] requiring each tixel to be
+/// co-signed by `k` of `n` keys (see [`crate::cosign`]). Core verification
+/// does not check it, so wrap a resolver in this one to reject tixels
+/// without enough valid co-signatures with [`ResolutionError::Invalid`].
+/// Strands without a policy are resolved as usual, while strands whose
+/// policy can not be read are rejected.
+///
+/// Each strand's policy is parsed once and kept for later tixels.
+///
+/// # Example
+///
+/// ```no_run
+/// # use twine_lib::{errors::ResolutionError, Cid};
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// # let store = twine_lib::store::MemoryStore::default();
+/// # let strand_cid = Cid::default();
+/// use twine_lib::resolver::{CosignedResolver, Resolver};
+/// let resolver = CosignedResolver::new(store);
+/// let latest = resolver.resolve_latest(strand_cid).await?;
+/// # Ok::<_, ResolutionError>(())
+/// # });
+/// ```
+#[derive(Clone)]
+pub struct CosignedResolver<R> {
+  inner: R,
+  policies: Arc<Cache<Cid, Arc<StrandPolicy>>>,
+}
+
+impl<R> CosignedResolver<R> {
+  /// Wrap a resolver so that co-signing policies are enforced
+  pub fn new(inner: R) -> Self {
+    Self {
+      inner,
+      policies: Arc::new(Cache::new(POLICY_CACHE_SIZE)),
+    }
+  }
+
+  /// Get the wrapped resolver
+  pub fn inner(&self) -> &R {
+    &self.inner
+  }
+
+  /// Unwrap the wrapped resolver
+  pub fn into_inner(self) -> R {
+    self.inner
+  }
+}
+
+fn is_rejected(err: &ResolutionError) -> bool {
+  matches!(err, ResolutionError::Invalid(_))
+}
+
+impl<R> CosignedResolver<R>
+where
+  R: BaseResolver,
+{
+  fn parse(&self, strand: Strand) -> Result<Arc<StrandPolicy>, VerificationError> {
+    let cid = strand.cid();
+    let policy = CosignPolicy::from_strand(&strand)?.map(|policy| (strand, policy));
+    let policy = Arc::new(policy);
+    self.policies.insert(cid, policy.clone());
+    Ok(policy)
+  }
+
+  async fn policy(&self, strand: &Cid) -> Result<Arc<StrandPolicy>, ResolutionError> {
+    if let Some(policy) = self.policies.get(strand) {
+      return Ok(policy);
+    }
+    let strand = self.inner.fetch_strand(strand).await?;
+    Ok(self.parse(strand)?)
+  }
+
+  async fn checked(&self, strand: &Cid, tixel: Tixel) -> Result<Tixel, ResolutionError> {
+    if let Some((strand, policy)) = self.policy(strand).await?.as_ref() {
+      policy.verify(strand, &tixel)?;
+    }
+    Ok(tixel)
+  }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<R> BaseResolver for CosignedResolver<R>
+where
+  R: BaseResolver,
+{
+  async fn has_index(&self, strand: &Cid, index: u64) -> Result<bool, ResolutionError> {
+    if !self.inner.has_index(strand, index).await? {
+      return Ok(false);
+    }
+    match self.fetch_index(strand, index).await {
+      Err(e) if is_rejected(&e) => Ok(false),
+      res => res.map(|_| true),
+    }
+  }
+
+  async fn has_twine(&self, strand: &Cid, cid: &Cid) -> Result<bool, ResolutionError> {
+    if !self.inner.has_twine(strand, cid).await? {
+      return Ok(false);
+    }
+    match self.fetch_tixel(strand, cid).await {
+      Err(e) if is_rejected(&e) => Ok(false),
+      res => res.map(|_| true),
+    }
+  }
+
+  async fn has_strand(&self, cid: &Cid) -> Result<bool, ResolutionError> {
+    if !self.inner.has_strand(cid).await? {
+      return Ok(false);
+    }
+    match self.policy(cid).await {
+      Err(e) if is_rejected(&e) => Ok(false),
+      res => res.map(|_| true),
+    }
+  }
+
+  async fn fetch_latest(&self, strand: &Cid) -> Result<Tixel, ResolutionError> {
+    let tixel = self.inner.fetch_latest(strand).await?;
+    self.checked(strand, tixel).await
+  }
+
+  async fn fetch_index(&self, strand: &Cid, index: u64) -> Result<Tixel, ResolutionError> {
+    let tixel = self.inner.fetch_index(strand, index).await?;
+    self.checked(strand, tixel).await
+  }
+
+  async fn fetch_tixel(&self, strand: &Cid, tixel: &Cid) -> Result<Tixel, ResolutionError> {
+    let tixel = self.inner.fetch_tixel(strand, tixel).await?;
+    self.checked(strand, tixel).await
+  }
+
+  async fn fetch_strand(&self, strand: &Cid) -> Result<Strand, ResolutionError> {
+    let strand = self.inner.fetch_strand(strand).await?;
+    if !self.policies.contains_key(&strand.cid()) {
+      self.parse(strand.clone())?;
+    }
+    Ok(strand)
+  }
+
+  async fn range_stream<'a>(
+    &'a self,
+    range: AbsoluteRange,
+  ) -> Result<TwineStream<'a, Tixel>, ResolutionError> {
+    let policy = self.policy(range.strand_cid()).await?;
+    let s = self
+      .inner
+      .range_stream(range)
+      .await?
+      .and_then(move |tixel| {
+        let res = match policy.as_ref() {
+          Some((strand, policy)) => policy.verify(strand, &tixel).map(|_| tixel),
+          None => Ok(tixel),
+        };
+        futures::future::ready(res.map_err(ResolutionError::from))
+      });
+    #[cfg(target_arch = "wasm32")]
+    {
+      Ok(s.boxed_local())
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+      Ok(s.boxed())
+    }
+  }
+
+  async fn fetch_strands<'a>(&'a self) -> Result<TwineStream<'a, Strand>, ResolutionError> {
+    let s = self
+      .inner
+      .fetch_strands()
+      .await?
+      .try_filter(move |strand| futures::future::ready(self.parse(strand.clone()).is_ok()));
+    #[cfg(target_arch = "wasm32")]
+    {
+      Ok(s.boxed_local())
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+      Ok(s.boxed())
+    }
+  }
+}
+
+impl<R> Resolver for CosignedResolver<R> where R: BaseResolver {}
@@ -1,9 +1,10 @@
 //! Utilities for retrieving twine data
 use crate::as_cid::AsCid;
-use crate::errors::ResolutionError;
+use crate::errors::{ResolutionError, VerificationError};
 use crate::twine::{Strand, Tixel, Twine};
 use crate::Cid;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use futures::{Stream, StreamExt, TryStreamExt};
 use std::collections::HashSet;
 use std::pin::Pin;
@@ -14,6 +15,12 @@ pub use query::*;
 mod resolution;
 pub use resolution::*;
 
+mod trusted;
+pub use trusted::*;
+
+mod cosigned;
+pub use cosigned::*;
+
 /// A module containing the [`BaseResolver`] trait that is
 /// meant to be implemented by any type that wants to be
 /// used as a Twine Resolver.
@@ -33,6 +40,11 @@ impl<T> MaybeSend for T where T: {}
 #[cfg(not(target_arch = "wasm32"))]
 impl<T> MaybeSend for T where T: Send {}
 
+/// How many tixels before the latest are searched for a handoff
+///
+/// See [`Resolver::resolve_successor`].
+pub const SUCCESSION_LOOKBACK: u64 = 16;
+
 /// This is a standardized interface for retrieving Twine objects
 ///
 /// Datastores will implement this trait (indirectly through [`BaseResolver`])
@@ -315,6 +327,167 @@ pub trait Resolver: BaseResolver {
     }
   }
 
+  /// Resolve the latest Twine created at or before a given time
+  ///
+  /// This relies on the timestamp convention described by
+  /// [`crate::twine::TIMESTAMP_FIELD`]. The strand is binary searched
+  /// by index, and every tixel visited along the way is checked to have
+  /// a timestamp consistent with the others, so a strand whose timestamps
+  /// are not monotonic will produce an error rather than a wrong answer.
+  ///
+  /// If the time is earlier than the first tixel, [`ResolutionError::NotFound`]
+  /// is returned.
+  ///
+  /// # Example
+  ///
+  /// ```no_run
+  /// # use twine_lib::{resolver::Resolver, errors::ResolutionError, Cid};
+  /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+  /// # use twine_lib::store::MemoryStore;
+  /// # let resolver = MemoryStore::default();
+  /// let strand_cid: Cid = "bafyrmieej3j3sprtnbfziv6vhixzr3xxrcabnma43ajb5grhsixdvxzdvu".parse().unwrap();
+  /// let noon = "2025-01-01T12:00:00Z".parse().unwrap();
+  /// let twine = resolver.resolve_at_time(strand_cid, noon).await?;
+  /// # Ok::<_, ResolutionError>(())
+  /// # });
+  /// ```
+  async fn resolve_at_time<C: AsCid + MaybeSend>(
+    &self,
+    strand: C,
+    time: DateTime<Utc>,
+  ) -> Result<TwineResolution, ResolutionError> {
+    let strand_cid = strand.as_cid();
+    let strand = self.resolve_strand(strand_cid).await?.unpack();
+    let latest = Twine::try_new(strand.clone(), self.fetch_latest(strand_cid).await?)?;
+    let latest_time = latest.timestamp()?;
+    if latest_time <= time {
+      return TwineResolution::try_new(
+        SingleQuery::Index(*strand_cid, latest.index() as i64),
+        latest,
+      );
+    }
+    let first = Twine::try_new(strand.clone(), self.fetch_index(strand_cid, 0).await?)?;
+    let first_time = first.timestamp()?;
+    if first_time > time {
+      return Err(ResolutionError::NotFound);
+    }
+    if first_time > latest_time {
+      return Err(ResolutionError::BadData(
+        "Tixel timestamps are not monotonic".into(),
+      ));
+    }
+    // invariant: lo is at or before the time, hi is after it
+    let (mut lo, mut lo_time) = (first, first_time);
+    let (mut hi, mut hi_time) = (latest, latest_time);
+    while hi.index() - lo.index() > 1 {
+      let mid = lo.index() + (hi.index() - lo.index()) / 2;
+      let twine = Twine::try_new(strand.clone(), self.fetch_index(strand_cid, mid).await?)?;
+      let mid_time = twine.timestamp()?;
+      if mid_time < lo_time || mid_time > hi_time {
+        return Err(ResolutionError::BadData(format!(
+          "Tixel timestamps are not monotonic (at index {})",
+          mid
+        )));
+      }
+      if mid_time <= time {
+        (lo, lo_time) = (twine, mid_time);
+      } else {
+        (hi, hi_time) = (twine, mid_time);
+      }
+    }
+    TwineResolution::try_new(SingleQuery::Index(*strand_cid, lo.index() as i64), lo)
+  }
+
+  /// Resolve the successor of a Strand whose key has been handed off
+  ///
+  /// If a Tixel of the strand names a successor (see
+  /// [`crate::twine::SUCCESSOR_FIELD`]), the first Twine of the successor
+  /// strand is returned. It must cross-stitch back to that handoff Tixel,
+  /// otherwise the handoff is not signed by the successor's key and an
+  /// error is returned. If there is no successor, `None` is returned.
+  ///
+  /// The handoff should be the final Tixel, but a few tixels appended after
+  /// it (by anyone still holding the old key) must not hide the succession.
+  /// So the latest Tixel is checked first, then at most
+  /// [`SUCCESSION_LOOKBACK`] tixels before it. A strand without a handoff
+  /// therefore costs a bounded number of reads, not its whole history.
+  async fn resolve_successor<C: AsCid + MaybeSend>(
+    &self,
+    strand: C,
+  ) -> Result<Option<TwineResolution>, ResolutionError> {
+    let strand_cid = *strand.as_cid();
+    let latest = self.resolve_latest(strand_cid).await?.unpack();
+    let (handoff, successor) = match latest.successor()? {
+      Some(successor) => (latest, successor),
+      None if latest.index() == 0 => return Ok(None),
+      None => {
+        let end = latest.index().saturating_sub(SUCCESSION_LOOKBACK);
+        let mut tixels = self
+          .resolve_range(AbsoluteRange::new(strand_cid, latest.index() - 1, end))
+          .await?;
+        loop {
+          match tixels.try_next().await? {
+            Some(twine) => {
+              if let Some(successor) = twine.successor()? {
+                break (twine, successor);
+              }
+            }
+            None => return Ok(None),
+          }
+        }
+      }
+    };
+    let first = self.resolve_index(successor, 0).await?;
+    let stitch = first.cross_stitches().get(&handoff.strand_cid()).cloned();
+    if stitch.map(|s| s.tixel) != Some(handoff.cid()) {
+      return Err(ResolutionError::Invalid(VerificationError::General(
+        format!(
+          "Successor strand {} does not stitch back to {}",
+          successor,
+          handoff.cid()
+        ),
+      )));
+    }
+    Ok(Some(first))
+  }
+
+  /// Resolve the chain of Strands that a Strand has been handed off to
+  ///
+  /// The returned list starts with the given strand and ends with the
+  /// current one. Every handoff along the way is checked as described
+  /// in [`Resolver::resolve_successor`].
+  async fn resolve_succession<C: AsCid + MaybeSend>(
+    &self,
+    strand: C,
+  ) -> Result<Vec<Strand>, ResolutionError> {
+    let mut chain = vec![self.resolve_strand(strand).await?.unpack()];
+    let mut current = chain[0].cid();
+    while let Some(next) = self.resolve_successor(current).await? {
+      let next = next.unpack().strand().clone();
+      current = next.cid();
+      if chain.iter().any(|s| s.cid() == current) {
+        return Err(ResolutionError::BadData(
+          "Strand succession contains a cycle".into(),
+        ));
+      }
+      chain.push(next);
+    }
+    Ok(chain)
+  }
+
+  /// Resolve the latest Twine of a Strand, following any key handoffs
+  ///
+  /// This is like [`Resolver::resolve_latest`], but if the strand has been
+  /// handed off to a successor the latest Twine of the current strand in
+  /// the succession is returned instead.
+  async fn resolve_latest_in_succession<C: AsCid + MaybeSend>(
+    &self,
+    strand: C,
+  ) -> Result<TwineResolution, ResolutionError> {
+    let chain = self.resolve_succession(strand).await?;
+    self.resolve_latest(chain.last().unwrap()).await
+  }
+
   /// Get a stream of all available Strand objects
   async fn strands<'a>(&'a self) -> Result<TwineStream<'a, Strand>, ResolutionError> {
     self.fetch_strands().await
@@ -0,0 +1,192 @@
+use super::*;
+use crate::trust::TrustStore;
+use quick_cache::sync::Cache;
+use std::sync::Arc;
+
+/// How many strands a [`TrustedResolver`] keeps for checking tixels
+const STRAND_CACHE_SIZE: usize = 1000;
+
+/// A resolver that enforces a [`TrustStore`] policy
+///
+/// Every Strand and Tixel fetched through this resolver is checked
+/// against the policy, so any `resolve_*` result that violates it fails
+/// with [`ResolutionError::Untrusted`]. Untrusted strands are left out
+/// of [`Resolver::strands`], and the `has_*` methods report untrusted
+/// twines as missing.
+///
+/// Strands are fetched once and kept for checking their later tixels.
+///
+/// Note that when a key is revoked after a cut-off, the latest tixel of
+/// its strand is untrusted, so resolving the latest tixel (or a range,
+/// which starts from the latest) fails. Earlier tixels can still be
+/// resolved by index or by stitch.
+///
+/// # Example
+///
+/// ```no_run
+/// # use twine_lib::{errors::ResolutionError, Cid};
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// # let store = twine_lib::store::MemoryStore::default();
+/// # let strand_cid = Cid::default();
+/// use twine_lib::resolver::{Resolver, TrustedResolver};
+/// use twine_lib::trust::TrustStore;
+/// let resolver = TrustedResolver::new(store, TrustStore::default().pin(strand_cid));
+/// let latest = resolver.resolve_latest(strand_cid).await?;
+/// # Ok::<_, ResolutionError>(())
+/// # });
+/// ```
+#[derive(Debug, Clone)]
+pub struct TrustedResolver<R> {
+  inner: R,
+  trust: TrustStore,
+  strands: Arc<Cache<Cid, Strand>>,
+}
+
+impl<R> TrustedResolver<R> {
+  /// Wrap a resolver with a trust policy
+  pub fn new(inner: R, trust: TrustStore) -> Self {
+    Self {
+      inner,
+      trust,
+      strands: Arc::new(Cache::new(STRAND_CACHE_SIZE)),
+    }
+  }
+
+  /// Get the trust policy
+  pub fn trust_store(&self) -> &TrustStore {
+    &self.trust
+  }
+
+  /// Get the wrapped resolver
+  pub fn inner(&self) -> &R {
+    &self.inner
+  }
+
+  /// Unwrap the wrapped resolver
+  pub fn into_inner(self) -> R {
+    self.inner
+  }
+}
+
+impl<R> TrustedResolver<R>
+where
+  R: BaseResolver,
+{
+  async fn strand(&self, strand: &Cid) -> Result<Strand, ResolutionError> {
+    if let Some(strand) = self.strands.get(strand) {
+      return Ok(strand);
+    }
+    let strand = self.inner.fetch_strand(strand).await?;
+    self.strands.insert(strand.cid(), strand.clone());
+    Ok(strand)
+  }
+
+  async fn checked(&self, strand: &Cid, tixel: Tixel) -> Result<Tixel, ResolutionError> {
+    let strand = self.strand(strand).await?;
+    self.trust.check_tixel(&strand, &tixel)?;
+    Ok(tixel)
+  }
+}
+
+fn is_untrusted(err: &ResolutionError) -> bool {
+  matches!(err, ResolutionError::Untrusted(_))
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<R> BaseResolver for TrustedResolver<R>
+where
+  R: BaseResolver,
+{
+  async fn has_index(&self, strand: &Cid, index: u64) -> Result<bool, ResolutionError> {
+    if !self.inner.has_index(strand, index).await? {
+      return Ok(false);
+    }
+    match self.fetch_index(strand, index).await {
+      Err(e) if is_untrusted(&e) => Ok(false),
+      res => res.map(|_| true),
+    }
+  }
+
+  async fn has_twine(&self, strand: &Cid, cid: &Cid) -> Result<bool, ResolutionError> {
+    if !self.inner.has_twine(strand, cid).await? {
+      return Ok(false);
+    }
+    match self.fetch_tixel(strand, cid).await {
+      Err(e) if is_untrusted(&e) => Ok(false),
+      res => res.map(|_| true),
+    }
+  }
+
+  async fn has_strand(&self, cid: &Cid) -> Result<bool, ResolutionError> {
+    if !self.inner.has_strand(cid).await? {
+      return Ok(false);
+    }
+    match self.fetch_strand(cid).await {
+      Err(e) if is_untrusted(&e) => Ok(false),
+      res => res.map(|_| true),
+    }
+  }
+
+  async fn fetch_latest(&self, strand: &Cid) -> Result<Tixel, ResolutionError> {
+    let tixel = self.inner.fetch_latest(strand).await?;
+    self.checked(strand, tixel).await
+  }
+
+  async fn fetch_index(&self, strand: &Cid, index: u64) -> Result<Tixel, ResolutionError> {
+    let tixel = self.inner.fetch_index(strand, index).await?;
+    self.checked(strand, tixel).await
+  }
+
+  async fn fetch_tixel(&self, strand: &Cid, tixel: &Cid) -> Result<Tixel, ResolutionError> {
+    let tixel = self.inner.fetch_tixel(strand, tixel).await?;
+    self.checked(strand, tixel).await
+  }
+
+  async fn fetch_strand(&self, strand: &Cid) -> Result<Strand, ResolutionError> {
+    let strand = self.strand(strand).await?;
+    self.trust.check_strand(&strand)?;
+    Ok(strand)
+  }
+
+  async fn range_stream<'a>(
+    &'a self,
+    range: AbsoluteRange,
+  ) -> Result<TwineStream<'a, Tixel>, ResolutionError> {
+    let strand = self.fetch_strand(range.strand_cid()).await?;
+    let s = self
+      .inner
+      .range_stream(range)
+      .await?
+      .and_then(move |tixel| {
+        let res = self.trust.check_tixel(&strand, &tixel).map(|_| tixel);
+        futures::future::ready(res.map_err(ResolutionError::from))
+      });
+    #[cfg(target_arch = "wasm32")]
+    {
+      Ok(s.boxed_local())
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+      Ok(s.boxed())
+    }
+  }
+
+  async fn fetch_strands<'a>(&'a self) -> Result<TwineStream<'a, Strand>, ResolutionError> {
+    let s = self
+      .inner
+      .fetch_strands()
+      .await?
+      .try_filter(move |strand| futures::future::ready(self.trust.check_strand(strand).is_ok()));
+    #[cfg(target_arch = "wasm32")]
+    {
+      Ok(s.boxed_local())
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+      Ok(s.boxed())
+    }
+  }
+}
+
+impl<R> Resolver for TrustedResolver<R> where R: BaseResolver {}
@@ -0,0 +1,492 @@
+use super::{MemoryCache, Store};
+use crate::as_cid::AsCid;
+use crate::errors::{ResolutionError, StoreError};
+use crate::resolver::{unchecked_base, AbsoluteRange, MaybeSend, Resolver};
+use crate::twine::{AnyTwine, Strand, Tixel};
+use crate::Cid;
+use async_trait::async_trait;
+use futures::lock::Mutex;
+use futures::stream::{Stream, StreamExt, TryStreamExt};
+use quick_cache::sync::Cache;
+use std::ops::Deref;
+
+/// How a [`CachedStore`] writes to the underlying store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WritePolicy {
+  /// Every save is written to the store before it returns
+  WriteThrough,
+  /// Saves are queued and written to the store in batches
+  ///
+  /// Once this many items are queued, the next save writes
+  /// the whole queue before returning. If that write fails, the
+  /// twines of the failing save are not queued, and the rest of the
+  /// queue is kept. Later saves retry it (returning its error) before
+  /// queueing anything else, so the queue stays bounded. If the store
+  /// keeps rejecting it, use [`CachedStore::discard_queue`].
+  ///
+  /// Queued saves are not written when the store is dropped, so
+  /// callers must call [`CachedStore::flush`] when they're done.
+  WriteBack(usize),
+}
+
+fn flush_error(e: StoreError) -> ResolutionError {
+  match e {
+    StoreError::Fetching(e) => e,
+    e => ResolutionError::Fetch(e.to_string()),
+  }
+}
+
+/// A [`Store`] with a [`MemoryCache`] in front of it
+///
+/// Unlike [`MemoryCache`], saved data goes into the cache as well as
+/// the store, and the latest tixel of each strand is kept up to date
+/// as tixels are saved, so [`CachedStore`] can be used anywhere a
+/// [`Store`] is needed.
+///
+/// With [`WritePolicy::WriteBack`], saves are queued and written in
+/// batches. Reads that miss the cache write the queue first, so they
+/// always see queued data.
+///
+/// # Flushing
+///
+/// Writing to the store is async, so queued saves can't be written on
+/// drop. With [`WritePolicy::WriteBack`] you must call
+/// [`CachedStore::flush`] before dropping the store, or any saves still
+/// in the queue are lost.
+///
+/// # Example
+///
+/// ```no_run
+/// use twine_lib::store::{CachedStore, MemoryStore};
+/// # async fn example() -> Result<(), twine_lib::errors::StoreError> {
+/// let store = CachedStore::new(MemoryStore::new())
+///   .write_back(100)
+///   .with_cache_size(10_000);
+/// // ... save twines ...
+/// store.flush().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct CachedStore<S: Store + Resolver> {
+  cache: MemoryCache<S>,
+  latest: Cache<Cid, Tixel>,
+  policy: WritePolicy,
+  queue: Mutex<Vec<AnyTwine>>,
+}
+
+impl<S: Store + Resolver> CachedStore<S> {
+  /// Create a new write-through cache in front of a store
+  pub fn new(store: S) -> Self {
+    Self {
+      cache: MemoryCache::new(store),
+      latest: Cache::new(100),
+      policy: WritePolicy::WriteThrough,
+      queue: Mutex::new(Vec::new()),
+    }
+  }
+
+  /// Queue up to `queue_size` saves before writing them to the store
+  ///
+  /// Remember to call [`CachedStore::flush`] before dropping the store.
+  pub fn write_back(mut self, queue_size: usize) -> Self {
+    self.policy = WritePolicy::WriteBack(queue_size.max(1));
+    self
+  }
+
+  /// Set the number of tixels kept in memory
+  ///
+  /// See [`MemoryCache::with_cache_size`]
+  pub fn with_cache_size(mut self, cache_size: usize) -> Self {
+    self.cache.set_cache_size(cache_size);
+    self
+  }
+
+  /// Set the number of strands kept in memory
+  ///
+  /// This also bounds the number of latest tixels kept.
+  pub fn with_strand_cache_size(mut self, strand_cache_size: usize) -> Self {
+    self.cache.set_strand_cache_size(strand_cache_size);
+    self.latest = Cache::new(strand_cache_size.max(1));
+    self
+  }
+
+  fn store(&self) -> &S {
+    &self.cache
+  }
+
+  /// Get the write policy
+  pub fn policy(&self) -> WritePolicy {
+    self.policy
+  }
+
+  /// Write any queued saves to the store
+  pub async fn flush(&self) -> Result<(), StoreError> {
+    let mut queue = self.queue.lock().await;
+    self.write_queue(&mut queue).await
+  }
+
+  /// Drop any queued saves without writing them
+  ///
+  /// This is the way out when the store keeps rejecting the queue, for
+  /// example because of an invalid twine in it. The dropped twines are
+  /// removed from the cache and returned, so they can be saved again.
+  pub async fn discard_queue(&self) -> Vec<AnyTwine> {
+    let queued = std::mem::take(&mut *self.queue.lock().await);
+    for twine in queued.iter() {
+      self.cache.evict(&twine.cid());
+    }
+    if !queued.is_empty() {
+      // the latest may be a dropped tixel
+      self.latest.clear();
+    }
+    queued
+  }
+
+  async fn write_queue(&self, queue: &mut Vec<AnyTwine>) -> Result<(), StoreError> {
+    if queue.is_empty() {
+      return Ok(());
+    }
+    // only dequeue once written, so a failed write can be retried
+    self.store().save_many(queue.iter().cloned()).await?;
+    queue.clear();
+    Ok(())
+  }
+
+  fn cache_twine(&self, twine: &AnyTwine) {
+    match twine {
+      AnyTwine::Strand(strand) => {
+        self.cache.cache_strand(strand.clone());
+      }
+      AnyTwine::Tixel(tixel) => {
+        self.cache.cache_tixel(tixel.clone());
+        // only move a known latest forward, otherwise the store decides
+        let strand = tixel.strand_cid();
+        if let Some(latest) = self.latest.get(&strand) {
+          if tixel.index() > latest.index() {
+            self.latest.insert(strand, tixel.clone());
+          }
+        }
+      }
+    }
+  }
+
+  async fn write(&self, twines: Vec<AnyTwine>) -> Result<(), StoreError> {
+    match self.policy {
+      WritePolicy::WriteThrough => self.store().save_many(twines.iter().cloned()).await?,
+      WritePolicy::WriteBack(queue_size) => {
+        let mut queue = self.queue.lock().await;
+        // a previous write failed, so retry it before queueing more
+        if queue.len() >= queue_size {
+          self.write_queue(&mut queue).await?;
+        }
+        if queue.len() + twines.len() >= queue_size {
+          // only queued once written, so a failed save leaves no trace
+          let all = queue.iter().chain(twines.iter()).cloned();
+          self.store().save_many(all).await?;
+          queue.clear();
+        } else {
+          queue.extend(twines.iter().cloned());
+        }
+      }
+    }
+    for twine in twines.iter() {
+      self.cache_twine(twine);
+    }
+    Ok(())
+  }
+
+  /// Make queued saves visible to the store before a cache miss
+  async fn before_miss(&self) -> Result<(), ResolutionError> {
+    self.flush().await.map_err(flush_error)
+  }
+}
+
+impl<S: Store + Resolver> Drop for CachedStore<S> {
+  fn drop(&mut self) {
+    let queued = self.queue.get_mut().len();
+    if queued > 0 {
+      log::error!(
+        "CachedStore dropped with {} unwritten saves, call flush() before dropping it",
+        queued
+      );
+    }
+  }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<S: Store + Resolver> unchecked_base::BaseResolver for CachedStore<S> {
+  async fn fetch_strands(
+    &self,
+  ) -> Result<unchecked_base::TwineStream<'_, Strand>, ResolutionError> {
+    self.before_miss().await?;
+    self.cache.fetch_strands().await
+  }
+
+  async fn has_index(&self, strand: &Cid, index: u64) -> Result<bool, ResolutionError> {
+    if self.cache.cached_index(strand, index).is_some() {
+      return Ok(true);
+    }
+    self.before_miss().await?;
+    self.cache.has_index(strand, index).await
+  }
+
+  async fn has_twine(&self, strand: &Cid, cid: &Cid) -> Result<bool, ResolutionError> {
+    if self.cache.cached_tixel(cid).is_some() {
+      return Ok(true);
+    }
+    self.before_miss().await?;
+    self.cache.has_twine(strand, cid).await
+  }
+
+  async fn has_strand(&self, cid: &Cid) -> Result<bool, ResolutionError> {
+    if self.cache.cached_strand(cid).is_some() {
+      return Ok(true);
+    }
+    self.before_miss().await?;
+    self.cache.has_strand(cid).await
+  }
+
+  async fn fetch_latest(&self, strand: &Cid) -> Result<Tixel, ResolutionError> {
+    if let Some(latest) = self.latest.get(strand) {
+      return Ok(latest);
+    }
+    self.before_miss().await?;
+    let latest = self.cache.fetch_latest(strand).await?;
+    self.latest.insert(*strand, latest.clone());
+    Ok(latest)
+  }
+
+  async fn fetch_index(&self, strand: &Cid, index: u64) -> Result<Tixel, ResolutionError> {
+    if let Some(tixel) = self.cache.cached_index(strand, index) {
+      return Ok(tixel);
+    }
+    self.before_miss().await?;
+    self.cache.fetch_index(strand, index).await
+  }
+
+  async fn fetch_tixel(&self, strand: &Cid, tixel: &Cid) -> Result<Tixel, ResolutionError> {
+    if let Some(tixel) = self.cache.cached_tixel(tixel) {
+      return Ok(tixel);
+    }
+    self.before_miss().await?;
+    self.cache.fetch_tixel(strand, tixel).await
+  }
+
+  async fn fetch_strand(&self, strand: &Cid) -> Result<Strand, ResolutionError> {
+    if let Some(strand) = self.cache.cached_strand(strand) {
+      return Ok(strand);
+    }
+    self.before_miss().await?;
+    self.cache.fetch_strand(strand).await
+  }
+
+  async fn range_stream<'a>(
+    &'a self,
+    range: AbsoluteRange,
+  ) -> Result<unchecked_base::TwineStream<'a, Tixel>, ResolutionError> {
+    self.before_miss().await?;
+    self.cache.range_stream(range).await
+  }
+}
+
+impl<S: Store + Resolver> Resolver for CachedStore<S> {}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<S: Store + Resolver> Store for CachedStore<S> {
+  async fn save<T: Into<AnyTwine> + MaybeSend>(&self, twine: T) -> Result<(), StoreError> {
+    self.write(vec![twine.into()]).await
+  }
+
+  async fn save_many<
+    I: Into<AnyTwine> + MaybeSend,
+    It: Iterator<Item = I> + MaybeSend,
+    T: IntoIterator<Item = I, IntoIter = It> + MaybeSend,
+  >(
+    &self,
+    twines: T,
+  ) -> Result<(), StoreError> {
+    self
+      .write(twines.into_iter().map(Into::into).collect())
+      .await
+  }
+
+  async fn save_stream<I: Into<AnyTwine> + MaybeSend, T: Stream<Item = I> + MaybeSend + Unpin>(
+    &self,
+    twines: T,
+  ) -> Result<(), StoreError> {
+    twines
+      .chunks(100)
+      .map(Ok::<_, StoreError>)
+      .try_for_each(|chunk| self.save_many(chunk))
+      .await
+  }
+
+  async fn delete<C: AsCid + MaybeSend>(&self, cid: C) -> Result<(), StoreError> {
+    let cid = *cid.as_cid();
+    self.flush().await?;
+    self.store().delete(cid).await?;
+    match self.cache.evict(&cid) {
+      // the latest may have moved back
+      Some(strand) => {
+        self.latest.remove(&strand);
+      }
+      None => {
+        // a strand, or a tixel of an unknown strand
+        if self.latest.remove(&cid).is_none() {
+          self.latest.clear();
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+impl<S: Store + Resolver> Deref for CachedStore<S> {
+  type Target = S;
+
+  fn deref(&self) -> &Self::Target {
+    &self.cache
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::resolver::unchecked_base::BaseResolver;
+  use crate::store::MemoryStore;
+  use crate::test::*;
+  use crate::twine::TwineBlock;
+
+  fn twines() -> (Strand, Tixel) {
+    let strand = Strand::from_tagged_dag_json(STRANDJSON).unwrap();
+    let tixel = Tixel::from_tagged_dag_json(TIXELJSON).unwrap();
+    (strand, tixel)
+  }
+
+  #[tokio::test]
+  async fn test_write_through() {
+    let (strand, tixel) = twines();
+    let store = CachedStore::new(MemoryStore::new());
+    store.save(strand.clone()).await.unwrap();
+    store.save(tixel.clone()).await.unwrap();
+    // written to the store
+    assert!(store.deref().has_strand(&strand.cid()).await.unwrap());
+    assert_eq!(
+      store.deref().fetch_latest(&strand.cid()).await.unwrap(),
+      tixel
+    );
+    // and cached
+    assert_eq!(store.cache.cached_tixel(&tixel.cid()), Some(tixel.clone()));
+    assert_eq!(
+      store
+        .fetch_index(&strand.cid(), tixel.index())
+        .await
+        .unwrap(),
+      tixel
+    );
+  }
+
+  #[tokio::test]
+  async fn test_write_back() {
+    let (strand, tixel) = twines();
+    let store = CachedStore::new(MemoryStore::new()).write_back(10);
+    store.save(strand.clone()).await.unwrap();
+    store.save(tixel.clone()).await.unwrap();
+    assert!(!store.deref().has_strand(&strand.cid()).await.unwrap());
+    // cached reads don't need the store
+    assert_eq!(store.fetch_strand(&strand.cid()).await.unwrap(), strand);
+    assert!(!store.deref().has_strand(&strand.cid()).await.unwrap());
+    // a miss writes the queue first
+    assert_eq!(store.fetch_latest(&strand.cid()).await.unwrap(), tixel);
+    assert!(store.deref().has_strand(&strand.cid()).await.unwrap());
+    assert!(store.queue.lock().await.is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_write_back_queue_bound() {
+    let (strand, tixel) = twines();
+    let store = CachedStore::new(MemoryStore::new()).write_back(2);
+    store.save(strand.clone()).await.unwrap();
+    assert_eq!(store.queue.lock().await.len(), 1);
+    store.save(tixel.clone()).await.unwrap();
+    assert!(store.queue.lock().await.is_empty());
+    assert!(store
+      .deref()
+      .has_twine(&strand.cid(), &tixel.cid())
+      .await
+      .unwrap());
+  }
+
+  #[tokio::test]
+  async fn test_write_back_failed_flush() {
+    let (strand, tixel) = twines();
+    let store = CachedStore::new(MemoryStore::new()).write_back(2);
+    // the store rejects tixels of strands it doesn't have
+    store.save(tixel.clone()).await.unwrap();
+    assert!(store.save(tixel.clone()).await.is_err());
+    // the failed save is not queued
+    assert_eq!(store.queue.lock().await.len(), 1);
+    assert!(store.save(tixel.clone()).await.is_err());
+    assert_eq!(store.queue.lock().await.len(), 1);
+
+    store.deref().save(strand.clone()).await.unwrap();
+    store.save(tixel.clone()).await.unwrap();
+    store.flush().await.unwrap();
+    assert!(store.queue.lock().await.is_empty());
+    assert!(store
+      .deref()
+      .has_twine(&strand.cid(), &tixel.cid())
+      .await
+      .unwrap());
+  }
+
+  #[tokio::test]
+  async fn test_discard_queue() {
+    let (strand, tixel) = twines();
+    let store = CachedStore::new(MemoryStore::new()).write_back(3);
+    store.save(tixel.clone()).await.unwrap();
+    store.save(tixel.clone()).await.unwrap();
+    // the queued tixel is rejected by every write
+    assert!(store.flush().await.is_err());
+    assert!(store.save(strand.clone()).await.is_err());
+    assert!(store.fetch_strand(&strand.cid()).await.is_err());
+
+    let discarded = store.discard_queue().await;
+    assert_eq!(discarded.len(), 2);
+    assert!(store.cache.cached_tixel(&tixel.cid()).is_none());
+    store.flush().await.unwrap();
+    store.save(strand.clone()).await.unwrap();
+    store.save_many(discarded).await.unwrap();
+    store.flush().await.unwrap();
+    assert!(store
+      .deref()
+      .has_twine(&strand.cid(), &tixel.cid())
+      .await
+      .unwrap());
+  }
+
+  #[tokio::test]
+  async fn test_latest_after_delete() {
+    let (strand, tixel) = twines();
+    let store = CachedStore::new(MemoryStore::new());
+    store.save(strand.clone()).await.unwrap();
+    store.save(tixel.clone()).await.unwrap();
+    assert_eq!(store.fetch_latest(&strand.cid()).await.unwrap(), tixel);
+
+    store.delete(tixel.cid()).await.unwrap();
+    assert!(store.cache.cached_tixel(&tixel.cid()).is_none());
+    assert!(matches!(
+      store.fetch_latest(&strand.cid()).await,
+      Err(ResolutionError::NotFound)
+    ));
+    assert!(!store.has_index(&strand.cid(), tixel.index()).await.unwrap());
+
+    store.save(tixel.clone()).await.unwrap();
+    assert_eq!(store.fetch_latest(&strand.cid()).await.unwrap(), tixel);
+    store.delete(strand.cid()).await.unwrap();
+    assert!(!store.has_strand(&strand.cid()).await.unwrap());
+  }
+}
@@ -6,12 +6,18 @@ use crate::Cid;
 use async_trait::async_trait;
 use futures::stream::StreamExt;
 use quick_cache::sync::Cache;
-use std::collections::HashMap;
+use std::convert::Infallible;
 use std::ops::Deref;
 use std::sync::{Arc, RwLock};
 
 type TixelCache = Cache<Cid, Tixel>;
-type StrandCache = HashMap<Cid, (Option<Strand>, Cache<u64, Cid>)>;
+type StrandCache = Cache<Cid, Arc<StrandEntry>>;
+
+#[derive(Debug)]
+struct StrandEntry {
+  strand: RwLock<Option<Strand>>,
+  indices: Cache<u64, Cid>,
+}
 
 /// A memory cache that implements [`Resolver`]
 ///
@@ -20,9 +26,11 @@ type StrandCache = HashMap<Cid, (Option<Strand>, Cache<u64, Cid>)>;
 /// sits on top of another [`Resolver`]
 /// and when a cache miss occurs it defers
 /// the call to the underlying Resolver.
+///
+/// To cache data as it is saved, use [`CachedStore`](super::CachedStore).
 #[derive(Debug)]
 pub struct MemoryCache<T: Resolver> {
-  strands: Arc<RwLock<StrandCache>>,
+  strands: StrandCache,
   tixels: TixelCache,
   resolver: T,
   cache_size: usize,
@@ -32,7 +40,7 @@ impl<T: Resolver> MemoryCache<T> {
   /// Create a new cache, wrapping existing resolver
   pub fn new(resolver: T) -> Self {
     Self {
-      strands: Arc::new(RwLock::new(HashMap::new())),
+      strands: Cache::new(100),
       tixels: Cache::new(1000),
       resolver,
       cache_size: 1000,
@@ -40,35 +48,95 @@ impl<T: Resolver> MemoryCache<T> {
   }
 
   /// Set a specific cache size
+  ///
+  /// This is the number of tixels kept in memory, and the number
+  /// of indices remembered for each strand.
   pub fn with_cache_size(mut self, cache_size: usize) -> Self {
-    self.cache_size = cache_size;
+    self.set_cache_size(cache_size);
     self
   }
 
-  fn cache_tixel(&self, tixel: Tixel) -> Tixel {
-    let strand_cid = tixel.strand_cid();
-    let mut store = self.strands.write().unwrap();
-    let cache = store
-      .entry(strand_cid)
-      .or_insert_with(|| (None, Cache::new(self.cache_size)));
-    let _ = cache
-      .1
-      .get_or_insert_with(&tixel.index(), || Ok::<_, ResolutionError>(tixel.cid()));
+  /// Set the number of strands kept in memory
+  ///
+  /// Defaults to 100.
+  pub fn with_strand_cache_size(mut self, strand_cache_size: usize) -> Self {
+    self.set_strand_cache_size(strand_cache_size);
+    self
+  }
+
+  pub(crate) fn set_cache_size(&mut self, cache_size: usize) {
+    self.cache_size = cache_size.max(1);
+    self.tixels = Cache::new(self.cache_size);
+  }
+
+  pub(crate) fn set_strand_cache_size(&mut self, strand_cache_size: usize) {
+    self.strands = Cache::new(strand_cache_size.max(1));
+  }
+
+  fn strand_entry(&self, strand: &Cid) -> Arc<StrandEntry> {
+    self
+      .strands
+      .get_or_insert_with(strand, || {
+        Ok::<_, Infallible>(Arc::new(StrandEntry {
+          strand: RwLock::new(None),
+          indices: Cache::new(self.cache_size),
+        }))
+      })
+      .unwrap()
+  }
+
+  pub(crate) fn cache_tixel(&self, tixel: Tixel) -> Tixel {
+    self
+      .strand_entry(&tixel.strand_cid())
+      .indices
+      .insert(tixel.index(), tixel.cid());
     self.tixels.insert(tixel.cid(), tixel.clone());
     tixel
   }
 
-  fn cache_strand(&self, strand: Strand) -> Strand {
-    let strand_cid = strand.cid();
-    let mut store = self.strands.write().unwrap();
-    let entry = store
-      .entry(strand_cid)
-      .or_insert_with(|| (None, Cache::new(self.cache_size)));
-    if entry.0.is_none() {
-      entry.0 = Some(strand.clone());
+  pub(crate) fn cache_strand(&self, strand: Strand) -> Strand {
+    let entry = self.strand_entry(&strand.cid());
+    let mut cached = entry.strand.write().unwrap();
+    if cached.is_none() {
+      *cached = Some(strand.clone());
     }
     strand
   }
+
+  /// Forget a strand or tixel
+  ///
+  /// Returns the strand cid of a forgotten tixel, if it was cached.
+  pub(crate) fn evict(&self, cid: &Cid) -> Option<Cid> {
+    if let Some((_, tixel)) = self.tixels.remove(cid) {
+      if let Some(entry) = self.strands.get(&tixel.strand_cid()) {
+        entry.indices.remove(&tixel.index());
+      }
+      return Some(tixel.strand_cid());
+    }
+    // the strand entry may already be evicted while its tixels are not
+    self.strands.remove(cid);
+    self.tixels.retain(|_, tixel| tixel.strand_cid() != *cid);
+    None
+  }
+
+  pub(crate) fn cached_strand(&self, strand: &Cid) -> Option<Strand> {
+    self
+      .strands
+      .get(strand)
+      .and_then(|entry| entry.strand.read().unwrap().clone())
+  }
+
+  pub(crate) fn cached_tixel(&self, tixel: &Cid) -> Option<Tixel> {
+    self.tixels.get(tixel)
+  }
+
+  pub(crate) fn cached_index(&self, strand: &Cid, index: u64) -> Option<Tixel> {
+    self
+      .strands
+      .get(strand)
+      .and_then(|entry| entry.indices.get(&index))
+      .and_then(|cid| self.tixels.get(&cid))
+  }
 }
 
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
@@ -95,11 +163,8 @@ impl<T: Resolver> unchecked_base::BaseResolver for MemoryCache<T> {
   }
 
   async fn has_index(&self, strand: &Cid, index: u64) -> Result<bool, ResolutionError> {
-    let has = match self.strands.read().unwrap().get(strand) {
-      Some((_, cache)) => match cache.get(&index) {
-        Some(_) => true,
-        None => false,
-      },
+    let has = match self.strands.get(strand) {
+      Some(entry) => entry.indices.get(&index).is_some(),
       None => false,
     };
     if has {
@@ -110,11 +175,8 @@ impl<T: Resolver> unchecked_base::BaseResolver for MemoryCache<T> {
   }
 
   async fn has_twine(&self, strand: &Cid, cid: &Cid) -> Result<bool, ResolutionError> {
-    let has = match self.strands.read().unwrap().get(strand) {
-      Some((_, _cache)) => match self.tixels.get(cid) {
-        Some(_) => true,
-        None => false,
-      },
+    let has = match self.strands.get(strand) {
+      Some(_) => self.tixels.get(cid).is_some(),
       None => false,
     };
     if has {
@@ -125,7 +187,7 @@ impl<T: Resolver> unchecked_base::BaseResolver for MemoryCache<T> {
   }
 
   async fn has_strand(&self, cid: &Cid) -> Result<bool, ResolutionError> {
-    let has = self.strands.read().unwrap().contains_key(cid);
+    let has = self.strands.get(cid).is_some();
     if has {
       Ok(true)
     } else {
@@ -140,15 +202,8 @@ impl<T: Resolver> unchecked_base::BaseResolver for MemoryCache<T> {
   }
 
   async fn fetch_index(&self, strand: &Cid, index: u64) -> Result<Tixel, ResolutionError> {
-    let maybe_cid = self
-      .strands
-      .read()
-      .unwrap()
-      .get(strand)
-      .map(|(_, cache)| cache.get(&index))
-      .flatten();
-    if let Some(tixel) = maybe_cid.map(|cid| self.tixels.get(&cid)).flatten() {
-      Ok(tixel.clone())
+    if let Some(tixel) = self.cached_index(strand, index) {
+      Ok(tixel)
     } else {
       let tixel = self.resolver.fetch_index(strand, index).await?;
       Ok(self.cache_tixel(tixel))
@@ -156,9 +211,8 @@ impl<T: Resolver> unchecked_base::BaseResolver for MemoryCache<T> {
   }
 
   async fn fetch_tixel(&self, strand: &Cid, tixel: &Cid) -> Result<Tixel, ResolutionError> {
-    let maybe_tixel = self.tixels.get(tixel);
-    if let Some(tixel) = maybe_tixel {
-      Ok(tixel.clone())
+    if let Some(tixel) = self.cached_tixel(tixel) {
+      Ok(tixel)
     } else {
       let tixel = self.resolver.fetch_tixel(strand, tixel).await?;
       Ok(self.cache_tixel(tixel))
@@ -166,14 +220,7 @@ impl<T: Resolver> unchecked_base::BaseResolver for MemoryCache<T> {
   }
 
   async fn fetch_strand(&self, strand: &Cid) -> Result<Strand, ResolutionError> {
-    let maybe_strand = self
-      .strands
-      .read()
-      .unwrap()
-      .get(strand)
-      .map(|(strand, _)| strand.clone())
-      .flatten();
-    if let Some(strand) = maybe_strand {
+    if let Some(strand) = self.cached_strand(strand) {
       Ok(strand)
     } else {
       let strand = self.resolver.fetch_strand(strand).await?;
@@ -216,6 +263,7 @@ impl<R: Resolver> Deref for MemoryCache<R> {
 mod test {
   use super::*;
   use crate::{test::*, twine::TwineBlock};
+  use std::collections::HashMap;
 
   #[derive(Debug, Clone)]
   struct DummyResolver {
@@ -394,4 +442,38 @@ mod test {
     assert_eq!(cache.strand_hits.read().unwrap().get(&strand_cid), Some(&1));
     assert_eq!(cache.tixel_hits.read().unwrap().get(&tixel_cid), Some(&1));
   }
+
+  #[test]
+  fn test_strand_cache_is_bounded() {
+    use multihash_codetable::{Code, MultihashDigest};
+    let cache = MemoryCache::new(crate::store::MemoryStore::new()).with_strand_cache_size(10);
+    for i in 0..1000u32 {
+      let cid = Cid::new_v1(0x71, Code::Sha2_256.digest(&i.to_le_bytes()));
+      cache.strand_entry(&cid);
+    }
+    assert!(cache.strands.len() <= 10);
+  }
+
+  #[test]
+  fn test_evict_strand_after_its_entry_is_evicted() {
+    use multihash_codetable::{Code, MultihashDigest};
+    let tixel = Tixel::from_tagged_dag_json(TIXELJSON).unwrap();
+    let strand_cid = tixel.strand_cid();
+    let cache = MemoryCache::new(crate::store::MemoryStore::new()).with_strand_cache_size(10);
+    let fill = |range: std::ops::Range<u32>| {
+      for i in range {
+        let cid = Cid::new_v1(0x71, Code::Sha2_256.digest(&i.to_le_bytes()));
+        cache.strand_entry(&cid);
+      }
+    };
+    // more strands than the cache holds push out the tixel's strand
+    fill(0..100);
+    cache.cache_tixel(tixel.clone());
+    fill(100..1000);
+    assert!(cache.strands.get(&strand_cid).is_none());
+    assert_eq!(cache.cached_tixel(&tixel.cid()), Some(tixel.clone()));
+
+    assert_eq!(cache.evict(&strand_cid), None);
+    assert!(cache.cached_tixel(&tixel.cid()).is_none());
+  }
 }
@@ -214,11 +214,13 @@ impl Store for MemoryStore {
 
   async fn delete<C: AsCid + MaybeSend>(&self, cid: C) -> Result<(), StoreError> {
     let cid = cid.as_cid();
-    if let Some(s) = self.strands.write().unwrap().remove(&cid) {
+    // bind first so the strands lock is released before the else branch
+    let removed = self.strands.write().unwrap().remove(cid);
+    if let Some(s) = removed {
       for tixel in s.by_index.values() {
         self.tixels.write().unwrap().remove(&tixel.cid());
       }
-    } else if let Some(tixel) = self.tixels.write().unwrap().remove(&cid) {
+    } else if let Some(tixel) = self.tixels.write().unwrap().remove(cid) {
       if let Some(strand) = self.strands.write().unwrap().get_mut(&tixel.strand_cid()) {
         strand.by_index.remove(&tixel.index());
       }
@@ -280,6 +282,18 @@ mod test {
       .is_err());
   }
 
+  #[tokio::test]
+  async fn test_memory_store_delete_tixel() {
+    let store = MemoryStore::new();
+    let strand = Strand::from_tagged_dag_json(STRANDJSON).unwrap();
+    let tixel = Tixel::from_tagged_dag_json(TIXELJSON).unwrap();
+    store.save(strand.clone()).await.unwrap();
+    store.save(tixel.clone()).await.unwrap();
+    store.delete(tixel.cid()).await.unwrap();
+    assert!(store.has_strand(&strand.cid()).await.unwrap());
+    assert!(!store.has_index(&strand.cid(), tixel.index()).await.unwrap());
+  }
+
   #[tokio::test]
   async fn test_memory_store_strand_list() {
     let store = MemoryStore::new();
@@ -6,3 +6,6 @@ pub use memory_store::*;
 
 mod memory_cache;
 pub use memory_cache::*;
+
+mod cached_store;
+pub use cached_store::*;
@@ -0,0 +1,334 @@
+//! Trust policies for pinning strands and revoking keys
+//!
+//! A [`TrustStore`] records which strands are trusted and which keys
+//! have been revoked. A revocation can apply to everything a key signed,
+//! or only to tixels after a cut-off index on each of its strands, so that
+//! a compromised key can be distrusted from the moment it was compromised
+//! while its earlier history remains valid.
+//!
+//! The policy is enforced by wrapping a resolver in a
+//! [`crate::resolver::TrustedResolver`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use twine_lib::{errors::ResolutionError, Cid};
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//! # let store = twine_lib::store::MemoryStore::default();
+//! # let strand_cid = Cid::default();
+//! use twine_lib::resolver::{Resolver, TrustedResolver};
+//! use twine_lib::trust::{Revocation, TrustStore};
+//!
+//! let fingerprint = "zQmcXNxxSnUBtn1fLRrsbmzJXmv4Pzbb3cKRX7jZUmP5Ck5".parse().unwrap();
+//! // record the cut-off on this strand as of the tixels we hold now
+//! let revocation = Revocation::new(fingerprint)
+//!   .after_time(&store, strand_cid, "2025-01-01T00:00:00Z".parse().unwrap())
+//!   .await?;
+//! let trust = TrustStore::default().pin(strand_cid).revoke(revocation);
+//! let resolver = TrustedResolver::new(store, trust);
+//! // fails with ResolutionError::Untrusted if the policy is violated
+//! let latest = resolver.resolve_latest(strand_cid).await?;
+//! # Ok::<_, ResolutionError>(())
+//! # });
+//! ```
+use crate::cid::multibase;
+use crate::crypto::PublicKey;
+use crate::errors::{KeyFormatError, ResolutionError, TrustViolation};
+use crate::resolver::Resolver;
+use crate::twine::{Strand, Tixel};
+use crate::Cid;
+use chrono::{DateTime, Utc};
+use multihash_codetable::Multihash;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// The fingerprint of a public key
+///
+/// This is the multihash of the key's SPKI (see [`PublicKey::fingerprint`]),
+/// written as a base58btc multibase string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Fingerprint(pub Multihash);
+
+impl Fingerprint {
+  /// Compute the fingerprint of a public key
+  pub fn of(key: &PublicKey) -> Result<Self, KeyFormatError> {
+    Ok(Self(key.fingerprint()?))
+  }
+}
+
+impl Display for Fingerprint {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "{}",
+      multibase::encode(multibase::Base::Base58Btc, self.0.to_bytes())
+    )
+  }
+}
+
+impl FromStr for Fingerprint {
+  type Err = KeyFormatError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (_, bytes) =
+      multibase::decode(s).map_err(|e| KeyFormatError::Malformed(format!("fingerprint: {}", e)))?;
+    let hash = Multihash::from_bytes(&bytes)
+      .map_err(|e| KeyFormatError::Malformed(format!("fingerprint: {}", e)))?;
+    Ok(Self(hash))
+  }
+}
+
+impl TryFrom<String> for Fingerprint {
+  type Error = KeyFormatError;
+
+  fn try_from(s: String) -> Result<Self, Self::Error> {
+    s.parse()
+  }
+}
+
+impl From<Fingerprint> for String {
+  fn from(fingerprint: Fingerprint) -> Self {
+    fingerprint.to_string()
+  }
+}
+
+/// A revoked key, optionally only after a cut-off on each strand
+///
+/// Indices only have meaning within one strand, so cut-offs are recorded
+/// per strand. On a strand with a cut-off, tixels after the cut-off index
+/// are not trusted. Nothing else signed by the key is trusted, including
+/// any of its strands without a cut-off.
+///
+/// There is no cut-off by time, because tixel timestamps are signed by
+/// the revoked key itself, so whoever holds it could backdate new tixels.
+/// Use [`Revocation::after_time`] to turn a time into an index once, when
+/// the revocation is recorded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Revocation {
+  /// The fingerprint of the revoked key
+  pub fingerprint: Fingerprint,
+  /// For each strand, distrust tixels with an index greater than this
+  #[serde(
+    default,
+    skip_serializing_if = "BTreeMap::is_empty",
+    with = "cid_index_map"
+  )]
+  pub after: BTreeMap<Cid, u64>,
+}
+
+impl Revocation {
+  /// Revoke everything signed by a key
+  pub fn new(fingerprint: Fingerprint) -> Self {
+    Self {
+      fingerprint,
+      after: BTreeMap::new(),
+    }
+  }
+
+  /// Only distrust tixels of a strand with an index greater than this
+  pub fn after_index(mut self, strand: Cid, index: u64) -> Self {
+    self.after.insert(strand, index);
+    self
+  }
+
+  /// Only distrust tixels of a strand that came after a time
+  ///
+  /// The cut-off is set at the tixel found by
+  /// [`Resolver::resolve_at_time`], so the tixels of the strand must carry
+  /// monotonic timestamps (see [`crate::twine::TIMESTAMP_FIELD`]). If the
+  /// very first tixel is after `time`, no cut-off is recorded and the
+  /// strand is distrusted entirely.
+  ///
+  /// The result is only as good as the tixels the resolver holds when this
+  /// is called, so call it when the compromise is discovered and store the
+  /// resulting index. Call it once for each strand signed by the key.
+  pub async fn after_time<R: Resolver>(
+    mut self,
+    resolver: &R,
+    strand: Cid,
+    time: DateTime<Utc>,
+  ) -> Result<Self, ResolutionError> {
+    if !resolver.has_strand(&strand).await? {
+      return Err(ResolutionError::NotFound);
+    }
+    match resolver.resolve_at_time(strand, time).await {
+      Ok(twine) => {
+        self.after.insert(strand, twine.index());
+      }
+      Err(ResolutionError::NotFound) => {
+        self.after.remove(&strand);
+      }
+      Err(e) => return Err(e),
+    }
+    Ok(self)
+  }
+
+  /// The cut-off index on a strand, if there is one
+  pub fn cutoff(&self, strand: &Cid) -> Option<u64> {
+    self.after.get(strand).copied()
+  }
+}
+
+/// A policy of pinned strands and revoked keys
+///
+/// If any strands are pinned, only those strands are trusted.
+/// The store can be (de)serialized, for example from a config file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrustStore {
+  #[serde(with = "cid_strings")]
+  pinned: BTreeSet<Cid>,
+  revoked: Vec<Revocation>,
+}
+
+impl TrustStore {
+  /// Pin a strand
+  pub fn pin(mut self, strand: Cid) -> Self {
+    self.pinned.insert(strand);
+    self
+  }
+
+  /// Revoke a key
+  pub fn revoke(mut self, revocation: Revocation) -> Self {
+    self.revoked.push(revocation);
+    self
+  }
+
+  /// The pinned strands
+  pub fn pinned(&self) -> impl Iterator<Item = &Cid> {
+    self.pinned.iter()
+  }
+
+  /// The key revocations
+  pub fn revocations(&self) -> &[Revocation] {
+    &self.revoked
+  }
+
+  /// Check if the policy places no restrictions
+  pub fn is_empty(&self) -> bool {
+    self.pinned.is_empty() && self.revoked.is_empty()
+  }
+
+  fn revocations_for(&self, strand: &Strand) -> Result<Vec<&Revocation>, TrustViolation> {
+    if self.revoked.is_empty() {
+      return Ok(vec![]);
+    }
+    let fingerprint = Fingerprint::of(&strand.key()).map_err(|e| {
+      TrustViolation(format!(
+        "Can not fingerprint key of strand {}: {}",
+        strand.cid(),
+        e
+      ))
+    })?;
+    Ok(
+      self
+        .revoked
+        .iter()
+        .filter(|r| r.fingerprint == fingerprint)
+        .collect(),
+    )
+  }
+
+  /// Check that a strand is trusted
+  pub fn check_strand(&self, strand: &Strand) -> Result<(), TrustViolation> {
+    self.check_revocations(strand).map(|_| ())
+  }
+
+  fn check_revocations(&self, strand: &Strand) -> Result<Vec<u64>, TrustViolation> {
+    if !self.pinned.is_empty() && !self.pinned.contains(&strand.cid()) {
+      return Err(TrustViolation(format!(
+        "Strand {} is not pinned",
+        strand.cid()
+      )));
+    }
+    self
+      .revocations_for(strand)?
+      .iter()
+      .map(|r| {
+        r.cutoff(&strand.cid())
+          .ok_or_else(|| TrustViolation(format!("Key of strand {} is revoked", strand.cid())))
+      })
+      .collect()
+  }
+
+  /// Check that a tixel of a strand is trusted
+  pub fn check_tixel(&self, strand: &Strand, tixel: &Tixel) -> Result<(), TrustViolation> {
+    for index in self.check_revocations(strand)? {
+      if tixel.index() > index {
+        return Err(TrustViolation(format!(
+          "Tixel {} is after the revocation of its key at index {}",
+          tixel.cid(),
+          index
+        )));
+      }
+    }
+    Ok(())
+  }
+}
+
+mod cid_strings {
+  use super::*;
+  use serde::{de::Error, Deserializer, Serializer};
+
+  pub fn serialize<S: Serializer>(cids: &BTreeSet<Cid>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(cids.iter().map(|cid| cid.to_string()))
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<BTreeSet<Cid>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+      .iter()
+      .map(|s| s.parse().map_err(D::Error::custom))
+      .collect()
+  }
+}
+
+mod cid_index_map {
+  use super::*;
+  use serde::{de::Error, Deserializer, Serializer};
+
+  pub fn serialize<S: Serializer>(
+    map: &BTreeMap<Cid, u64>,
+    serializer: S,
+  ) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(map.iter().map(|(cid, index)| (cid.to_string(), index)))
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<BTreeMap<Cid, u64>, D::Error> {
+    BTreeMap::<String, u64>::deserialize(deserializer)?
+      .into_iter()
+      .map(|(s, index)| Ok((s.parse().map_err(D::Error::custom)?, index)))
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use multihash_codetable::{Code, MultihashDigest};
+
+  #[test]
+  fn test_serialization() {
+    let fingerprint = Fingerprint(Code::Sha2_256.digest(b"key"));
+    assert_eq!(
+      fingerprint.to_string().parse::<Fingerprint>().unwrap(),
+      fingerprint
+    );
+    assert!("not a fingerprint".parse::<Fingerprint>().is_err());
+    let strand = Cid::new_v1(0x71, Code::Sha3_256.digest(b"strand"));
+    let trust = TrustStore::default()
+      .pin(strand)
+      .revoke(Revocation::new(fingerprint).after_index(strand, 10));
+    let json = serde_json::to_string(&trust).unwrap();
+    let back: TrustStore = serde_json::from_str(&json).unwrap();
+    assert_eq!(back, trust);
+    let empty: TrustStore = serde_json::from_str("{}").unwrap();
+    assert!(empty.is_empty());
+  }
+}
@@ -12,6 +12,7 @@ use crate::specification::Subspec;
 use crate::verify::Verified;
 use crate::Cid;
 use crate::Ipld;
+use chrono::{DateTime, Utc};
 use ipld_core::codec::Codec;
 use ipld_core::serde::from_ipld;
 use multihash_codetable::Code;
@@ -20,6 +21,22 @@ use serde::de::DeserializeOwned;
 use serde_ipld_dagcbor::codec::DagCborCodec;
 use serde_ipld_dagjson::codec::DagJsonCodec;
 
+/// The payload field used to record the time a Tixel was created
+///
+/// By convention, a Tixel payload that is a map may contain this field
+/// holding an RFC 3339 datetime string. Tools that look up tixels by time
+/// (such as [`crate::resolver::Resolver::resolve_at_time`]) rely on it.
+pub const TIMESTAMP_FIELD: &str = "timestamp";
+
+/// The payload field used to hand a Strand off to a successor
+///
+/// A strand's key can not change, so to retire a key its final Tixel names
+/// the CID of a successor strand (as a link) in this field. The first Tixel
+/// of the successor cross-stitches back to that final Tixel, so the handoff
+/// is signed by both the old and the new key.
+/// [`crate::resolver::Resolver::resolve_successor`] checks both sides.
+pub const SUCCESSOR_FIELD: &str = "successor";
+
 /// A Tixel is the chained data block of the Twine protocol
 ///
 /// A tixel alone can be checked for integrity, but not authenticity.
@@ -125,6 +142,44 @@ impl Tixel {
     from_ipld(payload.clone()).map_err(|e| VerificationError::Payload(e.to_string()))
   }
 
+  /// Get the timestamp recorded in the payload
+  ///
+  /// This reads the [`TIMESTAMP_FIELD`] of a map payload, which must
+  /// be an RFC 3339 datetime string.
+  pub fn timestamp(&self) -> Result<DateTime<Utc>, VerificationError> {
+    let value = match self.payload() {
+      Ipld::Map(map) => map.get(TIMESTAMP_FIELD),
+      _ => None,
+    };
+    match value {
+      Some(Ipld::String(s)) => DateTime::parse_from_rfc3339(s)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|e| VerificationError::Payload(format!("Invalid timestamp: {}", e))),
+      Some(_) => Err(VerificationError::Payload(
+        "Timestamp is not a string".into(),
+      )),
+      None => Err(VerificationError::Payload(
+        "Payload has no timestamp".into(),
+      )),
+    }
+  }
+
+  /// Get the successor strand named in the payload, if any
+  ///
+  /// This reads the [`SUCCESSOR_FIELD`] of a map payload, which must
+  /// be a link to the successor strand.
+  pub fn successor(&self) -> Result<Option<Cid>, VerificationError> {
+    let value = match self.payload() {
+      Ipld::Map(map) => map.get(SUCCESSOR_FIELD),
+      _ => None,
+    };
+    match value {
+      Some(Ipld::Link(cid)) => Ok(Some(*cid)),
+      Some(_) => Err(VerificationError::Payload("Successor is not a link".into())),
+      None => Ok(None),
+    }
+  }
+
   /// Get the drop index
   pub fn drop_index(&self) -> u64 {
     self.0.drop_index()
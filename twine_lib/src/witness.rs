@@ -0,0 +1,177 @@
+//! Witness attestations from independent strands
+//!
+//! A witness is a strand whose only job is to record that it observed
+//! tixels of other strands. Each witness tixel carries a compact
+//! [`WitnessPayload`] listing a batch of foreign stitches, and (usually)
+//! a timestamp, attesting "I saw tixel X at time T".
+//!
+//! Witness strands are identified by the [`WITNESS_SUBSPEC`] subspec, so
+//! that [`find_attestations`] only needs to scan those strands to count
+//! how many independent parties observed a given tixel.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use twine_lib::{errors::ResolutionError, twine::{Stitch, Twine}};
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//! # let resolver = twine_lib::store::MemoryStore::default();
+//! # let observed: Vec<Stitch> = vec![];
+//! # let make_tixel = |_: twine_lib::witness::WitnessPayload| -> Twine { unimplemented!() };
+//! use twine_lib::witness::{find_attestations, WitnessPayload};
+//!
+//! // a witness strand is built with `.subspec(WITNESS_SUBSPEC.into())`,
+//! // then each tixel records a batch of observed stitches
+//! // builder.build_next(&prev).payload(payload).timestamp_now().done()
+//! let witnessed = make_tixel(WitnessPayload::new(observed.clone()));
+//! // ...later, anyone can count the witnesses of a tixel
+//! let attestations = find_attestations(&resolver, observed[0]).await?;
+//! println!("seen by {} witnesses", attestations.len());
+//! # Ok::<_, ResolutionError>(())
+//! # });
+//! ```
+use crate::errors::{ResolutionError, VerificationError};
+use crate::resolver::{RangeQuery, Resolver};
+use crate::twine::{Stitch, Strand, Tixel, Twine};
+use crate::Cid;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+
+/// The subspec prefix that marks a strand as a witness
+pub const WITNESS_SUBSPEC_PREFIX: &str = "witness";
+
+/// The subspec to use when building a witness strand
+pub const WITNESS_SUBSPEC: &str = "witness/1.0.0";
+
+/// Check if a strand is a witness strand
+pub fn is_witness(strand: &Strand) -> bool {
+  strand
+    .subspec()
+    .is_some_and(|s| s.prefix() == WITNESS_SUBSPEC_PREFIX)
+}
+
+/// The payload of a witness tixel
+///
+/// The observed stitches are stored as sorted (strand, tixel) pairs.
+/// It can be used as the payload directly, or flattened into a larger
+/// payload struct with `#[serde(flatten)]`. A timestamp can be added
+/// alongside it with the builder's `timestamp()` method.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WitnessPayload {
+  /// The observed stitches as (strand, tixel) pairs
+  pub witnessed: Vec<(Cid, Cid)>,
+}
+
+impl WitnessPayload {
+  /// Create a payload attesting to a batch of stitches
+  pub fn new<I: IntoIterator<Item = Stitch>>(stitches: I) -> Self {
+    let mut witnessed: Vec<_> = stitches.into_iter().map(|s| (s.strand, s.tixel)).collect();
+    witnessed.sort();
+    witnessed.dedup();
+    Self { witnessed }
+  }
+
+  /// Read the payload of a witness tixel
+  pub fn from_tixel(tixel: &Tixel) -> Result<Self, VerificationError> {
+    tixel.extract_payload()
+  }
+
+  /// The observed stitches
+  pub fn stitches(&self) -> impl Iterator<Item = Stitch> + '_ {
+    self.witnessed.iter().map(|&pair| pair.into())
+  }
+
+  /// Check if this payload attests to a stitch
+  pub fn includes(&self, stitch: &Stitch) -> bool {
+    self.witnessed.contains(&(stitch.strand, stitch.tixel))
+  }
+}
+
+/// A witness tixel that attests to having seen another tixel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attestation {
+  /// The witness tixel
+  pub witness: Twine,
+  /// The stitch that was observed
+  pub stitch: Stitch,
+}
+
+impl Attestation {
+  /// Check a witness tixel for an attestation of a stitch
+  ///
+  /// Returns `None` if the witness does not attest to the stitch.
+  pub fn from_witness(witness: Twine, stitch: Stitch) -> Result<Option<Self>, VerificationError> {
+    if !is_witness(witness.strand()) {
+      return Err(VerificationError::General(format!(
+        "Strand {} is not a witness",
+        witness.strand_cid()
+      )));
+    }
+    let payload = WitnessPayload::from_tixel(&witness)?;
+    Ok(
+      payload
+        .includes(&stitch)
+        .then_some(Self { witness, stitch }),
+    )
+  }
+
+  /// The strand of the witness
+  pub fn witness_strand(&self) -> Cid {
+    self.witness.strand_cid()
+  }
+
+  /// The time at which the witness claims to have seen the tixel, if recorded
+  pub fn time(&self) -> Option<DateTime<Utc>> {
+    self.witness.timestamp().ok()
+  }
+}
+
+/// Find all witness attestations for a tixel in a resolver
+///
+/// Every witness strand known to the resolver is scanned. Only the earliest
+/// attestation from each witness strand is returned, so the number of
+/// attestations is the number of independent witnesses.
+pub async fn find_attestations<R: Resolver, S: Into<Stitch>>(
+  resolver: &R,
+  stitch: S,
+) -> Result<Vec<Attestation>, ResolutionError> {
+  let stitch = stitch.into();
+  let witnesses: Vec<Strand> = resolver
+    .strands()
+    .await?
+    .try_filter(|strand| futures::future::ready(is_witness(strand)))
+    .try_collect()
+    .await?;
+  let mut attestations = Vec::new();
+  for strand in witnesses {
+    let range = RangeQuery::from_range_bounds(&strand, ..);
+    let mut tixels = resolver.resolve_range(range).await?;
+    while let Some(witness) = tixels.try_next().await? {
+      if let Some(attestation) = Attestation::from_witness(witness, stitch)? {
+        attestations.push(attestation);
+        break;
+      }
+    }
+  }
+  Ok(attestations)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_payload_includes() {
+    use multihash_codetable::{Code, MultihashDigest};
+    let a = Cid::new_v1(0x71, Code::Sha3_256.digest(b"a"));
+    let b = Cid::new_v1(0x71, Code::Sha3_256.digest(b"b"));
+    let payload = WitnessPayload::new([
+      Stitch::from((b, a)),
+      Stitch::from((a, b)),
+      Stitch::from((b, a)),
+    ]);
+    assert_eq!(payload.witnessed, vec![(a, b), (b, a)]);
+    assert!(payload.includes(&Stitch::from((a, b))));
+    assert!(!payload.includes(&Stitch::from((a, a))));
+  }
+}
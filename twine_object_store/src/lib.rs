@@ -0,0 +1,759 @@
+#![doc = include_str!("../README.md")]
+use async_trait::async_trait;
+use futures::stream::{Stream, StreamExt, TryStreamExt};
+use object_store::{path::Path, PutMode, PutPayload, UpdateVersion};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use std::sync::Arc;
+use twine_lib::resolver::{unchecked_base::BaseResolver, AbsoluteRange, Resolver};
+use twine_lib::{as_cid::AsCid, errors::*, store::Store, twine::TwineBlock, twine::*, Cid};
+
+pub use object_store;
+
+/// The contents of a strand's latest pointer
+#[derive(Debug, Serialize, Deserialize)]
+struct LatestRecord {
+  index: u64,
+  cid: String,
+}
+
+/// Options for the ObjectStore
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectStoreOptions {
+  prefix: Path,
+  buffer_size: usize,
+  overwrite_latest: bool,
+}
+
+impl Default for ObjectStoreOptions {
+  fn default() -> Self {
+    Self {
+      prefix: Path::default(),
+      buffer_size: 32,
+      overwrite_latest: false,
+    }
+  }
+}
+
+impl ObjectStoreOptions {
+  /// Set the path under which all objects are stored
+  ///
+  /// Defaults to the root of the bucket.
+  pub fn prefix<P: Into<Path>>(mut self, prefix: P) -> Self {
+    self.prefix = prefix.into();
+    self
+  }
+
+  /// Set the buffer size for the store
+  ///
+  /// The buffer size is the number of requests made concurrently when
+  /// resolving ranges and saving batches
+  pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+    self.buffer_size = buffer_size.max(1);
+    self
+  }
+
+  /// Overwrite the latest pointer on backends without conditional updates
+  ///
+  /// The latest pointer of a strand is normally replaced with a conditional
+  /// write, so that concurrent writers never move it backwards. Some
+  /// backends, like the local filesystem, don't support that, and saving
+  /// tixels to them fails unless this is enabled. It is only safe when a
+  /// single writer saves to each strand. Defaults to `false`.
+  pub fn overwrite_latest(mut self, overwrite: bool) -> Self {
+    self.overwrite_latest = overwrite;
+    self
+  }
+}
+
+/// A [`Store`] that saves twine data to object storage
+///
+/// Any [`object_store::ObjectStore`] can be used as the backend.
+/// See the crate documentation for the object layout.
+#[derive(Debug, Clone)]
+pub struct ObjectStore {
+  store: Arc<dyn object_store::ObjectStore>,
+  options: ObjectStoreOptions,
+}
+
+fn fetch_error(e: object_store::Error) -> ResolutionError {
+  match e {
+    object_store::Error::NotFound { .. } => ResolutionError::NotFound,
+    e => ResolutionError::Fetch(e.to_string()),
+  }
+}
+
+fn saving_error(e: object_store::Error) -> StoreError {
+  match e {
+    object_store::Error::NotImplemented => StoreError::Saving(
+      "Backend does not support conditional updates of the latest pointer, \
+       see ObjectStoreOptions::overwrite_latest"
+        .to_string(),
+    ),
+    e => StoreError::Saving(e.to_string()),
+  }
+}
+
+fn parse_cid(bytes: &[u8]) -> Option<Cid> {
+  Cid::try_from(std::str::from_utf8(bytes).ok()?.trim()).ok()
+}
+
+/// The error for a tixel saved at an index that holds another tixel
+fn fork_error(tixel: &Tixel) -> StoreError {
+  StoreError::Saving(format!(
+    "A different tixel is already stored at index {} of strand {}",
+    tixel.index(),
+    tixel.strand_cid()
+  ))
+}
+
+impl ObjectStore {
+  /// Create a new ObjectStore
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use std::sync::Arc;
+  /// use twine_object_store::*;
+  /// let backend = Arc::new(object_store::memory::InMemory::new());
+  /// let store = ObjectStore::new(backend, ObjectStoreOptions::default().prefix("twine"));
+  /// ```
+  pub fn new(store: Arc<dyn object_store::ObjectStore>, options: ObjectStoreOptions) -> Self {
+    Self { store, options }
+  }
+
+  /// Create an ObjectStore that keeps everything in memory
+  pub fn in_memory() -> Self {
+    Self::new(
+      Arc::new(object_store::memory::InMemory::new()),
+      ObjectStoreOptions::default(),
+    )
+  }
+
+  /// Create an ObjectStore that saves objects as files in a local directory
+  ///
+  /// The directory must exist. The local filesystem has no conditional
+  /// updates, so saving tixels needs [`ObjectStoreOptions::overwrite_latest`].
+  pub fn local<P: AsRef<std::path::Path>>(
+    root: P,
+    options: ObjectStoreOptions,
+  ) -> object_store::Result<Self> {
+    let backend = object_store::local::LocalFileSystem::new_with_prefix(root)?;
+    Ok(Self::new(Arc::new(backend), options))
+  }
+
+  /// The backend of this store
+  pub fn backend(&self) -> &Arc<dyn object_store::ObjectStore> {
+    &self.store
+  }
+
+  fn block_path(&self, cid: &Cid) -> Path {
+    self.options.prefix.child("blocks").child(cid.to_string())
+  }
+
+  fn strands_path(&self) -> Path {
+    self.options.prefix.child("strands")
+  }
+
+  fn strand_path(&self, strand: &Cid) -> Path {
+    self.strands_path().child(strand.to_string())
+  }
+
+  fn marker_path(&self, strand: &Cid) -> Path {
+    self.strand_path(strand).child("strand")
+  }
+
+  fn latest_path(&self, strand: &Cid) -> Path {
+    self.strand_path(strand).child("latest.json")
+  }
+
+  fn index_dir(&self, strand: &Cid) -> Path {
+    self.strand_path(strand).child("index")
+  }
+
+  fn index_path(&self, strand: &Cid, index: u64) -> Path {
+    self.index_dir(strand).child(format!("{:020}", index))
+  }
+
+  async fn exists(&self, path: &Path) -> Result<bool, ResolutionError> {
+    match self.store.head(path).await {
+      Ok(_) => Ok(true),
+      Err(object_store::Error::NotFound { .. }) => Ok(false),
+      Err(e) => Err(fetch_error(e)),
+    }
+  }
+
+  async fn get(&self, cid: &Cid) -> Result<AnyTwine, ResolutionError> {
+    let result = self
+      .store
+      .get(&self.block_path(cid))
+      .await
+      .map_err(fetch_error)?;
+    let bytes = result.bytes().await.map_err(fetch_error)?;
+    Ok(AnyTwine::from_block(*cid, bytes)?)
+  }
+
+  async fn get_tixel(&self, strand: &Cid, cid: &Cid) -> Result<Tixel, ResolutionError> {
+    let result = self
+      .store
+      .get(&self.block_path(cid))
+      .await
+      .map_err(fetch_error)?;
+    let bytes = result.bytes().await.map_err(fetch_error)?;
+    let tixel = Tixel::from_block(*cid, bytes)?;
+    if tixel.strand_cid() != *strand {
+      return Err(ResolutionError::BadData(
+        "Tixel does not belong to strand".to_string(),
+      ));
+    }
+    Ok(tixel)
+  }
+
+  /// Read the cid stored at an index entry
+  async fn read_index_entry(&self, path: &Path) -> Result<Option<Cid>, ResolutionError> {
+    let result = match self.store.get(path).await {
+      Ok(result) => result,
+      Err(object_store::Error::NotFound { .. }) => return Ok(None),
+      Err(e) => return Err(fetch_error(e)),
+    };
+    let bytes = result.bytes().await.map_err(fetch_error)?;
+    let cid = parse_cid(&bytes)
+      .ok_or_else(|| ResolutionError::BadData("Invalid cid in index entry".to_string()))?;
+    Ok(Some(cid))
+  }
+
+  async fn index_cid(&self, strand: &Cid, index: u64) -> Result<Option<Cid>, ResolutionError> {
+    self.read_index_entry(&self.index_path(strand, index)).await
+  }
+
+  /// Read the latest pointer of a strand along with its version
+  async fn read_latest(
+    &self,
+    strand: &Cid,
+  ) -> Result<Option<(LatestRecord, UpdateVersion)>, ResolutionError> {
+    let result = match self.store.get(&self.latest_path(strand)).await {
+      Ok(result) => result,
+      Err(object_store::Error::NotFound { .. }) => return Ok(None),
+      Err(e) => return Err(fetch_error(e)),
+    };
+    let version = UpdateVersion {
+      e_tag: result.meta.e_tag.clone(),
+      version: result.meta.version.clone(),
+    };
+    let bytes = result.bytes().await.map_err(fetch_error)?;
+    let record =
+      serde_json::from_slice(&bytes).map_err(|e| ResolutionError::BadData(e.to_string()))?;
+    Ok(Some((record, version)))
+  }
+
+  /// Write the latest pointer of a strand
+  ///
+  /// Backends without conditional updates (like the local filesystem)
+  /// return [`object_store::Error::NotImplemented`], unless the store is
+  /// set to [`ObjectStoreOptions::overwrite_latest`].
+  async fn write_latest(
+    &self,
+    strand: &Cid,
+    index: u64,
+    cid: &Cid,
+    mode: PutMode,
+  ) -> object_store::Result<()> {
+    let record = LatestRecord {
+      index,
+      cid: cid.to_string(),
+    };
+    let payload = PutPayload::from(serde_json::to_vec(&record).expect("record serializes"));
+    let path = self.latest_path(strand);
+    match self
+      .store
+      .put_opts(&path, payload.clone(), mode.into())
+      .await
+    {
+      Err(object_store::Error::NotImplemented) if self.options.overwrite_latest => {
+        self.store.put(&path, payload).await?;
+        Ok(())
+      }
+      result => result.map(|_| ()),
+    }
+  }
+
+  /// Point the latest record at a tixel if it is newer
+  ///
+  /// The pointer is replaced with a conditional write, and re-read if
+  /// another writer changed it first.
+  async fn update_latest(&self, tixel: &Tixel) -> Result<(), StoreError> {
+    let strand = tixel.strand_cid();
+    loop {
+      let mode = match self.read_latest(&strand).await? {
+        Some((record, _)) if record.index >= tixel.index() => return Ok(()),
+        Some((_, version)) => PutMode::Update(version),
+        None => PutMode::Create,
+      };
+      match self
+        .write_latest(&strand, tixel.index(), &tixel.cid(), mode)
+        .await
+      {
+        Ok(()) => {
+          log::debug!("Updated latest for strand {}: {}", strand, tixel.index());
+          return Ok(());
+        }
+        Err(
+          object_store::Error::Precondition { .. } | object_store::Error::AlreadyExists { .. },
+        ) => {
+          log::debug!("Latest for strand {} changed, retrying", strand);
+        }
+        Err(e) => return Err(saving_error(e)),
+      }
+    }
+  }
+
+  async fn save_strand(&self, strand: &AnyTwine) -> Result<(), StoreError> {
+    let cid = strand.cid();
+    self
+      .store
+      .put(&self.block_path(&cid), strand.bytes().to_vec().into())
+      .await
+      .map_err(saving_error)?;
+    self
+      .store
+      .put(&self.marker_path(&cid), PutPayload::default())
+      .await
+      .map_err(saving_error)?;
+    Ok(())
+  }
+
+  /// Save a tixel without updating the latest record
+  async fn save_tixel(&self, tixel: &Tixel) -> Result<(), StoreError> {
+    let path = self.index_path(&tixel.strand_cid(), tixel.index());
+    // forks are rejected before their block is written
+    match self.read_index_entry(&path).await? {
+      Some(cid) if cid == tixel.cid() => return Ok(()),
+      Some(_) => return Err(fork_error(tixel)),
+      None => {}
+    }
+    // the block is written before the index entry that points to it
+    self
+      .store
+      .put(
+        &self.block_path(&tixel.cid()),
+        tixel.bytes().to_vec().into(),
+      )
+      .await
+      .map_err(saving_error)?;
+    let payload = PutPayload::from(tixel.cid().to_string().into_bytes());
+    match self
+      .store
+      .put_opts(&path, payload.clone(), PutMode::Create.into())
+      .await
+    {
+      Ok(_) => Ok(()),
+      Err(object_store::Error::AlreadyExists { .. }) => {
+        // another writer saved this index in the meantime
+        let cid = self
+          .read_index_entry(&path)
+          .await?
+          .ok_or_else(|| StoreError::Saving("Index entry removed concurrently".into()))?;
+        if cid != tixel.cid() {
+          self.delete_path(&self.block_path(&tixel.cid())).await?;
+          return Err(fork_error(tixel));
+        }
+        Ok(())
+      }
+      Err(object_store::Error::NotImplemented) => {
+        // without conditional writes, the check above has to do
+        self.store.put(&path, payload).await.map_err(saving_error)?;
+        Ok(())
+      }
+      Err(e) => Err(saving_error(e)),
+    }
+  }
+
+  async fn delete_path(&self, path: &Path) -> Result<(), StoreError> {
+    match self.store.delete(path).await {
+      Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+      Err(e) => Err(saving_error(e)),
+    }
+  }
+}
+
+#[async_trait]
+impl BaseResolver for ObjectStore {
+  async fn fetch_strands(
+    &self,
+  ) -> Result<
+    Pin<Box<dyn Stream<Item = Result<Strand, ResolutionError>> + Send + '_>>,
+    ResolutionError,
+  > {
+    let listing = self
+      .store
+      .list_with_delimiter(Some(&self.strands_path()))
+      .await
+      .map_err(fetch_error)?;
+    let cids: Vec<Cid> = listing
+      .common_prefixes
+      .iter()
+      .filter_map(|path| Cid::try_from(path.filename()?).ok())
+      .collect();
+    let stream = futures::stream::iter(cids)
+      .then(move |cid| async move { self.fetch_strand(&cid).await })
+      // the local filesystem leaves empty directories behind after deletes
+      .filter(|result| futures::future::ready(!matches!(result, Err(ResolutionError::NotFound))));
+    Ok(stream.boxed())
+  }
+
+  async fn has_strand(&self, cid: &Cid) -> Result<bool, ResolutionError> {
+    self.exists(&self.marker_path(cid.as_cid())).await
+  }
+
+  async fn has_index(&self, strand: &Cid, index: u64) -> Result<bool, ResolutionError> {
+    self.exists(&self.index_path(strand, index)).await
+  }
+
+  async fn has_twine(&self, _strand: &Cid, cid: &Cid) -> Result<bool, ResolutionError> {
+    self.exists(&self.block_path(cid.as_cid())).await
+  }
+
+  async fn fetch_strand(&self, strand: &Cid) -> Result<Strand, ResolutionError> {
+    let result = self
+      .store
+      .get(&self.block_path(strand))
+      .await
+      .map_err(fetch_error)?;
+    let bytes = result.bytes().await.map_err(fetch_error)?;
+    Ok(Strand::from_block(*strand, bytes)?)
+  }
+
+  async fn fetch_tixel(&self, strand: &Cid, tixel: &Cid) -> Result<Tixel, ResolutionError> {
+    self.get_tixel(strand, tixel).await
+  }
+
+  async fn fetch_index(&self, strand: &Cid, index: u64) -> Result<Tixel, ResolutionError> {
+    let cid = self
+      .index_cid(strand, index)
+      .await?
+      .ok_or(ResolutionError::NotFound)?;
+    let tixel = self.get_tixel(strand, &cid).await?;
+
+    if tixel.index() != index {
+      return Err(ResolutionError::BadData(format!(
+        "Expected index {}, found {}",
+        index,
+        tixel.index()
+      )));
+    }
+
+    Ok(tixel)
+  }
+
+  async fn fetch_latest(&self, strand: &Cid) -> Result<Tixel, ResolutionError> {
+    let (record, _) = self
+      .read_latest(strand)
+      .await?
+      .ok_or(ResolutionError::NotFound)?;
+    let cid =
+      Cid::try_from(record.cid.as_str()).map_err(|e| ResolutionError::BadData(e.to_string()))?;
+    self.get_tixel(strand, &cid).await
+  }
+
+  async fn range_stream(
+    &self,
+    range: AbsoluteRange,
+  ) -> Result<
+    Pin<Box<dyn Stream<Item = Result<Tixel, ResolutionError>> + Send + '_>>,
+    ResolutionError,
+  > {
+    let strand = range.strand;
+    let lower = range.start.min(range.end);
+    let upper = range.start.max(range.end);
+    let indices: Box<dyn Iterator<Item = u64> + Send> = if range.is_decreasing() {
+      Box::new((lower..=upper).rev())
+    } else {
+      Box::new(lower..=upper)
+    };
+    // each index entry is read directly, and the blocks are fetched in parallel
+    let stream = futures::stream::iter(indices)
+      .map(move |index| async move {
+        let cid = self
+          .index_cid(&strand, index)
+          .await?
+          .ok_or(ResolutionError::NotFound)?;
+        self.get_tixel(&strand, &cid).await
+      })
+      .buffered(self.options.buffer_size);
+    Ok(stream.boxed())
+  }
+}
+
+impl Resolver for ObjectStore {}
+
+#[async_trait]
+impl Store for ObjectStore {
+  async fn save<T: Into<AnyTwine> + Send>(&self, twine: T) -> Result<(), StoreError> {
+    let twine = twine.into();
+    match &twine {
+      AnyTwine::Strand(_) => self.save_strand(&twine).await,
+      AnyTwine::Tixel(tixel) => {
+        let strand = tixel.strand_cid();
+        if !self.has_strand(&strand).await? {
+          return Err(StoreError::Saving(format!(
+            "Strand {} not saved yet",
+            strand
+          )));
+        }
+        self.save_tixel(tixel).await?;
+        self.update_latest(tixel).await
+      }
+    }
+  }
+
+  async fn save_many<
+    I: Into<AnyTwine> + Send,
+    S: Iterator<Item = I> + Send,
+    T: IntoIterator<Item = I, IntoIter = S> + Send,
+  >(
+    &self,
+    twines: T,
+  ) -> Result<(), StoreError> {
+    let (strands, tixels) = twines
+      .into_iter()
+      .map(|i| i.into())
+      .partition::<Vec<AnyTwine>, _>(|twine| matches!(twine, AnyTwine::Strand(_)));
+
+    let mut stored_strands = HashSet::new();
+    for strand in &strands {
+      self.save_strand(strand).await?;
+      stored_strands.insert(strand.cid());
+    }
+
+    let tixels: Vec<Tixel> = tixels.into_iter().map(|t| t.unwrap_tixel()).collect();
+    let mut latests: HashMap<Cid, Tixel> = HashMap::new();
+    for tixel in &tixels {
+      let strand = tixel.strand_cid();
+      if !stored_strands.contains(&strand) {
+        if !self.has_strand(&strand).await? {
+          return Err(StoreError::Saving(format!(
+            "Strand {} not saved yet",
+            strand
+          )));
+        }
+        stored_strands.insert(strand);
+      }
+      latests
+        .entry(strand)
+        .and_modify(|t| {
+          if tixel.index() > t.index() {
+            *t = tixel.clone()
+          }
+        })
+        .or_insert(tixel.clone());
+    }
+
+    futures::stream::iter(tixels)
+      .map(|tixel| async move { self.save_tixel(&tixel).await })
+      .buffer_unordered(self.options.buffer_size)
+      .try_collect::<()>()
+      .await?;
+
+    for tixel in latests.values() {
+      self.update_latest(tixel).await?;
+    }
+    Ok(())
+  }
+
+  async fn save_stream<I: Into<AnyTwine> + Send, T: Stream<Item = I> + Send + Unpin>(
+    &self,
+    twines: T,
+  ) -> Result<(), StoreError> {
+    // save in batches
+    twines
+      .chunks(self.options.buffer_size)
+      .then(|chunk| self.save_many(chunk))
+      .try_for_each(|_| async { Ok(()) })
+      .await?;
+    Ok(())
+  }
+
+  async fn delete<C: AsCid + Send>(&self, cid: C) -> Result<(), StoreError> {
+    let twine = match self.get(cid.as_cid()).await {
+      Ok(twine) => twine,
+      Err(ResolutionError::NotFound) => return Ok(()),
+      Err(e) => return Err(StoreError::Saving(e.to_string())),
+    };
+    match &twine {
+      AnyTwine::Strand(strand) => {
+        // remove the strand along with all of its tixels
+        let objects: Vec<Path> = self
+          .store
+          .list(Some(&self.strand_path(&strand.cid())))
+          .map_ok(|meta| meta.location)
+          .try_collect()
+          .await
+          .map_err(saving_error)?;
+        let index_dir = self.index_dir(&strand.cid());
+        for path in objects {
+          if path.prefix_matches(&index_dir) {
+            if let Some(tixel) = self.read_index_entry(&path).await? {
+              self.delete_path(&self.block_path(&tixel)).await?;
+            }
+          }
+          self.delete_path(&path).await?;
+        }
+      }
+      AnyTwine::Tixel(tixel) => {
+        let strand = tixel.strand_cid();
+        let index = tixel.index();
+        self.delete_path(&self.index_path(&strand, index)).await?;
+        if let Some((record, version)) = self.read_latest(&strand).await? {
+          if record.index == index {
+            // the latest now is the highest remaining index
+            let mut previous = None;
+            for i in (0..index).rev() {
+              if let Some(cid) = self.index_cid(&strand, i).await? {
+                previous = Some((i, cid));
+                break;
+              }
+            }
+            match previous {
+              Some((index, cid)) => self
+                .write_latest(&strand, index, &cid, PutMode::Update(version))
+                .await
+                .map_err(saving_error)?,
+              None => self.delete_path(&self.latest_path(&strand)).await?,
+            }
+          }
+        }
+      }
+    }
+    self.delete_path(&self.block_path(&twine.cid())).await?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use twine_builder::test_util::chain;
+  use twine_lib::twine::Twine;
+
+  async fn check_store(store: ObjectStore) {
+    let (strand, tixels) = chain(25);
+    store.save(strand.clone()).await.unwrap();
+    store.save_many(tixels.clone()).await.unwrap();
+    assert_eq!(store.latest_index(&strand.cid()).await.unwrap(), 24);
+    assert_eq!(
+      store.resolve_index(&strand, 7).await.unwrap().unpack(),
+      tixels[7]
+    );
+
+    let decreasing: Vec<Twine> = store
+      .resolve_range((strand.cid(), 20, 2))
+      .await
+      .unwrap()
+      .try_collect()
+      .await
+      .unwrap();
+    let expected: Vec<Twine> = tixels[2..=20].iter().rev().cloned().collect();
+    assert_eq!(decreasing, expected);
+
+    store.delete(tixels[24].cid()).await.unwrap();
+    assert_eq!(store.latest_index(&strand.cid()).await.unwrap(), 23);
+
+    store.delete(strand.cid()).await.unwrap();
+    assert!(!store.has_strand(&strand.cid()).await.unwrap());
+    assert!(!store
+      .has_twine(&strand.cid(), &tixels[0].cid())
+      .await
+      .unwrap());
+    assert!(store.strands().await.unwrap().next().await.is_none());
+  }
+
+  #[tokio::test]
+  async fn test_in_memory() {
+    check_store(ObjectStore::new(
+      Arc::new(object_store::memory::InMemory::new()),
+      ObjectStoreOptions::default()
+        .prefix("archive")
+        .buffer_size(4),
+    ))
+    .await;
+  }
+
+  #[tokio::test]
+  async fn test_local() {
+    let dir = tempfile::tempdir().unwrap();
+    // the local filesystem can create the latest pointer, but not update it
+    let (strand, tixels) = chain(2);
+    let store = ObjectStore::local(dir.path(), ObjectStoreOptions::default()).unwrap();
+    store.save(strand).await.unwrap();
+    store.save(tixels[0].clone()).await.unwrap();
+    assert!(store.save(tixels[1].clone()).await.is_err());
+
+    let dir = tempfile::tempdir().unwrap();
+    let options = ObjectStoreOptions::default().overwrite_latest(true);
+    check_store(ObjectStore::local(dir.path(), options).unwrap()).await;
+  }
+
+  #[tokio::test]
+  async fn test_concurrent_latest() {
+    let (strand, tixels) = chain(20);
+    let store = ObjectStore::in_memory();
+    store.save(strand.clone()).await.unwrap();
+    // save every tixel concurrently, in reverse so the latest keeps moving
+    futures::future::try_join_all(tixels.iter().rev().map(|tixel| store.save(tixel.clone())))
+      .await
+      .unwrap();
+    assert_eq!(store.latest_index(&strand.cid()).await.unwrap(), 19);
+  }
+
+  #[tokio::test]
+  async fn test_rejects_forks() {
+    use twine_builder::{RingSigner, TwineBuilder};
+    use twine_lib::ipld_core::ipld;
+    let builder = TwineBuilder::new(RingSigner::generate_ed25519().unwrap());
+    let strand = builder.build_strand().done().unwrap();
+    let first = builder.build_first(strand.clone()).done().unwrap();
+    let next = builder.build_next(&first).done().unwrap();
+    let fork = builder
+      .build_next(&first)
+      .payload(ipld!({ "fork": true }))
+      .done()
+      .unwrap();
+    let store = ObjectStore::new(
+      Arc::new(object_store::memory::InMemory::new()),
+      ObjectStoreOptions::default(),
+    );
+    store.save(strand.clone()).await.unwrap();
+    store.save_many([first, next.clone()]).await.unwrap();
+    // saving the same tixel again is fine
+    store.save(next.clone()).await.unwrap();
+    assert!(store.save(fork.clone()).await.is_err());
+    assert!(store.save_many([fork.clone()]).await.is_err());
+    assert!(!store.has_twine(&strand.cid(), &fork.cid()).await.unwrap());
+    assert_eq!(
+      store.resolve_index(&strand, 1).await.unwrap().unpack(),
+      next
+    );
+  }
+
+  #[cfg(feature = "aws")]
+  #[tokio::test]
+  #[ignore = "needs TWINE_TEST_S3_ENDPOINT and TWINE_TEST_S3_BUCKET"]
+  async fn test_s3() {
+    // e.g. a local MinIO, with credentials in the AWS_* environment variables
+    let endpoint = std::env::var("TWINE_TEST_S3_ENDPOINT").expect("TWINE_TEST_S3_ENDPOINT not set");
+    let bucket = std::env::var("TWINE_TEST_S3_BUCKET").expect("TWINE_TEST_S3_BUCKET not set");
+    let backend = object_store::aws::AmazonS3Builder::from_env()
+      .with_endpoint(endpoint)
+      .with_bucket_name(bucket)
+      .with_allow_http(true)
+      .build()
+      .unwrap();
+    let prefix = format!("twine-test-{}", std::process::id());
+    check_store(ObjectStore::new(
+      Arc::new(backend),
+      ObjectStoreOptions::default().prefix(prefix.as_str()),
+    ))
+    .await;
+  }
+}
@@ -286,7 +286,8 @@ impl PickleDbStore {
       .set(&format!("{}", tixel_cid), &BlockRecord::from(tixel))
       .map_err(|e| StoreError::Saving(e.to_string()))?;
     push_list(&mut lock, &format!("tixels:{}", strand_cid), &tixel_cid)?;
-    self.flush()?;
+    // the lock is already held, so calling self.flush() would deadlock
+    lock.dump().map_err(|e| StoreError::Saving(e.to_string()))?;
     Ok(())
   }
 
@@ -442,3 +443,33 @@ impl Store for PickleDbStore {
     }
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use twine_builder::{RingSigner, TwineBuilder};
+
+  #[tokio::test]
+  async fn test_save_tixels() {
+    // saving a tixel used to deadlock when it flushed while holding the lock
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("store.db");
+    let builder = TwineBuilder::new(RingSigner::generate_ed25519().unwrap());
+    let strand = builder.build_strand().done().unwrap();
+    let first = builder.build_first(strand.clone()).done().unwrap();
+    let second = builder.build_next(&first).done().unwrap();
+    let store = PickleDbStore::new(&path).unwrap();
+    store.save(strand.clone()).await.unwrap();
+    store
+      .save_many(vec![first.clone(), second.clone()])
+      .await
+      .unwrap();
+    assert_eq!(
+      store.resolve_latest(&strand).await.unwrap().unpack(),
+      second
+    );
+    // each tixel is dumped as it is saved
+    let store = PickleDbStore::load_read_only(&path).unwrap();
+    assert_eq!(store.latest_index(&strand), Some(1));
+  }
+}
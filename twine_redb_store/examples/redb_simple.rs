@@ -0,0 +1,52 @@
+//! Example of creating twine data and saving it to a redb store
+use futures::TryStreamExt;
+use twine_builder::RingSigner;
+use twine_builder::TwineBuilder;
+use twine_lib::resolver::*;
+use twine_lib::store::Store;
+use twine_lib::twine::Twine;
+use twine_redb_store::*;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+  let tmp_dir = tempfile::tempdir()?;
+  let store = RedbStore::open(
+    tmp_dir.path().join("store.redb"),
+    RedbStoreOptions::default(),
+  )?;
+
+  let signer = RingSigner::generate_ed25519().unwrap();
+  let builder = TwineBuilder::new(signer);
+  let strand = builder.build_strand().radix(2).done()?;
+  let first = builder.build_first(strand.clone()).done()?;
+
+  let count = 1000;
+  let twines: Vec<Twine> = (1..count)
+    .scan(first.clone(), |prev, _| {
+      let next = builder.build_next(prev).done().unwrap();
+      *prev = next.clone();
+      Some(next)
+    })
+    .collect();
+
+  store.save(strand.clone()).await?;
+  store.save(first).await?;
+  store.save_many(twines).await?;
+
+  let latest = store.resolve_latest(&strand).await?.unpack();
+  println!("latest: {}", latest.index());
+
+  let start_time = std::time::Instant::now();
+  let resolved: Vec<Twine> = store
+    .resolve_range((strand.clone(), ..))
+    .await?
+    .try_collect()
+    .await?;
+  println!(
+    "Resolved {} twines in {}ms",
+    resolved.len(),
+    start_time.elapsed().as_millis()
+  );
+
+  Ok(())
+}
@@ -0,0 +1,27 @@
+//! Copy a twine sled database into a new redb database
+//!
+//! Usage: sled-to-redb <SLED_PATH> <REDB_PATH>
+use twine_redb_store::sled_migration::migrate_from_sled;
+use twine_redb_store::{RedbStore, RedbStoreOptions};
+use twine_sled_store::{sled, SledStore, SledStoreOptions};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  let [sled_path, redb_path] = args.as_slice() else {
+    eprintln!("Usage: sled-to-redb <SLED_PATH> <REDB_PATH>");
+    std::process::exit(2);
+  };
+  if std::path::Path::new(redb_path).exists() {
+    return Err(format!("{} already exists", redb_path).into());
+  }
+
+  let sled = SledStore::new(sled::open(sled_path)?, SledStoreOptions::default());
+  let redb = RedbStore::open(redb_path, RedbStoreOptions::default())?;
+  let report = migrate_from_sled(&sled, &redb).await?;
+  println!(
+    "Migrated {} strands and {} tixels",
+    report.strands, report.tixels
+  );
+  Ok(())
+}
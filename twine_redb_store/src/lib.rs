@@ -0,0 +1,680 @@
+#![doc = include_str!("../README.md")]
+use async_trait::async_trait;
+use either::Either;
+use futures::stream::{unfold, Stream, StreamExt};
+use redb::{Database, ReadOnlyTable, ReadTransaction, ReadableTable, TableDefinition};
+use std::collections::HashMap;
+use std::path::Path;
+use std::{pin::Pin, sync::Arc};
+use twine_lib::resolver::{unchecked_base::BaseResolver, AbsoluteRange, Resolver};
+use twine_lib::{as_cid::AsCid, errors::*, store::Store, twine::TwineBlock, twine::*, Cid};
+use zerocopy::{
+  byteorder::{BigEndian, U64},
+  FromBytes, FromZeros, Immutable, IntoBytes, KnownLayout, Unaligned,
+};
+
+pub use redb;
+
+#[cfg(feature = "sled")]
+pub mod sled_migration;
+
+/// Block bytes keyed by cid
+const BLOCKS: TableDefinition<&[u8], &[u8]> = TableDefinition::new("blocks");
+/// The cids of all saved strands
+const STRANDS: TableDefinition<&[u8], ()> = TableDefinition::new("strands");
+/// Tixel cids keyed by [`IndexKey`]
+const INDEX: TableDefinition<&[u8], &[u8]> = TableDefinition::new("index");
+/// [`LatestRecord`]s keyed by strand cid
+const LATEST: TableDefinition<&[u8], &[u8]> = TableDefinition::new("latest");
+
+#[derive(FromBytes, IntoBytes, Unaligned, KnownLayout, Immutable)]
+#[repr(C)]
+struct LatestRecord {
+  index: U64<BigEndian>,
+  cid: [u8; 68],
+}
+
+#[derive(FromBytes, IntoBytes, Unaligned, KnownLayout, Immutable)]
+#[repr(C)]
+struct IndexKey {
+  strand: [u8; 68],
+  index: U64<BigEndian>,
+}
+
+/// Options for the RedbStore
+#[derive(Debug, Clone, PartialEq)]
+pub struct RedbStoreOptions {
+  buffer_size: usize,
+}
+
+impl Default for RedbStoreOptions {
+  fn default() -> Self {
+    Self { buffer_size: 100 }
+  }
+}
+
+impl RedbStoreOptions {
+  /// Set the buffer size for the store
+  ///
+  /// The buffer size is the number of items saved per transaction when
+  /// saving streams, and the number of tixels read per transaction
+  /// when streaming ranges
+  pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+    self.buffer_size = buffer_size.max(1);
+    self
+  }
+}
+
+/// A [`Store`] that uses redb as the backend
+///
+/// Every save and delete runs in a single write transaction, so a batch
+/// passed to [`Store::save_many`] is stored completely or not at all.
+#[derive(Debug, Clone)]
+pub struct RedbStore {
+  db: Arc<Database>,
+  options: RedbStoreOptions,
+}
+
+impl RedbStore {
+  /// Create a new RedbStore
+  ///
+  /// # Example
+  ///
+  /// ```no_run
+  /// use twine_redb_store::*;
+  /// let db = redb::Database::create("./mydb.redb").unwrap();
+  /// let store = RedbStore::new(db, RedbStoreOptions::default());
+  /// ```
+  pub fn new(db: Database, options: RedbStoreOptions) -> Self {
+    Self {
+      db: Arc::new(db),
+      options,
+    }
+  }
+
+  /// Open the redb database at a path, creating it if needed
+  pub fn open<P: AsRef<Path>>(
+    path: P,
+    options: RedbStoreOptions,
+  ) -> Result<Self, redb::DatabaseError> {
+    Ok(Self::new(Database::create(path)?, options))
+  }
+
+  /// Get the options of this store
+  pub fn options(&self) -> &RedbStoreOptions {
+    &self.options
+  }
+}
+
+fn get_index_key(strand: &Cid, index: u64) -> Vec<u8> {
+  let mut key = IndexKey::new_zeroed();
+  let cid = strand.to_bytes();
+  key.strand[..cid.len()].copy_from_slice(&cid);
+  key.index.set(index);
+  key.as_bytes().to_vec()
+}
+
+fn get_latest_record(index: u64, cid: &[u8]) -> LatestRecord {
+  let mut record = LatestRecord::new_zeroed();
+  record.index.set(index);
+  record.cid[..cid.len()].copy_from_slice(cid);
+  record
+}
+
+fn read_latest_record(bytes: &[u8]) -> Option<(u64, Cid)> {
+  let record = LatestRecord::ref_from_bytes(bytes).ok()?;
+  let cid = Cid::read_bytes(record.cid.as_slice()).ok()?;
+  Some((record.index.get(), cid))
+}
+
+fn fetch_error<E: ToString>(e: E) -> ResolutionError {
+  ResolutionError::Fetch(e.to_string())
+}
+
+fn saving_error<E: ToString>(e: E) -> StoreError {
+  StoreError::Saving(e.to_string())
+}
+
+/// Open a table for reading
+///
+/// Tables are created by the first write to them, so a missing table is
+/// treated as empty and gives `None`.
+async fn read_table<K: redb::Key + 'static, V: redb::Value + 'static>(
+  txn: &ReadTransaction,
+  table: TableDefinition<'static, K, V>,
+) -> Result<Option<ReadOnlyTable<K, V>>, ResolutionError> {
+  match txn.open_table(table) {
+    Ok(table) => Ok(Some(table)),
+    Err(redb::TableError::TableDoesNotExist(_)) => Ok(None),
+    Err(e) => Err(fetch_error(e)),
+  }
+}
+
+impl RedbStore {
+  async fn contains<V: redb::Value + 'static>(
+    &self,
+    table: TableDefinition<'static, &'static [u8], V>,
+    key: &[u8],
+  ) -> Result<bool, ResolutionError> {
+    let txn = self.db.begin_read().map_err(fetch_error)?;
+    let Some(table) = read_table(&txn, table).await? else {
+      return Ok(false);
+    };
+    Ok(table.get(key).map_err(fetch_error)?.is_some())
+  }
+
+  async fn get_bytes(
+    &self,
+    table: TableDefinition<'static, &'static [u8], &'static [u8]>,
+    key: &[u8],
+  ) -> Result<Option<Vec<u8>>, ResolutionError> {
+    let txn = self.db.begin_read().map_err(fetch_error)?;
+    let Some(table) = read_table(&txn, table).await? else {
+      return Ok(None);
+    };
+    Ok(
+      table
+        .get(key)
+        .map_err(fetch_error)?
+        .map(|v| v.value().to_vec()),
+    )
+  }
+
+  async fn get(&self, cid: &Cid) -> Result<AnyTwine, ResolutionError> {
+    let bytes = self
+      .get_bytes(BLOCKS, &cid.to_bytes())
+      .await?
+      .ok_or(ResolutionError::NotFound)?;
+    Ok(AnyTwine::from_block(*cid, bytes)?)
+  }
+
+  async fn get_tixel(&self, strand: &Cid, cid: &Cid) -> Result<Tixel, ResolutionError> {
+    let bytes = self
+      .get_bytes(BLOCKS, &cid.to_bytes())
+      .await?
+      .ok_or(ResolutionError::NotFound)?;
+    let tixel = Tixel::from_block(*cid, bytes)?;
+    if tixel.strand_cid() != *strand {
+      return Err(ResolutionError::BadData(
+        "Tixel does not belong to strand".to_string(),
+      ));
+    }
+    Ok(tixel)
+  }
+
+  /// Read up to one buffer of tixels from `from` towards `to`, inclusive
+  ///
+  /// All tixels of a batch are read in the same transaction.
+  async fn range_batch(
+    &self,
+    strand: &Cid,
+    from: u64,
+    to: u64,
+  ) -> Result<Vec<Tixel>, ResolutionError> {
+    let txn = self.db.begin_read().map_err(fetch_error)?;
+    let (Some(index), Some(blocks)) = (
+      read_table(&txn, INDEX).await?,
+      read_table(&txn, BLOCKS).await?,
+    ) else {
+      return Ok(vec![]);
+    };
+    let lower = get_index_key(strand, from.min(to));
+    let upper = get_index_key(strand, from.max(to));
+    let range = index
+      .range(lower.as_slice()..=upper.as_slice())
+      .map_err(fetch_error)?;
+    let iter = if from > to {
+      Either::Left(range.rev())
+    } else {
+      Either::Right(range)
+    };
+    let mut tixels = Vec::with_capacity(self.options.buffer_size);
+    for item in iter.take(self.options.buffer_size) {
+      let (_, cid) = item.map_err(fetch_error)?;
+      let cid = Cid::try_from(cid.value()).map_err(|e| ResolutionError::BadData(e.to_string()))?;
+      let bytes = blocks
+        .get(cid.to_bytes().as_slice())
+        .map_err(fetch_error)?
+        .ok_or(ResolutionError::NotFound)?;
+      let tixel = Tixel::from_block(cid, bytes.value())?;
+      if tixel.strand_cid() != *strand {
+        return Err(ResolutionError::BadData(
+          "Tixel does not belong to strand".to_string(),
+        ));
+      }
+      tixels.push(tixel);
+    }
+    Ok(tixels)
+  }
+
+  /// Write twines in a single transaction
+  async fn write(&self, twines: Vec<AnyTwine>) -> Result<(), StoreError> {
+    let (strands, tixels): (Vec<_>, Vec<_>) = twines
+      .into_iter()
+      .partition(|twine| matches!(twine, AnyTwine::Strand(_)));
+    let txn = self.db.begin_write().map_err(saving_error)?;
+    {
+      let mut blocks = txn.open_table(BLOCKS).map_err(saving_error)?;
+      let mut strand_table = txn.open_table(STRANDS).map_err(saving_error)?;
+      let mut index_table = txn.open_table(INDEX).map_err(saving_error)?;
+      let mut latest_table = txn.open_table(LATEST).map_err(saving_error)?;
+
+      for strand in strands {
+        let cid = strand.cid().to_bytes();
+        strand_table
+          .insert(cid.as_slice(), ())
+          .map_err(saving_error)?;
+        blocks
+          .insert(cid.as_slice(), &*strand.bytes())
+          .map_err(saving_error)?;
+      }
+
+      let mut latests: HashMap<Cid, Tixel> = HashMap::new();
+      for tixel in tixels.into_iter().map(|t| t.unwrap_tixel()) {
+        let strand = tixel.strand_cid();
+        let has_strand = strand_table
+          .get(strand.to_bytes().as_slice())
+          .map_err(saving_error)?
+          .is_some();
+        if !has_strand {
+          return Err(StoreError::Saving(format!(
+            "Strand {} not saved yet",
+            strand
+          )));
+        }
+        let cid = tixel.cid().to_bytes();
+        let index_key = get_index_key(&strand, tixel.index());
+        let existing = index_table
+          .get(index_key.as_slice())
+          .map_err(saving_error)?
+          .map(|existing| existing.value() == cid.as_slice());
+        match existing {
+          // already saved
+          Some(true) => continue,
+          Some(false) => {
+            return Err(StoreError::Saving(format!(
+              "A different tixel is already stored at index {} of strand {}",
+              tixel.index(),
+              strand
+            )))
+          }
+          None => {}
+        }
+        index_table
+          .insert(index_key.as_slice(), cid.as_slice())
+          .map_err(saving_error)?;
+        blocks
+          .insert(cid.as_slice(), &*tixel.bytes())
+          .map_err(saving_error)?;
+        let index = tixel.index();
+        latests
+          .entry(strand)
+          .and_modify(|t| {
+            if index > t.index() {
+              *t = tixel.clone()
+            }
+          })
+          .or_insert(tixel);
+      }
+
+      for (strand, tixel) in latests {
+        let key = strand.to_bytes();
+        let latest_index = latest_table
+          .get(key.as_slice())
+          .map_err(saving_error)?
+          .and_then(|record| read_latest_record(record.value()))
+          .map(|(index, _)| index);
+        if latest_index.map(|i| tixel.index() > i).unwrap_or(true) {
+          let record = get_latest_record(tixel.index(), &tixel.cid().to_bytes());
+          latest_table
+            .insert(key.as_slice(), record.as_bytes())
+            .map_err(saving_error)?;
+          log::debug!("Updated latest for strand {}: {}", strand, tixel.index());
+        }
+      }
+    }
+    txn.commit().map_err(saving_error)?;
+    Ok(())
+  }
+}
+
+#[async_trait]
+impl BaseResolver for RedbStore {
+  async fn fetch_strands(
+    &self,
+  ) -> Result<
+    Pin<Box<dyn Stream<Item = Result<Strand, ResolutionError>> + Send + '_>>,
+    ResolutionError,
+  > {
+    let mut cids = vec![];
+    {
+      let txn = self.db.begin_read().map_err(fetch_error)?;
+      if let Some(table) = read_table(&txn, STRANDS).await? {
+        for item in table.iter().map_err(fetch_error)? {
+          let (key, _) = item.map_err(fetch_error)?;
+          let cid =
+            Cid::try_from(key.value()).map_err(|e| ResolutionError::BadData(e.to_string()))?;
+          cids.push(cid);
+        }
+      }
+    }
+    let stream =
+      futures::stream::iter(cids).then(move |cid| async move { self.fetch_strand(&cid).await });
+    Ok(stream.boxed())
+  }
+
+  async fn has_strand(&self, cid: &Cid) -> Result<bool, ResolutionError> {
+    self.contains(STRANDS, &cid.as_cid().to_bytes()).await
+  }
+
+  async fn has_index(&self, strand: &Cid, index: u64) -> Result<bool, ResolutionError> {
+    self.contains(INDEX, &get_index_key(strand, index)).await
+  }
+
+  async fn has_twine(&self, _strand: &Cid, cid: &Cid) -> Result<bool, ResolutionError> {
+    self.contains(BLOCKS, &cid.as_cid().to_bytes()).await
+  }
+
+  async fn fetch_strand(&self, strand: &Cid) -> Result<Strand, ResolutionError> {
+    let bytes = self
+      .get_bytes(BLOCKS, &strand.to_bytes())
+      .await?
+      .ok_or(ResolutionError::NotFound)?;
+    Ok(Strand::from_block(*strand, bytes)?)
+  }
+
+  async fn fetch_tixel(&self, strand: &Cid, tixel: &Cid) -> Result<Tixel, ResolutionError> {
+    self.get_tixel(strand, tixel).await
+  }
+
+  async fn fetch_index(&self, strand: &Cid, index: u64) -> Result<Tixel, ResolutionError> {
+    let cid = self
+      .get_bytes(INDEX, &get_index_key(strand, index))
+      .await?
+      .ok_or(ResolutionError::NotFound)?;
+    let cid = Cid::try_from(cid).map_err(|e| ResolutionError::BadData(e.to_string()))?;
+    let tixel = self.get_tixel(strand, &cid).await?;
+
+    if tixel.index() != index {
+      return Err(ResolutionError::BadData(format!(
+        "Expected index {}, found {}",
+        index,
+        tixel.index()
+      )));
+    }
+
+    Ok(tixel)
+  }
+
+  async fn fetch_latest(&self, strand: &Cid) -> Result<Tixel, ResolutionError> {
+    let record = self
+      .get_bytes(LATEST, &strand.to_bytes())
+      .await?
+      .ok_or(ResolutionError::NotFound)?;
+    let (_, cid) = read_latest_record(&record)
+      .ok_or_else(|| ResolutionError::BadData("Invalid latest record".to_string()))?;
+    self.get_tixel(strand, &cid).await
+  }
+
+  async fn range_stream(
+    &self,
+    range: AbsoluteRange,
+  ) -> Result<
+    Pin<Box<dyn Stream<Item = Result<Tixel, ResolutionError>> + Send + '_>>,
+    ResolutionError,
+  > {
+    let strand = range.strand;
+    let end = range.end;
+    let decreasing = range.is_decreasing();
+    let stream = unfold(Some(range.start), move |from| async move {
+      let from = from?;
+      match self.range_batch(&strand, from, end).await {
+        Ok(tixels) => {
+          let next = match tixels.last() {
+            Some(last) if tixels.len() == self.options.buffer_size && last.index() != end => {
+              if decreasing {
+                Some(last.index() - 1)
+              } else {
+                Some(last.index() + 1)
+              }
+            }
+            _ => None,
+          };
+          Some((tixels.into_iter().map(Ok).collect::<Vec<_>>(), next))
+        }
+        Err(e) => Some((vec![Err(e)], None)),
+      }
+    });
+    Ok(stream.flat_map(futures::stream::iter).boxed())
+  }
+}
+
+impl Resolver for RedbStore {}
+
+#[async_trait]
+impl Store for RedbStore {
+  async fn save<T: Into<AnyTwine> + Send>(&self, twine: T) -> Result<(), StoreError> {
+    self.write(vec![twine.into()]).await
+  }
+
+  async fn save_many<
+    I: Into<AnyTwine> + Send,
+    S: Iterator<Item = I> + Send,
+    T: IntoIterator<Item = I, IntoIter = S> + Send,
+  >(
+    &self,
+    twines: T,
+  ) -> Result<(), StoreError> {
+    let twines: Vec<AnyTwine> = twines.into_iter().map(|t| t.into()).collect();
+    if twines.is_empty() {
+      return Ok(());
+    }
+    self.write(twines).await
+  }
+
+  async fn save_stream<I: Into<AnyTwine> + Send, T: Stream<Item = I> + Send + Unpin>(
+    &self,
+    twines: T,
+  ) -> Result<(), StoreError> {
+    use futures::stream::TryStreamExt;
+    // save in batches
+    twines
+      .chunks(self.options.buffer_size)
+      .then(|chunk| self.save_many(chunk))
+      .try_for_each(|_| async { Ok(()) })
+      .await?;
+    Ok(())
+  }
+
+  async fn delete<C: AsCid + Send>(&self, cid: C) -> Result<(), StoreError> {
+    let twine = match self.get(cid.as_cid()).await {
+      Ok(twine) => twine,
+      Err(ResolutionError::NotFound) => return Ok(()),
+      Err(e) => return Err(StoreError::Saving(e.to_string())),
+    };
+    let txn = self.db.begin_write().map_err(saving_error)?;
+    {
+      let mut blocks = txn.open_table(BLOCKS).map_err(saving_error)?;
+      let mut strand_table = txn.open_table(STRANDS).map_err(saving_error)?;
+      let mut index_table = txn.open_table(INDEX).map_err(saving_error)?;
+      let mut latest_table = txn.open_table(LATEST).map_err(saving_error)?;
+      match &twine {
+        AnyTwine::Strand(strand) => {
+          // remove the strand along with all of its tixels
+          let strand_cid = strand.cid();
+          let lower = get_index_key(&strand_cid, 0);
+          let upper = get_index_key(&strand_cid, u64::MAX);
+          let mut entries = vec![];
+          for item in index_table
+            .range(lower.as_slice()..=upper.as_slice())
+            .map_err(saving_error)?
+          {
+            let (key, cid) = item.map_err(saving_error)?;
+            entries.push((key.value().to_vec(), cid.value().to_vec()));
+          }
+          for (key, cid) in entries {
+            index_table.remove(key.as_slice()).map_err(saving_error)?;
+            blocks.remove(cid.as_slice()).map_err(saving_error)?;
+          }
+          let key = strand_cid.to_bytes();
+          latest_table.remove(key.as_slice()).map_err(saving_error)?;
+          strand_table.remove(key.as_slice()).map_err(saving_error)?;
+        }
+        AnyTwine::Tixel(tixel) => {
+          let strand = tixel.strand_cid();
+          let index = tixel.index();
+          index_table
+            .remove(get_index_key(&strand, index).as_slice())
+            .map_err(saving_error)?;
+          let key = strand.to_bytes();
+          let latest_index = latest_table
+            .get(key.as_slice())
+            .map_err(saving_error)?
+            .and_then(|record| read_latest_record(record.value()))
+            .map(|(index, _)| index);
+          if latest_index == Some(index) {
+            // the latest now is the highest remaining index
+            let lower = get_index_key(&strand, 0);
+            let upper = get_index_key(&strand, index);
+            let previous = index_table
+              .range(lower.as_slice()..upper.as_slice())
+              .map_err(saving_error)?
+              .next_back()
+              .transpose()
+              .map_err(saving_error)?
+              .map(|(key, cid)| (key.value().to_vec(), cid.value().to_vec()));
+            match previous {
+              Some((index_key, cid)) => {
+                let index = IndexKey::ref_from_bytes(&index_key)
+                  .map_err(saving_error)?
+                  .index
+                  .get();
+                let record = get_latest_record(index, &cid);
+                latest_table
+                  .insert(key.as_slice(), record.as_bytes())
+                  .map_err(saving_error)?;
+              }
+              None => {
+                latest_table.remove(key.as_slice()).map_err(saving_error)?;
+              }
+            }
+          }
+        }
+      }
+      blocks
+        .remove(twine.cid().to_bytes().as_slice())
+        .map_err(saving_error)?;
+    }
+    txn.commit().map_err(saving_error)?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use futures::TryStreamExt;
+  use twine_builder::{test_util::chain, RingSigner, TwineBuilder};
+  use twine_lib::twine::Twine;
+
+  fn temp_store(dir: &tempfile::TempDir, options: RedbStoreOptions) -> RedbStore {
+    RedbStore::open(dir.path().join("store.redb"), options).unwrap()
+  }
+
+  #[tokio::test]
+  async fn test_range_batches() {
+    let (strand, tixels) = chain(25);
+    let dir = tempfile::tempdir().unwrap();
+    let store = temp_store(&dir, RedbStoreOptions::default().buffer_size(4));
+    store.save(strand.clone()).await.unwrap();
+    store.save_many(tixels.clone()).await.unwrap();
+    assert_eq!(store.latest_index(&strand.cid()).await.unwrap(), 24);
+
+    let increasing: Vec<Twine> = store
+      .resolve_range((strand.cid(), 3..=17))
+      .await
+      .unwrap()
+      .try_collect()
+      .await
+      .unwrap();
+    assert_eq!(increasing, tixels[3..=17].to_vec());
+
+    let decreasing: Vec<Twine> = store
+      .resolve_range((strand.cid(), 24, 0))
+      .await
+      .unwrap()
+      .try_collect()
+      .await
+      .unwrap();
+    let expected: Vec<Twine> = tixels.iter().rev().cloned().collect();
+    assert_eq!(decreasing, expected);
+  }
+
+  #[tokio::test]
+  async fn test_save_many_is_atomic() {
+    let (strand, tixels) = chain(3);
+    let (other, other_tixels) = chain(1);
+    let dir = tempfile::tempdir().unwrap();
+    let store = temp_store(&dir, RedbStoreOptions::default());
+    store.save(strand.clone()).await.unwrap();
+    let mut batch = tixels.clone();
+    batch.push(other_tixels[0].clone());
+    // the other strand is not saved, so nothing is saved
+    assert!(store.save_many(batch).await.is_err());
+    assert!(!store.has_index(&strand.cid(), 0).await.unwrap());
+    assert!(!store.has_strand(&other.cid()).await.unwrap());
+  }
+
+  #[tokio::test]
+  async fn test_rejects_forks() {
+    use twine_lib::ipld_core::ipld;
+    let builder = TwineBuilder::new(RingSigner::generate_ed25519().unwrap());
+    let strand = builder.build_strand().done().unwrap();
+    let first = builder.build_first(strand.clone()).done().unwrap();
+    let next = builder.build_next(&first).done().unwrap();
+    let fork = builder
+      .build_next(&first)
+      .payload(ipld!({ "fork": true }))
+      .done()
+      .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let store = temp_store(&dir, RedbStoreOptions::default());
+    store.save(strand.clone()).await.unwrap();
+    store.save_many([first, next.clone()]).await.unwrap();
+    // saving the same tixel again is fine
+    store.save(next.clone()).await.unwrap();
+    assert!(store.save(fork).await.is_err());
+    assert_eq!(
+      store.resolve_index(&strand, 1).await.unwrap().unpack(),
+      next
+    );
+  }
+
+  #[tokio::test]
+  async fn test_delete() {
+    let (strand, tixels) = chain(3);
+    let dir = tempfile::tempdir().unwrap();
+    let store = temp_store(&dir, RedbStoreOptions::default());
+    store.save(strand.clone()).await.unwrap();
+    store.save_many(tixels.clone()).await.unwrap();
+
+    store.delete(tixels[2].cid()).await.unwrap();
+    assert_eq!(store.latest_index(&strand.cid()).await.unwrap(), 1);
+    assert!(!store
+      .has_twine(&strand.cid(), &tixels[2].cid())
+      .await
+      .unwrap());
+
+    store.delete(strand.cid()).await.unwrap();
+    assert!(!store.has_strand(&strand.cid()).await.unwrap());
+    assert!(!store.has_index(&strand.cid(), 0).await.unwrap());
+    assert!(!store
+      .has_twine(&strand.cid(), &tixels[0].cid())
+      .await
+      .unwrap());
+    assert!(matches!(
+      store.fetch_latest(&strand.cid()).await,
+      Err(ResolutionError::NotFound)
+    ));
+  }
+}
@@ -0,0 +1,122 @@
+//! Migration of a [`twine_sled_store::SledStore`] into a [`RedbStore`]
+//!
+//! Every strand and tixel is decoded and verified as it is read from
+//! sled, so a corrupt or incomplete sled database fails the migration
+//! instead of being copied. Once a strand is copied, its latest tixel is
+//! resolved from redb and compared against sled.
+//!
+//! The `sled-to-redb` binary runs this as a one-shot tool.
+use crate::RedbStore;
+use futures::TryStreamExt;
+use twine_lib::errors::{ResolutionError, StoreError};
+use twine_lib::resolver::{AbsoluteRange, Resolver};
+use twine_lib::store::Store;
+use twine_sled_store::SledStore;
+
+/// A summary of a completed migration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+  /// The number of strands copied
+  pub strands: usize,
+  /// The number of tixels copied
+  pub tixels: usize,
+}
+
+/// Copy all twine data from a sled store into a redb store
+///
+/// Tixels are written in batches of the redb store's buffer size.
+pub async fn migrate_from_sled(
+  sled: &SledStore,
+  redb: &RedbStore,
+) -> Result<MigrationReport, StoreError> {
+  let mut report = MigrationReport::default();
+  let strands: Vec<_> = sled.strands().await?.try_collect().await?;
+  for strand in strands {
+    redb.save(strand.clone()).await?;
+    report.strands += 1;
+
+    let latest = match sled.resolve_latest(&strand).await {
+      Ok(latest) => latest.unpack(),
+      Err(ResolutionError::NotFound) => continue,
+      Err(e) => return Err(e.into()),
+    };
+    let range = AbsoluteRange::new(strand.cid(), 0, latest.index());
+    let mut tixels = sled.resolve_range(range).await?;
+    let mut batch = Vec::with_capacity(redb.options().buffer_size);
+    while let Some(tixel) = tixels.try_next().await? {
+      batch.push(tixel);
+      if batch.len() == redb.options().buffer_size {
+        report.tixels += batch.len();
+        redb.save_many(std::mem::take(&mut batch)).await?;
+      }
+    }
+    report.tixels += batch.len();
+    redb.save_many(batch).await?;
+
+    let copied = redb.resolve_latest(&strand).await?.unpack();
+    if copied != latest {
+      return Err(StoreError::Saving(format!(
+        "Latest tixel of strand {} differs after migration",
+        strand.cid()
+      )));
+    }
+    log::info!(
+      "Migrated strand {} ({} tixels)",
+      strand.cid(),
+      latest.index() + 1
+    );
+  }
+  Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::RedbStoreOptions;
+  use twine_builder::{RingSigner, TwineBuilder};
+  use twine_sled_store::SledStoreOptions;
+
+  #[tokio::test]
+  async fn test_migrate_from_sled() {
+    let builder = TwineBuilder::new(RingSigner::generate_ed25519().unwrap());
+    let strand = builder.build_strand().done().unwrap();
+    let mut tixels = vec![builder.build_first(strand.clone()).done().unwrap()];
+    for _ in 0..9 {
+      let next = builder.build_next(tixels.last().unwrap()).done().unwrap();
+      tixels.push(next);
+    }
+    let empty = builder.build_strand().done().unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let sled = SledStore::new(
+      twine_sled_store::sled::open(dir.path().join("sled")).unwrap(),
+      SledStoreOptions::default(),
+    );
+    sled.save(strand.clone()).await.unwrap();
+    sled.save(empty.clone()).await.unwrap();
+    sled.save_many(tixels.clone()).await.unwrap();
+
+    let redb = RedbStore::open(
+      dir.path().join("store.redb"),
+      RedbStoreOptions::default().buffer_size(3),
+    )
+    .unwrap();
+    let report = migrate_from_sled(&sled, &redb).await.unwrap();
+    assert_eq!(
+      report,
+      MigrationReport {
+        strands: 2,
+        tixels: 10
+      }
+    );
+    assert_eq!(redb.resolve_strand(&empty).await.unwrap().unpack(), empty);
+    let copied: Vec<_> = redb
+      .resolve_range((strand.cid(), ..))
+      .await
+      .unwrap()
+      .try_collect()
+      .await
+      .unwrap();
+    assert_eq!(copied, tixels);
+  }
+}
@@ -0,0 +1,62 @@
+//! Throughput of large range queries against an in-memory sqlite store
+//!
+//! Run with `cargo bench -p twine_sql_store`
+// criterion_group! generates an undocumented public function
+#![allow(missing_docs)]
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures::TryStreamExt;
+use twine_builder::{RingSigner, TwineBuilder};
+use twine_lib::{resolver::Resolver, store::Store, twine::Strand};
+use twine_sql_store::{sqlite::SqliteStore, SqlStoreOptions};
+
+const TIXELS: u64 = 10_000;
+
+async fn populated_store() -> (SqliteStore, Strand) {
+  let store = SqliteStore::open("sqlite::memory:").await.unwrap();
+  store.migrate().await.unwrap();
+  let builder = TwineBuilder::new(RingSigner::generate_ed25519().unwrap());
+  let strand = builder.build_strand().done().unwrap();
+  store.save(strand.clone()).await.unwrap();
+  let mut prev = builder.build_first(strand.clone()).done().unwrap();
+  store.save(prev.clone()).await.unwrap();
+  for _ in 1..TIXELS {
+    prev = builder.build_next(&prev).done().unwrap();
+    store.save(prev.clone()).await.unwrap();
+  }
+  (store, strand)
+}
+
+fn range_throughput(c: &mut Criterion) {
+  let runtime = tokio::runtime::Runtime::new().unwrap();
+  let (store, strand) = runtime.block_on(populated_store());
+
+  let mut group = c.benchmark_group("sqlite_range");
+  group.throughput(Throughput::Elements(TIXELS));
+  group.sample_size(10);
+  for batch_size in [100, 1000, 10_000] {
+    let store = store
+      .clone()
+      .with_options(SqlStoreOptions::default().range_batch_size(batch_size));
+    for (name, range) in [
+      ("increasing", (strand.cid(), 0, -1)),
+      ("decreasing", (strand.cid(), -1, 0)),
+    ] {
+      group.bench_with_input(BenchmarkId::new(name, batch_size), &range, |b, range| {
+        b.to_async(&runtime).iter(|| async {
+          let tixels: Vec<_> = store
+            .resolve_range(*range)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+          assert_eq!(tixels.len() as u64, TIXELS);
+        })
+      });
+    }
+  }
+  group.finish();
+}
+
+criterion_group!(benches, range_throughput);
+criterion_main!(benches);
@@ -7,7 +7,15 @@ CREATE TABLE IF NOT EXISTS Strands (
   data BLOB NOT NULL
 );
 
-CREATE INDEX idx_strands_cid ON Strands (cid);
+-- MySQL has no CREATE INDEX IF NOT EXISTS, so check for it first
+SET @stmt = (
+  SELECT IF(COUNT(*) = 0, 'CREATE INDEX idx_strands_cid ON Strands (cid)', 'DO 0')
+  FROM information_schema.statistics
+  WHERE table_schema = DATABASE() AND table_name = 'Strands' AND index_name = 'idx_strands_cid'
+);
+PREPARE stmt FROM @stmt;
+EXECUTE stmt;
+DEALLOCATE PREPARE stmt;
 
 CREATE TABLE IF NOT EXISTS Tixels (
   cid VARBINARY(82) UNIQUE NOT NULL,
@@ -20,4 +28,12 @@ CREATE TABLE IF NOT EXISTS Tixels (
   FOREIGN KEY (strand) REFERENCES Strands(id) ON DELETE CASCADE
 );
 
-CREATE INDEX idx_tixels_cid ON Tixels (cid);
+-- Guarded like idx_strands_cid above
+SET @stmt = (
+  SELECT IF(COUNT(*) = 0, 'CREATE INDEX idx_tixels_cid ON Tixels (cid)', 'DO 0')
+  FROM information_schema.statistics
+  WHERE table_schema = DATABASE() AND table_name = 'Tixels' AND index_name = 'idx_tixels_cid'
+);
+PREPARE stmt FROM @stmt;
+EXECUTE stmt;
+DEALLOCATE PREPARE stmt;
@@ -0,0 +1,5 @@
+
+-- BLOB columns are limited to 64KiB. Post-quantum signatures
+-- (up to ~30KiB for SLH-DSA) can push tixels with large payloads past that.
+ALTER TABLE Strands MODIFY data MEDIUMBLOB NOT NULL;
+ALTER TABLE Tixels MODIFY data MEDIUMBLOB NOT NULL;
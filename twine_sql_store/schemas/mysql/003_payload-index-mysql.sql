@@ -0,0 +1,2 @@
+-- Indexed payload values, as a JSON object keyed by path
+ALTER TABLE Tixels ADD COLUMN payload_index JSON NULL;
@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS Strands (
+  id BIGINT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+  cid BYTEA UNIQUE NOT NULL,
+  spec TEXT NOT NULL,
+  data BYTEA NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS Tixels (
+  cid BYTEA UNIQUE NOT NULL,
+  strand BIGINT NOT NULL,
+  idx BIGINT NOT NULL,
+  data BYTEA NOT NULL,
+
+  -- Keys
+  PRIMARY KEY (strand, idx),
+  FOREIGN KEY (strand) REFERENCES Strands(id) ON DELETE CASCADE
+);
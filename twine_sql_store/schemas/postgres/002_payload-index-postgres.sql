@@ -0,0 +1,4 @@
+-- Indexed payload values, as a JSON object keyed by path
+ALTER TABLE Tixels ADD COLUMN IF NOT EXISTS payload_index JSONB;
+
+CREATE INDEX IF NOT EXISTS idx_tixels_payload_index ON Tixels USING GIN (payload_index jsonb_path_ops);
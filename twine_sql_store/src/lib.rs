@@ -1,12 +1,15 @@
 #![doc = include_str!("../README.md")]
 use async_trait::async_trait;
-use futures::stream::Stream;
+use futures::stream::{unfold, Stream, StreamExt};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
 use twine_lib::as_cid::AsCid;
-use twine_lib::errors::{ResolutionError, StoreError};
+use twine_lib::errors::{ResolutionError, StoreError, VerificationError};
 use twine_lib::resolver::AbsoluteRange;
 use twine_lib::resolver::{unchecked_base, Resolver};
 use twine_lib::store::Store;
-use twine_lib::twine::AnyTwine;
+use twine_lib::twine::{AnyTwine, Twine};
 use twine_lib::{
   twine::{Strand, Tixel},
   Cid,
@@ -15,8 +18,13 @@ use twine_lib::{
 pub use sqlx;
 #[cfg(feature = "mysql")]
 pub mod mysql;
+pub mod payload;
+#[cfg(feature = "postgres")]
+pub mod postgres;
 #[cfg(feature = "sqlite")]
 pub mod sqlite;
+#[cfg(test)]
+mod test_util;
 
 type Block = (Vec<u8>, Vec<u8>);
 
@@ -31,6 +39,116 @@ fn to_storage_error(err: sqlx::Error) -> StoreError {
   StoreError::Saving(err.to_string())
 }
 
+/// A row of a paginated query: its key, then the cid and data of a block
+type KeyedBlock<K> = (K, Vec<u8>, Vec<u8>);
+
+type BlockStream<'a, T> = Pin<Box<dyn Stream<Item = Result<T, ResolutionError>> + Send + 'a>>;
+
+/// Stream the blocks returned by a query in batches, with keyset pagination
+///
+/// Each batch is one query, run by `fetch` with the position to continue
+/// from and the number of rows to return. This keeps every query a bounded
+/// scan of an index, however far into the results it is. After a full
+/// batch, `next` gives the position following the key of its last row, and
+/// a short batch ends the stream. At most `limit` rows are returned, if
+/// given. Each row is decoded with `decode`.
+///
+/// Only the SQL differs between backends, so they share this loop.
+fn keyset_stream<'a, P, K, T, F, Fut, N, D>(
+  start: P,
+  batch_size: u32,
+  limit: Option<u64>,
+  fetch: F,
+  next: N,
+  decode: D,
+) -> BlockStream<'a, T>
+where
+  P: Clone + Send + 'a,
+  K: Send + 'a,
+  T: Send + 'a,
+  F: Fn(P, u64) -> Fut + Send + Sync + 'a,
+  Fut: Future<Output = Result<Vec<KeyedBlock<K>>, sqlx::Error>> + Send + 'a,
+  N: Fn(P, &K) -> Option<P> + Send + Sync + 'a,
+  D: Fn(Cid, Vec<u8>) -> Result<T, VerificationError> + Send + Sync + 'a,
+{
+  let batch_size = batch_size as u64;
+  let fns = Arc::new((fetch, next, decode));
+  unfold(Some((start, limit)), move |state| {
+    let fns = fns.clone();
+    async move {
+      let (fetch, next, decode) = &*fns;
+      let (position, remaining) = state?;
+      let batch = remaining.map_or(batch_size, |r| r.min(batch_size));
+      if batch == 0 {
+        return None;
+      }
+      let rows = match fetch(position.clone(), batch).await {
+        Ok(rows) => rows,
+        Err(e) => return Some((vec![Err(to_resolution_error(e))], None)),
+      };
+      let state = match rows.last() {
+        Some((key, _, _)) if rows.len() as u64 == batch => {
+          next(position, key).map(|position| (position, remaining.map(|r| r - batch)))
+        }
+        Some(_) => None,
+        None => return None,
+      };
+      let mut blocks = Vec::with_capacity(rows.len());
+      for (_, cid, data) in rows {
+        blocks.push(match Cid::try_from(cid) {
+          Ok(cid) => decode(cid, data).map_err(ResolutionError::from),
+          Err(e) => Err(ResolutionError::Fetch(e.to_string())),
+        });
+      }
+      Some((blocks, state))
+    }
+  })
+  .flat_map(futures::stream::iter)
+  .boxed()
+}
+
+/// Options for the SQL stores
+///
+/// # Example
+///
+/// ```no_run
+/// use twine_sql_store::{SqlStore, SqlStoreOptions};
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let store = SqlStore::open("sqlite:my_database.db")
+///   .await
+///   .unwrap()
+///   .with_options(SqlStoreOptions::default().range_batch_size(5000));
+/// # });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SqlStoreOptions {
+  strand_batch_size: u32,
+  range_batch_size: u32,
+}
+
+impl Default for SqlStoreOptions {
+  fn default() -> Self {
+    Self {
+      strand_batch_size: 100,
+      range_batch_size: 1000,
+    }
+  }
+}
+
+impl SqlStoreOptions {
+  /// Set the number of strands to fetch per query when listing strands
+  pub fn strand_batch_size(mut self, size: u32) -> Self {
+    self.strand_batch_size = size.max(1);
+    self
+  }
+
+  /// Set the number of tixels to fetch per query when streaming a range
+  pub fn range_batch_size(mut self, size: u32) -> Self {
+    self.range_batch_size = size.max(1);
+    self
+  }
+}
+
 /// A SQL-based store for Twine data
 ///
 /// This store is a facade over the specific sql store implementations
@@ -45,6 +163,10 @@ pub enum SqlStore {
   /// A store that uses MySQL as the backend
   #[cfg(feature = "mysql")]
   Mysql(mysql::MysqlStore),
+
+  /// A store that uses PostgreSQL as the backend
+  #[cfg(feature = "postgres")]
+  Postgres(postgres::PostgresStore),
   //...
 }
 
@@ -76,9 +198,85 @@ impl SqlStore {
         return Ok(SqlStore::Mysql(mysql::MysqlStore::open(uri).await?));
       }
     }
+    #[cfg(feature = "postgres")]
+    {
+      if uri.starts_with("postgres:") || uri.starts_with("postgresql:") {
+        return Ok(SqlStore::Postgres(
+          postgres::PostgresStore::open(uri).await?,
+        ));
+      }
+    }
     unimplemented!("unsupported uri: {}", uri);
   }
 
+  /// Set the options for the store
+  pub fn with_options(self, options: SqlStoreOptions) -> Self {
+    match self {
+      #[cfg(feature = "sqlite")]
+      SqlStore::Sqlite(store) => SqlStore::Sqlite(store.with_options(options)),
+      #[cfg(feature = "mysql")]
+      SqlStore::Mysql(store) => SqlStore::Mysql(store.with_options(options)),
+      #[cfg(feature = "postgres")]
+      SqlStore::Postgres(store) => SqlStore::Postgres(store.with_options(options)),
+      #[allow(unreachable_patterns)]
+      _ => unimplemented!(),
+    }
+  }
+
+  /// Set the payload paths to index when saving tixels
+  ///
+  /// See [`crate::payload`].
+  pub fn with_payload_index(self, index: payload::PayloadIndex) -> Self {
+    match self {
+      #[cfg(feature = "sqlite")]
+      SqlStore::Sqlite(store) => SqlStore::Sqlite(store.with_payload_index(index)),
+      #[cfg(feature = "mysql")]
+      SqlStore::Mysql(store) => SqlStore::Mysql(store.with_payload_index(index)),
+      #[cfg(feature = "postgres")]
+      SqlStore::Postgres(store) => SqlStore::Postgres(store.with_payload_index(index)),
+      #[allow(unreachable_patterns)]
+      _ => unimplemented!(),
+    }
+  }
+
+  /// Find the tixels of a strand by indexed payload values
+  ///
+  /// The query runs as SQL, and the returned twines are verified.
+  /// See [`crate::payload`].
+  pub async fn query_payload(
+    &self,
+    query: payload::PayloadQuery,
+  ) -> Result<unchecked_base::TwineStream<'_, Twine>, payload::PayloadError> {
+    match self {
+      #[cfg(feature = "sqlite")]
+      SqlStore::Sqlite(store) => store.query_payload(query).await,
+      #[cfg(feature = "mysql")]
+      SqlStore::Mysql(store) => store.query_payload(query).await,
+      #[cfg(feature = "postgres")]
+      SqlStore::Postgres(store) => store.query_payload(query).await,
+      #[allow(unreachable_patterns)]
+      _ => unimplemented!(),
+    }
+  }
+
+  /// Apply any pending schema migrations
+  ///
+  /// Each backend has its own ordered set of migrations (in `./schemas/`),
+  /// and records the ones it has applied in a `_sqlx_migrations` table.
+  /// Call this after opening a store to bring its schema up to date.
+  pub async fn migrate(&self) -> Result<(), sqlx::Error> {
+    match self {
+      #[cfg(feature = "sqlite")]
+      SqlStore::Sqlite(store) => store.migrate().await,
+      #[cfg(feature = "mysql")]
+      SqlStore::Mysql(store) => store.migrate().await,
+      #[cfg(feature = "postgres")]
+      SqlStore::Postgres(store) => store.migrate().await,
+      #[allow(unreachable_patterns)]
+      _ => unimplemented!(),
+    }
+  }
+
   /// If the store is a SQLite store, create the necessary tables
   pub async fn create_sqlite_tables(&self) -> Result<(), sqlx::Error> {
     match self {
@@ -88,6 +286,16 @@ impl SqlStore {
       _ => unimplemented!(),
     }
   }
+
+  /// If the store is a PostgreSQL store, create the necessary tables
+  pub async fn create_postgres_tables(&self) -> Result<(), sqlx::Error> {
+    match self {
+      #[cfg(feature = "postgres")]
+      SqlStore::Postgres(store) => store.create_tables().await,
+      #[allow(unreachable_patterns)]
+      _ => unimplemented!(),
+    }
+  }
 }
 
 #[async_trait]
@@ -98,6 +306,8 @@ impl unchecked_base::BaseResolver for SqlStore {
       SqlStore::Sqlite(store) => store.has_index(strand, index).await,
       #[cfg(feature = "mysql")]
       SqlStore::Mysql(store) => store.has_index(strand, index).await,
+      #[cfg(feature = "postgres")]
+      SqlStore::Postgres(store) => store.has_index(strand, index).await,
       #[allow(unreachable_patterns)]
       _ => unimplemented!(),
     }
@@ -109,6 +319,8 @@ impl unchecked_base::BaseResolver for SqlStore {
       SqlStore::Sqlite(store) => store.has_twine(strand, cid).await,
       #[cfg(feature = "mysql")]
       SqlStore::Mysql(store) => store.has_twine(strand, cid).await,
+      #[cfg(feature = "postgres")]
+      SqlStore::Postgres(store) => store.has_twine(strand, cid).await,
       #[allow(unreachable_patterns)]
       _ => unimplemented!(),
     }
@@ -120,6 +332,8 @@ impl unchecked_base::BaseResolver for SqlStore {
       SqlStore::Sqlite(store) => store.has_strand(cid).await,
       #[cfg(feature = "mysql")]
       SqlStore::Mysql(store) => store.has_strand(cid).await,
+      #[cfg(feature = "postgres")]
+      SqlStore::Postgres(store) => store.has_strand(cid).await,
       #[allow(unreachable_patterns)]
       _ => unimplemented!(),
     }
@@ -131,6 +345,8 @@ impl unchecked_base::BaseResolver for SqlStore {
       SqlStore::Sqlite(store) => store.fetch_latest(strand).await,
       #[cfg(feature = "mysql")]
       SqlStore::Mysql(store) => store.fetch_latest(strand).await,
+      #[cfg(feature = "postgres")]
+      SqlStore::Postgres(store) => store.fetch_latest(strand).await,
       #[allow(unreachable_patterns)]
       _ => unimplemented!(),
     }
@@ -142,6 +358,8 @@ impl unchecked_base::BaseResolver for SqlStore {
       SqlStore::Sqlite(store) => store.fetch_index(strand, index).await,
       #[cfg(feature = "mysql")]
       SqlStore::Mysql(store) => store.fetch_index(strand, index).await,
+      #[cfg(feature = "postgres")]
+      SqlStore::Postgres(store) => store.fetch_index(strand, index).await,
       #[allow(unreachable_patterns)]
       _ => unimplemented!(),
     }
@@ -153,6 +371,8 @@ impl unchecked_base::BaseResolver for SqlStore {
       SqlStore::Sqlite(store) => store.fetch_tixel(strand, tixel).await,
       #[cfg(feature = "mysql")]
       SqlStore::Mysql(store) => store.fetch_tixel(strand, tixel).await,
+      #[cfg(feature = "postgres")]
+      SqlStore::Postgres(store) => store.fetch_tixel(strand, tixel).await,
       #[allow(unreachable_patterns)]
       _ => unimplemented!(),
     }
@@ -164,6 +384,8 @@ impl unchecked_base::BaseResolver for SqlStore {
       SqlStore::Sqlite(store) => store.fetch_strand(strand).await,
       #[cfg(feature = "mysql")]
       SqlStore::Mysql(store) => store.fetch_strand(strand).await,
+      #[cfg(feature = "postgres")]
+      SqlStore::Postgres(store) => store.fetch_strand(strand).await,
       #[allow(unreachable_patterns)]
       _ => unimplemented!(),
     }
@@ -178,6 +400,8 @@ impl unchecked_base::BaseResolver for SqlStore {
       SqlStore::Sqlite(store) => store.range_stream(range).await,
       #[cfg(feature = "mysql")]
       SqlStore::Mysql(store) => store.range_stream(range).await,
+      #[cfg(feature = "postgres")]
+      SqlStore::Postgres(store) => store.range_stream(range).await,
       #[allow(unreachable_patterns)]
       _ => unimplemented!(),
     }
@@ -191,6 +415,8 @@ impl unchecked_base::BaseResolver for SqlStore {
       SqlStore::Sqlite(store) => store.fetch_strands().await,
       #[cfg(feature = "mysql")]
       SqlStore::Mysql(store) => store.fetch_strands().await,
+      #[cfg(feature = "postgres")]
+      SqlStore::Postgres(store) => store.fetch_strands().await,
       #[allow(unreachable_patterns)]
       _ => unimplemented!(),
     }
@@ -207,6 +433,8 @@ impl Store for SqlStore {
       SqlStore::Sqlite(store) => store.save(twine).await,
       #[cfg(feature = "mysql")]
       SqlStore::Mysql(store) => store.save(twine).await,
+      #[cfg(feature = "postgres")]
+      SqlStore::Postgres(store) => store.save(twine).await,
       #[allow(unreachable_patterns)]
       _ => unimplemented!(),
     }
@@ -225,6 +453,8 @@ impl Store for SqlStore {
       SqlStore::Sqlite(store) => store.save_many(twines).await,
       #[cfg(feature = "mysql")]
       SqlStore::Mysql(store) => store.save_many(twines).await,
+      #[cfg(feature = "postgres")]
+      SqlStore::Postgres(store) => store.save_many(twines).await,
       #[allow(unreachable_patterns)]
       _ => unimplemented!(),
     }
@@ -239,6 +469,8 @@ impl Store for SqlStore {
       SqlStore::Sqlite(store) => store.save_stream(twines).await,
       #[cfg(feature = "mysql")]
       SqlStore::Mysql(store) => store.save_stream(twines).await,
+      #[cfg(feature = "postgres")]
+      SqlStore::Postgres(store) => store.save_stream(twines).await,
       #[allow(unreachable_patterns)]
       _ => unimplemented!(),
     }
@@ -250,6 +482,8 @@ impl Store for SqlStore {
       SqlStore::Sqlite(store) => store.delete(cid).await,
       #[cfg(feature = "mysql")]
       SqlStore::Mysql(store) => store.delete(cid).await,
+      #[cfg(feature = "postgres")]
+      SqlStore::Postgres(store) => store.delete(cid).await,
       #[allow(unreachable_patterns)]
       _ => unimplemented!(),
     }
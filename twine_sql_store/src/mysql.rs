@@ -1,8 +1,14 @@
 //! MySQL store implementation for Twine
-use super::{to_resolution_error, to_storage_error, Block};
+use super::payload::{
+  index_payload, json_path, PayloadError, PayloadFilter, PayloadIndex, PayloadQuery, ValueKind,
+};
+use super::{keyset_stream, to_resolution_error, to_storage_error, Block, SqlStoreOptions};
 use async_trait::async_trait;
-use futures::stream::{unfold, Stream};
+use futures::stream::Stream;
 use futures::stream::{StreamExt, TryStreamExt};
+use sqlx::migrate::Migrator;
+use sqlx::QueryBuilder;
+use std::ops::Bound;
 use std::pin::Pin;
 use twine_lib::as_cid::AsCid;
 use twine_lib::errors::{ResolutionError, StoreError};
@@ -11,20 +17,45 @@ use twine_lib::resolver::{unchecked_base, Resolver};
 use twine_lib::store::Store;
 use twine_lib::twine::{AnyTwine, TwineBlock};
 use twine_lib::{
-  twine::{Strand, Tixel},
+  twine::{Strand, Tixel, Twine},
   Cid,
 };
 
+/// The migrations for the MySQL store
+pub static MIGRATOR: Migrator = sqlx::migrate!("./schemas/mysql");
+
+type Row = (u64, Vec<u8>, Vec<u8>);
+
 /// A MySQL-based store for Twine data
 #[derive(Debug, Clone)]
 pub struct MysqlStore {
   pool: sqlx::MySqlPool,
+  options: SqlStoreOptions,
+  payload_index: PayloadIndex,
 }
 
 impl MysqlStore {
   /// Create a new MySQL store from a sqlx pool
   pub fn new(pool: sqlx::MySqlPool) -> Self {
-    Self { pool }
+    Self {
+      pool,
+      options: SqlStoreOptions::default(),
+      payload_index: PayloadIndex::default(),
+    }
+  }
+
+  /// Set the options for the store
+  pub fn with_options(mut self, options: SqlStoreOptions) -> Self {
+    self.options = options;
+    self
+  }
+
+  /// Set the payload paths to index when saving tixels
+  ///
+  /// See [`crate::payload`].
+  pub fn with_payload_index(mut self, index: PayloadIndex) -> Self {
+    self.payload_index = index;
+    self
   }
 
   /// Open a new MySQL store from a URI
@@ -43,39 +74,93 @@ impl MysqlStore {
     Ok(Self::new(pool))
   }
 
+  /// Apply any pending schema migrations
+  ///
+  /// Applied migrations are recorded in the `_sqlx_migrations` table,
+  /// so this is safe to call every time the store is opened.
+  pub async fn migrate(&self) -> Result<(), sqlx::Error> {
+    MIGRATOR.run(&self.pool).await?;
+    Ok(())
+  }
+
   async fn all_strands(
     &self,
   ) -> Result<
     Pin<Box<dyn Stream<Item = Result<Strand, ResolutionError>> + Send + '_>>,
     ResolutionError,
   > {
-    let query = "SELECT cid, data FROM Strands LIMIT 10 OFFSET ?";
+    // keyset pagination on the primary key
+    let query = "SELECT id, cid, data FROM Strands WHERE id > ? ORDER BY id LIMIT ?";
+    let stream = keyset_stream(
+      0,
+      self.options.strand_batch_size,
+      None,
+      move |after, limit| {
+        sqlx::query_as::<_, Row>(query)
+          .bind(after)
+          .bind(limit as i64)
+          .fetch_all(&self.pool)
+      },
+      |_, id| Some(*id),
+      Strand::from_block,
+    );
 
-    let stream = unfold(0, move |offset| async move {
-      let mut conn = match self.pool.acquire().await.map_err(to_resolution_error) {
-        Ok(conn) => conn,
-        Err(e) => return Some((Err(e), offset)),
-      };
-      let strands: Result<Vec<_>, ResolutionError> = sqlx::query_as::<_, Block>(query)
-        .bind(offset)
-        .fetch(&mut *conn)
-        .map_err(to_resolution_error)
-        .map_ok(|(cid, data)| {
-          let cid = Cid::try_from(cid).map_err(|e| ResolutionError::Fetch(e.to_string()))?;
-          Ok::<_, ResolutionError>(Strand::from_block(cid, data)?)
-        })
-        .try_collect()
-        .await;
-      if let Ok(strands) = &strands {
-        if strands.is_empty() {
-          return None;
+    Ok(stream)
+  }
+
+  async fn strand_id(&self, cid: &Cid) -> Result<Option<u64>, ResolutionError> {
+    let query = "SELECT id FROM Strands WHERE cid = ?";
+
+    let mut conn = self.pool.acquire().await.map_err(to_resolution_error)?;
+
+    sqlx::query_scalar(query)
+      .bind(cid.to_bytes())
+      .fetch_optional(&mut *conn)
+      .await
+      .map_err(to_resolution_error)
+  }
+
+  /// Find the tixels of a strand by indexed payload values
+  ///
+  /// The query runs as SQL, and the returned twines are verified.
+  /// See [`crate::payload`].
+  pub async fn query_payload(
+    &self,
+    query: PayloadQuery,
+  ) -> Result<unchecked_base::TwineStream<'_, Twine>, PayloadError> {
+    query
+      .check(&self.payload_index)
+      .map_err(PayloadError::InvalidQuery)?;
+    let strand = self.get_strand(&query.strand).await?;
+    let Some(strand_id) = self.strand_id(&query.strand).await? else {
+      return Ok(futures::stream::empty().boxed());
+    };
+    let stream = keyset_stream(
+      None,
+      self.options.range_batch_size,
+      query.limit,
+      move |after, limit| {
+        let query = query.clone();
+        async move {
+          let mut builder =
+            QueryBuilder::<sqlx::MySql>::new("SELECT idx, cid, data FROM Tixels WHERE strand = ");
+          builder.push_bind(strand_id);
+          if let Some(after) = after {
+            builder.push(" AND idx > ").push_bind(after);
+          }
+          for filter in &query.filters {
+            builder.push(" AND ");
+            push_payload_filter(&mut builder, filter);
+          }
+          builder
+            .push(" ORDER BY idx ASC LIMIT ")
+            .push_bind(limit as i64);
+          builder.build_query_as::<Row>().fetch_all(&self.pool).await
         }
-      }
-      Some((strands, offset + 10))
-    })
-    .map_ok(|v| futures::stream::iter(v.into_iter()))
-    .try_flatten()
-    .boxed();
+      },
+      |_, idx| Some(Some(*idx)),
+      move |cid, data| Twine::try_new(strand.clone(), Tixel::from_block(cid, data)?),
+    );
 
     Ok(stream)
   }
@@ -225,8 +310,8 @@ impl MysqlStore {
     }
 
     let query = "
-      INSERT INTO Tixels (cid, data, strand, idx)
-      SELECT ?, ?, s.id, ?
+      INSERT INTO Tixels (cid, data, strand, idx, payload_index)
+      SELECT ?, ?, s.id, ?, ?
       FROM Strands s
       WHERE s.cid = ?
         AND (? = 0 OR EXISTS (
@@ -241,11 +326,13 @@ impl MysqlStore {
     let cid = tixel.cid().to_bytes();
     let data = tixel.bytes().to_vec();
     let index = tixel.index();
+    let payload_index = index_payload(self.payload_index.paths(), tixel.payload());
 
     let _ret = sqlx::query(&query)
       .bind(&cid)
       .bind(&data)
       .bind(index)
+      .bind(payload_index)
       .bind(tixel.strand_cid().to_bytes())
       .bind(index)
       .bind(index)
@@ -295,6 +382,59 @@ impl MysqlStore {
   }
 }
 
+/// Push the SQL condition for a payload filter
+fn push_payload_filter(builder: &mut QueryBuilder<'_, sqlx::MySql>, filter: &PayloadFilter) {
+  let path = json_path(filter.path());
+  match filter {
+    PayloadFilter::Eq(_, value) => {
+      builder
+        .push("JSON_EXTRACT(payload_index, ")
+        .push_bind(path)
+        .push(") = CAST(")
+        .push_bind(value.to_string())
+        .push(" AS JSON)");
+    }
+    PayloadFilter::Range(_, lower, upper) => {
+      let types = match ValueKind::of_range(lower, upper) {
+        ValueKind::Number => "IN ('INTEGER', 'UNSIGNED INTEGER', 'DOUBLE', 'DECIMAL')",
+        ValueKind::String => "= 'STRING'",
+        ValueKind::Other => "IS NOT NULL",
+      };
+      builder
+        .push("JSON_TYPE(JSON_EXTRACT(payload_index, ")
+        .push_bind(path.clone())
+        .push(")) ")
+        .push(types);
+      for (bound, inclusive, exclusive) in [(lower, ">=", ">"), (upper, "<=", "<")] {
+        let (op, value) = match bound {
+          Bound::Included(value) => (inclusive, value),
+          Bound::Excluded(value) => (exclusive, value),
+          Bound::Unbounded => continue,
+        };
+        builder
+          .push(" AND JSON_EXTRACT(payload_index, ")
+          .push_bind(path.clone())
+          .push(") ")
+          .push(op)
+          .push(" CAST(")
+          .push_bind(value.to_string())
+          .push(" AS JSON)");
+      }
+    }
+    PayloadFilter::Prefix(_, prefix) => {
+      builder
+        .push("JSON_TYPE(JSON_EXTRACT(payload_index, ")
+        .push_bind(path.clone())
+        .push(")) = 'STRING' AND LEFT(JSON_UNQUOTE(JSON_EXTRACT(payload_index, ")
+        .push_bind(path)
+        .push(")), CHAR_LENGTH(")
+        .push_bind(prefix.clone())
+        .push(")) = ")
+        .push_bind(prefix.clone());
+    }
+  }
+}
+
 #[async_trait]
 impl unchecked_base::BaseResolver for MysqlStore {
   async fn fetch_strands(
@@ -351,39 +491,51 @@ impl unchecked_base::BaseResolver for MysqlStore {
     Pin<Box<dyn Stream<Item = Result<Tixel, ResolutionError>> + Send + '_>>,
     ResolutionError,
   > {
-    let batches = range.batches(100);
-    let stream = unfold(batches.into_iter(), move |mut batches| async move {
-      let batch = batches.next()?;
-      let mut conn = match self.pool.acquire().await.map_err(to_resolution_error) {
-        Ok(conn) => conn,
-        Err(e) => return Some((Err(e), batches)),
-      };
-      let dir = if range.is_increasing() { "ASC" } else { "DESC" };
-      let tixels: Result<Vec<_>, ResolutionError> = sqlx::query_as::<_, Block>(&format!(
-        "
-          SELECT t.cid, t.data
-          FROM Tixels t JOIN Strands s ON t.strand = s.id
-          WHERE s.cid = ? AND t.idx >= ? AND t.idx <= ?
-          ORDER BY t.idx {}
-        ",
-        dir
-      ))
-      .bind(range.strand.to_bytes())
-      .bind(batch.lower() as i64)
-      .bind(batch.upper() as i64)
-      .fetch(&mut *conn)
-      .map_err(to_resolution_error)
-      .map_ok(|(cid, data)| {
-        let cid = Cid::try_from(cid).map_err(|e| ResolutionError::Fetch(e.to_string()))?;
-        Ok::<_, ResolutionError>(Tixel::from_block(cid, data)?)
-      })
-      .try_collect()
-      .await;
-      Some((tixels, batches))
-    })
-    .map_ok(|v| futures::stream::iter(v.into_iter()))
-    .try_flatten()
-    .boxed();
+    let Some(strand) = self.strand_id(&range.strand).await? else {
+      return Ok(futures::stream::empty().boxed());
+    };
+    // Keyset pagination: each batch continues from the last index seen,
+    // so every query is a bounded scan of the (strand, idx) primary key.
+    // The query text is fixed so the prepared statement is reused.
+    let increasing = range.is_increasing();
+    let query = if increasing {
+      "
+        SELECT idx, cid, data FROM Tixels
+        WHERE strand = ? AND idx >= ? AND idx <= ?
+        ORDER BY idx ASC
+        LIMIT ?
+      "
+    } else {
+      "
+        SELECT idx, cid, data FROM Tixels
+        WHERE strand = ? AND idx >= ? AND idx <= ?
+        ORDER BY idx DESC
+        LIMIT ?
+      "
+    };
+    let bounds = (range.lower(), range.upper());
+    let stream = keyset_stream(
+      bounds,
+      self.options.range_batch_size,
+      None,
+      move |(lower, upper), limit| {
+        sqlx::query_as::<_, Row>(query)
+          .bind(strand)
+          .bind(lower)
+          .bind(upper)
+          .bind(limit as i64)
+          .fetch_all(&self.pool)
+      },
+      move |(lower, upper), idx| {
+        let next = if increasing {
+          Some((idx + 1, upper))
+        } else {
+          idx.checked_sub(1).map(|upper| (lower, upper))
+        };
+        next.filter(|(lower, upper)| lower <= upper)
+      },
+      Tixel::from_block,
+    );
 
     Ok(stream)
   }
@@ -436,3 +588,33 @@ impl Store for MysqlStore {
     }
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  /// Connect to the database in `TWINE_TEST_MYSQL_URL`
+  ///
+  /// For example: `mysql://root@localhost/twine_test`. The tests
+  /// that need it are ignored by default, run them with
+  /// `cargo test --features mysql -- --ignored`.
+  async fn test_store() -> MysqlStore {
+    let uri = std::env::var("TWINE_TEST_MYSQL_URL").expect("TWINE_TEST_MYSQL_URL not set");
+    let store = MysqlStore::open(&uri).await.unwrap();
+    store.migrate().await.unwrap();
+    store
+  }
+
+  #[tokio::test]
+  #[ignore = "needs TWINE_TEST_MYSQL_URL"]
+  async fn test_payload_query() {
+    crate::test_util::payload_query(crate::SqlStore::Mysql(test_store().await)).await;
+  }
+
+  #[cfg(feature = "pq")]
+  #[tokio::test]
+  #[ignore = "needs TWINE_TEST_MYSQL_URL"]
+  async fn test_post_quantum_roundtrip() {
+    crate::test_util::post_quantum_roundtrip(crate::SqlStore::Mysql(test_store().await)).await;
+  }
+}
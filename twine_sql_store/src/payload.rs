@@ -0,0 +1,351 @@
+//! Queries over indexed payload fields
+//!
+//! Tixel data is stored as opaque DAG-CBOR, so payload fields can only be
+//! queried in SQL if they are indexed. Paths to index are configured with
+//! a [`PayloadIndex`], and the values found at those paths are extracted
+//! into the `payload_index` JSON column when a tixel is saved. Tixels saved
+//! before a path was configured are not indexed for it.
+//!
+//! Queries that use paths which are not indexed, or mix the kinds of range
+//! bounds, fail with [`PayloadError::InvalidQuery`].
+//!
+//! A [`PayloadQuery`] then selects tixels of a strand by those values.
+//!
+//! Only postgres indexes the extracted values, with a GIN index that
+//! equality filters use. Range and prefix filters, and every filter on
+//! sqlite and mysql, are evaluated on each tixel of the strand being
+//! queried.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use futures::TryStreamExt;
+//! use twine_sql_store::payload::{PayloadError, PayloadIndex, PayloadQuery};
+//! use twine_sql_store::SqlStore;
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//! # let strand_cid = twine_lib::Cid::default();
+//! let index = PayloadIndex::default()
+//!   .path("sensor")?
+//!   .path("reading.value")?;
+//! let store = SqlStore::open("sqlite:my_database.db")
+//!   .await
+//!   .unwrap()
+//!   .with_payload_index(index);
+//! let query = PayloadQuery::new(strand_cid)
+//!   .eq("sensor", "x")
+//!   .range("reading.value", 10..20)
+//!   .limit(100);
+//! let twines: Vec<_> = store.query_payload(query).await?.try_collect().await?;
+//! # Ok::<_, PayloadError>(())
+//! # });
+//! ```
+use serde_json::{Map, Number, Value};
+use std::ops::{Bound, RangeBounds};
+use thiserror::Error;
+use twine_lib::as_cid::AsCid;
+use twine_lib::errors::ResolutionError;
+use twine_lib::{Cid, Ipld};
+
+/// Errors from configuring or running payload queries
+#[derive(Error, Debug)]
+pub enum PayloadError {
+  /// Indicates a path that can not be indexed
+  #[error("Invalid payload path: {0}")]
+  InvalidPath(String),
+  /// Indicates a query that the store can not run
+  ///
+  /// For example, one that filters on a path which is not indexed
+  #[error("Invalid payload query: {0}")]
+  InvalidQuery(String),
+  /// Indicates a problem resolving the strand being queried
+  #[error(transparent)]
+  Resolution(Box<ResolutionError>),
+}
+
+impl From<ResolutionError> for PayloadError {
+  fn from(err: ResolutionError) -> Self {
+    Self::Resolution(Box::new(err))
+  }
+}
+
+/// The payload paths that a store indexes
+///
+/// # Example
+///
+/// ```
+/// use twine_sql_store::payload::PayloadIndex;
+/// let index = PayloadIndex::default().path("reading.value").unwrap();
+/// assert!(PayloadIndex::default().path("bad\"path").is_err());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PayloadIndex {
+  paths: Vec<String>,
+}
+
+impl PayloadIndex {
+  /// Index the payload value at a path so it can be queried
+  ///
+  /// Paths are dot separated map keys, like `reading.value`, made of
+  /// letters, digits, `_` and `-`. Only scalar values are indexed.
+  pub fn path<P: Into<String>>(mut self, path: P) -> Result<Self, PayloadError> {
+    let path = path.into();
+    if !is_valid_path(&path) {
+      return Err(PayloadError::InvalidPath(path));
+    }
+    if !self.paths.contains(&path) {
+      self.paths.push(path);
+    }
+    Ok(self)
+  }
+
+  /// The indexed payload paths
+  pub fn paths(&self) -> &[String] {
+    &self.paths
+  }
+}
+
+/// A condition on the value at an indexed payload path
+#[derive(Debug, Clone, PartialEq)]
+pub enum PayloadFilter {
+  /// The value equals this value
+  Eq(String, Value),
+  /// The value is within the bounds. Bounds must be numbers or strings.
+  Range(String, Bound<Value>, Bound<Value>),
+  /// The value is a string starting with this prefix
+  Prefix(String, String),
+}
+
+impl PayloadFilter {
+  /// The payload path this filter applies to
+  pub fn path(&self) -> &str {
+    match self {
+      Self::Eq(path, _) | Self::Range(path, _, _) | Self::Prefix(path, _) => path,
+    }
+  }
+}
+
+/// A query for the tixels of a strand by indexed payload values
+///
+/// All filters must match. Results are in increasing index order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PayloadQuery {
+  /// The strand to search
+  pub strand: Cid,
+  /// The conditions that tixels must meet
+  pub filters: Vec<PayloadFilter>,
+  /// The maximum number of tixels to return
+  pub limit: Option<u64>,
+}
+
+impl PayloadQuery {
+  /// Create a query over the tixels of a strand
+  pub fn new<C: AsCid>(strand: C) -> Self {
+    Self {
+      strand: *strand.as_cid(),
+      filters: vec![],
+      limit: None,
+    }
+  }
+
+  /// Require the value at a path to equal a value
+  pub fn eq<P: Into<String>, V: Into<Value>>(mut self, path: P, value: V) -> Self {
+    self
+      .filters
+      .push(PayloadFilter::Eq(path.into(), value.into()));
+    self
+  }
+
+  /// Require the value at a path to be within a range
+  pub fn range<P: Into<String>, V: Into<Value> + Clone, R: RangeBounds<V>>(
+    mut self,
+    path: P,
+    range: R,
+  ) -> Self {
+    let convert = |bound: Bound<&V>| match bound {
+      Bound::Included(v) => Bound::Included(v.clone().into()),
+      Bound::Excluded(v) => Bound::Excluded(v.clone().into()),
+      Bound::Unbounded => Bound::Unbounded,
+    };
+    self.filters.push(PayloadFilter::Range(
+      path.into(),
+      convert(range.start_bound()),
+      convert(range.end_bound()),
+    ));
+    self
+  }
+
+  /// Require the value at a path to be a string with a prefix
+  pub fn prefix<P: Into<String>, S: Into<String>>(mut self, path: P, prefix: S) -> Self {
+    self
+      .filters
+      .push(PayloadFilter::Prefix(path.into(), prefix.into()));
+    self
+  }
+
+  /// Return at most this many tixels
+  pub fn limit(mut self, limit: u64) -> Self {
+    self.limit = Some(limit);
+    self
+  }
+
+  /// Check that the query only uses indexed paths and valid bounds
+  pub(crate) fn check(&self, index: &PayloadIndex) -> Result<(), String> {
+    for filter in &self.filters {
+      if !index.paths().iter().any(|p| p == filter.path()) {
+        return Err(format!("Payload path {} is not indexed", filter.path()));
+      }
+      if let PayloadFilter::Range(path, lower, upper) = filter {
+        let kinds: Vec<_> = [lower, upper]
+          .into_iter()
+          .filter_map(|bound| match bound {
+            Bound::Included(v) | Bound::Excluded(v) => Some(ValueKind::of(v)),
+            Bound::Unbounded => None,
+          })
+          .collect();
+        match kinds.as_slice() {
+          [] => {}
+          [ValueKind::Number] | [ValueKind::Number, ValueKind::Number] => {}
+          [ValueKind::String] | [ValueKind::String, ValueKind::String] => {}
+          _ => {
+            return Err(format!(
+              "Range bounds for {} must both be numbers or strings",
+              path
+            ))
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+/// The kind of JSON value a range compares
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ValueKind {
+  Number,
+  String,
+  Other,
+}
+
+impl ValueKind {
+  pub(crate) fn of(value: &Value) -> Self {
+    match value {
+      Value::Number(_) => Self::Number,
+      Value::String(_) => Self::String,
+      _ => Self::Other,
+    }
+  }
+
+  /// The kind of a checked range
+  pub(crate) fn of_range(lower: &Bound<Value>, upper: &Bound<Value>) -> Self {
+    match (lower, upper) {
+      (Bound::Included(v) | Bound::Excluded(v), _) => Self::of(v),
+      (_, Bound::Included(v) | Bound::Excluded(v)) => Self::of(v),
+      _ => Self::Other,
+    }
+  }
+}
+
+/// Check that a path can be indexed
+///
+/// Paths are dot separated map keys made of letters, digits, `_` and `-`.
+pub(crate) fn is_valid_path(path: &str) -> bool {
+  !path.is_empty()
+    && path.split('.').all(|key| {
+      !key.is_empty()
+        && key
+          .chars()
+          .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    })
+}
+
+/// The JSON path expression for an indexed path (for sqlite and mysql)
+#[cfg(any(feature = "sqlite", feature = "mysql"))]
+pub(crate) fn json_path(path: &str) -> String {
+  format!("$.\"{}\"", path)
+}
+
+fn to_json(ipld: &Ipld) -> Option<Value> {
+  match ipld {
+    Ipld::Null => Some(Value::Null),
+    Ipld::Bool(b) => Some(Value::Bool(*b)),
+    Ipld::Integer(i) => i64::try_from(*i)
+      .ok()
+      .map(Number::from)
+      .or_else(|| u64::try_from(*i).ok().map(Number::from))
+      .map(Value::Number),
+    Ipld::Float(f) => Number::from_f64(*f).map(Value::Number),
+    Ipld::String(s) => Some(Value::String(s.clone())),
+    // only scalars are indexed
+    _ => None,
+  }
+}
+
+fn lookup<'a>(payload: &'a Ipld, path: &str) -> Option<&'a Ipld> {
+  path.split('.').try_fold(payload, |value, key| match value {
+    Ipld::Map(map) => map.get(key),
+    _ => None,
+  })
+}
+
+/// Extract the indexed paths of a payload as JSON text
+///
+/// Returns `None` if no paths are indexed.
+pub(crate) fn index_payload(paths: &[String], payload: &Ipld) -> Option<String> {
+  if paths.is_empty() {
+    return None;
+  }
+  let index: Map<String, Value> = paths
+    .iter()
+    .filter_map(|path| Some((path.clone(), to_json(lookup(payload, path)?)?)))
+    .collect();
+  Some(Value::Object(index).to_string())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use twine_lib::ipld_core::ipld;
+
+  #[test]
+  fn test_index_payload() {
+    let payload = ipld!({
+      "sensor": "x",
+      "reading": { "value": 12, "raw": [1, 2] },
+    });
+    let paths = vec![
+      "sensor".to_string(),
+      "reading.value".to_string(),
+      "reading.raw".to_string(),
+      "missing".to_string(),
+    ];
+    let index: Value = serde_json::from_str(&index_payload(&paths, &payload).unwrap()).unwrap();
+    assert_eq!(
+      index,
+      serde_json::json!({ "sensor": "x", "reading.value": 12 })
+    );
+    assert_eq!(index_payload(&[], &payload), None);
+    assert!(is_valid_path("reading.value"));
+    assert!(!is_valid_path("reading..value"));
+    assert!(!is_valid_path("bad\"path"));
+  }
+
+  #[test]
+  fn test_check_query() {
+    let indexed = PayloadIndex::default().path("a").unwrap();
+    let query = PayloadQuery::new(Cid::default()).range("a", 1..5);
+    assert!(query.check(&indexed).is_ok());
+    assert!(PayloadQuery::new(Cid::default())
+      .eq("b", 1)
+      .check(&indexed)
+      .is_err());
+    let mixed = PayloadQuery::new(Cid::default()).range(
+      "a",
+      (
+        Bound::Included(Value::from(1)),
+        Bound::Excluded(Value::from("z")),
+      ),
+    );
+    assert!(mixed.check(&indexed).is_err());
+  }
+}
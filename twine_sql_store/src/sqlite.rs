@@ -1,8 +1,14 @@
 //! SQLite store implementation for Twine
-use super::{to_resolution_error, to_storage_error, Block};
+use super::payload::{
+  index_payload, json_path, PayloadError, PayloadFilter, PayloadIndex, PayloadQuery, ValueKind,
+};
+use super::{keyset_stream, to_resolution_error, to_storage_error, Block, SqlStoreOptions};
 use async_trait::async_trait;
-use futures::stream::{unfold, Stream};
+use futures::stream::Stream;
 use futures::stream::{StreamExt, TryStreamExt};
+use sqlx::migrate::Migrator;
+use sqlx::QueryBuilder;
+use std::ops::Bound;
 use std::pin::Pin;
 use twine_lib::as_cid::AsCid;
 use twine_lib::errors::{ResolutionError, StoreError};
@@ -12,46 +18,51 @@ use twine_lib::resolver::{unchecked_base, Resolver};
 use twine_lib::store::Store;
 use twine_lib::twine::{AnyTwine, TwineBlock};
 use twine_lib::{
-  twine::{Strand, Tixel},
+  twine::{Strand, Tixel, Twine},
   Cid,
 };
 
-/// The SQL schema for the SQLite store
-pub const SCHEMA: &str = r#"
-CREATE TABLE IF NOT EXISTS Strands (
-  id INTEGER PRIMARY KEY AUTOINCREMENT,
-  -- Cid bytes (2x varint (9) + 512bit hash (64)) = 18 + 64 = 82
-  cid BINARY(82) UNIQUE NOT NULL,
-  spec TEXT NOT NULL,
-  data BLOB NOT NULL
-);
-
-CREATE INDEX IF NOT EXISTS idx_strands_cid ON Strands (cid);
-
-CREATE TABLE IF NOT EXISTS Tixels (
-  cid BINARY(82) UNIQUE NOT NULL,
-  strand INTEGER NOT NULL,
-  idx INTEGER NOT NULL,
-  data BLOB NOT NULL,
+/// The initial SQL schema for the SQLite store
+///
+/// This is the first migration in `./schemas/sqlite/`. Prefer
+/// [`SqliteStore::migrate`], which also applies any later migrations.
+pub const SCHEMA: &str = include_str!("../schemas/sqlite/001_init-sqlite.sql");
 
-  -- Keys
-  PRIMARY KEY (strand, idx),
-  FOREIGN KEY (strand) REFERENCES Strands(id) ON DELETE CASCADE
-);
+/// The migrations for the SQLite store
+pub static MIGRATOR: Migrator = sqlx::migrate!("./schemas/sqlite");
 
-CREATE INDEX IF NOT EXISTS idx_tixels_cid ON Tixels (cid);
-"#;
+type Row = (i64, Vec<u8>, Vec<u8>);
 
 /// A Sqlite store for Twine data
 #[derive(Debug, Clone)]
 pub struct SqliteStore {
   pool: sqlx::SqlitePool,
+  options: SqlStoreOptions,
+  payload_index: PayloadIndex,
 }
 
 impl SqliteStore {
   /// Create a new Sqlite store from a sqlx pool
   pub fn new(pool: sqlx::SqlitePool) -> Self {
-    Self { pool }
+    Self {
+      pool,
+      options: SqlStoreOptions::default(),
+      payload_index: PayloadIndex::default(),
+    }
+  }
+
+  /// Set the options for the store
+  pub fn with_options(mut self, options: SqlStoreOptions) -> Self {
+    self.options = options;
+    self
+  }
+
+  /// Set the payload paths to index when saving tixels
+  ///
+  /// See [`crate::payload`].
+  pub fn with_payload_index(mut self, index: PayloadIndex) -> Self {
+    self.payload_index = index;
+    self
   }
 
   /// Open a new Sqlite store from a URI
@@ -71,10 +82,17 @@ impl SqliteStore {
 
   /// Create the tables for the store
   ///
-  /// This will create the necessary tables for the store if they do not already exist
+  /// This is the same as [`SqliteStore::migrate`]
   pub async fn create_tables(&self) -> Result<(), sqlx::Error> {
-    let mut conn = self.pool.acquire().await?;
-    sqlx::query(SCHEMA).execute(&mut *conn).await?;
+    self.migrate().await
+  }
+
+  /// Apply any pending schema migrations
+  ///
+  /// Applied migrations are recorded in the `_sqlx_migrations` table,
+  /// so this is safe to call every time the store is opened.
+  pub async fn migrate(&self) -> Result<(), sqlx::Error> {
+    MIGRATOR.run(&self.pool).await?;
     Ok(())
   }
 
@@ -84,33 +102,78 @@ impl SqliteStore {
     Pin<Box<dyn Stream<Item = Result<Strand, ResolutionError>> + Send + '_>>,
     ResolutionError,
   > {
-    let query = "SELECT cid, data FROM Strands LIMIT 10 OFFSET $1";
+    // keyset pagination on the primary key
+    let query = "SELECT id, cid, data FROM Strands WHERE id > $1 ORDER BY id LIMIT $2";
+    let stream = keyset_stream(
+      0,
+      self.options.strand_batch_size,
+      None,
+      move |after, limit| {
+        sqlx::query_as::<_, Row>(query)
+          .bind(after)
+          .bind(limit as i64)
+          .fetch_all(&self.pool)
+      },
+      |_, id| Some(*id),
+      Strand::from_block,
+    );
 
-    let stream = unfold(0, move |offset| async move {
-      let mut conn = match self.pool.acquire().await.map_err(to_resolution_error) {
-        Ok(conn) => conn,
-        Err(e) => return Some((Err(e), offset)),
-      };
-      let strands: Result<Vec<_>, ResolutionError> = sqlx::query_as::<_, Block>(query)
-        .bind(offset)
-        .fetch(&mut *conn)
-        .map_err(to_resolution_error)
-        .map_ok(|(cid, data)| {
-          let cid = Cid::try_from(cid).map_err(|e| ResolutionError::Fetch(e.to_string()))?;
-          Ok::<_, ResolutionError>(Strand::from_block(cid, data)?)
-        })
-        .try_collect()
-        .await;
-      if let Ok(strands) = &strands {
-        if strands.is_empty() {
-          return None;
+    Ok(stream)
+  }
+
+  async fn strand_id(&self, cid: &Cid) -> Result<Option<i64>, ResolutionError> {
+    let query = "SELECT id FROM Strands WHERE cid = $1";
+
+    let mut conn = self.pool.acquire().await.map_err(to_resolution_error)?;
+
+    sqlx::query_scalar(query)
+      .bind(cid.to_bytes())
+      .fetch_optional(&mut *conn)
+      .await
+      .map_err(to_resolution_error)
+  }
+
+  /// Find the tixels of a strand by indexed payload values
+  ///
+  /// The query runs as SQL, and the returned twines are verified.
+  /// See [`crate::payload`].
+  pub async fn query_payload(
+    &self,
+    query: PayloadQuery,
+  ) -> Result<unchecked_base::TwineStream<'_, Twine>, PayloadError> {
+    query
+      .check(&self.payload_index)
+      .map_err(PayloadError::InvalidQuery)?;
+    let strand = self.get_strand(&query.strand).await?;
+    let Some(strand_id) = self.strand_id(&query.strand).await? else {
+      return Ok(futures::stream::empty().boxed());
+    };
+    let stream = keyset_stream(
+      None,
+      self.options.range_batch_size,
+      query.limit,
+      move |after, limit| {
+        let query = query.clone();
+        async move {
+          let mut builder =
+            QueryBuilder::<sqlx::Sqlite>::new("SELECT idx, cid, data FROM Tixels WHERE strand = ");
+          builder.push_bind(strand_id);
+          if let Some(after) = after {
+            builder.push(" AND idx > ").push_bind(after);
+          }
+          for filter in &query.filters {
+            builder.push(" AND ");
+            push_payload_filter(&mut builder, filter);
+          }
+          builder
+            .push(" ORDER BY idx ASC LIMIT ")
+            .push_bind(limit as i64);
+          builder.build_query_as::<Row>().fetch_all(&self.pool).await
         }
-      }
-      Some((strands, offset + 10))
-    })
-    .map_ok(|v| futures::stream::iter(v.into_iter()))
-    .try_flatten()
-    .boxed();
+      },
+      |_, idx| Some(Some(*idx)),
+      move |cid, data| Twine::try_new(strand.clone(), Tixel::from_block(cid, data)?),
+    );
 
     Ok(stream)
   }
@@ -259,8 +322,8 @@ impl SqliteStore {
     }
 
     let query = "
-      INSERT OR IGNORE INTO Tixels (cid, data, strand, idx)
-      SELECT $1, $2, s.id, $4 FROM Strands s
+      INSERT OR IGNORE INTO Tixels (cid, data, strand, idx, payload_index)
+      SELECT $1, $2, s.id, $4, $5 FROM Strands s
       WHERE s.cid = $3 AND
       ($4 = 0 OR EXISTS (
         SELECT 1 FROM Tixels t WHERE t.strand = s.id AND t.idx = $4 - 1
@@ -269,12 +332,14 @@ impl SqliteStore {
 
     let cid = tixel.cid().to_bytes();
     let data = tixel.bytes().to_vec();
+    let payload_index = index_payload(self.payload_index.paths(), tixel.payload());
 
     let _ret = sqlx::query(&query)
       .bind(&cid)
       .bind(&data)
       .bind(tixel.strand_cid().to_bytes())
       .bind(tixel.index() as i64)
+      .bind(payload_index)
       .execute(&mut *conn)
       .await
       .map_err(to_storage_error)?;
@@ -311,6 +376,59 @@ impl SqliteStore {
   }
 }
 
+/// Push the SQL condition for a payload filter
+fn push_payload_filter(builder: &mut QueryBuilder<'_, sqlx::Sqlite>, filter: &PayloadFilter) {
+  let path = json_path(filter.path());
+  match filter {
+    PayloadFilter::Eq(_, value) => {
+      builder
+        .push("json_extract(payload_index, ")
+        .push_bind(path)
+        .push(") = json_extract(")
+        .push_bind(value.to_string())
+        .push(", '$')");
+    }
+    PayloadFilter::Range(_, lower, upper) => {
+      let types = match ValueKind::of_range(lower, upper) {
+        ValueKind::Number => "IN ('integer', 'real')",
+        ValueKind::String => "= 'text'",
+        ValueKind::Other => "IS NOT NULL",
+      };
+      builder
+        .push("json_type(payload_index, ")
+        .push_bind(path.clone())
+        .push(") ")
+        .push(types);
+      for (bound, inclusive, exclusive) in [(lower, ">=", ">"), (upper, "<=", "<")] {
+        let (op, value) = match bound {
+          Bound::Included(value) => (inclusive, value),
+          Bound::Excluded(value) => (exclusive, value),
+          Bound::Unbounded => continue,
+        };
+        builder
+          .push(" AND json_extract(payload_index, ")
+          .push_bind(path.clone())
+          .push(") ")
+          .push(op)
+          .push(" json_extract(")
+          .push_bind(value.to_string())
+          .push(", '$')");
+      }
+    }
+    PayloadFilter::Prefix(_, prefix) => {
+      builder
+        .push("json_type(payload_index, ")
+        .push_bind(path.clone())
+        .push(") = 'text' AND substr(json_extract(payload_index, ")
+        .push_bind(path)
+        .push("), 1, length(")
+        .push_bind(prefix.clone())
+        .push(")) = ")
+        .push_bind(prefix.clone());
+    }
+  }
+}
+
 #[async_trait]
 impl unchecked_base::BaseResolver for SqliteStore {
   async fn fetch_strands(
@@ -367,39 +485,51 @@ impl unchecked_base::BaseResolver for SqliteStore {
     Pin<Box<dyn Stream<Item = Result<Tixel, ResolutionError>> + Send + '_>>,
     ResolutionError,
   > {
-    let batches = range.batches(100);
-    let stream = unfold(batches.into_iter(), move |mut batches| async move {
-      let batch = batches.next()?;
-      let mut conn = match self.pool.acquire().await.map_err(to_resolution_error) {
-        Ok(conn) => conn,
-        Err(e) => return Some((Err(e), batches)),
-      };
-      let dir = if range.is_increasing() { "ASC" } else { "DESC" };
-      let tixels: Result<Vec<_>, ResolutionError> = sqlx::query_as::<_, Block>(&format!(
-        "
-          SELECT t.cid, t.data
-          FROM Tixels t JOIN Strands s ON t.strand = s.id
-          WHERE s.cid = $1 AND t.idx >= $2 AND t.idx <= $3
-          ORDER BY t.idx {}
-        ",
-        dir
-      ))
-      .bind(range.strand.to_bytes())
-      .bind(batch.lower() as i64)
-      .bind(batch.upper() as i64)
-      .fetch(&mut *conn)
-      .map_err(to_resolution_error)
-      .map_ok(|(cid, data)| {
-        let cid = Cid::try_from(cid).map_err(|e| ResolutionError::Fetch(e.to_string()))?;
-        Ok::<_, ResolutionError>(Tixel::from_block(cid, data)?)
-      })
-      .try_collect()
-      .await;
-      Some((tixels, batches))
-    })
-    .map_ok(|v| futures::stream::iter(v.into_iter()))
-    .try_flatten()
-    .boxed();
+    let Some(strand) = self.strand_id(&range.strand).await? else {
+      return Ok(futures::stream::empty().boxed());
+    };
+    // Keyset pagination: each batch continues from the last index seen,
+    // so every query is a bounded scan of the (strand, idx) primary key.
+    // The query text is fixed so the prepared statement is reused.
+    let increasing = range.is_increasing();
+    let query = if increasing {
+      "
+        SELECT idx, cid, data FROM Tixels
+        WHERE strand = $1 AND idx >= $2 AND idx <= $3
+        ORDER BY idx ASC
+        LIMIT $4
+      "
+    } else {
+      "
+        SELECT idx, cid, data FROM Tixels
+        WHERE strand = $1 AND idx >= $2 AND idx <= $3
+        ORDER BY idx DESC
+        LIMIT $4
+      "
+    };
+    let bounds = (range.lower() as i64, range.upper() as i64);
+    let stream = keyset_stream(
+      bounds,
+      self.options.range_batch_size,
+      None,
+      move |(lower, upper), limit| {
+        sqlx::query_as::<_, Row>(query)
+          .bind(strand)
+          .bind(lower)
+          .bind(upper)
+          .bind(limit as i64)
+          .fetch_all(&self.pool)
+      },
+      move |(lower, upper), idx| {
+        let next = if increasing {
+          Some((idx + 1, upper))
+        } else {
+          idx.checked_sub(1).map(|upper| (lower, upper))
+        };
+        next.filter(|(lower, upper)| lower <= upper)
+      },
+      Tixel::from_block,
+    );
 
     Ok(stream)
   }
@@ -452,3 +582,87 @@ impl Store for SqliteStore {
     }
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use twine_builder::TwineBuilder;
+  use twine_lib::resolver::Resolver;
+
+  #[tokio::test]
+  async fn test_migrate() {
+    let store = SqliteStore::open("sqlite::memory:").await.unwrap();
+    // a database set up from the schema before migrations existed
+    sqlx::raw_sql(SCHEMA).execute(&store.pool).await.unwrap();
+    store.migrate().await.unwrap();
+    // applying again is a no-op
+    store.migrate().await.unwrap();
+    let applied: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM _sqlx_migrations")
+      .fetch_one(&store.pool)
+      .await
+      .unwrap();
+    assert_eq!(applied as usize, MIGRATOR.iter().count());
+  }
+
+  #[cfg(feature = "pq")]
+  #[tokio::test]
+  async fn test_post_quantum_roundtrip() {
+    let store = SqliteStore::open("sqlite::memory:").await.unwrap();
+    store.migrate().await.unwrap();
+    crate::test_util::post_quantum_roundtrip(crate::SqlStore::Sqlite(store)).await;
+  }
+
+  #[tokio::test]
+  async fn test_keyset_pagination() {
+    use futures::TryStreamExt;
+    let options = SqlStoreOptions::default()
+      .strand_batch_size(2)
+      .range_batch_size(7);
+    let store = SqliteStore::open("sqlite::memory:")
+      .await
+      .unwrap()
+      .with_options(options);
+    store.migrate().await.unwrap();
+    let builder = TwineBuilder::new(twine_builder::RingSigner::generate_ed25519().unwrap());
+    let mut strands = vec![];
+    for _ in 0..5 {
+      let strand = builder.build_strand().done().unwrap();
+      store.save(strand.clone()).await.unwrap();
+      strands.push(strand);
+    }
+    let strand = strands[0].clone();
+    let mut tixels = vec![builder.build_first(strand.clone()).done().unwrap()];
+    for _ in 1..50 {
+      let next = builder.build_next(tixels.last().unwrap()).done().unwrap();
+      tixels.push(next);
+    }
+    store.save_many(tixels.clone()).await.unwrap();
+
+    let found: Vec<_> = store.strands().await.unwrap().try_collect().await.unwrap();
+    assert_eq!(found, strands);
+    let forward: Vec<_> = store
+      .resolve_range((&strand, 3..=48))
+      .await
+      .unwrap()
+      .try_collect()
+      .await
+      .unwrap();
+    assert_eq!(forward, tixels[3..=48]);
+    let backward: Vec<_> = store
+      .resolve_range((strand.cid(), 48, 0))
+      .await
+      .unwrap()
+      .try_collect()
+      .await
+      .unwrap();
+    let expected: Vec<_> = tixels[0..=48].iter().rev().cloned().collect();
+    assert_eq!(backward, expected);
+  }
+
+  #[tokio::test]
+  async fn test_payload_query() {
+    let store = SqliteStore::open("sqlite::memory:").await.unwrap();
+    store.migrate().await.unwrap();
+    crate::test_util::payload_query(crate::SqlStore::Sqlite(store)).await;
+  }
+}
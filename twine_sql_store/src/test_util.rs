@@ -0,0 +1,97 @@
+//! Test bodies shared by every backend, run through the [`SqlStore`] facade
+use crate::payload::{PayloadError, PayloadIndex, PayloadQuery};
+use crate::{SqlStore, SqlStoreOptions};
+use futures::TryStreamExt;
+use twine_builder::{RingSigner, TwineBuilder};
+use twine_lib::ipld_core::ipld;
+use twine_lib::store::Store;
+
+pub(crate) async fn payload_query(store: SqlStore) {
+  let options = SqlStoreOptions::default().range_batch_size(3);
+  let index = PayloadIndex::default()
+    .path("sensor")
+    .unwrap()
+    .path("reading.value")
+    .unwrap();
+  let store = store.with_options(options).with_payload_index(index);
+  let builder = TwineBuilder::new(RingSigner::generate_ed25519().unwrap());
+  let strand = builder.build_strand().done().unwrap();
+  store.save(strand.clone()).await.unwrap();
+  let mut tixels = vec![builder
+    .build_first(strand.clone())
+    .payload(ipld!({ "sensor": "start" }))
+    .done()
+    .unwrap()];
+  for i in 1..20 {
+    let sensor = if i % 2 == 0 { "even" } else { "odd" };
+    let next = builder
+      .build_next(tixels.last().unwrap())
+      .payload(ipld!({ "sensor": sensor, "reading": { "value": i } }))
+      .done()
+      .unwrap();
+    tixels.push(next);
+  }
+  store.save_many(tixels.clone()).await.unwrap();
+
+  let run = |query: PayloadQuery| {
+    let store = &store;
+    async move {
+      let found: Vec<_> = store
+        .query_payload(query)
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+      found.iter().map(|t| t.index()).collect::<Vec<_>>()
+    }
+  };
+  let query = PayloadQuery::new(strand.cid());
+  assert_eq!(
+    run(query.clone().eq("sensor", "odd")).await,
+    vec![1, 3, 5, 7, 9, 11, 13, 15, 17, 19]
+  );
+  assert_eq!(
+    run(
+      query
+        .clone()
+        .eq("sensor", "even")
+        .range("reading.value", 5..=12)
+    )
+    .await,
+    vec![6, 8, 10, 12]
+  );
+  assert_eq!(
+    run(query.clone().range("reading.value", 17..)).await,
+    vec![17, 18, 19]
+  );
+  assert_eq!(
+    run(query.clone().prefix("sensor", "ev").limit(4)).await,
+    vec![2, 4, 6, 8]
+  );
+  assert_eq!(run(query.clone().prefix("sensor", "st")).await, vec![0]);
+  assert_eq!(run(query.clone().eq("reading.value", 7)).await, vec![7]);
+  assert!(matches!(
+    store.query_payload(query.clone().eq("unindexed", 1)).await,
+    Err(PayloadError::InvalidQuery(_))
+  ));
+}
+
+/// ML-DSA-87 keys and signatures are several kilobytes, more than a
+/// plain mysql `BLOB` holds
+#[cfg(feature = "pq")]
+pub(crate) async fn post_quantum_roundtrip(store: SqlStore) {
+  use twine_builder::PqSigner;
+  use twine_lib::crypto::SignatureAlgorithm;
+  use twine_lib::resolver::Resolver;
+  let builder = TwineBuilder::new(PqSigner::generate(SignatureAlgorithm::MlDsa87).unwrap());
+  let strand = builder.build_strand().done().unwrap();
+  let first = builder.build_first(strand.clone()).done().unwrap();
+  store.save(strand.clone()).await.unwrap();
+  store.save(first.clone()).await.unwrap();
+  assert_eq!(
+    store.resolve_strand(&strand).await.unwrap().unpack(),
+    strand
+  );
+  assert_eq!(store.resolve_latest(&strand).await.unwrap().unpack(), first);
+}